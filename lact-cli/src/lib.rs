@@ -1,6 +1,10 @@
 use anyhow::Result;
 use lact_client::DaemonClient;
-use lact_schema::args::{CliArgs, CliCommand};
+use lact_schema::{
+    args::{CliArgs, CliCommand},
+    ValueRange,
+};
+use std::fmt::Display;
 
 pub fn run(args: CliArgs) -> Result<()> {
     let rt = tokio::runtime::Builder::new_current_thread()
@@ -13,6 +17,9 @@ pub fn run(args: CliArgs) -> Result<()> {
         match args.subcommand {
             CliCommand::ListGpus => list_gpus(&args, &client).await,
             CliCommand::Info => info(&args, &client).await,
+            CliCommand::Capabilities => capabilities(&args, &client).await,
+            CliCommand::Power => power(&client).await,
+            CliCommand::Status => status(&client).await,
             CliCommand::Snapshot => snapshot(&client).await,
         }
     })
@@ -24,7 +31,7 @@ async fn list_gpus(_: &CliArgs, client: &DaemonClient) -> Result<()> {
         let id = entry.id;
         let device_type = entry.device_type;
 
-        if let Some(name) = entry.name {
+        if let Some(name) = entry.alias.or(entry.name) {
             println!("{id} ({name}) [{device_type}]");
         } else {
             println!("{id} [{device_type}]");
@@ -48,10 +55,81 @@ async fn info(args: &CliArgs, client: &DaemonClient) -> Result<()> {
                 println!("{name}: {value}");
             }
         }
+
+        for warning in client.get_health_warnings(&id).await? {
+            println!("Warning: {warning}");
+        }
     }
     Ok(())
 }
 
+async fn capabilities(args: &CliArgs, client: &DaemonClient) -> Result<()> {
+    for id in extract_gpu_ids(args, client).await {
+        let gpu_line = format!("GPU {id}:");
+        println!("{gpu_line}");
+        println!("{}", "=".repeat(gpu_line.len()));
+
+        let capabilities = client.get_capabilities(&id).await?;
+
+        print_range("Power cap (W)", capabilities.power_cap);
+        print_range("Max core clock (MHz)", capabilities.max_core_clock);
+        print_range("Min core clock (MHz)", capabilities.min_core_clock);
+        print_range("Max memory clock (MHz)", capabilities.max_memory_clock);
+        print_range("Min memory clock (MHz)", capabilities.min_memory_clock);
+        print_range("Max voltage (mV)", capabilities.max_voltage);
+        print_range("Min voltage (mV)", capabilities.min_voltage);
+        print_range("Voltage offset (mV)", capabilities.voltage_offset);
+        print_range(
+            "Fan curve temperature (°C)",
+            capabilities.fan_curve_temperature,
+        );
+        print_range("Fan curve speed (%)", capabilities.fan_curve_speed);
+        if let Some(points) = capabilities.fan_curve_points {
+            println!("Fan curve points: {points}");
+        }
+
+        for (pstate, range) in &capabilities.gpu_clock_offsets {
+            print_range(&format!("GPU clock offset P{pstate} (MHz)"), Some(*range));
+        }
+        for (pstate, range) in &capabilities.mem_clock_offsets {
+            print_range(
+                &format!("Memory clock offset P{pstate} (MHz)"),
+                Some(*range),
+            );
+        }
+
+        println!("Fan control: {}", capabilities.fan_control);
+        println!("Performance level: {}", capabilities.performance_level);
+        println!("Power profile mode: {}", capabilities.power_profile_mode);
+    }
+    Ok(())
+}
+
+async fn power(client: &DaemonClient) -> Result<()> {
+    let power = client.get_system_power().await?;
+    for (id, watts) in &power.gpus {
+        println!("{id}: {watts:.1} W");
+    }
+    for id in &power.excluded_gpus {
+        println!("{id}: not reported");
+    }
+    println!("Total: {:.1} W", power.total);
+    Ok(())
+}
+
+async fn status(client: &DaemonClient) -> Result<()> {
+    for line in client.get_status_line().await? {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+fn print_range<T: Display>(name: &str, range: Option<ValueRange<T>>) {
+    if let Some(range) = range {
+        println!("{name}: {} - {}", range.min, range.max);
+    }
+}
+
 async fn extract_gpu_ids(args: &CliArgs, client: &DaemonClient) -> Vec<String> {
     match args.gpu_id {
         Some(ref id) => vec![id.clone()],
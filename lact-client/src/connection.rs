@@ -1,9 +1,10 @@
 pub mod tcp;
 pub mod unix;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
 use futures::future::BoxFuture;
-use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use lact_schema::CompressedFrame;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 
 pub trait DaemonConnection {
     fn request<'a>(&'a mut self, payload: &'a str) -> BoxFuture<'a, anyhow::Result<String>>;
@@ -26,5 +27,18 @@ async fn request(
     let mut response_payload = String::new();
     socket.read_line(&mut response_payload).await?;
 
+    if let Some(frame) = CompressedFrame::parse_header(&response_payload)
+        .context("Could not parse compressed response header")?
+    {
+        // The compressed data is followed by a newline
+        let mut data = vec![0; frame.length + 1];
+        socket.read_exact(&mut data).await?;
+        data.pop();
+
+        let payload = frame.codec.decompress(&data)?;
+        response_payload =
+            String::from_utf8(payload).context("Decompressed response is not valid UTF-8")?;
+    }
+
     Ok(response_payload)
 }
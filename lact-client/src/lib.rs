@@ -4,18 +4,26 @@ mod macros;
 
 pub use lact_schema as schema;
 use lact_schema::{
-    config::{GpuConfig, Profile, ProfileHooks},
-    ProcessList, ProfileRule,
+    config::{
+        ClockVoltagePoint, FanCurve, FanCurvePreset, FanCurveUnit, GpuConfig, LedSettings, Profile,
+        ProfileHooks,
+    },
+    PowerCapEstimate, ProcessList, ProfileRule,
 };
 
-use amdgpu_sysfs::gpu_handle::power_profile_mode::PowerProfileModesTable;
+use amdgpu_sysfs::gpu_handle::{power_profile_mode::PowerProfileModesTable, PowerLevelKind};
 use anyhow::Context;
 use connection::{tcp::TcpConnection, unix::UnixConnection, DaemonConnection};
+use indexmap::IndexMap;
 use nix::unistd::getuid;
 use schema::{
     request::{ConfirmCommand, ProfileBase, SetClocksCommand},
-    ClocksInfo, DeviceInfo, DeviceListEntry, DeviceStats, PowerStates, ProfilesInfo, Request,
-    Response, SystemInfo,
+    CaptureSummary, ClocksInfo, CompressionCodec, ConfigDifference, CrashInfo, DaemonEvent,
+    DaemonInfo, DeviceInfo, DeviceListEntry, DeviceStats, EnergyDelta, FanCurveValidation,
+    FanResponse, GpuCapabilities, GpuStatusLine, HealthWarning, HistoryMetric, HistorySample,
+    ModelBroadcast, ModuleConfigStatus, ModuleConfigUpdate, OperationInfo, OverclockStatus,
+    PowerStates, ProfilesInfo, Request, Response, SelfTestEntry, SessionExtremes,
+    SettingProvenance, StatsExportFormat, SysfsWrite, SystemInfo, SystemPower, VersionsInfo,
 };
 use serde::de::DeserializeOwned;
 use std::{
@@ -124,14 +132,70 @@ impl DaemonClient {
         self.make_request(Request::ListDevices).await
     }
 
+    pub async fn set_gpu_alias(&self, id: &str, alias: Option<String>) -> anyhow::Result<()> {
+        self.make_request(Request::SetGpuAlias { id, alias }).await
+    }
+
+    pub async fn get_event_log(&self, since: Option<i64>) -> anyhow::Result<Vec<DaemonEvent>> {
+        self.make_request(Request::GetEventLog { since }).await
+    }
+
+    pub async fn list_operations(&self) -> anyhow::Result<Vec<OperationInfo>> {
+        self.make_request(Request::ListOperations).await
+    }
+
+    pub async fn cancel_operation(&self, id: u64) -> anyhow::Result<()> {
+        self.make_request(Request::CancelOperation { id }).await
+    }
+
+    /// Asks the daemon to compress large responses on the current connection.
+    /// Returns `None` if the daemon doesn't support any of the codecs, or doesn't know
+    /// about compression at all, in which case the responses stay uncompressed.
+    /// This has to be called again after reconnecting.
+    pub async fn enable_compression(&self) -> Option<CompressionCodec> {
+        let codecs = CompressionCodec::SUPPORTED.to_vec();
+        match self
+            .make_request(Request::NegotiateCompression { codecs })
+            .await
+        {
+            Ok(codec) => codec,
+            Err(err) => {
+                info!("daemon does not support compression: {err:#}");
+                None
+            }
+        }
+    }
+
     request_plain!(get_system_info, SystemInfo, SystemInfo);
+    request_plain!(get_daemon_info, GetDaemonInfo, DaemonInfo);
+    request_plain!(get_system_power, GetSystemPower, SystemPower);
+    request_plain!(get_status_line, GetStatusLine, Vec<GpuStatusLine>);
+    request_plain!(get_last_crash, GetLastCrash, Option<CrashInfo>);
+    request_with_id!(get_health_warnings, GetHealthWarnings, Vec<HealthWarning>);
+    request_with_id!(
+        get_setting_provenance,
+        GetSettingProvenance,
+        Vec<SettingProvenance>
+    );
     request_plain!(enable_overdrive, EnableOverdrive, String);
     request_plain!(disable_overdrive, DisableOverdrive, String);
+    request_plain!(
+        get_module_config_status,
+        GetModuleConfigStatus,
+        ModuleConfigStatus
+    );
+    request_plain!(get_overclock_status, GetOverclockStatus, OverclockStatus);
     request_plain!(generate_debug_snapshot, GenerateSnapshot, String);
     request_plain!(reset_config, RestConfig, ());
+    request_plain!(confirm_settings, ConfirmSettings, ());
     request_with_id!(get_device_info, DeviceInfo, DeviceInfo);
     request_with_id!(get_device_stats, DeviceStats, DeviceStats);
+    request_with_id!(get_stats_now, GetStatsNow, DeviceStats);
+    request_with_id!(get_config_diff, GetConfigDiff, Vec<ConfigDifference>);
     request_with_id!(get_device_clocks_info, DeviceClocksInfo, ClocksInfo);
+    request_with_id!(get_capabilities, GetCapabilities, GpuCapabilities);
+    request_with_id!(get_versions, GetVersions, VersionsInfo);
+    request_with_id!(get_firmware_fan_curve, GetFirmwareFanCurve, FanCurve);
     request_with_id!(
         get_device_power_profile_modes,
         DevicePowerProfileModes,
@@ -139,9 +203,43 @@ impl DaemonClient {
     );
     request_with_id!(get_power_states, GetPowerStates, PowerStates);
     request_with_id!(reset_pmfw, ResetPmfw, u64);
+    request_with_id!(begin_capture, BeginCapture, ());
+    request_with_id!(end_capture, EndCapture, CaptureSummary);
+    request_with_id!(get_extremes, GetExtremes, SessionExtremes);
+    request_with_id!(reset_extremes, ResetExtremes, ());
+    request_with_id!(energy_delta, EnergyDelta, Option<EnergyDelta>);
     request_with_id!(dump_vbios, VbiosDump, Vec<u8>);
+    request_with_id!(get_dry_run_log, GetDryRunLog, Vec<SysfsWrite>);
+    request_with_id!(run_self_test, RunSelfTest, Vec<SelfTestEntry>);
     request_with_id!(get_process_list, ProcessList, ProcessList);
 
+    pub async fn set_module_config(
+        &self,
+        ppfeaturemask: u64,
+    ) -> anyhow::Result<ModuleConfigUpdate> {
+        self.make_request(Request::SetModuleConfig { ppfeaturemask })
+            .await
+    }
+
+    pub async fn generate_fan_curve(
+        &self,
+        id: &str,
+        preset: FanCurvePreset,
+    ) -> anyhow::Result<FanCurve> {
+        self.make_request(Request::GenerateFanCurve { id, preset })
+            .await
+    }
+
+    pub async fn validate_fan_curve(
+        &self,
+        id: &str,
+        curve: FanCurve,
+        unit: FanCurveUnit,
+    ) -> anyhow::Result<FanCurveValidation> {
+        self.make_request(Request::ValidateFanCurve { id, curve, unit })
+            .await
+    }
+
     pub async fn list_profiles(&self, include_state: bool) -> anyhow::Result<ProfilesInfo> {
         self.make_request(Request::ListProfiles { include_state })
             .await
@@ -156,6 +254,18 @@ impl DaemonClient {
             .await
     }
 
+    pub async fn apply_temporary_profile(
+        &self,
+        name: String,
+        duration_secs: u64,
+    ) -> anyhow::Result<()> {
+        self.make_request(Request::ApplyTemporaryProfile {
+            name,
+            duration_secs,
+        })
+        .await
+    }
+
     pub async fn create_profile(&self, name: String, base: ProfileBase) -> anyhow::Result<()> {
         self.make_request(Request::CreateProfile { name, base })
             .await
@@ -184,6 +294,143 @@ impl DaemonClient {
             .await
     }
 
+    request_with_id!(export_gpu_config, ExportGpuConfig, String);
+
+    pub async fn import_gpu_config(
+        &self,
+        id: &str,
+        snippet: String,
+        allow_model_mismatch: bool,
+    ) -> anyhow::Result<u64> {
+        self.make_request(Request::ImportGpuConfig {
+            id,
+            snippet,
+            allow_model_mismatch,
+        })
+        .await
+    }
+
+    request_plain!(export_config, ExportConfig, String);
+
+    pub async fn import_config(
+        &self,
+        config: String,
+        apply: bool,
+        allow_missing_gpus: bool,
+    ) -> anyhow::Result<Option<u64>> {
+        self.make_request(Request::ImportConfig {
+            config,
+            apply,
+            allow_missing_gpus,
+        })
+        .await
+    }
+
+    pub async fn broadcast_to_model(
+        &self,
+        model: &str,
+        changes: GpuConfig,
+    ) -> anyhow::Result<ModelBroadcast> {
+        self.make_request(Request::BroadcastToModel { model, changes })
+            .await
+    }
+
+    /// The staged config is reverted if this client disconnects before confirming it
+    pub async fn stage_settings(
+        &self,
+        id: &str,
+        config: GpuConfig,
+        timeout_secs: Option<u64>,
+    ) -> anyhow::Result<u64> {
+        self.make_request(Request::StageSettings {
+            id,
+            config,
+            timeout_secs,
+        })
+        .await
+    }
+
+    pub async fn set_management_enabled(&self, enabled: bool) -> anyhow::Result<()> {
+        self.make_request(Request::SetManagementEnabled { enabled })
+            .await
+    }
+
+    pub async fn set_log_level(&self, level: &str) -> anyhow::Result<()> {
+        self.make_request(Request::SetLogLevel { level }).await
+    }
+
+    pub async fn get_history(
+        &self,
+        id: &str,
+        metrics: Vec<HistoryMetric>,
+    ) -> anyhow::Result<Vec<HistorySample>> {
+        self.make_request(Request::GetHistory { id, metrics }).await
+    }
+
+    pub async fn estimate_power_cap(
+        &self,
+        id: &str,
+        target_temperature: f64,
+    ) -> anyhow::Result<PowerCapEstimate> {
+        self.make_request(Request::EstimatePowerCap {
+            id,
+            target_temperature,
+        })
+        .await
+    }
+
+    pub async fn export_stats(
+        &self,
+        id: &str,
+        format: StatsExportFormat,
+        since: Option<i64>,
+    ) -> anyhow::Result<String> {
+        self.make_request(Request::ExportStats { id, format, since })
+            .await
+    }
+
+    pub async fn set_fan_static(&self, id: &str, pwm: u8) -> anyhow::Result<u64> {
+        self.make_request(Request::SetFanStatic { id, pwm }).await
+    }
+
+    pub async fn set_fan_zero_rpm(&self, id: &str, enabled: bool) -> anyhow::Result<u64> {
+        self.make_request(Request::SetFanZeroRpm { id, enabled })
+            .await
+    }
+
+    pub async fn measure_fan_response(
+        &self,
+        id: &str,
+        from_pwm: u8,
+        to_pwm: u8,
+    ) -> anyhow::Result<FanResponse> {
+        self.make_request(Request::MeasureFanResponse {
+            id,
+            from_pwm,
+            to_pwm,
+        })
+        .await
+    }
+
+    pub async fn set_power_cap_percent(&self, id: &str, percent: f64) -> anyhow::Result<u64> {
+        self.make_request(Request::SetPowerCapPercent { id, percent })
+            .await
+    }
+
+    pub async fn set_voltage_offset(&self, id: &str, offset: Option<i32>) -> anyhow::Result<u64> {
+        self.make_request(Request::SetVoltageOffset { id, offset })
+            .await
+    }
+
+    pub async fn set_memory_clock_offset(
+        &self,
+        id: &str,
+        offset: Option<i32>,
+    ) -> anyhow::Result<u64> {
+        self.make_request(Request::SetMemoryClockOffset { id, offset })
+            .await
+    }
+
     pub async fn set_clocks_value(
         &self,
         id: &str,
@@ -193,6 +440,56 @@ impl DaemonClient {
             .await
     }
 
+    pub async fn set_clock_states(
+        &self,
+        id: &str,
+        kind: PowerLevelKind,
+        states: IndexMap<u8, ClockVoltagePoint>,
+    ) -> anyhow::Result<u64> {
+        self.make_request(Request::SetClockStates { id, kind, states })
+            .await
+    }
+
+    pub async fn set_clock_state(
+        &self,
+        id: &str,
+        kind: PowerLevelKind,
+        index: u8,
+        point: Option<ClockVoltagePoint>,
+    ) -> anyhow::Result<u64> {
+        self.make_request(Request::SetClockState {
+            id,
+            kind,
+            index,
+            point,
+        })
+        .await
+    }
+
+    pub async fn set_memory_clock_level(&self, id: &str, level: Option<u8>) -> anyhow::Result<u64> {
+        self.make_request(Request::SetMemoryClockLevel { id, level })
+            .await
+    }
+
+    pub async fn set_pcie_level(&self, id: &str, level: Option<u8>) -> anyhow::Result<u64> {
+        self.make_request(Request::SetPcieLevel { id, level }).await
+    }
+
+    pub async fn set_idle_lock(&self, id: &str, enabled: bool) -> anyhow::Result<u64> {
+        self.make_request(Request::SetIdleLock { id, enabled })
+            .await
+    }
+
+    pub async fn set_led(
+        &self,
+        id: &str,
+        name: &str,
+        settings: Option<LedSettings>,
+    ) -> anyhow::Result<u64> {
+        self.make_request(Request::SetLed { id, name, settings })
+            .await
+    }
+
     pub async fn set_profile_rule(
         &self,
         name: String,
@@ -1,16 +1,19 @@
-use crate::server::gpu_controller::{GpuController, VENDOR_NVIDIA};
+use crate::server::gpu_controller::{common::retry::WriteRetry, GpuController, VENDOR_NVIDIA};
 use anyhow::Context;
 use indexmap::IndexMap;
-use lact_schema::config::{GpuConfig, Profile, ProfileHooks};
+use lact_schema::{
+    config::{GpuConfig, Profile, ProfileHooks},
+    TemperatureUnit,
+};
 use nix::unistd::{getuid, Group};
 use notify::{RecommendedWatcher, Watcher};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use std::{
     cell::Cell,
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     env, fs, iter,
-    path::PathBuf,
+    path::{Path, PathBuf},
     rc::Rc,
     time::{Duration, Instant},
 };
@@ -23,6 +26,9 @@ const DEFAULT_ADMIN_GROUPS: [&str; 2] = ["wheel", "sudo"];
 const CONFIG_RELOAD_INTERVAL_MILLIS: u64 = 50;
 /// Period when config changes are ignored after LACT itself has edited the config
 const SELF_CONFIG_EDIT_PERIOD_MILLIS: u64 = 1000;
+/// Lower bound for the stats polling interval, to avoid excessive CPU usage
+pub const MIN_STATS_POLL_INTERVAL_MS: u64 = 100;
+pub const MIN_FAN_CONTROL_INTERVAL_MS: u64 = 50;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Config {
@@ -39,6 +45,21 @@ pub struct Config {
     pub current_profile: Option<Rc<str>>,
     #[serde(default)]
     pub auto_switch_profiles: bool,
+    #[serde(default, skip_serializing_if = "PowerSourceProfiles::is_empty")]
+    pub power_source_profiles: PowerSourceProfiles,
+    /// PCI slot names of GPUs that should not be managed at all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignored_gpus: Vec<String>,
+    /// Names shown for GPUs in place of their model, by GPU id
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub gpu_aliases: IndexMap<String, String>,
+    /// Profile which is written to the file in place of the current one,
+    /// set while a temporary profile is active
+    #[serde(skip)]
+    pub saved_profile: Option<Option<Rc<str>>>,
+    /// Directory the config was loaded from, uses the default location when not set
+    #[serde(skip)]
+    pub(crate) dir: Option<PathBuf>,
 }
 
 impl Default for Config {
@@ -50,7 +71,12 @@ impl Default for Config {
             profiles: IndexMap::new(),
             current_profile: None,
             auto_switch_profiles: false,
+            power_source_profiles: PowerSourceProfiles::default(),
+            ignored_gpus: vec![],
+            gpu_aliases: IndexMap::new(),
+            saved_profile: None,
             version: 5,
+            dir: None,
         }
     }
 }
@@ -64,10 +90,99 @@ pub struct Daemon {
     pub admin_groups: Vec<String>,
     pub admin_user: Option<String>,
     pub admin_group: Option<String>,
+    /// When set, socket clients which are not root or members of this group
+    /// can only use the requests which don't change any settings
+    pub control_group: Option<String>,
     #[serde(default)]
     pub disable_clocks_cleanup: bool,
     pub disable_nvapi: Option<bool>,
     pub tcp_listen_address: Option<String>,
+    /// Only log the settings that would be written to the GPUs, without applying them
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Amount of stats samples to keep in the per-GPU history buffer
+    #[serde(default = "default_history_length")]
+    pub history_length: usize,
+    /// How often the stats history gets sampled
+    #[serde(default = "default_stats_poll_interval_ms")]
+    pub stats_poll_interval_ms: u64,
+    /// How often the fan curves are evaluated, in place of the interval in the fan settings of
+    /// each GPU. It doesn't depend on the stats polling, the temperature is read on every evaluation.
+    pub fan_control_interval_ms: Option<u64>,
+    /// Periodically check that the applied settings are still in effect, reapplying them if not
+    pub integrity_check_interval_secs: Option<u64>,
+    /// Also write the log to a file, for systems without journald
+    pub log_file: Option<LogFile>,
+    /// Give GPUs without any stored settings the balanced fan curve preset when they are detected
+    #[serde(default)]
+    pub first_run_fan_curve: bool,
+    #[serde(default)]
+    pub shutdown_fan_behavior: ShutdownFanBehavior,
+    /// Unit that clients should show temperatures in
+    #[serde(default)]
+    pub temperature_unit: TemperatureUnit,
+    /// How long to wait after a resume before applying the settings that are delayed on resume
+    #[serde(default = "default_resume_warm_up_ms")]
+    pub resume_warm_up_ms: u64,
+    /// Don't apply settings before the system has been running for this long,
+    /// even when the GPUs are already in sysfs
+    #[serde(default = "default_min_system_uptime_secs")]
+    pub min_system_uptime_secs: u64,
+    /// How long after boot to keep waiting for the GPUs with stored settings to show up
+    /// before the settings are first applied
+    #[serde(default = "default_gpu_ready_timeout_secs")]
+    pub gpu_ready_timeout_secs: u64,
+    /// How many times to retry a sysfs write which failed because the GPU was busy
+    #[serde(default = "default_sysfs_write_retries")]
+    pub sysfs_write_retries: u32,
+    /// Delay before the first retry of a sysfs write, doubled on each further retry
+    #[serde(default = "default_sysfs_write_backoff_ms")]
+    pub sysfs_write_backoff_ms: u64,
+    /// Shell command to run after every profile switch, with the name of the new profile as `$1`.
+    /// It runs with the privileges of the daemon.
+    pub on_profile_change: Option<String>,
+    /// Nice value to run the daemon with, applied on startup.
+    /// The fan curves are evaluated on the same thread as everything else in the daemon,
+    /// so a lower value makes fan speed changes less likely to get delayed on a busy system.
+    pub nice: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LogFile {
+    pub path: PathBuf,
+    /// The file gets rotated once it would grow past this size
+    #[serde(default = "default_log_file_max_size_mb")]
+    pub max_size_mb: u64,
+    /// Amount of rotated files to keep, older ones are deleted
+    #[serde(default = "default_log_file_retention")]
+    pub retention: usize,
+}
+
+/// What happens to the fans of the GPUs when the daemon exits
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownFanBehavior {
+    /// Give the fan control back to the firmware
+    #[default]
+    RestoreFirmwareControl,
+    /// Keep the fans at the speed last set by the daemon
+    LeaveAsIs,
+    /// Run the fans at full speed, so the GPU stays cool without anything controlling them
+    SetFullSpeed,
+}
+
+/// Profiles to switch to depending on whether the system is running on AC or battery power
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PowerSourceProfiles {
+    pub ac: Option<Rc<str>>,
+    pub battery: Option<Rc<str>>,
+}
+
+impl PowerSourceProfiles {
+    pub fn is_empty(&self) -> bool {
+        self.ac.is_none() && self.battery.is_none()
+    }
 }
 
 impl Default for Daemon {
@@ -82,20 +197,56 @@ impl Default for Daemon {
             admin_user,
             admin_group: find_existing_group(&DEFAULT_ADMIN_GROUPS),
             admin_groups: vec![],
+            control_group: None,
             disable_clocks_cleanup: false,
             tcp_listen_address: None,
             disable_nvapi: None,
+            dry_run: false,
+            history_length: default_history_length(),
+            stats_poll_interval_ms: default_stats_poll_interval_ms(),
+            fan_control_interval_ms: None,
+            integrity_check_interval_secs: None,
+            log_file: None,
+            first_run_fan_curve: false,
+            shutdown_fan_behavior: ShutdownFanBehavior::default(),
+            temperature_unit: TemperatureUnit::default(),
+            resume_warm_up_ms: default_resume_warm_up_ms(),
+            min_system_uptime_secs: default_min_system_uptime_secs(),
+            gpu_ready_timeout_secs: default_gpu_ready_timeout_secs(),
+            sysfs_write_retries: default_sysfs_write_retries(),
+            sysfs_write_backoff_ms: default_sysfs_write_backoff_ms(),
+            on_profile_change: None,
+            nice: None,
+        }
+    }
+}
+
+impl Daemon {
+    pub fn stats_poll_interval(&self) -> Duration {
+        Duration::from_millis(self.stats_poll_interval_ms.max(MIN_STATS_POLL_INTERVAL_MS))
+    }
+
+    pub fn fan_control_interval(&self) -> Option<Duration> {
+        self.fan_control_interval_ms
+            .map(|interval| Duration::from_millis(interval.max(MIN_FAN_CONTROL_INTERVAL_MS)))
+    }
+
+    pub fn sysfs_write_retry(&self) -> WriteRetry {
+        WriteRetry {
+            max_retries: self.sysfs_write_retries,
+            backoff: Duration::from_millis(self.sysfs_write_backoff_ms),
         }
     }
 }
 
 impl Config {
-    pub fn load() -> anyhow::Result<Option<Self>> {
-        let path = get_path(FILE_NAME);
+    pub fn load(dir: Option<&Path>) -> anyhow::Result<Option<Self>> {
+        let path = path_in(dir, FILE_NAME);
         if path.exists() {
             let raw_config = fs::read_to_string(path).context("Could not open config file")?;
-            let config =
+            let mut config: Config =
                 serde_yml::from_str(&raw_config).context("Could not deserialize config")?;
+            config.dir = dir.map(Path::to_path_buf);
             Ok(Some(config))
         } else {
             let parent = path.parent().unwrap();
@@ -114,12 +265,12 @@ impl Config {
         config_last_saved: &Cell<Instant>,
         filename: &str,
     ) -> anyhow::Result<()> {
-        let path = get_path(filename);
+        let path = path_in(self.dir(), filename);
         debug!("saving config to {path:?}");
 
         #[cfg(not(test))]
         {
-            let raw_config = serde_yml::to_string(self)?;
+            let raw_config = self.to_yaml()?;
             fs::write(path, raw_config).context("Could not write config")?;
         }
 
@@ -128,16 +279,39 @@ impl Config {
         Ok(())
     }
 
-    pub fn load_or_create() -> anyhow::Result<Self> {
-        if let Some(config) = Config::load()? {
+    /// Serializes the config in the format of the config file, without the temporary profile
+    pub fn to_yaml(&self) -> anyhow::Result<String> {
+        let raw_config = match &self.saved_profile {
+            Some(saved_profile) => {
+                let mut config = self.clone();
+                config.current_profile.clone_from(saved_profile);
+                serde_yml::to_string(&config)
+            }
+            None => serde_yml::to_string(self),
+        };
+        raw_config.context("Could not serialize config")
+    }
+
+    pub fn load_or_create(dir: Option<&Path>) -> anyhow::Result<Self> {
+        if let Some(config) = Config::load(dir)? {
             Ok(config)
         } else {
-            let config = Config::default();
+            let config = Config::default().with_dir(dir);
             config.save(&Cell::new(Instant::now()))?;
             Ok(config)
         }
     }
 
+    #[must_use]
+    pub fn with_dir(mut self, dir: Option<&Path>) -> Self {
+        self.dir = dir.map(Path::to_path_buf);
+        self
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
     #[allow(clippy::cast_precision_loss)]
     pub fn migrate_versions(&mut self, gpu_controllers: &BTreeMap<String, Box<dyn GpuController>>) {
         loop {
@@ -238,6 +412,105 @@ impl Config {
         }
     }
 
+    /// If there are settings stored for the GPU, either in the base config or in any of the profiles
+    pub fn has_stored_gpu_config(&self, id: &str) -> bool {
+        self.gpus.contains_key(id)
+            || self
+                .profiles
+                .values()
+                .any(|profile| profile.gpus.contains_key(id))
+    }
+
+    /// Ids of the GPUs which have settings or an alias stored, in any of the profiles
+    pub fn stored_gpu_ids(&self) -> BTreeSet<&str> {
+        self.gpus
+            .keys()
+            .chain(self.gpu_aliases.keys())
+            .chain(
+                self.profiles
+                    .values()
+                    .flat_map(|profile| profile.gpus.keys()),
+            )
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Settings of the GPUs in the base config and in all of the profiles
+    pub fn all_gpu_configs(&self) -> impl Iterator<Item = (&str, &GpuConfig)> {
+        self.gpus
+            .iter()
+            .chain(
+                self.profiles
+                    .values()
+                    .flat_map(|profile| profile.gpus.iter()),
+            )
+            .map(|(id, gpu_config)| (id.as_str(), gpu_config))
+    }
+
+    /// Moves stored settings whose GPU is no longer present to a detected GPU of the same model,
+    /// which happens when cards are moved to different PCI slots.
+    /// Settings are primarily matched by the full id, which includes the PCI slot,
+    /// the remaining ones are matched in order among the GPUs of each model.
+    /// GPUs which have settings of their own in any profile never get the settings of another one,
+    /// and aliases are only moved when there is a single GPU of the model they could belong to.
+    /// Returns if any settings were moved.
+    pub fn reattach_gpu_configs(&mut self, detected_ids: &[&str]) -> bool {
+        let moves = self.plan_reattach(detected_ids);
+
+        let mut reattached = false;
+        for (old_id, new_id, unambiguous) in &moves {
+            reattached |= move_gpu_entry(&mut self.gpus, old_id, new_id);
+            for profile in self.profiles.values_mut() {
+                reattached |= move_gpu_entry(&mut profile.gpus, old_id, new_id);
+            }
+            if *unambiguous {
+                reattached |= move_gpu_entry(&mut self.gpu_aliases, old_id, new_id);
+            }
+        }
+        reattached
+    }
+
+    /// Pairs the ids of missing GPUs with detected GPUs of the same model that don't have
+    /// anything stored, so the same GPU gets the same settings in every profile.
+    /// The pairs are marked as unambiguous when the model has only one candidate on either side.
+    fn plan_reattach(&self, detected_ids: &[&str]) -> Vec<(String, String, bool)> {
+        let stored_ids = self.stored_gpu_ids();
+        let missing_ids: Vec<&str> = stored_ids
+            .iter()
+            .filter(|id| !detected_ids.contains(id))
+            .copied()
+            .collect();
+        let unclaimed_ids: Vec<&str> = detected_ids
+            .iter()
+            .filter(|id| !stored_ids.contains(**id))
+            .copied()
+            .collect();
+
+        let mut moves = vec![];
+        let mut claimed = vec![false; unclaimed_ids.len()];
+        for old_id in &missing_ids {
+            let model_id = gpu_model_id(old_id);
+            let Some(position) = unclaimed_ids
+                .iter()
+                .zip(&claimed)
+                .position(|(id, claimed)| !claimed && gpu_model_id(id) == model_id)
+            else {
+                continue;
+            };
+            claimed[position] = true;
+
+            let same_model =
+                |ids: &[&str]| ids.iter().filter(|id| gpu_model_id(id) == model_id).count();
+            let unambiguous = same_model(&missing_ids) == 1 && same_model(&unclaimed_ids) == 1;
+            moves.push((
+                (*old_id).to_owned(),
+                unclaimed_ids[position].to_owned(),
+                unambiguous,
+            ));
+        }
+        moves
+    }
+
     /// Same as [`gpus`], but with a mutable reference
     pub fn gpus_mut(&mut self) -> anyhow::Result<&mut IndexMap<String, GpuConfig>> {
         match &self.current_profile {
@@ -268,14 +541,30 @@ impl Config {
         }
     }
 
+    /// Takes the GPU settings, profiles and aliases of an imported config,
+    /// the daemon settings of this system are kept
+    pub fn import_settings(&mut self, imported: Config) {
+        self.gpus = imported.gpus;
+        self.profiles = imported.profiles;
+        self.current_profile = imported.current_profile;
+        self.auto_switch_profiles = imported.auto_switch_profiles;
+        self.power_source_profiles = imported.power_source_profiles;
+        self.gpu_aliases = imported.gpu_aliases;
+        self.saved_profile = None;
+    }
+
     pub fn clear(&mut self) {
         self.gpus.clear();
         self.profiles.clear();
         self.current_profile = None;
+        self.saved_profile = None;
     }
 }
 
-pub fn start_watcher(config_last_saved: Rc<Cell<Instant>>) -> mpsc::UnboundedReceiver<Config> {
+pub fn start_watcher(
+    config_last_saved: Rc<Cell<Instant>>,
+    dir: Option<PathBuf>,
+) -> mpsc::UnboundedReceiver<Config> {
     let (config_tx, config_rx) = mpsc::unbounded_channel();
     let (event_tx, mut event_rx) = mpsc::channel(64);
 
@@ -284,7 +573,7 @@ pub fn start_watcher(config_last_saved: Rc<Cell<Instant>>) -> mpsc::UnboundedRec
             RecommendedWatcher::new(SenderEventHandler(event_tx), notify::Config::default())
                 .expect("Could not create config file watcher");
 
-        let config_path = get_path(FILE_NAME);
+        let config_path = path_in(dir.as_deref(), FILE_NAME);
         let watch_path = config_path
             .parent()
             .expect("Config path always has a parent");
@@ -335,7 +624,7 @@ pub fn start_watcher(config_last_saved: Rc<Cell<Instant>>) -> mpsc::UnboundedRec
                             }
                         }
 
-                        match Config::load() {
+                        match Config::load(dir.as_deref()) {
                             Ok(Some(new_config)) => config_tx.send(new_config).unwrap(),
                             Ok(None) => error!("config was removed!"),
                             Err(err) => {
@@ -362,7 +651,28 @@ impl notify::EventHandler for SenderEventHandler {
     }
 }
 
-fn get_path(filename: &str) -> PathBuf {
+fn move_gpu_entry<T>(gpus: &mut IndexMap<String, T>, old_id: &str, new_id: &str) -> bool {
+    match gpus.shift_remove(old_id) {
+        Some(entry) => {
+            info!("GPU {old_id} is now at {new_id}, moving its settings");
+            gpus.insert(new_id.to_owned(), entry);
+            true
+        }
+        None => false,
+    }
+}
+
+/// The part of a GPU id without the PCI slot, e.g. `1002:73BF-1DA2:440E`
+pub(crate) fn gpu_model_id(id: &str) -> &str {
+    id.rsplit_once('-').map_or(id, |(model_id, _)| model_id)
+}
+
+/// Returns the path of a file in the given config directory, or in the default one
+pub(crate) fn path_in(dir: Option<&Path>, filename: &str) -> PathBuf {
+    dir.map_or_else(|| get_path(filename), |dir| dir.join(filename))
+}
+
+pub(crate) fn get_path(filename: &str) -> PathBuf {
     if let Ok(path) = env::var("LACT_DAEMON_CONFIG_DIR") {
         PathBuf::from(&path).join(filename)
     } else {
@@ -380,6 +690,42 @@ fn get_path(filename: &str) -> PathBuf {
     }
 }
 
+fn default_history_length() -> usize {
+    300
+}
+
+fn default_stats_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_resume_warm_up_ms() -> u64 {
+    10_000
+}
+
+fn default_min_system_uptime_secs() -> u64 {
+    5
+}
+
+fn default_gpu_ready_timeout_secs() -> u64 {
+    60
+}
+
+fn default_sysfs_write_retries() -> u32 {
+    3
+}
+
+fn default_sysfs_write_backoff_ms() -> u64 {
+    50
+}
+
+fn default_log_file_max_size_mb() -> u64 {
+    10
+}
+
+fn default_log_file_retention() -> usize {
+    5
+}
+
 fn default_apply_settings_timer() -> u64 {
     5
 }
@@ -393,14 +739,19 @@ fn find_existing_group(groups: &[impl AsRef<str>]) -> Option<String> {
 
 #[cfg(test)]
 mod tests {
-    use crate::config::{Config, Daemon};
+    use crate::config::{
+        Config, Daemon, PowerSourceProfiles, MIN_FAN_CONTROL_INTERVAL_MS,
+        MIN_STATS_POLL_INTERVAL_MS,
+    };
     use indexmap::IndexMap;
     use insta::assert_yaml_snapshot;
     use lact_schema::{
-        config::{ClocksConfiguration, FanControlSettings, FanCurve, GpuConfig},
+        config::{
+            ClocksConfiguration, FanControlSettings, FanCurve, FanCurveUnit, GpuConfig, Profile,
+        },
         FanControlMode, PmfwOptions,
     };
-    use std::collections::BTreeMap;
+    use std::{collections::BTreeMap, time::Duration};
 
     #[test]
     fn serde_de_full() {
@@ -412,6 +763,8 @@ mod tests {
                     fan_control_enabled: true,
                     fan_control_settings: Some(FanControlSettings {
                         curve: FanCurve::default(),
+                        curve_unit: FanCurveUnit::default(),
+                        curve_offset: None,
                         temperature_key: "edge".to_owned(),
                         interval_ms: 500,
                         mode: FanControlMode::Curve,
@@ -419,6 +772,10 @@ mod tests {
                         spindown_delay_ms: Some(5000),
                         change_threshold: Some(3),
                         auto_threshold: Some(40),
+                        min_pwm: None,
+                        max_pwm: None,
+                        spindown_step: Some(0.05),
+                        external_temperatures: vec![],
                     }),
                     ..Default::default()
                 },
@@ -431,6 +788,25 @@ mod tests {
         assert_eq!(config, deserialized_config);
     }
 
+    #[test]
+    fn temporary_profile_not_saved() {
+        let config = Config {
+            profiles: [
+                ("quiet".into(), Profile::default()),
+                ("gaming".into(), Profile::default()),
+            ]
+            .into_iter()
+            .collect(),
+            current_profile: Some("gaming".into()),
+            saved_profile: Some(Some("quiet".into())),
+            ..Default::default()
+        };
+
+        let saved_config: Config = serde_yml::from_str(&config.to_yaml().unwrap()).unwrap();
+        assert_eq!(Some("quiet"), saved_config.current_profile.as_deref());
+        assert_eq!(None, saved_config.saved_profile);
+    }
+
     #[test]
     fn parse_doc() {
         let doc = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../docs/CONFIG.md"));
@@ -460,6 +836,12 @@ mod tests {
             power_profile_mode_index: None,
             custom_power_profile_mode_hueristics: vec![],
             power_states: IndexMap::new(),
+            memory_clock_level: None,
+            pcie_level: None,
+            idle_lock: false,
+            thermal_protection: None,
+            leds: IndexMap::new(),
+            delayed_on_resume: vec![],
         };
 
         assert!(!gpu.is_core_clocks_used());
@@ -500,6 +882,11 @@ mod tests {
             profiles: IndexMap::new(),
             current_profile: None,
             auto_switch_profiles: false,
+            power_source_profiles: PowerSourceProfiles::default(),
+            ignored_gpus: vec![],
+            gpu_aliases: IndexMap::new(),
+            saved_profile: None,
+            dir: None,
         };
 
         config.migrate_versions(&BTreeMap::new());
@@ -532,4 +919,141 @@ mod tests {
             Some(920),
         );
     }
+
+    #[test]
+    fn stats_poll_interval_floor() {
+        let mut daemon = Daemon {
+            stats_poll_interval_ms: 250,
+            ..Default::default()
+        };
+        assert_eq!(Duration::from_millis(250), daemon.stats_poll_interval());
+
+        daemon.stats_poll_interval_ms = 0;
+        assert_eq!(
+            Duration::from_millis(MIN_STATS_POLL_INTERVAL_MS),
+            daemon.stats_poll_interval()
+        );
+    }
+
+    #[test]
+    fn fan_control_interval_floor() {
+        let mut daemon = Daemon::default();
+        assert_eq!(None, daemon.fan_control_interval());
+
+        daemon.fan_control_interval_ms = Some(200);
+        assert_eq!(
+            Some(Duration::from_millis(200)),
+            daemon.fan_control_interval()
+        );
+
+        daemon.fan_control_interval_ms = Some(0);
+        assert_eq!(
+            Some(Duration::from_millis(MIN_FAN_CONTROL_INTERVAL_MS)),
+            daemon.fan_control_interval()
+        );
+    }
+
+    #[test]
+    fn reattach_moved_gpus() {
+        let gpu_config = |cap| GpuConfig {
+            power_cap: Some(cap),
+            ..Default::default()
+        };
+        let mut config = Config::default();
+        let gpus = config.gpus_mut().unwrap();
+        for (id, cap) in [
+            ("1002:73BF-1DA2:440E-0000:03:00.0", 200.0),
+            ("1002:73BF-1DA2:440E-0000:0c:00.0", 250.0),
+            ("1002:73BF-1DA2:440E-0000:0d:00.0", 300.0),
+            ("10DE:2704-1462:5110-0000:09:00.0", 320.0),
+        ] {
+            gpus.insert(id.to_owned(), gpu_config(cap));
+        }
+
+        // The GPU in the 0c slot stays in place, the other two were moved
+        let detected_ids = [
+            "1002:73BF-1DA2:440E-0000:04:00.0",
+            "1002:73BF-1DA2:440E-0000:05:00.0",
+            "1002:73BF-1DA2:440E-0000:0c:00.0",
+            "1002:67DF-1DA2:E387-0000:09:00.0",
+        ];
+        for (id, alias) in [
+            ("1002:73BF-1DA2:440E-0000:03:00.0", "Render Card"),
+            ("1002:73BF-1DA2:440E-0000:0d:00.0", "Compute Card"),
+            ("1002:67DF-1DA2:E387-0000:08:00.0", "Display Card"),
+        ] {
+            config.gpu_aliases.insert(id.to_owned(), alias.to_owned());
+        }
+        assert!(config.reattach_gpu_configs(&detected_ids));
+
+        // Aliases are only moved when it's clear which GPU they belong to
+        let aliases: Vec<(&str, &str)> = config
+            .gpu_aliases
+            .iter()
+            .map(|(id, alias)| (id.as_str(), alias.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("1002:73BF-1DA2:440E-0000:03:00.0", "Render Card"),
+                ("1002:73BF-1DA2:440E-0000:0d:00.0", "Compute Card"),
+                ("1002:67DF-1DA2:E387-0000:09:00.0", "Display Card"),
+            ],
+            aliases
+        );
+
+        let gpus = config.gpus().unwrap();
+        let power_caps: Vec<(&str, Option<f64>)> = gpus
+            .iter()
+            .map(|(id, gpu_config)| (id.as_str(), gpu_config.power_cap))
+            .collect();
+        assert_eq!(
+            vec![
+                ("1002:73BF-1DA2:440E-0000:0c:00.0", Some(250.0)),
+                // The GPU of a different model is not present, so its settings are kept as is
+                ("10DE:2704-1462:5110-0000:09:00.0", Some(320.0)),
+                ("1002:73BF-1DA2:440E-0000:04:00.0", Some(200.0)),
+                ("1002:73BF-1DA2:440E-0000:05:00.0", Some(300.0)),
+            ],
+            power_caps
+        );
+
+        assert!(!config.reattach_gpu_configs(&detected_ids));
+    }
+
+    #[test]
+    fn reattach_skips_configured_gpus() {
+        let gpu_config = |cap| GpuConfig {
+            power_cap: Some(cap),
+            ..Default::default()
+        };
+        let mut config = Config::default();
+        config.gpus_mut().unwrap().insert(
+            "1002:73BF-1DA2:440E-0000:03:00.0".to_owned(),
+            gpu_config(200.0),
+        );
+        let mut profile = Profile::default();
+        profile.gpus.insert(
+            "1002:73BF-1DA2:440E-0000:04:00.0".to_owned(),
+            gpu_config(250.0),
+        );
+        config.profiles.insert("gaming".into(), profile);
+
+        let detected_ids = [
+            "1002:73BF-1DA2:440E-0000:04:00.0",
+            "1002:73BF-1DA2:440E-0000:05:00.0",
+        ];
+        assert!(config.reattach_gpu_configs(&detected_ids));
+
+        // The GPU with settings in a profile is not treated as the missing one
+        let gpus = config.gpus().unwrap();
+        assert_eq!(
+            vec!["1002:73BF-1DA2:440E-0000:05:00.0"],
+            gpus.keys().collect::<Vec<_>>()
+        );
+        let profile_gpus = &config.profile("gaming").unwrap().gpus;
+        assert_eq!(
+            Some(250.0),
+            profile_gpus["1002:73BF-1DA2:440E-0000:04:00.0"].power_cap
+        );
+    }
 }
@@ -0,0 +1,118 @@
+use anyhow::Context;
+use lact_schema::{config::GpuConfig, CrashInfo};
+use std::{
+    backtrace::Backtrace,
+    collections::BTreeMap,
+    fs, io,
+    panic::{self, PanicHookInfo},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+use tracing::error;
+
+const CRASH_FILE_NAME: &str = "last_crash.json";
+
+/// Settings which were most recently applied to each GPU, included in the crash report.
+/// Shared with the panic hook, which can run on any thread.
+pub type AppliedConfigs = Arc<Mutex<BTreeMap<String, GpuConfig>>>;
+
+/// Installs a panic hook which writes a crash report before running the default hook
+pub fn install_hook(config_dir: Option<&Path>, applied_configs: AppliedConfigs) {
+    let path = crash_file_path(config_dir);
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        let report = report_from_panic(info, &applied_configs);
+        match serde_json::to_vec_pretty(&report) {
+            Ok(data) => {
+                if let Err(err) = fs::write(&path, data) {
+                    error!("could not write crash report to {}: {err}", path.display());
+                }
+            }
+            Err(err) => error!("could not serialize crash report: {err}"),
+        }
+        default_hook(info);
+    }));
+}
+
+pub fn record_applied_config(applied_configs: &AppliedConfigs, id: &str, config: &GpuConfig) {
+    applied_configs
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(id.to_owned(), config.clone());
+}
+
+/// Reads the report of the most recent crash, if there was one
+pub fn last_crash(config_dir: Option<&Path>) -> anyhow::Result<Option<CrashInfo>> {
+    let path = crash_file_path(config_dir);
+    match fs::read(&path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map(Some)
+            .context("Could not parse crash report"),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("Could not read {}", path.display())),
+    }
+}
+
+fn report_from_panic(info: &PanicHookInfo<'_>, applied_configs: &AppliedConfigs) -> CrashInfo {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| (*message).to_owned())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+
+    CrashInfo {
+        timestamp: chrono::Local::now().timestamp_millis(),
+        message,
+        location: info.location().map(ToString::to_string),
+        thread: std::thread::current().name().map(str::to_owned),
+        backtrace: Backtrace::force_capture().to_string(),
+        gpu_configs: applied_configs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone(),
+    }
+}
+
+fn crash_file_path(config_dir: Option<&Path>) -> PathBuf {
+    crate::config::path_in(config_dir, CRASH_FILE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{install_hook, last_crash, record_applied_config, AppliedConfigs};
+    use lact_schema::config::GpuConfig;
+    use std::{panic, thread};
+
+    #[test]
+    fn panic_writes_report() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(None, last_crash(Some(dir.path())).unwrap());
+
+        let gpu_config = GpuConfig {
+            power_cap: Some(150.0),
+            ..Default::default()
+        };
+        let applied_configs = AppliedConfigs::default();
+        record_applied_config(&applied_configs, "crash-test-gpu", &gpu_config);
+
+        install_hook(Some(dir.path()), applied_configs);
+        let result = thread::Builder::new()
+            .name("crash-test".to_owned())
+            .spawn(|| panic!("test crash {}", 1))
+            .unwrap()
+            .join();
+        // Restore the default hook
+        let _ = panic::take_hook();
+        assert!(result.is_err());
+
+        let report = last_crash(Some(dir.path())).unwrap().unwrap();
+        assert_eq!("test crash 1", report.message);
+        assert!(report.location.unwrap().contains("crash.rs"));
+        assert_eq!(Some("crash-test"), report.thread.as_deref());
+        assert!(!report.backtrace.is_empty());
+        assert!(report.timestamp > 0);
+        assert_eq!(Some(&gpu_config), report.gpu_configs.get("crash-test-gpu"));
+    }
+}
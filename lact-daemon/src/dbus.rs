@@ -0,0 +1,444 @@
+use crate::{
+    server::{handle_payload, handler::Handler, Connection},
+    socket,
+};
+use anyhow::Context;
+use lact_schema::{request::ConfirmCommand, ClientCredentials, FanOptions, Request, Response};
+use nix::unistd::{Gid, Group, Uid, User};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info};
+use zbus::{fdo, interface, message::Header, names::BusName};
+
+pub const SERVICE_NAME: &str = "io.github.ilya_zlobintsev.LACT.Daemon";
+pub const OBJECT_PATH: &str = "/io/github/ilya_zlobintsev/LACT/Daemon";
+const REQUEST_CHANNEL_SIZE: usize = 16;
+
+/// Request forwarded from the D-Bus object to the handler, which lives on the local task set
+pub struct ForwardedRequest {
+    payload: String,
+    caller: ClientCredentials,
+    response_tx: oneshot::Sender<anyhow::Result<Vec<u8>>>,
+}
+
+pub async fn run(handler: Handler) {
+    let access = {
+        let config = handler.config.read().await;
+        match AccessPolicy::from_config(&config.daemon) {
+            Ok(access) => access,
+            Err(err) => {
+                error!("could not set up D-Bus access policy: {err:#}");
+                return;
+            }
+        }
+    };
+
+    let (request_tx, request_rx) = mpsc::channel(REQUEST_CHANNEL_SIZE);
+    let interface = DaemonInterface { request_tx, access };
+
+    let connection = async {
+        zbus::conn::Builder::system()?
+            .name(SERVICE_NAME)?
+            .serve_at(OBJECT_PATH, interface)?
+            .build()
+            .await
+    }
+    .await;
+
+    match connection {
+        Ok(_connection) => {
+            info!("D-Bus interface available as {SERVICE_NAME}");
+            serve_requests(handler, request_rx).await;
+        }
+        Err(err) => {
+            error!("could not register D-Bus interface: {err}");
+        }
+    }
+}
+
+/// Runs the requests coming from the D-Bus object through the same path as socket requests,
+/// including the control group check for the caller
+pub async fn serve_requests(handler: Handler, mut request_rx: mpsc::Receiver<ForwardedRequest>) {
+    while let Some(request) = request_rx.recv().await {
+        let mut connection = Connection::dbus(request.caller);
+        let response = handle_payload(&request.payload, &handler, &mut connection).await;
+        let _ = request.response_tx.send(response);
+    }
+}
+
+/// Only allows users who have access to the socket: its owner, members of its group and root
+#[derive(Debug, Clone)]
+pub struct AccessPolicy {
+    owner: Uid,
+    group: Gid,
+}
+
+impl AccessPolicy {
+    pub fn from_config(daemon_config: &crate::config::Daemon) -> anyhow::Result<Self> {
+        let (owner, group) = socket::resolve_owner(daemon_config)?;
+        Ok(Self {
+            owner: owner.unwrap_or_else(Uid::current),
+            group,
+        })
+    }
+
+    fn allows(&self, user: &User) -> bool {
+        user.uid.is_root()
+            || user.uid == self.owner
+            || user.gid == self.group
+            || Group::from_gid(self.group)
+                .ok()
+                .flatten()
+                .is_some_and(|group| group.mem.contains(&user.name))
+    }
+}
+
+pub struct DaemonInterface {
+    request_tx: mpsc::Sender<ForwardedRequest>,
+    access: AccessPolicy,
+}
+
+impl DaemonInterface {
+    pub fn new(request_tx: mpsc::Sender<ForwardedRequest>, access: AccessPolicy) -> Self {
+        Self { request_tx, access }
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        request: &Request<'_>,
+        caller: ClientCredentials,
+    ) -> fdo::Result<T> {
+        let payload = serde_json::to_string(request)
+            .map_err(|err| fdo::Error::Failed(format!("Could not serialize request: {err}")))?;
+
+        let (response_tx, response_rx) = oneshot::channel();
+        self.request_tx
+            .send(ForwardedRequest {
+                payload,
+                caller,
+                response_tx,
+            })
+            .await
+            .map_err(|_| fdo::Error::Failed("The daemon is shutting down".to_owned()))?;
+
+        let raw_response = response_rx
+            .await
+            .context("The request was dropped")
+            .and_then(|response| response)
+            .map_err(|err| fdo::Error::Failed(format!("{err:#}")))?;
+
+        let response: Response<T> = serde_json::from_slice(&raw_response)
+            .map_err(|err| fdo::Error::Failed(format!("Could not deserialize response: {err}")))?;
+        match response {
+            Response::Ok(data) => Ok(data),
+            Response::Error(err) => Err(fdo::Error::Failed(err.to_string())),
+        }
+    }
+
+    /// Identifies the caller of a method, which has to have access to the socket
+    async fn caller(
+        &self,
+        header: &Header<'_>,
+        connection: &zbus::Connection,
+    ) -> fdo::Result<ClientCredentials> {
+        let uid = match header.sender() {
+            Some(sender) => {
+                fdo::DBusProxy::new(connection)
+                    .await?
+                    .get_connection_unix_user(BusName::from(sender.to_owned()))
+                    .await?
+            }
+            // Peer-to-peer connections have no bus to ask, so the socket credentials are used
+            None => connection
+                .peer_credentials()
+                .await
+                .ok()
+                .and_then(|credentials| credentials.unix_user_id())
+                .ok_or_else(|| {
+                    fdo::Error::AccessDenied("Could not identify the caller".to_owned())
+                })?,
+        };
+
+        self.authorize(Uid::from_raw(uid))
+    }
+
+    fn authorize(&self, uid: Uid) -> fdo::Result<ClientCredentials> {
+        let user = User::from_uid(uid)
+            .ok()
+            .flatten()
+            .ok_or_else(|| fdo::Error::AccessDenied(format!("Could not find user {uid}")))?;
+        if !self.access.allows(&user) {
+            return Err(fdo::Error::AccessDenied(format!(
+                "User {uid} does not have access to the daemon"
+            )));
+        }
+
+        Ok(ClientCredentials {
+            pid: None,
+            uid: uid.as_raw(),
+            gid: user.gid.as_raw(),
+        })
+    }
+}
+
+/// Read methods return the same JSON data as the socket API
+#[interface(name = "io.github.ilya_zlobintsev.LACT.Daemon1")]
+impl DaemonInterface {
+    async fn list_gpus(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> fdo::Result<String> {
+        let caller = self.caller(&header, connection).await?;
+        let devices: Value = self.request(&Request::ListDevices, caller).await?;
+        Ok(devices.to_string())
+    }
+
+    async fn get_stats(
+        &self,
+        id: &str,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> fdo::Result<String> {
+        let caller = self.caller(&header, connection).await?;
+        let stats: Value = self.request(&Request::DeviceStats { id }, caller).await?;
+        Ok(stats.to_string())
+    }
+
+    /// An empty name switches to the default profile
+    async fn set_profile(
+        &self,
+        name: &str,
+        auto_switch: bool,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> fdo::Result<()> {
+        let caller = self.caller(&header, connection).await?;
+
+        let name = (!name.is_empty()).then(|| name.to_owned());
+        self.request(&Request::SetProfile { name, auto_switch }, caller)
+            .await
+    }
+
+    /// An empty mode keeps the current one. The change has to be confirmed with
+    /// `ConfirmPendingConfig` before the returned amount of seconds passes.
+    async fn set_fan_control(
+        &self,
+        id: &str,
+        enabled: bool,
+        mode: &str,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> fdo::Result<u64> {
+        let caller = self.caller(&header, connection).await?;
+
+        let mode = if mode.is_empty() {
+            None
+        } else {
+            let parsed_mode = serde_json::from_value(Value::String(mode.to_owned()));
+            let parsed_mode = parsed_mode.map_err(|_| {
+                fdo::Error::InvalidArgs(format!("Invalid fan control mode '{mode}'"))
+            })?;
+            Some(parsed_mode)
+        };
+
+        let opts = FanOptions {
+            id,
+            enabled,
+            mode,
+            ..Default::default()
+        };
+        self.request(&Request::SetFanControl(opts), caller).await
+    }
+
+    async fn confirm_pending_config(
+        &self,
+        confirm: bool,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> fdo::Result<()> {
+        let caller = self.caller(&header, connection).await?;
+
+        let command = if confirm {
+            ConfirmCommand::Confirm
+        } else {
+            ConfirmCommand::Revert
+        };
+        self.request(&Request::ConfirmPendingConfig(command), caller)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{serve_requests, AccessPolicy, DaemonInterface, OBJECT_PATH};
+    use crate::{
+        config::Config,
+        server::handler::{read_pci_db, Handler},
+        tests::copy_dir,
+    };
+    use lact_schema::{DeviceListEntry, DeviceStats, Request};
+    use nix::unistd::{Gid, Uid, User};
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+    use tokio::{net::UnixStream, sync::mpsc, task::LocalSet};
+    use zbus::{fdo, proxy, Guid};
+
+    #[proxy(
+        interface = "io.github.ilya_zlobintsev.LACT.Daemon1",
+        default_path = "/io/github/ilya_zlobintsev/LACT/Daemon"
+    )]
+    trait Daemon {
+        fn list_gpus(&self) -> zbus::Result<String>;
+
+        fn get_stats(&self, id: &str) -> zbus::Result<String>;
+
+        fn set_profile(&self, name: &str, auto_switch: bool) -> zbus::Result<()>;
+
+        fn set_fan_control(&self, id: &str, enabled: bool, mode: &str) -> zbus::Result<u64>;
+
+        fn confirm_pending_config(&self, confirm: bool) -> zbus::Result<()>;
+    }
+
+    async fn connect(
+        sysfs_path: &Path,
+        access: AccessPolicy,
+    ) -> (zbus::Connection, zbus::Connection) {
+        let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+        copy_dir(&data_dir, sysfs_path);
+        let handler = Handler::with_base_path(sysfs_path, Config::default(), &read_pci_db())
+            .await
+            .unwrap();
+
+        let (request_tx, request_rx) = mpsc::channel(1);
+        tokio::task::spawn_local(serve_requests(handler, request_rx));
+
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let guid = Guid::generate();
+
+        let server = async {
+            zbus::conn::Builder::unix_stream(server_stream)
+                .server(guid)?
+                .p2p()
+                .serve_at(OBJECT_PATH, DaemonInterface::new(request_tx, access))?
+                .build()
+                .await
+        };
+        let client = zbus::conn::Builder::unix_stream(client_stream)
+            .p2p()
+            .build();
+
+        let (server, client) = tokio::join!(server, client);
+        (server.unwrap(), client.unwrap())
+    }
+
+    fn current_user_policy() -> AccessPolicy {
+        AccessPolicy {
+            owner: Uid::current(),
+            group: Gid::current(),
+        }
+    }
+
+    #[tokio::test]
+    async fn read_methods() {
+        LocalSet::new()
+            .run_until(async {
+                let sysfs_dir = tempdir().unwrap();
+                let (_server, client) = connect(sysfs_dir.path(), current_user_policy()).await;
+                let proxy = DaemonProxy::builder(&client).build().await.unwrap();
+
+                let devices: Vec<DeviceListEntry> =
+                    serde_json::from_str(&proxy.list_gpus().await.unwrap()).unwrap();
+                assert_eq!(1, devices.len());
+                let id = &devices[0].id;
+
+                let stats: DeviceStats =
+                    serde_json::from_str(&proxy.get_stats(id).await.unwrap()).unwrap();
+                assert_eq!(Some(155.0), stats.power.cap.current);
+
+                let err = proxy.get_stats("missing").await.unwrap_err();
+                assert!(err.to_string().contains("Controller 'missing' not found"));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn write_methods() {
+        LocalSet::new()
+            .run_until(async {
+                let sysfs_dir = tempdir().unwrap();
+                let (_server, client) = connect(sysfs_dir.path(), current_user_policy()).await;
+                let proxy = DaemonProxy::builder(&client).build().await.unwrap();
+
+                proxy.set_profile("", false).await.unwrap();
+                let err = proxy.set_profile("missing", false).await.unwrap_err();
+                assert!(err.to_string().contains("Profile missing not found"));
+
+                let devices: Vec<DeviceListEntry> =
+                    serde_json::from_str(&proxy.list_gpus().await.unwrap()).unwrap();
+                let id = &devices[0].id;
+
+                let err = proxy.set_fan_control(id, true, "fast").await.unwrap_err();
+                assert!(matches!(fdo::Error::from(err), fdo::Error::InvalidArgs(_)));
+
+                let timer = proxy.set_fan_control(id, true, "static").await.unwrap();
+                assert_eq!(5, timer);
+                proxy.confirm_pending_config(false).await.unwrap();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn write_methods_denied() {
+        LocalSet::new()
+            .run_until(async {
+                let nobody = User::from_name("nobody").unwrap().unwrap();
+                let policy = AccessPolicy {
+                    owner: Uid::from_raw(0),
+                    group: Gid::from_raw(0),
+                };
+                let (request_tx, _request_rx) = mpsc::channel(1);
+                let mut interface = DaemonInterface::new(request_tx, policy);
+
+                // Users without access to the socket can't read anything either
+                let err = interface.authorize(nobody.uid).unwrap_err();
+                assert!(matches!(err, fdo::Error::AccessDenied(_)));
+
+                // Members of the socket group are still subject to the control group
+                interface.access.group = nobody.gid;
+                let caller = interface.authorize(nobody.uid).unwrap();
+                assert_eq!(nobody.gid.as_raw(), caller.gid);
+
+                let data_dir =
+                    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+                let sysfs_dir = tempdir().unwrap();
+                copy_dir(&data_dir, sysfs_dir.path());
+                let mut config = Config::default();
+                config.daemon.control_group = Some("root".to_owned());
+                let handler = Handler::with_base_path(sysfs_dir.path(), config, &read_pci_db())
+                    .await
+                    .unwrap();
+
+                let (request_tx, request_rx) = mpsc::channel(1);
+                tokio::task::spawn_local(serve_requests(handler, request_rx));
+                let interface = DaemonInterface::new(request_tx, interface.access);
+
+                let devices: Value = interface
+                    .request(&Request::ListDevices, caller)
+                    .await
+                    .unwrap();
+                assert_eq!(1, devices.as_array().unwrap().len());
+
+                let request = Request::SetProfile {
+                    name: None,
+                    auto_switch: false,
+                };
+                let err = interface.request::<()>(&request, caller).await.unwrap_err();
+                assert!(err.to_string().contains("not in the root group"), "{err}");
+            })
+            .await;
+    }
+}
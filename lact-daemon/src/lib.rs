@@ -3,6 +3,13 @@
 
 mod bindings;
 mod config;
+mod crash;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod logging;
+mod power_supply;
+mod readiness;
+mod safe_mode;
 mod server;
 mod socket;
 mod suspend;
@@ -12,29 +19,39 @@ mod tests;
 
 use anyhow::Context;
 use config::Config;
+use crash::AppliedConfigs;
 use futures::future::select_all;
-use server::{handle_stream, handler::Handler, Server};
+use server::{
+    handle_stream,
+    handler::{drm_base_path, Handler, HandlerOptions},
+    Server,
+};
 use std::sync::Arc;
-use std::{os::unix::net::UnixStream as StdUnixStream, time::Duration};
+use std::{
+    os::unix::net::UnixStream as StdUnixStream,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
 use tokio::net::UnixStream;
-use tokio::sync::Notify;
+use tokio::sync::{mpsc, Notify};
 use tokio::time::timeout;
 use tokio::{
     runtime,
     signal::unix::{signal, SignalKind},
     task::LocalSet,
 };
-use tracing::level_filters::LevelFilter;
 use tracing::{debug, debug_span, error, info, warn, Instrument};
-use tracing_subscriber::EnvFilter;
 
 /// RDNA3, minimum family that supports the new pmfw interface
 pub const AMDGPU_FAMILY_GC_11_0_0: u32 = 145;
 
 pub use system::BASE_MODULE_CONF_PATH;
 
-const MIN_SYSTEM_UPTIME_SECS: f32 = 15.0;
 const DRM_EVENT_TIMEOUT_PERIOD_MS: u64 = 100;
+const THERMAL_PROTECTION_INTERVAL_MS: u64 = 1000;
+/// How often to check if the integrity check got enabled while it is disabled
+const INTEGRITY_CHECK_DISABLED_POLL_SECS: u64 = 10;
 const SHUTDOWN_SIGNALS: [SignalKind; 4] = [
     SignalKind::terminate(),
     SignalKind::interrupt(),
@@ -42,34 +59,97 @@ const SHUTDOWN_SIGNALS: [SignalKind; 4] = [
     SignalKind::hangup(),
 ];
 
+/// Paths used by the daemon, which default to the standard locations when not set.
+/// Using different paths allows running multiple instances side by side.
+#[derive(Debug, Clone, Default)]
+pub struct DaemonOptions {
+    /// Directory containing the config file and other daemon state
+    pub config_dir: Option<PathBuf>,
+    pub socket_path: Option<PathBuf>,
+}
+
 /// Run the daemon, binding to the default socket.
 ///
 /// # Errors
 /// Returns an error when the daemon cannot initialize.
 pub fn run() -> anyhow::Result<()> {
+    run_with_options(DaemonOptions::default())
+}
+
+/// Run the daemon with the given paths.
+///
+/// # Errors
+/// Returns an error when the daemon cannot initialize.
+pub fn run_with_options(options: DaemonOptions) -> anyhow::Result<()> {
     let rt = runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .expect("Could not initialize tokio runtime");
     rt.block_on(async {
-        let config = Config::load_or_create()?;
+        let DaemonOptions {
+            config_dir,
+            socket_path,
+        } = options;
+        let socket_path = socket_path.unwrap_or_else(socket::get_socket_path);
+        let config = Config::load_or_create(config_dir.as_deref())?;
 
-        let env_filter = EnvFilter::builder()
-            .with_default_directive(LevelFilter::INFO.into())
-            .parse(&config.daemon.log_level)
-            .context("Invalid log level")?;
-        tracing_subscriber::fmt().with_env_filter(env_filter).init();
+        let logging = logging::init(&config.daemon.log_level, config.daemon.log_file.as_ref())?;
+        let applied_configs = AppliedConfigs::default();
+        crash::install_hook(config_dir.as_deref(), applied_configs.clone());
+        if let Some(nice) = config.daemon.nice {
+            match system::set_nice(nice) {
+                Ok(()) => info!("running with nice value {nice}"),
+                Err(err) => warn!("could not set nice value {nice}: {err}"),
+            }
+        }
 
-        ensure_sufficient_uptime().await;
+        ensure_sufficient_uptime(config.daemon.min_system_uptime_secs).await;
+        wait_for_gpus(&config, config_dir.as_deref()).await;
+        let safe_mode = safe_mode::check_startup(config_dir.as_deref());
+        if safe_mode {
+            if let Ok(Some(crash)) = crash::last_crash(config_dir.as_deref()) {
+                warn!("last crash: {}", crash.message);
+            }
+        }
 
         LocalSet::new()
             .run_until(async move {
-                let server = Server::new(config).await?;
+                let handler_options = HandlerOptions {
+                    safe_mode,
+                    applied_configs,
+                    logging: Some(Rc::new(logging)),
+                };
+                let server = Server::new(config, &socket_path, handler_options).await?;
                 let handler = server.handler.clone();
 
-                tokio::task::spawn_local(listen_config_changes(handler.clone()));
-                tokio::task::spawn_local(listen_exit_signals(handler.clone()));
+                let seen_ids = handler
+                    .list_devices()
+                    .await
+                    .into_iter()
+                    .map(|entry| entry.id)
+                    .collect::<Vec<_>>();
+                let seen_gpus_path = readiness::seen_gpus_path(config_dir.as_deref());
+                if let Err(err) = readiness::record_seen_gpus(&seen_gpus_path, &seen_ids) {
+                    warn!("could not record the present GPUs: {err:#}");
+                }
+
+                tokio::task::spawn_local(listen_config_changes(
+                    handler.clone(),
+                    config_dir.clone(),
+                ));
+                tokio::task::spawn_local(listen_exit_signals(
+                    handler.clone(),
+                    socket_path,
+                    config_dir.clone(),
+                ));
                 tokio::task::spawn_local(listen_device_events(handler.clone()));
+                tokio::task::spawn_local(record_stats_history(handler.clone()));
+                tokio::task::spawn_local(monitor_thermal_protection(handler.clone()));
+                tokio::task::spawn_local(monitor_config_integrity(handler.clone()));
+                tokio::task::spawn_local(power_supply::listen_events(handler.clone()));
+                tokio::task::spawn_local(safe_mode::reset_after_clean_run(config_dir));
+                #[cfg(feature = "dbus")]
+                tokio::task::spawn_local(dbus::run(handler.clone()));
                 tokio::task::spawn_local(suspend::listen_events(handler));
 
                 server.run().await;
@@ -93,7 +173,7 @@ pub fn run_embedded(stream: StdUnixStream) -> anyhow::Result<()> {
         LocalSet::new()
             .run_until(async move {
                 let config = Config::default();
-                let handler = Handler::new(config).await?;
+                let handler = Handler::new(config, HandlerOptions::default()).await?;
                 let stream = UnixStream::try_from(stream)?;
 
                 handle_stream(stream, handler).await
@@ -102,7 +182,7 @@ pub fn run_embedded(stream: StdUnixStream) -> anyhow::Result<()> {
     })
 }
 
-async fn listen_exit_signals(handler: Handler) {
+async fn listen_exit_signals(handler: Handler, socket_path: PathBuf, config_dir: Option<PathBuf>) {
     let mut signals = SHUTDOWN_SIGNALS
         .map(|signal_kind| signal(signal_kind).expect("Could not listen to shutdown signal"));
     let signal_futures = signals.iter_mut().map(|signal| Box::pin(signal.recv()));
@@ -111,19 +191,21 @@ async fn listen_exit_signals(handler: Handler) {
     info!("cleaning up and shutting down...");
     async {
         handler.cleanup().await;
-        socket::cleanup();
+        socket::cleanup(&socket_path);
+        // A clean shutdown shortly after starting should not count towards entering safe mode
+        safe_mode::reset_on_shutdown(config_dir.as_deref());
     }
     .instrument(debug_span!("shutdown_cleanup"))
     .await;
+    handler.flush_logs();
     std::process::exit(0);
 }
 
-async fn listen_config_changes(handler: Handler) {
-    let mut rx = config::start_watcher(handler.config_last_saved.clone());
+async fn listen_config_changes(handler: Handler, config_dir: Option<PathBuf>) {
+    let mut rx = config::start_watcher(handler.config_last_saved.clone(), config_dir);
     while let Some(new_config) = rx.recv().await {
         info!("config file was changed, reloading");
-        *handler.config.write().await = new_config;
-        match handler.apply_current_config().await {
+        match handler.replace_config(new_config, true).await {
             Ok(()) => {
                 info!("configuration reloaded");
             }
@@ -137,12 +219,20 @@ async fn listen_config_changes(handler: Handler) {
 async fn listen_device_events(handler: Handler) {
     let notify = Arc::new(Notify::new());
     let task_notify = notify.clone();
+    let (reset_tx, mut reset_rx) = mpsc::unbounded_channel();
     tokio::task::spawn_blocking(move || {
-        if let Err(err) = system::listen_netlink_kernel_event(&task_notify) {
+        if let Err(err) = system::listen_netlink_kernel_event(&task_notify, &reset_tx) {
             error!("kernel event listener error: {err:#}");
         }
     });
 
+    let reset_handler = handler.clone();
+    tokio::task::spawn_local(async move {
+        while let Some(pci_slot_name) = reset_rx.recv().await {
+            reset_handler.handle_gpu_reset(&pci_slot_name).await;
+        }
+    });
+
     loop {
         notify.notified().await;
 
@@ -160,12 +250,52 @@ async fn listen_device_events(handler: Handler) {
     }
 }
 
-async fn ensure_sufficient_uptime() {
+async fn record_stats_history(handler: Handler) {
+    loop {
+        let interval = handler.config.read().await.daemon.stats_poll_interval();
+        tokio::select! {
+            () = tokio::time::sleep(interval) => handler.record_history().await,
+            // The config was reloaded, start waiting again with the new interval
+            () = handler.stats_poll_notify.notified() => (),
+        }
+    }
+}
+
+async fn monitor_thermal_protection(handler: Handler) {
+    let mut interval = tokio::time::interval(Duration::from_millis(THERMAL_PROTECTION_INTERVAL_MS));
+    loop {
+        interval.tick().await;
+        handler.check_thermal_protection().await;
+    }
+}
+
+async fn monitor_config_integrity(handler: Handler) {
+    loop {
+        let interval = handler
+            .config
+            .read()
+            .await
+            .daemon
+            .integrity_check_interval_secs;
+        match interval {
+            Some(interval) => {
+                tokio::time::sleep(Duration::from_secs(interval.max(1))).await;
+                handler.check_config_integrity().await;
+            }
+            None => {
+                tokio::time::sleep(Duration::from_secs(INTEGRITY_CHECK_DISABLED_POLL_SECS)).await;
+            }
+        }
+    }
+}
+
+async fn ensure_sufficient_uptime(min_uptime_secs: u64) {
     match get_uptime() {
         Ok(current_uptime) => {
             debug!("current system uptime: {current_uptime:.1}s");
 
-            let diff = MIN_SYSTEM_UPTIME_SECS - current_uptime;
+            #[allow(clippy::cast_precision_loss)]
+            let diff = min_uptime_secs as f32 - current_uptime;
             if diff > 0.0 {
                 info!("service started too early, waiting {diff:.1} seconds");
 
@@ -179,6 +309,29 @@ async fn ensure_sufficient_uptime() {
     }
 }
 
+/// Waits for the GPUs with stored settings to show up, this is only needed right after boot.
+/// GPUs which were not there on the last startup are not waited for.
+async fn wait_for_gpus(config: &Config, config_dir: Option<&Path>) {
+    let ready_timeout = Duration::from_secs(config.daemon.gpu_ready_timeout_secs);
+    let remaining = match get_uptime() {
+        Ok(uptime) => ready_timeout.saturating_sub(Duration::from_secs_f32(uptime)),
+        Err(_) => ready_timeout,
+    };
+    if remaining.is_zero() {
+        return;
+    }
+
+    let expected_ids = config
+        .gpus()
+        .map(|gpus| gpus.keys().map(String::as_str).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let expected_ids =
+        readiness::filter_recently_seen(&readiness::seen_gpus_path(config_dir), expected_ids);
+    if !readiness::wait_for_gpus(&drm_base_path(), &expected_ids, remaining).await {
+        warn!("not all GPUs with stored settings showed up in time");
+    }
+}
+
 fn get_uptime() -> anyhow::Result<f32> {
     let raw_uptime = std::fs::read_to_string("/proc/uptime").context("Could not read uptime")?;
     raw_uptime
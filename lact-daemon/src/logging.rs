@@ -0,0 +1,235 @@
+use crate::config::LogFile;
+use anyhow::{ensure, Context};
+use std::{
+    cell::RefCell,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{prelude::*, reload, EnvFilter, Registry};
+
+type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Controls the subscriber installed by [`init`] while the daemon is running
+pub struct Logging {
+    filter_handle: FilterHandle,
+    /// Keeps the background file writer running, pending lines are written out when it gets dropped
+    file_writer_guard: RefCell<Option<WorkerGuard>>,
+}
+
+impl Logging {
+    /// Changes the log level of the running daemon
+    pub fn set_level(&self, level: &str) -> anyhow::Result<()> {
+        reload_filter(&self.filter_handle, level)
+    }
+
+    /// Writes out the lines which are still queued for the log file, used before exiting
+    pub fn flush(&self) {
+        let guard = self.file_writer_guard.borrow_mut().take();
+        drop(guard);
+    }
+}
+
+/// Installs the global subscriber with the given log level
+pub fn init(level: &str, log_file: Option<&LogFile>) -> anyhow::Result<Logging> {
+    let mut file_writer_guard = None;
+    let file_layer = match log_file {
+        Some(log_file) => {
+            let writer = RotatingFileWriter::open(
+                &log_file.path,
+                log_file.max_size_mb.saturating_mul(1024 * 1024),
+                log_file.retention,
+            )?;
+            let (writer, guard) = tracing_appender::non_blocking(writer);
+            file_writer_guard = Some(guard);
+
+            let layer = tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(writer);
+            Some(layer)
+        }
+        None => None,
+    };
+
+    let (filter, handle) = reload::Layer::new(parse_filter(level)?);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(file_layer)
+        .try_init()
+        .context("Logging is already initialized")?;
+
+    Ok(Logging {
+        filter_handle: handle,
+        file_writer_guard: RefCell::new(file_writer_guard),
+    })
+}
+
+fn reload_filter(handle: &FilterHandle, level: &str) -> anyhow::Result<()> {
+    let filter = parse_filter(level)?;
+    handle
+        .reload(filter)
+        .context("Could not change the log level")
+}
+
+fn parse_filter(level: &str) -> anyhow::Result<EnvFilter> {
+    EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .parse(level)
+        .context("Invalid log level")
+}
+
+/// Log file which gets rotated by size.
+/// The rolling appender of `tracing-appender` can only rotate based on time,
+/// so this is used as the inner writer of its non-blocking appender instead.
+///
+/// Rotated files get a numbered suffix, with `.1` being the most recent one.
+struct RotatingFileWriter {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    retention: usize,
+}
+
+impl RotatingFileWriter {
+    fn open(path: &Path, max_size: u64, retention: usize) -> anyhow::Result<Self> {
+        ensure!(max_size > 0, "The maximum log file size has to be set");
+        ensure!(
+            path.file_name().is_some(),
+            "Log file path {} is not a file",
+            path.display()
+        );
+
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create log directory {}", dir.display()))?;
+        }
+        let file = open_log_file(path)
+            .with_context(|| format!("Log file {} is not writable", path.display()))?;
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            size,
+            max_size,
+            retention,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.retention == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.retention).rev() {
+                let rotated = rotated_path(&self.path, index);
+                if rotated.exists() {
+                    fs::rename(rotated, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        self.file = open_log_file(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_log_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_filter, reload_filter, rotated_path, RotatingFileWriter};
+    use std::{fs, io::Write};
+    use tracing::Level;
+    use tracing_subscriber::{prelude::*, reload};
+
+    #[test]
+    fn change_level() {
+        let (filter, handle) = reload::Layer::new(parse_filter("info").unwrap());
+        let subscriber = tracing_subscriber::registry().with(filter);
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert!(tracing::enabled!(Level::INFO));
+            assert!(!tracing::enabled!(Level::DEBUG));
+
+            reload_filter(&handle, "debug").unwrap();
+            assert!(tracing::enabled!(Level::DEBUG));
+            assert!(!tracing::enabled!(Level::TRACE));
+
+            reload_filter(&handle, "warn").unwrap();
+            assert!(!tracing::enabled!(Level::INFO));
+            assert!(tracing::enabled!(Level::WARN));
+
+            // The previous filter stays in effect
+            assert!(reload_filter(&handle, "lact=loud").is_err());
+            assert!(!tracing::enabled!(Level::INFO));
+        });
+    }
+
+    #[test]
+    fn rotate_log_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs/lactd.log");
+
+        let mut writer = RotatingFileWriter::open(&path, 100, 2).unwrap();
+        let line = format!("{:<29}\n", "log line");
+        for _ in 0..12 {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.flush().unwrap();
+
+        // 3 lines fit in each file, the oldest ones are deleted past the retention count
+        for path in [path.clone(), rotated_path(&path, 1), rotated_path(&path, 2)] {
+            assert_eq!(line.repeat(3), fs::read_to_string(path).unwrap());
+        }
+        assert!(!rotated_path(&path, 3).exists());
+
+        // Appending to an existing file counts its current size
+        let mut writer = RotatingFileWriter::open(&path, 100, 2).unwrap();
+        writer.write_all(line.as_bytes()).unwrap();
+        assert_eq!(line, fs::read_to_string(&path).unwrap());
+    }
+
+    #[test]
+    fn unwritable_log_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+
+        let Err(err) = RotatingFileWriter::open(&file.join("lactd.log"), 100, 2) else {
+            panic!("opening a log file inside of a file should fail");
+        };
+        assert!(format!("{err:#}").contains("Could not create log directory"));
+
+        assert!(RotatingFileWriter::open(&dir.path().join("lactd.log"), 0, 2).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{config::PowerSourceProfiles, server::handler::Handler};
+use std::{
+    fmt, fs,
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use tokio::time;
+use tracing::{debug, error, info};
+
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+const POLL_INTERVAL_MS: u64 = 2000;
+/// How long a new power source has to stay active before switching profiles
+const DEBOUNCE_PERIOD_MS: u64 = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+impl fmt::Display for PowerSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerSource::Ac => write!(f, "AC"),
+            PowerSource::Battery => write!(f, "battery"),
+        }
+    }
+}
+
+pub async fn listen_events(handler: Handler) {
+    if read_power_source(Path::new(POWER_SUPPLY_PATH)).is_none() {
+        debug!("no external power supply found, not watching power source changes");
+        return;
+    }
+
+    let mut tracker = PowerSourceTracker::default();
+    let mut interval = time::interval(Duration::from_millis(POLL_INTERVAL_MS));
+
+    loop {
+        interval.tick().await;
+
+        let Some(source) = read_power_source(Path::new(POWER_SUPPLY_PATH)) else {
+            continue;
+        };
+        let Some(source) = tracker.update(source, Instant::now()) else {
+            continue;
+        };
+
+        let new_profile = {
+            let config = handler.config.read().await;
+            if config.auto_switch_profiles {
+                debug!("automatic profile switching is enabled, ignoring power source change");
+                continue;
+            }
+
+            match config.power_source_profiles.profile_for(source) {
+                Some(profile) if config.current_profile.as_ref() != Some(profile) => {
+                    profile.clone()
+                }
+                _ => continue,
+            }
+        };
+
+        info!("power source changed to {source}, switching to profile '{new_profile}'");
+        if let Err(err) = handler.set_current_profile(Some(new_profile)).await {
+            error!("could not switch profile: {err:#}");
+        }
+    }
+}
+
+impl PowerSourceProfiles {
+    pub fn profile_for(&self, source: PowerSource) -> Option<&Rc<str>> {
+        match source {
+            PowerSource::Ac => self.ac.as_ref(),
+            PowerSource::Battery => self.battery.as_ref(),
+        }
+    }
+}
+
+/// Tracks the power source, only reporting a change once it has been stable for the debounce period
+#[derive(Default)]
+struct PowerSourceTracker {
+    current: Option<PowerSource>,
+    pending: Option<(PowerSource, Instant)>,
+}
+
+impl PowerSourceTracker {
+    fn update(&mut self, source: PowerSource, now: Instant) -> Option<PowerSource> {
+        if self.current == Some(source) {
+            self.pending = None;
+            return None;
+        }
+
+        match self.pending {
+            Some((pending_source, since)) if pending_source == source => {
+                if now.duration_since(since) >= Duration::from_millis(DEBOUNCE_PERIOD_MS) {
+                    self.current = Some(source);
+                    self.pending = None;
+                    Some(source)
+                } else {
+                    None
+                }
+            }
+            _ => {
+                self.pending = Some((source, now));
+                None
+            }
+        }
+    }
+}
+
+/// Returns `None` if there are no external power supplies (e.g. on desktops)
+fn read_power_source(base_path: &Path) -> Option<PowerSource> {
+    let mut found_supply = false;
+
+    for entry in fs::read_dir(base_path).ok()?.flatten() {
+        let path = entry.path();
+        let Ok(supply_type) = fs::read_to_string(path.join("type")) else {
+            continue;
+        };
+
+        if matches!(supply_type.trim(), "Mains" | "USB") {
+            found_supply = true;
+
+            if let Ok(online) = fs::read_to_string(path.join("online")) {
+                if online.trim() == "1" {
+                    return Some(PowerSource::Ac);
+                }
+            }
+        }
+    }
+
+    found_supply.then_some(PowerSource::Battery)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_power_source, PowerSource, PowerSourceTracker, DEBOUNCE_PERIOD_MS};
+    use crate::config::PowerSourceProfiles;
+    use std::{
+        fs,
+        path::Path,
+        time::{Duration, Instant},
+    };
+
+    fn set_online(base_path: &Path, online: bool) {
+        fs::write(
+            base_path.join("AC/online"),
+            if online { "1\n" } else { "0\n" },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn no_power_supply() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("BAT0")).unwrap();
+        fs::write(dir.path().join("BAT0/type"), "Battery\n").unwrap();
+
+        assert_eq!(None, read_power_source(dir.path()));
+    }
+
+    #[test]
+    fn ac_battery_transitions() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("AC")).unwrap();
+        fs::write(dir.path().join("AC/type"), "Mains\n").unwrap();
+
+        let profiles = PowerSourceProfiles {
+            ac: Some("performance".into()),
+            battery: Some("quiet".into()),
+        };
+
+        let start = Instant::now();
+        let debounce = Duration::from_millis(DEBOUNCE_PERIOD_MS);
+        let mut tracker = PowerSourceTracker::default();
+        let mut applied = vec![];
+
+        let mut poll = |online: bool, elapsed: Duration| {
+            set_online(dir.path(), online);
+            let source = read_power_source(dir.path()).unwrap();
+            if let Some(source) = tracker.update(source, start + elapsed) {
+                applied.push(profiles.profile_for(source).unwrap().to_string());
+            }
+        };
+
+        poll(true, Duration::ZERO);
+        poll(true, debounce);
+        // Brief blip that should be ignored
+        poll(false, debounce * 2);
+        poll(true, debounce * 2 + Duration::from_secs(1));
+        // Unplugged
+        poll(false, debounce * 3);
+        poll(false, debounce * 4);
+        // Plugged back in
+        poll(true, debounce * 5);
+        poll(true, debounce * 6);
+
+        assert_eq!(vec!["performance", "quiet", "performance"], applied);
+        assert_eq!(
+            Some(PowerSource::Ac),
+            read_power_source(dir.path()),
+            "final power source"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    config::{gpu_model_id, path_in},
+    server::gpu_controller::read_pci_model_id,
+};
+use anyhow::Context;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+/// How often to check for the GPUs while waiting for them
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const SEEN_GPUS_FILE_NAME: &str = "seen_gpus";
+
+pub fn seen_gpus_path(config_dir: Option<&Path>) -> PathBuf {
+    path_in(config_dir, SEEN_GPUS_FILE_NAME)
+}
+
+/// Records the ids of the GPUs which are present now, to be used by [`filter_recently_seen`] on the next startup
+pub fn record_seen_gpus(path: &Path, ids: &[String]) -> anyhow::Result<()> {
+    fs::write(path, ids.join("\n")).with_context(|| format!("Could not write {}", path.display()))
+}
+
+/// Only keeps the ids of the GPUs which were present on the last startup,
+/// so a GPU which got removed doesn't delay every following startup until the timeout runs out.
+/// All of the ids are kept when there is no record of the last startup.
+pub fn filter_recently_seen<'a>(path: &Path, expected_ids: Vec<&'a str>) -> Vec<&'a str> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return expected_ids;
+    };
+    let seen_models: HashSet<String> = contents
+        .lines()
+        .map(|id| gpu_model_id(id).to_ascii_uppercase())
+        .collect();
+
+    expected_ids
+        .into_iter()
+        .filter(|id| seen_models.contains(&gpu_model_id(id).to_ascii_uppercase()))
+        .collect()
+}
+
+/// Waits until the GPUs with the given ids show up in sysfs, or until the timeout runs out.
+/// Returns whether all of them were found.
+///
+/// GPUs are matched by their model, so GPUs which were moved to another PCI slot are found too.
+/// When no ids are given, the first GPU to show up is enough.
+pub async fn wait_for_gpus(base_path: &Path, expected_ids: &[&str], timeout: Duration) -> bool {
+    let mut expected_models = HashMap::<String, usize>::new();
+    for id in expected_ids {
+        *expected_models
+            .entry(gpu_model_id(id).to_ascii_uppercase())
+            .or_default() += 1;
+    }
+
+    let start = Instant::now();
+    loop {
+        let present_models = read_present_models(base_path);
+        let ready = if expected_models.is_empty() {
+            !present_models.is_empty()
+        } else {
+            expected_models.iter().all(|(model, count)| {
+                present_models
+                    .get(model)
+                    .is_some_and(|present_count| present_count >= count)
+            })
+        };
+
+        if ready {
+            debug!("GPUs are ready after {:?}", start.elapsed());
+            return true;
+        }
+        if start.elapsed() >= timeout {
+            return false;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Amount of GPUs of each model which are currently in sysfs
+fn read_present_models(base_path: &Path) -> HashMap<String, usize> {
+    let mut models = HashMap::new();
+
+    if let Ok(entries) = fs::read_dir(base_path) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with("card") && !name.contains('-') {
+                if let Some(model) = read_pci_model_id(&entry.path().join("device")) {
+                    *models.entry(model).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    models
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{filter_recently_seen, record_seen_gpus, wait_for_gpus};
+    use crate::tests::copy_dir;
+    use std::{
+        path::PathBuf,
+        time::{Duration, Instant},
+    };
+    use tempfile::tempdir;
+
+    const RX580_ID: &str = "1002:67DF-1DA2:E387-0000:09:00.0";
+
+    #[tokio::test]
+    async fn gpu_appears_during_wait() {
+        let device_data_dir =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+        let sysfs_dir = tempdir().unwrap();
+        let sysfs_path = sysfs_dir.path().to_owned();
+
+        let start = Instant::now();
+        let copy_task = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            copy_dir(&device_data_dir, &sysfs_path);
+        });
+
+        // The GPU being in another slot doesn't matter
+        let moved_id = RX580_ID.replace("0000:09:00.0", "0000:03:00.0");
+        let timeout = Duration::from_secs(30);
+        let ready = wait_for_gpus(sysfs_dir.path(), &[moved_id.as_str()], timeout).await;
+        assert!(ready);
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(500), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(5), "{elapsed:?}");
+        copy_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn gpu_ready_timeout() {
+        let device_data_dir =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+        let sysfs_dir = tempdir().unwrap();
+        copy_dir(&device_data_dir, sysfs_dir.path());
+
+        // The GPU is already there
+        let timeout = Duration::from_millis(500);
+        assert!(wait_for_gpus(sysfs_dir.path(), &[RX580_ID], timeout).await);
+        assert!(wait_for_gpus(sysfs_dir.path(), &[], timeout).await);
+
+        // The second identical GPU never shows up
+        let start = Instant::now();
+        let ready = wait_for_gpus(sysfs_dir.path(), &[RX580_ID, RX580_ID], timeout).await;
+        assert!(!ready);
+        assert!(start.elapsed() >= timeout);
+
+        let empty_dir = tempdir().unwrap();
+        assert!(!wait_for_gpus(empty_dir.path(), &[], timeout).await);
+    }
+
+    #[test]
+    fn only_wait_for_recently_seen() {
+        const REMOVED_ID: &str = "1002:687F-1043:0555-0000:0b:00.0";
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("seen_gpus");
+
+        // Nothing is known about the last startup
+        let expected = vec![RX580_ID, REMOVED_ID];
+        assert_eq!(expected, filter_recently_seen(&path, expected.clone()));
+
+        // The GPU got moved to another slot since it was last seen
+        let moved_id = RX580_ID.replace("0000:09:00.0", "0000:03:00.0");
+        record_seen_gpus(&path, &[moved_id]).unwrap();
+        assert_eq!(vec![RX580_ID], filter_recently_seen(&path, expected));
+    }
+}
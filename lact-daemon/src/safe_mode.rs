@@ -0,0 +1,124 @@
+use anyhow::Context;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tracing::{debug, error, warn};
+
+const STATE_FILE_NAME: &str = "startup_failures";
+/// Amount of consecutive unclean startups after which safe mode gets enabled
+const MAX_STARTUP_FAILURES: u32 = 3;
+/// How long the daemon has to be running for the startup to be considered successful
+const CLEAN_RUN_DURATION_SECS: u64 = 60;
+
+/// Records a new startup attempt, returns if safe mode should be used because the previous startups did not run for long enough
+pub fn check_startup(config_dir: Option<&Path>) -> bool {
+    match record_startup(&state_file_path(config_dir)) {
+        Ok(true) => {
+            warn!(
+                "the daemon did not run successfully for the last {MAX_STARTUP_FAILURES} startups, \
+                starting in safe mode without applying the configuration"
+            );
+            true
+        }
+        Ok(false) => false,
+        Err(err) => {
+            error!("could not record startup attempt: {err:#}");
+            false
+        }
+    }
+}
+
+/// Marks the startup as successful once the daemon has been running for long enough
+pub async fn reset_after_clean_run(config_dir: Option<PathBuf>) {
+    tokio::time::sleep(Duration::from_secs(CLEAN_RUN_DURATION_SECS)).await;
+
+    debug!("daemon has been running for {CLEAN_RUN_DURATION_SECS}s, resetting startup failures");
+    if let Err(err) = reset_failures(&state_file_path(config_dir.as_deref())) {
+        error!("could not reset startup failures: {err:#}");
+    }
+}
+
+/// Marks the startup as successful when the daemon is shut down cleanly before the clean run duration
+pub fn reset_on_shutdown(config_dir: Option<&Path>) {
+    if let Err(err) = reset_failures(&state_file_path(config_dir)) {
+        error!("could not reset startup failures: {err:#}");
+    }
+}
+
+fn state_file_path(config_dir: Option<&Path>) -> PathBuf {
+    crate::config::path_in(config_dir, STATE_FILE_NAME)
+}
+
+/// Returns if safe mode should be used
+fn record_startup(path: &Path) -> anyhow::Result<bool> {
+    let failures = match fs::read_to_string(path) {
+        Ok(contents) => contents.trim().parse::<u32>().unwrap_or_default(),
+        Err(_) => 0,
+    };
+
+    // The counter is only reset once the daemon has been running for a while,
+    // so a startup that crashes is counted as a failure on the next one
+    fs::write(path, (failures + 1).to_string())
+        .with_context(|| format!("Could not write {}", path.display()))?;
+
+    Ok(failures >= MAX_STARTUP_FAILURES)
+}
+
+fn reset_failures(path: &Path) -> anyhow::Result<()> {
+    fs::write(path, "0").with_context(|| format!("Could not write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        record_startup, reset_failures, reset_on_shutdown, state_file_path, MAX_STARTUP_FAILURES,
+    };
+
+    #[test]
+    fn crash_loop_enables_safe_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("startup_failures");
+
+        for _ in 0..MAX_STARTUP_FAILURES {
+            assert!(!record_startup(&path).unwrap());
+        }
+        assert!(record_startup(&path).unwrap());
+        assert!(record_startup(&path).unwrap());
+    }
+
+    #[test]
+    fn clean_run_resets_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("startup_failures");
+
+        for _ in 0..MAX_STARTUP_FAILURES {
+            record_startup(&path).unwrap();
+        }
+        reset_failures(&path).unwrap();
+        assert!(!record_startup(&path).unwrap());
+    }
+
+    #[test]
+    fn clean_shutdowns_do_not_enable_safe_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = state_file_path(Some(dir.path()));
+
+        for _ in 0..=MAX_STARTUP_FAILURES {
+            assert!(!record_startup(&path).unwrap());
+            reset_on_shutdown(Some(dir.path()));
+        }
+        assert_eq!("0", std::fs::read_to_string(&path).unwrap());
+    }
+
+    #[test]
+    fn invalid_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("startup_failures");
+        std::fs::write(&path, "garbage").unwrap();
+
+        assert!(!record_startup(&path).unwrap());
+        assert_eq!("1", std::fs::read_to_string(&path).unwrap());
+    }
+}
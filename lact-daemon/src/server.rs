@@ -1,19 +1,29 @@
+mod event_log;
+mod fan_response;
 pub mod gpu_controller;
 pub mod handler;
+mod health;
+mod history;
 mod opencl;
+mod operations;
 mod profiles;
+mod thermal_protection;
 mod vulkan;
 
-use self::handler::Handler;
+use self::handler::{Handler, HandlerOptions};
 use crate::{config::Config, socket, system};
 use anyhow::Context;
 use futures::future::join_all;
-use lact_schema::{Pong, Request, Response};
+use lact_schema::{
+    request::ConfirmCommand, BroadcastOutcome, ClientCredentials, CompressedFrame,
+    CompressionCodec, ErrorKind, Pong, Request, Response, COMPRESSION_THRESHOLD,
+};
+use nix::unistd::{Gid, Uid};
 use serde::Serialize;
-use std::fmt::Debug;
+use std::{fmt::Debug, path::Path};
 use tokio::{
     io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
-    net::{TcpListener, UnixListener},
+    net::{TcpListener, UnixListener, UnixStream},
 };
 use tracing::{error, info, instrument, trace};
 
@@ -24,8 +34,12 @@ pub struct Server {
 }
 
 impl Server {
-    pub async fn new(config: Config) -> anyhow::Result<Self> {
-        let (unix_listener, socket_path) = socket::listen()?;
+    pub async fn new(
+        config: Config,
+        socket_path: &Path,
+        handler_options: HandlerOptions,
+    ) -> anyhow::Result<Self> {
+        let unix_listener = socket::listen(socket_path)?;
 
         let tcp_listener = if let Some(address) = &config.daemon.tcp_listen_address {
             let listener = TcpListener::bind(address)
@@ -40,13 +54,13 @@ impl Server {
 
         system::power_profiles_daemon::setup().await;
 
-        let handler = Handler::new(config).await?;
+        let handler = Handler::new(config, handler_options).await?;
 
-        socket::set_permissions(&socket_path, &handler.config.read().await.daemon)
+        socket::set_permissions(socket_path, &handler.config.read().await.daemon)
             .await
             .inspect_err(|_| {
                 // Clean up the socket if permissions failed to be set
-                socket::cleanup();
+                socket::cleanup(socket_path);
             })?;
 
         Ok(Self {
@@ -66,7 +80,7 @@ impl Server {
                     Ok((stream, _)) => {
                         let handler = unix_handler.clone();
                         tokio::task::spawn_local(async move {
-                            if let Err(error) = handle_stream(stream, handler).await {
+                            if let Err(error) = handle_unix_stream(stream, handler).await {
                                 error!("{error}");
                             }
                         });
@@ -86,7 +100,7 @@ impl Server {
                         Ok((stream, _)) => {
                             let handler = self.handler.clone();
                             tokio::task::spawn_local(async move {
-                                if let Err(error) = handle_stream(stream, handler).await {
+                                if let Err(error) = handle_tcp_stream(stream, handler).await {
                                     error!("{error}");
                                 }
                             });
@@ -104,52 +118,264 @@ impl Server {
     }
 }
 
-#[instrument(level = "debug", skip(stream, handler))]
 pub async fn handle_stream<T: AsyncRead + AsyncWrite + Unpin>(
     stream: T,
     handler: Handler,
+) -> anyhow::Result<()> {
+    handle_connection(stream, handler, Connection::default()).await
+}
+
+/// TCP clients can't be identified, so they are limited to read-only requests by the control group
+async fn handle_tcp_stream<T: AsyncRead + AsyncWrite + Unpin>(
+    stream: T,
+    handler: Handler,
+) -> anyhow::Result<()> {
+    handle_connection(stream, handler, Connection::tcp()).await
+}
+
+/// Unix socket clients are identified by their peer credentials
+pub async fn handle_unix_stream(stream: UnixStream, handler: Handler) -> anyhow::Result<()> {
+    let peer = match stream.peer_cred() {
+        Ok(cred) => Some(ClientCredentials {
+            pid: cred.pid(),
+            uid: cred.uid(),
+            gid: cred.gid(),
+        }),
+        Err(err) => {
+            error!("could not get peer credentials: {err}");
+            None
+        }
+    };
+    handle_connection(stream, handler, Connection::with_peer(peer)).await
+}
+
+#[instrument(level = "debug", skip(stream, handler, connection))]
+async fn handle_connection<T: AsyncRead + AsyncWrite + Unpin>(
+    stream: T,
+    handler: Handler,
+    mut connection: Connection,
 ) -> anyhow::Result<()> {
     let mut stream = BufReader::new(stream);
 
-    let mut buf = String::new();
-    while stream.read_line(&mut buf).await? != 0 {
-        trace!("handling request: {}", buf.trim_end());
+    let result: anyhow::Result<()> = async {
+        let mut buf = String::new();
+        while stream.read_line(&mut buf).await? != 0 {
+            let response = handle_payload(&buf, &handler, &mut connection).await?;
+            let response = match connection.compression {
+                Some(codec) if response.len() >= COMPRESSION_THRESHOLD => {
+                    CompressedFrame::encode(codec, &response)?
+                }
+                _ => response,
+            };
+
+            stream.write_all(&response).await?;
+            stream.write_all(b"\n").await?;
+
+            buf.clear();
+        }
+        Ok(())
+    }
+    .await;
+
+    if let Some(token) = connection.staged_settings {
+        handler.revert_staged_settings(token);
+    }
+
+    result
+}
+
+/// Handles a single serialized request, returning the serialized response
+pub(crate) async fn handle_payload(
+    payload: &str,
+    handler: &Handler,
+    connection: &mut Connection,
+) -> anyhow::Result<Vec<u8>> {
+    trace!("handling request: {}", payload.trim_end());
+
+    let maybe_request = serde_json::from_str::<Request>(payload);
+    let response = match maybe_request {
+        Ok(request) => {
+            let setting_change = request.gpu_setting_change();
+
+            let result: anyhow::Result<Vec<u8>> = async {
+                ensure_request_allowed(&request, handler, connection).await?;
+                handle_request(request, handler, connection).await
+            }
+            .await;
 
-        let maybe_request = serde_json::from_str(&buf);
-        let response = match maybe_request {
-            Ok(request) => match handle_request(request, &handler).await {
-                Ok(response) => response,
+            match result {
+                Ok(response) => {
+                    if let Some((id, name)) = setting_change {
+                        handler.record_setting_provenance(&id, &name, connection.peer);
+                    }
+                    response
+                }
                 Err(error) => serde_json::to_vec(&Response::<()>::from(error))?,
-            },
-            Err(error) => serde_json::to_vec(&Response::<()>::from(
-                anyhow::Error::new(error).context("Failed to deserialize"),
-            ))?,
-        };
+            }
+        }
+        Err(error) => serde_json::to_vec(&Response::<()>::from(
+            anyhow::Error::new(error).context("Failed to deserialize"),
+        ))?,
+    };
+    Ok(response)
+}
+
+/// Socket clients can be limited to read-only requests with the control group.
+/// Clients which can't be identified are denied changes while the group is set.
+async fn ensure_request_allowed(
+    request: &Request<'_>,
+    handler: &Handler,
+    connection: &Connection,
+) -> anyhow::Result<()> {
+    if connection.origin == ClientOrigin::Local || request.is_read_only() {
+        return Ok(());
+    }
+
+    let config = handler.config.read().await;
+    let Some(group) = &config.daemon.control_group else {
+        return Ok(());
+    };
 
-        stream.write_all(&response).await?;
-        stream.write_all(b"\n").await?;
+    let peer = match (connection.origin, connection.peer) {
+        (ClientOrigin::Tcp, _) => {
+            return Err(ErrorKind::PermissionDenied.error(format!(
+                "TCP clients can only use read-only requests while the {group} group is set"
+            )));
+        }
+        (_, Some(peer)) => peer,
+        (_, None) => {
+            return Err(ErrorKind::PermissionDenied.error(format!(
+                "Could not identify the client, which is required to change settings while the {group} group is set"
+            )));
+        }
+    };
 
-        buf.clear();
+    let uid = Uid::from_raw(peer.uid);
+    let allowed = socket::peer_in_group(uid, Gid::from_raw(peer.gid), group)
+        .context("Could not check the permissions of the client")?;
+    if allowed {
+        Ok(())
+    } else {
+        Err(ErrorKind::PermissionDenied.error(format!(
+            "User {uid} is not in the {group} group, which is required to change settings"
+        )))
     }
+}
+
+/// Where a connection comes from, which decides how the control group applies to it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ClientOrigin {
+    /// In-process callers
+    #[default]
+    Local,
+    Unix,
+    Tcp,
+    /// Callers on the system bus, identified by the credentials the bus reports for them
+    #[cfg(feature = "dbus")]
+    DBus,
+}
 
-    Ok(())
+/// State of a single client connection
+#[derive(Default)]
+pub(crate) struct Connection {
+    /// Settings staged by this client, which get reverted if it disconnects without confirming
+    staged_settings: Option<u64>,
+    origin: ClientOrigin,
+    peer: Option<ClientCredentials>,
+    /// Large responses are compressed once the client has negotiated a codec
+    compression: Option<CompressionCodec>,
 }
 
-#[instrument(level = "debug", skip(handler))]
-async fn handle_request<'a>(request: Request<'a>, handler: &'a Handler) -> anyhow::Result<Vec<u8>> {
+impl Connection {
+    /// A Unix socket connection, `peer` is `None` when its credentials could not be read
+    pub(crate) fn with_peer(peer: Option<ClientCredentials>) -> Self {
+        Self {
+            origin: ClientOrigin::Unix,
+            peer,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn tcp() -> Self {
+        Self {
+            origin: ClientOrigin::Tcp,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(feature = "dbus")]
+    pub(crate) fn dbus(peer: ClientCredentials) -> Self {
+        Self {
+            origin: ClientOrigin::DBus,
+            peer: Some(peer),
+            ..Default::default()
+        }
+    }
+}
+
+#[instrument(level = "debug", skip(handler, connection))]
+async fn handle_request<'a>(
+    request: Request<'a>,
+    handler: &'a Handler,
+    connection: &mut Connection,
+) -> anyhow::Result<Vec<u8>> {
     match request {
         Request::Ping => ok_response(ping()),
-        Request::SystemInfo => ok_response(system::info().await?),
+        Request::SystemInfo => ok_response(system::info(handler.is_safe_mode()).await?),
+        Request::GetDaemonInfo => ok_response(system::daemon_info()),
+        Request::NegotiateCompression { codecs } => {
+            connection.compression = codecs
+                .into_iter()
+                .find(|codec| CompressionCodec::SUPPORTED.contains(codec));
+            ok_response(connection.compression)
+        }
         Request::ListDevices => ok_response(handler.list_devices().await),
+        Request::SetGpuAlias { id, alias } => ok_response(handler.set_gpu_alias(id, alias).await?),
         Request::DeviceInfo { id } => ok_response(handler.get_device_info(id).await?),
+        Request::GetSystemPower => ok_response(handler.get_system_power().await),
+        Request::GetStatusLine => ok_response(handler.get_status_line().await),
+        Request::GetLastCrash => ok_response(handler.get_last_crash().await?),
+        Request::SetManagementEnabled { enabled } => {
+            ok_response(handler.set_management_enabled(enabled).await?)
+        }
+        Request::SetLogLevel { level } => ok_response(handler.set_log_level(level)?),
+        Request::GetHealthWarnings { id } => ok_response(handler.get_health_warnings(id).await?),
+        Request::GetSettingProvenance { id } => {
+            ok_response(handler.get_setting_provenance(id).await?)
+        }
+        Request::GetEventLog { since } => ok_response(handler.get_event_log(since)),
+        Request::ListOperations => ok_response(handler.list_operations()),
+        Request::CancelOperation { id } => ok_response(handler.cancel_operation(id)?),
         Request::DeviceStats { id } => ok_response(handler.get_gpu_stats(id).await?),
+        Request::GetStatsNow { id } => ok_response(handler.get_stats_now(id).await?),
+        Request::GetConfigDiff { id } => ok_response(handler.get_config_diff(id).await?),
         Request::DeviceClocksInfo { id } => ok_response(handler.get_clocks_info(id).await?),
+        Request::GetCapabilities { id } => ok_response(handler.get_capabilities(id).await?),
+        Request::GetVersions { id } => ok_response(handler.get_versions(id).await?),
         Request::DevicePowerProfileModes { id } => {
             ok_response(handler.get_power_profile_modes(id).await?)
         }
         Request::SetFanControl(opts) => ok_response(handler.set_fan_control(opts).await?),
+        Request::SetFanStatic { id, pwm } => ok_response(handler.set_fan_static(id, pwm).await?),
         Request::ResetPmfw { id } => ok_response(handler.reset_pmfw(id).await?),
+        Request::SetFanZeroRpm { id, enabled } => {
+            ok_response(handler.set_fan_zero_rpm(id, enabled).await?)
+        }
+        Request::MeasureFanResponse {
+            id,
+            from_pwm,
+            to_pwm,
+        } => ok_response(handler.measure_fan_response(id, from_pwm, to_pwm).await?),
         Request::SetPowerCap { id, cap } => ok_response(handler.set_power_cap(id, cap).await?),
+        Request::SetPowerCapPercent { id, percent } => {
+            ok_response(handler.set_power_cap_percent(id, percent).await?)
+        }
+        Request::SetVoltageOffset { id, offset } => {
+            ok_response(handler.set_voltage_offset(id, offset).await?)
+        }
+        Request::SetMemoryClockOffset { id, offset } => {
+            ok_response(handler.set_memory_clock_offset(id, offset).await?)
+        }
         Request::SetPerformanceLevel {
             id,
             performance_level,
@@ -173,6 +399,44 @@ async fn handle_request<'a>(request: Request<'a>, handler: &'a Handler) -> anyho
         Request::SetEnabledPowerStates { id, kind, states } => {
             ok_response(handler.set_enabled_power_states(id, kind, states).await?)
         }
+        Request::SetClockStates { id, kind, states } => {
+            ok_response(handler.set_clock_states(id, kind, states).await?)
+        }
+        Request::SetClockState {
+            id,
+            kind,
+            index,
+            point,
+        } => ok_response(handler.set_clock_state(id, kind, index, point).await?),
+        Request::SetMemoryClockLevel { id, level } => {
+            ok_response(handler.set_memory_clock_level(id, level).await?)
+        }
+        Request::SetPcieLevel { id, level } => {
+            ok_response(handler.set_pcie_level(id, level).await?)
+        }
+        Request::SetIdleLock { id, enabled } => {
+            ok_response(handler.set_idle_lock(id, enabled).await?)
+        }
+        Request::SetLed { id, name, settings } => {
+            ok_response(handler.set_led(id, name, settings).await?)
+        }
+        Request::GetDryRunLog { id } => ok_response(handler.get_dry_run_log(id).await?),
+        Request::RunSelfTest { id } => ok_response(handler.run_self_test(id).await?),
+        Request::GetHistory { id, metrics } => {
+            ok_response(handler.get_history(id, &metrics).await?)
+        }
+        Request::BeginCapture { id } => ok_response(handler.begin_capture(id).await?),
+        Request::EndCapture { id } => ok_response(handler.end_capture(id).await?),
+        Request::GetExtremes { id } => ok_response(handler.get_extremes(id).await?),
+        Request::ResetExtremes { id } => ok_response(handler.reset_extremes(id).await?),
+        Request::EstimatePowerCap {
+            id,
+            target_temperature,
+        } => ok_response(handler.estimate_power_cap(id, target_temperature).await?),
+        Request::EnergyDelta { id } => ok_response(handler.energy_delta(id).await?),
+        Request::ExportStats { id, format, since } => {
+            ok_response(handler.export_stats(id, format, since).await?)
+        }
         Request::VbiosDump { id } => ok_response(handler.vbios_dump(id).await?),
         Request::ListProfiles { include_state } => {
             ok_response(handler.list_profiles(include_state).await)
@@ -185,6 +449,14 @@ async fn handle_request<'a>(request: Request<'a>, handler: &'a Handler) -> anyho
                 .set_profile(name.map(Into::into), auto_switch)
                 .await?,
         ),
+        Request::ApplyTemporaryProfile {
+            name,
+            duration_secs,
+        } => ok_response(
+            handler
+                .apply_temporary_profile(name.into(), duration_secs)
+                .await?,
+        ),
         Request::CreateProfile { name, base } => {
             ok_response(handler.create_profile(name, base).await?)
         }
@@ -200,9 +472,66 @@ async fn handle_request<'a>(request: Request<'a>, handler: &'a Handler) -> anyho
         Request::SetGpuConfig { id, config } => {
             ok_response(handler.set_gpu_config(id, config).await?)
         }
+        Request::ExportGpuConfig { id } => ok_response(handler.export_gpu_config(id).await?),
+        Request::ImportGpuConfig {
+            id,
+            snippet,
+            allow_model_mismatch,
+        } => ok_response(
+            handler
+                .import_gpu_config(id, &snippet, allow_model_mismatch)
+                .await?,
+        ),
+        Request::ExportConfig => ok_response(handler.export_config().await?),
+        Request::ImportConfig {
+            config,
+            apply,
+            allow_missing_gpus,
+        } => ok_response(
+            handler
+                .import_config(&config, apply, allow_missing_gpus)
+                .await?,
+        ),
+        Request::StageSettings {
+            id,
+            config,
+            timeout_secs,
+        } => {
+            let staged = handler.stage_settings(id, config, timeout_secs).await?;
+            connection.staged_settings = Some(staged.token);
+            ok_response(staged.timeout_secs)
+        }
+        Request::ConfirmSettings => {
+            handler.confirm_staged_settings(connection.staged_settings)?;
+            connection.staged_settings = None;
+            ok_response(())
+        }
+        Request::GenerateFanCurve { id, preset } => {
+            ok_response(handler.generate_fan_curve(id, preset).await?)
+        }
+        Request::ValidateFanCurve { id, curve, unit } => {
+            ok_response(handler.validate_fan_curve(id, &curve, unit).await?)
+        }
+        Request::GetFirmwareFanCurve { id } => {
+            ok_response(handler.get_firmware_fan_curve(id).await?)
+        }
+        Request::BroadcastToModel { model, changes } => {
+            let broadcast = handler.broadcast_to_model(model, changes).await?;
+            for (id, outcome) in &broadcast.gpus {
+                if *outcome == BroadcastOutcome::Applied {
+                    handler.record_setting_provenance(id, "broadcast_to_model", connection.peer);
+                }
+            }
+            ok_response(broadcast)
+        }
         Request::ProcessList { id } => ok_response(handler.process_list(id).await?),
         Request::EnableOverdrive => ok_response(system::enable_overdrive().await?),
         Request::DisableOverdrive => ok_response(system::disable_overdrive().await?),
+        Request::GetModuleConfigStatus => ok_response(system::module_config_status()),
+        Request::GetOverclockStatus => ok_response(system::overclock_status()),
+        Request::SetModuleConfig { ppfeaturemask } => {
+            ok_response(system::set_module_config(ppfeaturemask).await?)
+        }
         Request::GenerateSnapshot => ok_response(handler.generate_snapshot().await?),
         Request::ConfirmPendingConfig(command) => {
             ok_response(handler.confirm_pending_config(command)?)
@@ -0,0 +1,54 @@
+use lact_schema::{DaemonEvent, DaemonEventKind};
+use std::collections::VecDeque;
+
+/// Amount of events kept in memory, older ones get dropped
+const CAPACITY: usize = 500;
+
+/// Bounded log of the actions taken by the daemon, separate from the text log
+#[derive(Default)]
+pub struct EventLog {
+    events: VecDeque<DaemonEvent>,
+}
+
+impl EventLog {
+    pub fn push(&mut self, timestamp: i64, kind: DaemonEventKind) {
+        while self.events.len() >= CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(DaemonEvent { timestamp, kind });
+    }
+
+    pub fn get(&self, since: Option<i64>) -> Vec<DaemonEvent> {
+        self.events
+            .iter()
+            .filter(|event| since.is_none_or(|since| event.timestamp >= since))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventLog, CAPACITY};
+    use lact_schema::DaemonEventKind;
+
+    #[test]
+    fn drop_oldest_events() {
+        let mut log = EventLog::default();
+        for timestamp in 0..CAPACITY + 10 {
+            let kind = DaemonEventKind::ProfileSwitched { profile: None };
+            log.push(i64::try_from(timestamp).unwrap(), kind);
+        }
+
+        let events = log.get(None);
+        assert_eq!(CAPACITY, events.len());
+        assert_eq!(10, events[0].timestamp);
+
+        let timestamps: Vec<i64> = log
+            .get(Some(500))
+            .into_iter()
+            .map(|event| event.timestamp)
+            .collect();
+        assert_eq!((500..510).collect::<Vec<i64>>(), timestamps);
+    }
+}
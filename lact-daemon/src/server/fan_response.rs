@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+/// How often the fan speed is read while waiting for it to settle
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+/// Give up when the fan speed still keeps changing after this long
+pub const SETTLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// The speed has to stay within the tolerance for this many samples in a row
+const STABLE_SAMPLES: u32 = 5;
+/// Readings within this fraction of each other are considered the same speed
+const RPM_TOLERANCE: f64 = 0.02;
+/// Tachometers are not precise at low speeds, so small absolute differences are always allowed
+const MIN_RPM_TOLERANCE: u32 = 20;
+
+/// Detects when the fan speed stops changing after a PWM step
+#[derive(Debug, Default)]
+pub struct SettleDetector {
+    /// When the first reading of the current stable run was taken, and its speed
+    run_start: Option<(Duration, u32)>,
+    run_length: u32,
+}
+
+impl SettleDetector {
+    /// Adds a reading taken `elapsed` after the PWM step.
+    /// Returns when the speed reached the value it settled at, once it has been stable long enough.
+    pub fn push(&mut self, elapsed: Duration, rpm: u32) -> Option<Duration> {
+        match self.run_start {
+            Some((_, start_rpm)) if is_same_speed(start_rpm, rpm) => self.run_length += 1,
+            _ => {
+                self.run_start = Some((elapsed, rpm));
+                self.run_length = 1;
+            }
+        }
+
+        if self.run_length >= STABLE_SAMPLES {
+            self.run_start.map(|(start, _)| start)
+        } else {
+            None
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn is_same_speed(a: u32, b: u32) -> bool {
+    let tolerance = (f64::from(a.max(b)) * RPM_TOLERANCE) as u32;
+    a.abs_diff(b) <= tolerance.max(MIN_RPM_TOLERANCE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SettleDetector, SAMPLE_INTERVAL, STABLE_SAMPLES};
+    use std::time::Duration;
+
+    /// Feeds readings of a fan which covers half of the remaining distance to its target speed
+    /// on every sample. Returns the settle time, the last reading and the amount of readings.
+    fn converge(initial_rpm: u32, target_rpm: u32) -> (Duration, u32, u32) {
+        let mut detector = SettleDetector::default();
+        let mut rpm = initial_rpm;
+
+        for sample in 1..100 {
+            rpm = if rpm < target_rpm {
+                rpm + (target_rpm - rpm).div_ceil(2)
+            } else {
+                rpm - (rpm - target_rpm).div_ceil(2)
+            };
+
+            if let Some(settle_time) = detector.push(SAMPLE_INTERVAL * sample, rpm) {
+                return (settle_time, rpm, sample);
+            }
+        }
+        panic!("the fan speed did not settle");
+    }
+
+    #[test]
+    fn speed_up() {
+        let (settle_time, rpm, samples) = converge(800, 2400);
+        assert!(rpm.abs_diff(2400) <= 20, "{rpm}");
+        // The readings starting from 2350 RPM stay within the tolerance
+        assert_eq!(SAMPLE_INTERVAL * 5, settle_time);
+        assert_eq!(5 + STABLE_SAMPLES - 1, samples);
+    }
+
+    #[test]
+    fn slow_down() {
+        let (settle_time, rpm, _) = converge(3000, 1000);
+        assert_eq!(1000, rpm);
+        assert_eq!(SAMPLE_INTERVAL * 8, settle_time);
+    }
+
+    #[test]
+    fn already_stable() {
+        let mut detector = SettleDetector::default();
+        for sample in 1..STABLE_SAMPLES {
+            assert_eq!(None, detector.push(SAMPLE_INTERVAL * sample, 1500));
+        }
+        assert_eq!(
+            Some(SAMPLE_INTERVAL),
+            detector.push(SAMPLE_INTERVAL * STABLE_SAMPLES, 1510)
+        );
+    }
+
+    #[test]
+    fn unstable_reading_restarts() {
+        let mut detector = SettleDetector::default();
+        let readings = [1500, 1500, 1500, 1800, 1500, 1500, 1500, 1500, 1500];
+
+        let settled = readings
+            .into_iter()
+            .zip(1..)
+            .find_map(|(rpm, sample)| detector.push(SAMPLE_INTERVAL * sample, rpm));
+        assert_eq!(Some(SAMPLE_INTERVAL * 5), settled);
+    }
+}
@@ -1,16 +1,20 @@
 #![allow(clippy::module_name_repetitions)]
 mod amd;
 pub mod common;
+mod dry_run;
 mod intel;
 #[cfg(feature = "nvidia")]
 mod nvidia;
+mod spanned;
 
 use amd::AmdGpuController;
+pub use dry_run::DryRunController;
 use intel::IntelGpuController;
 use lact_schema::DeviceType;
 use lact_schema::ProcessList;
 #[cfg(feature = "nvidia")]
 use nvidia::NvidiaGpuController;
+use spanned::SpannedController;
 
 pub const VENDOR_AMD: &str = "1002";
 pub const VENDOR_NVIDIA: &str = "10DE";
@@ -19,15 +23,28 @@ use crate::bindings::intel::IntelDrm;
 use amdgpu_sysfs::gpu_handle::power_profile_mode::PowerProfileModesTable;
 use anyhow::anyhow;
 use anyhow::Context;
+use common::{
+    fan_control::{self, TemperatureReader, TemperatureSources},
+    retry::WriteRetry,
+};
 use futures::{future::LocalBoxFuture, FutureExt};
 use lact_schema::{
-    config::GpuConfig, ClocksInfo, DeviceInfo, DeviceStats, GpuPciInfo, PciInfo, PowerStates,
+    config::{FanControlSettings, FanCurve, FanCurveUnit, GpuConfig},
+    ClocksInfo, ConfigDifference, DeviceInfo, DeviceStats, ErrorKind, FanCurveValidation,
+    GpuCapabilities, GpuPciInfo, PciInfo, PowerStates, SelfTestEntry, SysfsWrite, VersionsInfo,
 };
 use libdrm_amdgpu_sys::LibDrmAmdgpu;
 use std::io;
-use std::{cell::LazyCell, collections::HashMap, fs, path::PathBuf, rc::Rc};
+use std::{
+    cell::{LazyCell, RefCell},
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
 use tokio::{sync::Notify, task::JoinHandle};
-use tracing::{error, warn};
+use tracing::{error, info, info_span, warn, Span};
 
 #[cfg(feature = "nvidia")]
 pub use nvidia::nvapi::NvApi;
@@ -35,7 +52,36 @@ pub use nvidia::nvapi::NvApi;
 use nvml_wrapper::Nvml;
 
 pub type DynGpuController = Box<dyn GpuController>;
-type FanControlHandle = (Rc<Notify>, JoinHandle<()>);
+
+struct FanControlHandle {
+    notify: Rc<Notify>,
+    task: JoinHandle<()>,
+    settings: FanControlSettings,
+    /// Read by the task on every evaluation, so that the curve can be changed without a restart
+    curve: Rc<RefCell<FanCurve>>,
+    /// Makes the task evaluate the curve right away instead of waiting for the next interval
+    reevaluate: Rc<Notify>,
+}
+
+impl FanControlHandle {
+    /// Replaces the curve used by the running task, which then applies it right away if it changed.
+    /// Returns `false` when the task has to be restarted to apply the other settings.
+    fn update_curve(&self, curve: &FanCurve, settings: &FanControlSettings) -> bool {
+        let mut running_settings = self.settings.clone();
+        running_settings.curve.clone_from(&settings.curve);
+
+        if self.task.is_finished() || running_settings != *settings {
+            return false;
+        }
+
+        let mut running_curve = self.curve.borrow_mut();
+        if *running_curve != *curve {
+            running_curve.clone_from(curve);
+            self.reevaluate.notify_one();
+        }
+        true
+    }
+}
 
 pub trait GpuController {
     fn controller_info(&self) -> &CommonControllerInfo;
@@ -46,14 +92,70 @@ pub trait GpuController {
 
     fn apply_config<'a>(&'a self, config: &'a GpuConfig) -> LocalBoxFuture<'a, anyhow::Result<()>>;
 
+    /// Whether every write done by [`GpuController::apply_config`] can be recorded in place of
+    /// being performed, see [`CommonControllerInfo::write_once`]
+    fn supports_dry_run(&self) -> bool {
+        false
+    }
+
+    /// Writes that were planned instead of being performed, only available in dry run mode
+    fn dry_run_log(&self) -> Option<Vec<SysfsWrite>> {
+        None
+    }
+
+    /// Compares the current device state against the given applied config,
+    /// returning every setting that no longer matches it
+    fn verify_config(&self, _config: &GpuConfig) -> Vec<ConfigDifference> {
+        vec![]
+    }
+
     fn get_stats(&self, gpu_config: Option<&GpuConfig>) -> DeviceStats;
 
     fn get_clocks_info(&self, gpu_config: Option<&GpuConfig>) -> anyhow::Result<ClocksInfo>;
 
     fn get_power_states(&self, gpu_config: Option<&GpuConfig>) -> PowerStates;
 
+    /// Settings that can be changed on the GPU and their allowed ranges
+    fn get_capabilities(&self) -> GpuCapabilities {
+        let clocks_info = self.get_clocks_info(None).ok();
+        common::base_capabilities(&self.get_stats(None), clocks_info.as_ref())
+    }
+
     fn reset_pmfw_settings(&self);
 
+    /// Versions of the driver and the firmware, the kernel version is filled in by the handler
+    fn get_versions(&self) -> VersionsInfo {
+        let info = self.controller_info();
+        VersionsInfo {
+            driver: info.driver.clone(),
+            driver_version: common::read_driver_version(&info.sysfs_path),
+            ..Default::default()
+        }
+    }
+
+    /// Reads the fan curve used by the firmware, converted into an editable software curve
+    fn get_firmware_fan_curve(&self) -> anyhow::Result<FanCurve> {
+        Err(ErrorKind::Unsupported.error("The GPU does not have a firmware fan curve"))
+    }
+
+    /// Checks the curve against the ranges allowed by the GPU, without applying it
+    fn validate_fan_curve(&self, curve: &FanCurve, unit: FanCurveUnit) -> FanCurveValidation {
+        fan_control::validate_curve(curve, unit, None)
+    }
+
+    /// Used by the fan curves of other GPUs which take this GPU's temperature into account
+    fn temperature_reader(&self) -> Option<TemperatureReader> {
+        None
+    }
+
+    /// Temperatures of the other GPUs, which can be referenced by the fan curve
+    fn set_temperature_sources(&self, _sources: TemperatureSources) {}
+
+    /// Checks which of the controls can be written to, without changing any settings
+    fn self_test(&self) -> anyhow::Result<Vec<SelfTestEntry>> {
+        Err(ErrorKind::Unsupported.error("The self-test is not supported on this GPU"))
+    }
+
     fn cleanup(&self) -> LocalBoxFuture<'_, ()> {
         async {}.boxed_local()
     }
@@ -73,6 +175,11 @@ pub(crate) struct CommonControllerInfo {
     pub pci_info: GpuPciInfo,
     pub pci_slot_name: String,
     pub driver: String,
+    pub write_retry: WriteRetry,
+    /// Evaluation interval of the fan curves which is used in place of the one in the fan settings
+    pub fan_control_interval: Option<Duration>,
+    /// Writes which were recorded instead of being performed, only set in dry run mode
+    pub dry_run_writes: Rc<RefCell<Option<Vec<SysfsWrite>>>>,
 }
 
 impl CommonControllerInfo {
@@ -92,6 +199,76 @@ impl CommonControllerInfo {
         )
     }
 
+    /// Span which the log messages about this GPU are recorded in.
+    /// Unlike the other spans it is on the info level, so that the GPU shows up with the default
+    /// log level.
+    pub fn span(&self) -> Span {
+        info_span!("gpu", id = %self.build_id())
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run_writes.borrow().is_some()
+    }
+
+    /// In dry run mode the writes only get recorded, and `true` is returned so that the caller
+    /// skips them. Repeating the last recorded write, like the fan control task does with the same
+    /// speed on every evaluation, doesn't add another entry.
+    ///
+    /// Writes should go through [`Self::write_once`], [`Self::write_with`] or [`Self::write_file`]
+    /// rather than checking this on their own.
+    fn record_dry_run_writes(&self, path: &Path, values: &[&str]) -> bool {
+        let mut dry_run_writes = self.dry_run_writes.borrow_mut();
+        let Some(writes) = dry_run_writes.as_mut() else {
+            return false;
+        };
+
+        for value in values {
+            let write = SysfsWrite {
+                path: path.display().to_string(),
+                value: (*value).to_owned(),
+            };
+            if writes.last() != Some(&write) {
+                info!("dry run: would write '{}' to {}", write.value, write.path);
+                writes.push(write);
+            }
+        }
+        true
+    }
+
+    /// Runs a write a single time, for writes which return a handle that has to be committed.
+    /// The path and values describe what it writes, in dry run mode `None` is returned.
+    pub fn write_once<T, E>(
+        &self,
+        path: &Path,
+        values: &[&str],
+        write: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Option<T>, E> {
+        if self.record_dry_run_writes(path, values) {
+            return Ok(None);
+        }
+        write().map(Some)
+    }
+
+    /// Runs a write with retries. The path and values describe what it writes, for dry run mode.
+    pub async fn write_with<T>(
+        &self,
+        path: &Path,
+        values: &[&str],
+        write: impl FnMut() -> anyhow::Result<T>,
+    ) -> anyhow::Result<Option<T>> {
+        if self.record_dry_run_writes(path, values) {
+            return Ok(None);
+        }
+        self.write_retry.run(write).await.map(Some)
+    }
+
+    pub async fn write_file(&self, path: &Path, value: &str) -> anyhow::Result<()> {
+        if self.record_dry_run_writes(path, &[value]) {
+            return Ok(());
+        }
+        self.write_retry.write(path, value).await
+    }
+
     pub fn get_slot_info(&self) -> anyhow::Result<PciSlotInfo> {
         let [domain, bus, dev, func] = self
             .pci_slot_name
@@ -136,13 +313,12 @@ pub type NvidiaLibs = ();
 pub(crate) fn init_controller(
     path: PathBuf,
     pci_db: &pciid_parser::Database,
-    nvml: &LazyCell<Option<NvidiaLibs>>,
+    nvml: &LazyCell<Option<NvidiaLibs>, impl FnOnce() -> Option<NvidiaLibs>>,
     amd_drm: &LazyCell<Option<LibDrmAmdgpu>>,
     intel_drm: &LazyCell<Option<Rc<IntelDrm>>>,
+    write_retry: WriteRetry,
+    fan_control_interval: Option<Duration>,
 ) -> anyhow::Result<Box<dyn GpuController>> {
-    #[cfg(not(feature = "nvidia"))]
-    let _ = nvml;
-
     let uevent_path = path.join("uevent");
     let uevent = fs::read_to_string(uevent_path).context("Could not read 'uevent'")?;
     let mut uevent_map = parse_uevent(&uevent);
@@ -209,8 +385,26 @@ pub(crate) fn init_controller(
         pci_info,
         pci_slot_name,
         driver,
+        write_retry,
+        fan_control_interval,
+        dry_run_writes: Rc::default(),
     };
 
+    let span = common.span();
+    let _guard = span.enter();
+    let controller = init_vendor_controller(common, nvml, amd_drm, intel_drm)?;
+    Ok(Box::new(SpannedController::new(controller, span.clone())))
+}
+
+fn init_vendor_controller(
+    common: CommonControllerInfo,
+    nvml: &LazyCell<Option<NvidiaLibs>, impl FnOnce() -> Option<NvidiaLibs>>,
+    amd_drm: &LazyCell<Option<LibDrmAmdgpu>>,
+    intel_drm: &LazyCell<Option<Rc<IntelDrm>>>,
+) -> anyhow::Result<DynGpuController> {
+    #[cfg(not(feature = "nvidia"))]
+    let _ = nvml;
+
     match common.driver.as_str() {
         "amdgpu" | "radeon" => {
             match AmdGpuController::new_from_path(common.clone(), amd_drm.as_ref()) {
@@ -258,6 +452,23 @@ pub(crate) fn init_controller(
     ))
 }
 
+/// Reads the PCI slot name (e.g. `0000:03:00.0`) of a device from its `uevent` file
+pub(crate) fn read_pci_slot_name(path: &Path) -> Option<String> {
+    let uevent = fs::read_to_string(path.join("uevent")).ok()?;
+    parse_uevent(&uevent)
+        .get("PCI_SLOT_NAME")
+        .map(|slot_name| (*slot_name).to_owned())
+}
+
+/// The part of the GPU id without the PCI slot
+pub(crate) fn read_pci_model_id(path: &Path) -> Option<String> {
+    let uevent = fs::read_to_string(path.join("uevent")).ok()?;
+    let uevent = parse_uevent(&uevent);
+    let device_id = uevent.get("PCI_ID")?;
+    let subsystem_id = uevent.get("PCI_SUBSYS_ID")?;
+    Some(format!("{device_id}-{subsystem_id}").to_ascii_uppercase())
+}
+
 fn parse_uevent(data: &str) -> HashMap<&str, &str> {
     data.lines()
         .filter_map(|line| line.split_once('='))
@@ -1,11 +1,27 @@
+mod firmware;
+mod gpu_metrics;
+mod self_test;
+mod vf_curve;
+
 use super::{CommonControllerInfo, FanControlHandle, GpuController, VENDOR_AMD};
-use crate::server::{
-    gpu_controller::common::{
-        fan_control::FanCurveExt,
-        fdinfo::{self, DrmUtilMap},
+use crate::{
+    server::{
+        gpu_controller::common::{
+            self,
+            displays::read_displays,
+            fan_control::{
+                evaluation_ticker, from_pmfw_curve, validate_curve, validate_pwm_limits,
+                with_external_temperatures, FanCurveExt, TemperatureReader, TemperatureSources,
+            },
+            fdinfo::{self, DrmUtilMap},
+            leds::{check_led_settings, led_state, led_writes, read_leds},
+            resizable_bar::read_resizable_bar,
+            thermal_limits::read_thermal_limits,
+        },
+        opencl::get_opencl_info,
+        vulkan::get_vulkan_info,
     },
-    opencl::get_opencl_info,
-    vulkan::get_vulkan_info,
+    AMDGPU_FAMILY_GC_11_0_0,
 };
 use amdgpu_sysfs::{
     error::Error,
@@ -21,24 +37,29 @@ use amdgpu_sysfs::{
 use anyhow::{anyhow, Context};
 use futures::{future::LocalBoxFuture, FutureExt};
 use lact_schema::{
-    config::{ClocksConfiguration, FanControlSettings, FanCurve, GpuConfig},
-    ClocksInfo, ClockspeedStats, DeviceInfo, DeviceStats, DeviceType, DrmInfo, FanStats,
-    IntelDrmInfo, LinkInfo, PmfwInfo, PowerState, PowerStates, PowerStats, ProcessList,
-    ProcessUtilizationType, RopInfo, VoltageStats, VramStats,
+    config::{
+        ClocksConfiguration, FanControlSettings, FanCurve, FanCurveUnit, GpuConfig, LedSettings,
+    },
+    ClocksInfo, ClockspeedStats, ConfigDifference, DeviceInfo, DeviceStats, DeviceType, DrmInfo,
+    ErrorKind, FanCurveValidation, FanStats, GpuCapabilities, GpuMetricsStats, HardwareFanMode,
+    IntelDrmInfo, LinkInfo, PmfwInfo, PowerCap, PowerState, PowerStates, PowerStats, ProcessList,
+    ProcessUtilizationType, RopInfo, SelfTestEntry, TemperatureUnit, ThrottleReason, ValueRange,
+    VbiosInfo, VersionsInfo, VfPoint, VoltageStats, VramStats,
 };
 use libdrm_amdgpu_sys::AMDGPU::{GpuMetrics, ThrottlerBit};
 use libdrm_amdgpu_sys::{LibDrmAmdgpu, AMDGPU::SENSOR_INFO::SENSOR_TYPE, PCI};
 use std::{
-    cell::RefCell,
+    borrow::Cow,
+    cell::{Cell, RefCell},
     cmp,
-    collections::{HashMap, HashSet, VecDeque},
-    path::PathBuf,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
     rc::Rc,
     time::Duration,
 };
-use std::{collections::BTreeMap, fs, time::Instant};
-use tokio::{select, sync::Notify, time::sleep};
-use tracing::{debug, error, info, trace, warn};
+use std::{collections::BTreeMap, fmt, fs, time::Instant};
+use tokio::{select, sync::Notify};
+use tracing::{debug, error, info, trace, warn, Instrument};
 
 use {
     lact_schema::DrmMemoryInfo,
@@ -46,6 +67,9 @@ use {
 };
 
 const FAN_CONTROL_RETRIES: u32 = 10;
+/// Values of `pwm1_enable` for the fan control methods, for describing the writes in dry run mode
+const PWM_ENABLE_MANUAL: &str = "1";
+const PWM_ENABLE_AUTO: &str = "2";
 const MAX_PSTATE_READ_ATTEMPTS: u32 = 5;
 const STEAM_DECK_IDS: [&str; 2] = ["163F", "1435"];
 const AMDGPU_IDS_FLAGS_FUSION: u64 = 0x1;
@@ -62,9 +86,17 @@ const DRM_ENGINES: &[(&str, ProcessUtilizationType)] = &[
 pub struct AmdGpuController {
     handle: GpuHandle,
     drm_handle: Option<DrmHandle>,
+    family_id: Option<u32>,
     common: CommonControllerInfo,
     fan_control_handle: RefCell<Option<FanControlHandle>>,
     last_drm_util: RefCell<Option<DrmUtilMap>>,
+    temperature_sources: RefCell<TemperatureSources>,
+    /// DPM levels forced by LACT, so only those get re-enabled once the level is removed
+    forced_levels: RefCell<HashMap<PowerLevelKind, u8>>,
+    /// State of the LEDs from before LACT changed them, restored once their settings are removed
+    original_leds: RefCell<HashMap<String, LedSettings>>,
+    /// Maximum memory clock of the clocks table after it was reset, which memory clock offsets are relative to
+    stock_max_mclk: Cell<Option<i32>>,
 }
 
 impl AmdGpuController {
@@ -88,15 +120,41 @@ impl AmdGpuController {
             }
         }
 
+        let family_id = drm_handle.as_ref().and_then(|drm_handle| {
+            let drm_info = drm_handle.device_info().ok()?;
+            Some(drm_info.family_id())
+        });
+
         Ok(Self {
             handle,
             drm_handle,
+            family_id,
             common,
             fan_control_handle: RefCell::new(None),
             last_drm_util: RefCell::new(None),
+            temperature_sources: RefCell::default(),
+            forced_levels: RefCell::default(),
+            original_leds: RefCell::default(),
+            stock_max_mclk: Cell::new(None),
         })
     }
 
+    /// Until the clocks table is reset when applying, the current table is used as the stock one
+    fn stock_max_mclk(&self, table: &ClocksTableGen) -> Option<i32> {
+        self.stock_max_mclk.get().or_else(|| table.get_max_mclk())
+    }
+
+    /// Only known once the clocks table was reset by applying the settings
+    fn mem_clock_offset(&self, table: &ClocksTableGen) -> Option<i32> {
+        Some(table.get_max_mclk()? - self.stock_max_mclk.get()?)
+    }
+
+    /// Contents of `pp_od_clk_voltage`, which both the clocks table and the VF curve are parsed from
+    fn read_od_table(&self) -> Option<String> {
+        let path = self.handle.get_path().join("pp_od_clk_voltage");
+        fs::read_to_string(path).ok()
+    }
+
     fn hw_mon_and_then<U>(&self, f: fn(&HwMon) -> Result<U, Error>) -> Option<U> {
         self.handle.hw_monitors.first().and_then(|mon| f(mon).ok())
     }
@@ -105,6 +163,78 @@ impl AmdGpuController {
         self.handle.hw_monitors.first().map(f)
     }
 
+    /// Reads the `power1_cap*` files of the first monitor, the ones which are missing are left out
+    fn read_power_cap(&self) -> PowerCap {
+        PowerCap {
+            current: self.hw_mon_and_then(HwMon::get_power_cap),
+            max: self.hw_mon_and_then(HwMon::get_power_cap_max),
+            min: self.hw_mon_and_then(HwMon::get_power_cap_min),
+            default: self.hw_mon_and_then(HwMon::get_power_cap_default),
+        }
+    }
+
+    /// Reads the mode from the hardware, as it may have been changed by something other than LACT
+    fn read_hardware_fan_mode(&self) -> Option<HardwareFanMode> {
+        let method = self.hw_mon_and_then(HwMon::get_fan_control_method)?;
+        Some(match method {
+            FanControlMethod::None => HardwareFanMode::Full,
+            FanControlMethod::Manual => HardwareFanMode::Manual,
+            FanControlMethod::Auto => HardwareFanMode::Auto,
+        })
+    }
+
+    /// The firmware fan control interface (`gpu_od/fan_ctrl`) is only present on RDNA3 and newer.
+    /// When the family is not known, such as without a DRM handle, the interface is probed instead.
+    fn is_pmfw_supported(&self) -> bool {
+        match self.family_id {
+            Some(family) => family >= AMDGPU_FAMILY_GC_11_0_0,
+            None => self.handle.get_path().join("gpu_od/fan_ctrl").exists(),
+        }
+    }
+
+    /// Fan control is detected separately from speed reporting,
+    /// as some fans only have a tachometer and others only have PWM control
+    fn is_fan_control_supported(&self) -> bool {
+        self.handle.get_fan_curve().is_ok()
+            || self.handle.hw_monitors.first().is_some_and(|hw_mon| {
+                let path = hw_mon.get_path();
+                path.join("pwm1").exists() && path.join("pwm1_enable").exists()
+            })
+    }
+
+    async fn set_manual_fan_control(&self, hw_mon: &HwMon) -> anyhow::Result<()> {
+        let path = hw_mon.get_path().join("pwm1_enable");
+        self.common
+            .write_with(&path, &[PWM_ENABLE_MANUAL], || {
+                hw_mon
+                    .set_fan_control_method(FanControlMethod::Manual)
+                    .context("Could not set fan control method")
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// The firmware fan settings are written to their file in `gpu_od/fan_ctrl` and committed
+    /// afterwards. In dry run mode the writes only get recorded, and `None` is returned.
+    fn write_pmfw<T, E>(
+        &self,
+        file_name: &str,
+        values: &[String],
+        write: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Option<T>, E> {
+        let path = self
+            .handle
+            .get_path()
+            .join("gpu_od/fan_ctrl")
+            .join(file_name);
+        let values = values
+            .iter()
+            .map(String::as_str)
+            .chain(["c"])
+            .collect::<Vec<_>>();
+        self.common.write_once(&path, &values, write)
+    }
+
     async fn set_static_fan_control(&self, static_speed: f32) -> anyhow::Result<Vec<CommitHandle>> {
         // Stop existing task to set static speed
         self.stop_fan_control(false).await?;
@@ -114,9 +244,11 @@ impl AmdGpuController {
         // Use PMFW curve functionality for static speed when it is available
         if let Ok(current_curve) = self.handle.get_fan_curve() {
             if let Ok(true) = self.handle.get_fan_zero_rpm_enable() {
-                match self.handle.set_fan_zero_rpm_enable(false) {
+                match self.write_pmfw("fan_zero_rpm_enable", &["0".to_owned()], || {
+                    self.handle.set_fan_zero_rpm_enable(false)
+                }) {
                     Ok(zero_rpm_commit) => {
-                        commit_handles.push(zero_rpm_commit);
+                        commit_handles.extend(zero_rpm_commit);
                     }
                     Err(err) => {
                         error!("could not disable zero RPM mode for static fan control: {err}");
@@ -147,10 +279,11 @@ impl AmdGpuController {
             debug!("setting static curve {new_curve:?}");
 
             let curve_commit = self
-                .handle
-                .set_fan_curve(&new_curve)
+                .write_pmfw("fan_curve", &pmfw_curve_values(&new_curve), || {
+                    self.handle.set_fan_curve(&new_curve)
+                })
                 .context("Could not set fan curve")?;
-            commit_handles.push(curve_commit);
+            commit_handles.extend(curve_commit);
 
             Ok(commit_handles)
         } else {
@@ -161,16 +294,22 @@ impl AmdGpuController {
                 .cloned()
                 .context("This GPU has no monitor")?;
 
-            hw_mon
-                .set_fan_control_method(FanControlMethod::Manual)
-                .context("Could not set fan control method")?;
+            self.set_manual_fan_control(&hw_mon).await?;
 
             #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
             let static_pwm = (f32::from(u8::MAX) * static_speed) as u8;
 
-            hw_mon
-                .set_fan_pwm(static_pwm)
-                .context("could not set fan speed")?;
+            self.common
+                .write_with(
+                    &hw_mon.get_path().join("pwm1"),
+                    &[&static_pwm.to_string()],
+                    || {
+                        hw_mon
+                            .set_fan_pwm(static_pwm)
+                            .context("could not set fan speed")
+                    },
+                )
+                .await?;
 
             debug!("set fan speed to {}", static_speed);
 
@@ -183,9 +322,27 @@ impl AmdGpuController {
         curve: FanCurve,
         settings: FanControlSettings,
     ) -> anyhow::Result<Option<CommitHandle>> {
+        curve
+            .validate_with_unit(settings.curve_unit)
+            .context("Invalid fan curve")?;
+        validate_pwm_limits(&settings)?;
+
         // Use the PMFW curve functionality when it is available
         // Otherwise, fall back to manual fan control via a task
         if let Ok(current_curve) = self.handle.get_fan_curve() {
+            if !settings.external_temperatures.is_empty() {
+                warn!("the firmware fan curve does not use the temperatures of other GPUs");
+            }
+            if settings.temperature_key != "edge" {
+                warn!(
+                    "the firmware fan curve follows the edge temperature, '{}' is not used",
+                    settings.temperature_key
+                );
+            }
+            let curve = match settings.curve_unit {
+                FanCurveUnit::Percentage => curve,
+                FanCurveUnit::Pwm => curve.to_percentage_curve(),
+            };
             let new_curve = curve
                 .into_pmfw_curve(current_curve.clone())
                 .context("Invalid fan curve")?;
@@ -193,11 +350,12 @@ impl AmdGpuController {
             debug!("setting pmfw curve {new_curve:?}");
 
             let commit_handle = self
-                .handle
-                .set_fan_curve(&new_curve)
+                .write_pmfw("fan_curve", &pmfw_curve_values(&new_curve), || {
+                    self.handle.set_fan_curve(&new_curve)
+                })
                 .context("Could not set fan curve")?;
 
-            Ok(Some(commit_handle))
+            Ok(commit_handle)
         } else {
             self.start_curve_fan_control_task(curve, settings).await?;
             Ok(None)
@@ -209,9 +367,6 @@ impl AmdGpuController {
         curve: FanCurve,
         settings: FanControlSettings,
     ) -> anyhow::Result<()> {
-        // Stop existing task to re-apply new curve
-        self.stop_fan_control(false).await?;
-
         let hw_mon = self
             .handle
             .hw_monitors
@@ -219,6 +374,20 @@ impl AmdGpuController {
             .cloned()
             .context("This GPU has no monitor")?;
 
+        let curve_updated = self
+            .fan_control_handle
+            .borrow()
+            .as_ref()
+            .is_some_and(|running| running.update_curve(&curve, &settings));
+        if curve_updated {
+            debug!("updated the curve of the running fan control task");
+            self.set_manual_fan_control(&hw_mon).await?;
+            return Ok(());
+        }
+
+        // Stop existing task to re-apply new settings
+        self.stop_fan_control(false).await?;
+
         let temps = hw_mon.get_temps();
         match temps.len() {
             0 => return Err(anyhow!("GPU has no temperature reporting")),
@@ -240,9 +409,7 @@ impl AmdGpuController {
             }
         }
 
-        hw_mon
-            .set_fan_control_method(FanControlMethod::Manual)
-            .context("Could not set fan control method")?;
+        self.set_manual_fan_control(&hw_mon).await?;
 
         let mut notify_guard = self
             .fan_control_handle
@@ -251,17 +418,43 @@ impl AmdGpuController {
 
         let notify = Rc::new(Notify::new());
         let task_notify = notify.clone();
+        let reevaluate = Rc::new(Notify::new());
+        let task_reevaluate = reevaluate.clone();
+        let curve = Rc::new(RefCell::new(curve));
+        let task_curve = curve.clone();
+        let running_settings = settings.clone();
+
+        let temperature_sources = self.temperature_sources.borrow().clone();
+        for external in &settings.external_temperatures {
+            if !temperature_sources.contains_key(&external.gpu_id) {
+                warn!(
+                    "GPU {} referenced by the fan curve was not found, skipping its temperature",
+                    external.gpu_id
+                );
+            }
+        }
 
         debug!("spawning new fan control task");
-        let handle = tokio::task::spawn_local(async move {
+        let interval = self
+            .common
+            .fan_control_interval
+            .unwrap_or(Duration::from_millis(settings.interval_ms));
+        let common = self.common.clone();
+        let pwm_path = hw_mon.get_path().join("pwm1");
+        let pwm_enable_path = hw_mon.get_path().join("pwm1_enable");
+        let fan_task = async move {
             let mut last_pwm = (None, Instant::now());
             let mut last_temp = 0.0;
+            // The fan is being slowed down in steps, which continues even if the temperature
+            // doesn't change anymore
+            let mut spindown_pending = false;
 
             // If the fan speed could was able to be set at least once
             let mut control_available = false;
 
             let temp_key = settings.temperature_key.clone();
-            let interval = Duration::from_millis(settings.interval_ms);
+            let curve_unit = settings.curve_unit;
+            let mut ticker = evaluation_ticker(interval);
             let spindown_delay = Duration::from_millis(settings.spindown_delay_ms.unwrap_or(0));
             #[allow(clippy::cast_precision_loss)]
             let change_threshold = settings.change_threshold.unwrap_or(0) as f32;
@@ -269,9 +462,16 @@ impl AmdGpuController {
             let mut retries = 0;
 
             loop {
-                select! {
-                    () = sleep(interval) => (),
+                // The interval starts over after a curve change gets applied, so the next tick
+                // doesn't write the same value again right after it.
+                // Multiple changes before the task wakes up are only evaluated once.
+                let reevaluating = select! {
+                    _ = ticker.tick() => false,
+                    () = task_reevaluate.notified() => true,
                     () = task_notify.notified() => break,
+                };
+                if reevaluating {
+                    ticker.reset();
                 }
 
                 let mut temps = hw_mon.get_temps();
@@ -289,20 +489,30 @@ impl AmdGpuController {
                     error!("could not get temperature sensor {temp_key} from {} sensors (assuming error is temporary, attempt {retries}/{FAN_CONTROL_RETRIES})", temps.len());
                     continue;
                 };
+                let temp = with_external_temperatures(
+                    temp,
+                    &settings.external_temperatures,
+                    &temperature_sources,
+                );
 
                 let current_temp = temp.current.expect("Missing temp");
 
-                if (last_temp - current_temp).abs() < change_threshold {
+                if !reevaluating
+                    && !spindown_pending
+                    && (last_temp - current_temp).abs() < change_threshold
+                {
                     trace!("temperature changed from {last_temp}°C to {current_temp}°C, which is less than the {change_threshold}°C threshold, skipping speed adjustment");
                     continue;
                 }
 
-                let target_pwm = curve.pwm_at_temp(temp);
+                let curve_pwm = settings.clamp_pwm(settings.apply_curve_offset(
+                    task_curve.borrow().pwm_at_temp_with_unit(temp, curve_unit),
+                ));
                 let now = Instant::now();
 
                 if let (Some(previous_pwm), previous_timestamp) = last_pwm {
                     let diff = now - previous_timestamp;
-                    if target_pwm < previous_pwm && diff < spindown_delay {
+                    if curve_pwm < previous_pwm && !spindown_pending && diff < spindown_delay {
                         trace!(
                             "delaying fan spindown ({}ms left)",
                             (spindown_delay - diff).as_millis()
@@ -311,13 +521,21 @@ impl AmdGpuController {
                     }
                 }
 
+                let target_pwm = last_pwm.0.map_or(curve_pwm, |previous_pwm| {
+                    settings.limit_spindown(previous_pwm, curve_pwm)
+                });
+                spindown_pending = target_pwm != curve_pwm;
+
                 last_pwm = (Some(target_pwm), now);
                 last_temp = current_temp;
 
                 trace!("fan control tick: setting pwm to {target_pwm}");
 
-                match hw_mon.set_fan_pwm(target_pwm) {
-                    Ok(()) => control_available = true,
+                let result = common.write_once(&pwm_path, &[&target_pwm.to_string()], || {
+                    hw_mon.set_fan_pwm(target_pwm)
+                });
+                match result {
+                    Ok(_) => control_available = true,
                     Err(err) => {
                         error!("could not set fan speed: {err}");
                         if control_available {
@@ -335,9 +553,11 @@ impl AmdGpuController {
                                 Ok(FanControlMethod::Manual),
                             ) {
                                 info!("fan control method was changed externally, setting back to manual");
-                                if let Err(err) =
-                                    hw_mon.set_fan_control_method(FanControlMethod::Manual)
-                                {
+                                if let Err(err) = common.write_once(
+                                    &pwm_enable_path,
+                                    &[PWM_ENABLE_MANUAL],
+                                    || hw_mon.set_fan_control_method(FanControlMethod::Manual),
+                                ) {
                                     error!("could not set fan control back to manual: {err}");
                                     break;
                                 }
@@ -352,16 +572,26 @@ impl AmdGpuController {
             }
             debug!("exited fan control task");
 
-            if let Err(err) = hw_mon.set_fan_control_method(FanControlMethod::Auto) {
+            if let Err(err) = common.write_once(&pwm_enable_path, &[PWM_ENABLE_AUTO], || {
+                hw_mon.set_fan_control_method(FanControlMethod::Auto)
+            }) {
                 error!("could not reset fan control back to auto: {err}");
             }
+        };
+        // The task outlives the call which started it, so it doesn't inherit the GPU span
+        let task = tokio::task::spawn_local(fan_task.instrument(self.common.span()));
+
+        *notify_guard = Some(FanControlHandle {
+            notify,
+            task,
+            settings: running_settings,
+            curve,
+            reevaluate,
         });
 
-        *notify_guard = Some((notify, handle));
-
         debug!(
             "started fan control with interval {}ms",
-            settings.interval_ms
+            interval.as_millis()
         );
 
         Ok(())
@@ -373,23 +603,28 @@ impl AmdGpuController {
             .try_borrow_mut()
             .map_err(|err| anyhow!("Lock error: {err}"))?
             .take();
-        if let Some((notify, handle)) = maybe_notify {
+        if let Some(FanControlHandle { notify, task, .. }) = maybe_notify {
             notify.notify_one();
-            handle.await?;
+            task.await?;
         }
 
         if reset_mode {
             if self.handle.get_fan_curve().is_ok() {
-                if let Err(err) = self.handle.reset_fan_curve() {
+                if let Err(err) = self.write_pmfw("fan_curve", &["r".to_owned()], || {
+                    self.handle.reset_fan_curve()
+                }) {
                     warn!("could not reset fan curve: {err:#}");
                 }
             }
 
             if let Some(hw_mon) = self.handle.hw_monitors.first().cloned() {
                 if let Ok(current_control) = hw_mon.get_fan_control_method() {
+                    let path = hw_mon.get_path().join("pwm1_enable");
                     if !matches!(current_control, FanControlMethod::Auto) {
-                        hw_mon
-                            .set_fan_control_method(FanControlMethod::Auto)
+                        self.common
+                            .write_once(&path, &[PWM_ENABLE_AUTO], || {
+                                hw_mon.set_fan_control_method(FanControlMethod::Auto)
+                            })
                             .context("Could not set fan control back to automatic")?;
                     }
                 }
@@ -445,7 +680,11 @@ impl AmdGpuController {
             .context("GPU has no hardware monitor")
     }
 
-    fn get_clockspeed(&self) -> ClockspeedStats {
+    fn get_clockspeed(
+        &self,
+        metrics_table: Option<&GpuMetricsStats>,
+        clocks_table: Option<&ClocksTableGen>,
+    ) -> ClockspeedStats {
         let vram_clockspeed = self
             .drm_handle
             .as_ref()
@@ -455,12 +694,21 @@ impl AmdGpuController {
 
         ClockspeedStats {
             gpu_clockspeed: self.hw_mon_and_then(HwMon::get_gpu_clockspeed),
-            current_gfxclk: self.get_current_gfxclk(),
+            current_gfxclk: self.get_current_gfxclk(metrics_table),
             vram_clockspeed,
+            vram_clock_offset: clocks_table.and_then(|table| self.mem_clock_offset(table)),
         }
     }
 
-    fn get_current_gfxclk(&self) -> Option<u64> {
+    fn get_current_gfxclk(&self, metrics_table: Option<&GpuMetricsStats>) -> Option<u64> {
+        if let Some(table) = metrics_table {
+            // Multi-die tables only have the clock of each die
+            return table
+                .current_gfxclk
+                .or_else(|| table.dies.values().find_map(|die| die.current_gfxclk))
+                .map(u64::from);
+        }
+
         self.drm_handle
             .as_ref()
             .and_then(|drm_handle| drm_handle.get_gpu_metrics().ok())
@@ -478,6 +726,31 @@ impl AmdGpuController {
         self.handle.get_vbios_version().ok()
     }
 
+    fn get_vbios_info(&self) -> Option<VbiosInfo> {
+        let part_number = self.handle.get_vbios_version().ok();
+        let drm_vbios_info = self
+            .drm_handle
+            .as_ref()
+            .and_then(|drm_handle| drm_handle.get_vbios_info().ok());
+
+        let (version, build_date) = match drm_vbios_info {
+            Some(info) => (
+                info.ver,
+                Some(info.date).filter(|date| !date.trim().is_empty()),
+            ),
+            None => (part_number.clone()?, None),
+        };
+
+        let subsystem = &self.common.pci_info.subsystem_pci_info;
+        Some(VbiosInfo {
+            version,
+            part_number,
+            build_date,
+            subsystem_vendor_id: subsystem.vendor_id.clone(),
+            subsystem_device_id: subsystem.model_id.clone(),
+        })
+    }
+
     fn get_drm_info(&self) -> Option<DrmInfo> {
         use libdrm_amdgpu_sys::AMDGPU::VRAM_TYPE;
 
@@ -534,6 +807,175 @@ impl AmdGpuController {
         }
     }
 
+    /// Includes the profiling levels, which are not a part of `PerformanceLevel`
+    fn read_performance_level(&self) -> Option<String> {
+        let path = self
+            .handle
+            .get_path()
+            .join("power_dpm_force_performance_level");
+        let contents = fs::read_to_string(path).ok()?;
+        Some(contents.trim().to_owned())
+    }
+
+    /// The kernel does not list the accepted levels, but `radeon` has no manual level,
+    /// and with `amdgpu` it only does something when there are DPM levels to force
+    fn read_performance_levels(&self) -> Vec<PerformanceLevel> {
+        let mut levels = vec![
+            PerformanceLevel::Auto,
+            PerformanceLevel::Low,
+            PerformanceLevel::High,
+        ];
+        if self.common.driver == "amdgpu" && self.handle.get_path().join("pp_dpm_sclk").exists() {
+            levels.push(PerformanceLevel::Manual);
+        }
+        levels
+    }
+
+    /// Reported by the RAS interface of GPUs with ECC memory
+    fn get_ecc_errors(&self) -> Option<u64> {
+        let path = self.handle.get_path().join("ras/umc_err_count");
+        let contents = fs::read_to_string(path).ok()?;
+        parse_corrected_errors(&contents)
+    }
+
+    fn get_pcie_levels(&self) -> anyhow::Result<Vec<String>> {
+        let path = self.handle.get_path().join("pp_dpm_pcie");
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        parse_pcie_levels(&contents)
+    }
+
+    /// Forces the given memory clock DPM level, or re-enables all of them when `None`
+    async fn set_memory_clock_level(&self, level: Option<u8>) -> anyhow::Result<()> {
+        let levels = self
+            .handle
+            .get_clock_levels(PowerLevelKind::MemoryClock)
+            .context("Could not read memory clock levels")?
+            .levels;
+
+        if let Some(level) = level {
+            if usize::from(level) >= levels.len() {
+                let available = levels
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| format!("{i}: {value}MHz"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(anyhow!(
+                    "Memory clock level {level} is not available, supported levels: {available}"
+                ));
+            }
+        }
+
+        self.set_forced_level(PowerLevelKind::MemoryClock, level, levels.len())
+            .await
+    }
+
+    /// Forces the given PCIe DPM level, or re-enables all of them when `None`
+    async fn set_pcie_level(&self, level: Option<u8>) -> anyhow::Result<()> {
+        let levels = self.get_pcie_levels()?;
+
+        if let Some(level) = level {
+            if usize::from(level) >= levels.len() {
+                let available = levels
+                    .iter()
+                    .enumerate()
+                    .map(|(i, level)| format!("{i}: {level}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(anyhow!(
+                    "PCIe level {level} is not available, supported levels: {available}"
+                ));
+            }
+        }
+
+        self.set_forced_level(PowerLevelKind::PcieSpeed, level, levels.len())
+            .await
+    }
+
+    /// Forces a single DPM level through the power levels API.
+    /// Clearing the level only re-enables all levels if LACT was the one forcing it.
+    async fn set_forced_level(
+        &self,
+        kind: PowerLevelKind,
+        level: Option<u8>,
+        level_count: usize,
+    ) -> anyhow::Result<()> {
+        let levels: Vec<u8> = match level {
+            Some(level) => vec![level],
+            None if self.forced_levels.borrow().contains_key(&kind) => (0..level_count)
+                .filter_map(|i| u8::try_from(i).ok())
+                .collect(),
+            None => return Ok(()),
+        };
+
+        self.common
+            .write_with(
+                &power_levels_path(self.handle.get_path(), kind),
+                &[&join_levels(&levels)],
+                || {
+                    self.handle
+                        .set_enabled_power_levels(kind, &levels)
+                        .with_context(|| format!("Could not set {kind:?} power levels"))
+                },
+            )
+            .await?;
+
+        let mut forced_levels = self.forced_levels.borrow_mut();
+        match level {
+            Some(level) => forced_levels.insert(kind, level),
+            None => forced_levels.remove(&kind),
+        };
+        Ok(())
+    }
+
+    /// The level forced by LACT, as long as the hardware is actually running at it
+    fn active_forced_level(&self, kind: PowerLevelKind, active: Option<usize>) -> Option<u8> {
+        self.forced_levels
+            .borrow()
+            .get(&kind)
+            .copied()
+            .filter(|level| active == Some(usize::from(*level)))
+    }
+
+    async fn apply_leds(&self, config: &GpuConfig) -> anyhow::Result<()> {
+        let device_path = self.handle.get_path();
+        let leds = read_leds(device_path);
+        for (name, settings) in &config.leds {
+            check_led_settings(&leds, name, settings)?;
+        }
+
+        for (name, settings) in &config.leds {
+            if let Some(led) = leds.iter().find(|led| led.name == *name) {
+                self.original_leds
+                    .borrow_mut()
+                    .entry(name.clone())
+                    .or_insert_with(|| led_state(led));
+            }
+
+            for (path, value) in led_writes(device_path, name, settings) {
+                self.common.write_file(&path, &value).await?;
+            }
+        }
+
+        let removed: Vec<(String, LedSettings)> = self
+            .original_leds
+            .borrow()
+            .iter()
+            .filter(|(name, _)| !config.leds.contains_key(*name))
+            .map(|(name, settings)| (name.clone(), settings.clone()))
+            .collect();
+        for (name, original) in removed {
+            debug!("restoring the original state of LED `{name}`");
+            for (path, value) in led_writes(device_path, &name, &original) {
+                self.common.write_file(&path, &value).await?;
+            }
+            self.original_leds.borrow_mut().remove(&name);
+        }
+
+        Ok(())
+    }
+
     fn get_link_info(&self) -> LinkInfo {
         #[cfg(not(test))]
         let gpu_pcie_port_bus = self
@@ -574,6 +1016,7 @@ impl AmdGpuController {
         }
     }
 
+    /// The throttler bits are ASIC specific, so these are still decoded by libdrm
     fn get_throttle_info(&self) -> Option<BTreeMap<String, Vec<String>>> {
         use libdrm_amdgpu_sys::AMDGPU::ThrottlerType;
 
@@ -625,154 +1068,763 @@ impl AmdGpuController {
         None
     }
 
-    fn is_steam_deck(&self) -> bool {
-        self.common.pci_info.device_pci_info.vendor_id == VENDOR_AMD
-            && STEAM_DECK_IDS.contains(&self.common.pci_info.device_pci_info.model_id.as_str())
-    }
-}
+    /// Sets the performance level and power profile mode
+    async fn apply_performance_level(&self, config: &GpuConfig) -> anyhow::Result<()> {
+        // Checking the raw value, as the profiling levels can't be parsed but can still be changed
+        if self.read_performance_level().is_some() {
+            let performance_level = config.performance_level.unwrap_or(PerformanceLevel::Auto);
+
+            self.common
+                .write_with(
+                    &self.performance_level_path(),
+                    &[&performance_level.to_string()],
+                    || {
+                        self.handle
+                            .set_power_force_performance_level(performance_level)
+                            .context("Failed to set power performance level")
+                    },
+                )
+                .await?;
+        } else {
+            error!("could not get current performance level");
+        }
 
-impl GpuController for AmdGpuController {
-    fn controller_info(&self) -> &CommonControllerInfo {
-        &self.common
+        if let Some(mode_index) = config.power_profile_mode_index {
+            if config.performance_level != Some(PerformanceLevel::Manual) {
+                return Err(anyhow!(
+                    "Performance level has to be set to `manual` to use power profile modes"
+                ));
+            }
+
+            let modes_table = self
+                .handle
+                .get_power_profile_modes()
+                .context("Failed to read power profile modes")?;
+            if !modes_table.modes.contains_key(&mode_index) {
+                let available = modes_table
+                    .modes
+                    .iter()
+                    .map(|(index, mode)| format!("{index} ({})", mode.name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(anyhow!(
+                    "Power profile mode {mode_index} is not available, supported modes: {available}"
+                ));
+            }
+
+            let mode_path = self.handle.get_path().join("pp_power_profile_mode");
+            if config.custom_power_profile_mode_hueristics.is_empty() {
+                self.common
+                    .write_once(&mode_path, &[&mode_index.to_string()], || {
+                        self.handle.set_active_power_profile_mode(mode_index)
+                    })
+                    .context("Failed to set active power profile mode")?;
+            } else {
+                check_custom_heuristics(
+                    &modes_table,
+                    &config.custom_power_profile_mode_hueristics,
+                )?;
+                let heuristics = format!(
+                    "{mode_index} {:?}",
+                    config.custom_power_profile_mode_hueristics
+                );
+                self.common
+                    .write_once(&mode_path, &[&heuristics], || {
+                        self.handle.set_custom_power_profile_mode_heuristics(
+                            &config.custom_power_profile_mode_hueristics,
+                        )
+                    })
+                    .context("Failed to set custom power profile mode heuristics")?;
+            }
+        }
+
+        Ok(())
     }
 
-    fn device_type(&self) -> DeviceType {
-        self.drm_handle
-            .as_ref()
-            .and_then(|drm| drm.device_info().ok())
-            .map_or(DeviceType::Dedicated, |info| {
-                if (info.ids_flags & AMDGPU_IDS_FLAGS_FUSION) > 0 {
-                    DeviceType::Integrated
-                } else {
-                    DeviceType::Dedicated
+    /// Writes the clocks table, which also contains the voltage settings.
+    /// The table only gets committed in the commit step, after the other settings were written.
+    async fn apply_clocks(
+        &self,
+        config: &GpuConfig,
+        commit_clocks_table: &mut bool,
+    ) -> anyhow::Result<()> {
+        let table_path = self.handle.get_path().join("pp_od_clk_voltage");
+
+        if self.is_steam_deck() {
+            // Van Gogh/Sephiroth only allow clock settings to be used with manual performance mode
+            self.common
+                .write_once(
+                    &self.performance_level_path(),
+                    &[&PerformanceLevel::Manual.to_string()],
+                    || {
+                        self.handle
+                            .set_power_force_performance_level(PerformanceLevel::Manual)
+                    },
+                )
+                .ok();
+        }
+
+        // Reset the clocks table in case the settings get reverted back to not having a clocks value configured
+        if table_path.exists() {
+            self.common
+                .write_once(&table_path, &["r"], || self.handle.reset_clocks_table())
+                .ok();
+        }
+
+        let original_table = self.handle.get_clocks_table();
+        if let Ok(table) = &original_table {
+            if !self.common.is_dry_run() {
+                self.stock_max_mclk.set(table.get_max_mclk());
+            }
+        }
+
+        if config.is_core_clocks_used() {
+            match original_table {
+                Ok(original_table) => {
+                    let mut table = original_table.clone();
+                    apply_clocks_config_to_table(
+                        &config.clocks_configuration,
+                        self.stock_max_mclk(&original_table),
+                        &mut table,
+                    )
+                    .context("Failed to apply clocks configuration to table")?;
+
+                    let commands = table
+                        .get_commands(&original_table)
+                        .context("Failed to get table commands")?;
+                    debug!("writing clocks commands: {commands:#?}");
+
+                    let command_values = commands.iter().map(String::as_str).collect::<Vec<_>>();
+                    let _commit_handle = self
+                        .common
+                        .write_with(&table_path, &command_values, || {
+                            self.handle
+                                .set_clocks_table(&table)
+                                .context("Could not write clocks table")
+                        })
+                        .await
+                        .with_context(|| format!("Clocks table commands: {commands:?}"))?;
+                    *commit_clocks_table = true;
                 }
-            })
+                Err(err) => {
+                    error!("custom clock settings are present but will be ignored, could not get clocks table: {err}");
+                }
+            }
+        }
+
+        if self.is_steam_deck() {
+            let performance_level = config.performance_level.unwrap_or(PerformanceLevel::Auto);
+            self.common
+                .write_once(
+                    &self.performance_level_path(),
+                    &[&performance_level.to_string()],
+                    || {
+                        self.handle
+                            .set_power_force_performance_level(performance_level)
+                    },
+                )
+                .ok();
+        }
+
+        Ok(())
     }
 
-    fn get_info(&self) -> LocalBoxFuture<'_, DeviceInfo> {
-        Box::pin(async move {
-            let vulkan_instances = get_vulkan_info(&self.common).await.unwrap_or_else(|err| {
-                warn!("could not load vulkan info: {err:#}");
-                vec![]
-            });
-            let pci_info = Some(self.common.pci_info.clone());
-            let driver = self.handle.get_driver().to_owned();
-            let vbios_version = self.get_full_vbios_version();
-            let link_info = self.get_link_info();
-            let drm_info = self.get_drm_info();
-            let opencl_info = get_opencl_info(&self.common);
+    async fn commit_clocks_table(&self) -> anyhow::Result<()> {
+        let path = self.handle.get_path().join("pp_od_clk_voltage");
+        self.common
+            .write_file(&path, "c")
+            .await
+            .context("Could not commit clocks table")
+    }
 
-            DeviceInfo {
-                pci_info,
-                vulkan_instances,
-                driver,
-                vbios_version,
-                link_info,
-                opencl_info,
-                drm_info,
+    async fn apply_power_cap(&self, config: &GpuConfig) -> anyhow::Result<()> {
+        if let Some(configured_cap) = config.power_cap {
+            let hw_mon = self.first_hw_mon()?;
+
+            self.common
+                .write_with(
+                    &hw_mon.get_path().join("power1_cap"),
+                    &[&power_cap_value(configured_cap)],
+                    || {
+                        hw_mon
+                            .set_power_cap(configured_cap)
+                            .with_context(|| format!("Failed to set power cap: {configured_cap}"))
+                    },
+                )
+                .await?;
+        } else if let Ok(hw_mon) = self.first_hw_mon() {
+            if let Ok(default_cap) = hw_mon.get_power_cap_default() {
+                let path = hw_mon.get_path().join("power1_cap");
+                if Ok(default_cap) != hw_mon.get_power_cap() {
+                    self.common
+                        .write_once(&path, &[&power_cap_value(default_cap)], || {
+                            hw_mon.set_power_cap(default_cap)
+                        })
+                        .with_context(|| {
+                            format!("Failed to set power cap to default cap: {default_cap}")
+                        })?;
+                }
             }
-        })
+        }
+
+        Ok(())
     }
 
-    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-    fn get_stats(&self, gpu_config: Option<&GpuConfig>) -> DeviceStats {
-        let metrics = GpuMetrics::get_from_sysfs_path(self.handle.get_path()).ok();
-        let metrics = metrics.as_ref();
+    async fn apply_fan_control(
+        &self,
+        config: &GpuConfig,
+        commit_handles: &mut VecDeque<CommitHandle>,
+    ) -> anyhow::Result<()> {
+        if config.fan_control_enabled {
+            if !self.is_fan_control_supported() {
+                return Err(ErrorKind::Unsupported.error(
+                    "The GPU fan does not support speed control, it can only be monitored",
+                ));
+            }
 
-        let pmfw_curve = self.handle.get_fan_curve().ok();
-        let pmfw_curve_range = pmfw_curve
-            .as_ref()
-            .and_then(|curve| curve.allowed_ranges.as_ref())
-            .map(|range| &range.speed_range);
+            if let Some(ref settings) = config.fan_control_settings {
+                match settings.mode {
+                    lact_schema::FanControlMode::Static => {
+                        let fan_handles = self
+                            .set_static_fan_control(settings.static_speed)
+                            .await
+                            .context("Failed to set static fan control")?;
 
-        let pwm_max = pmfw_curve_range
-            .map(|range| *range.end())
-            .map(|percent| (f64::from(percent) * 2.55) as u32)
-            .or_else(|| self.hw_mon_and_then(HwMon::get_fan_max_pwm).map(u32::from));
-        let pwm_min = pmfw_curve_range
-            .map(|range| *range.start())
-            .map(|percent| (f64::from(percent) * 2.55) as u32)
-            .or_else(|| self.hw_mon_and_then(HwMon::get_fan_min_pwm).map(u32::from));
+                        for handle in fan_handles {
+                            commit_handles.push_front(handle);
+                        }
+                    }
+                    lact_schema::FanControlMode::Curve => {
+                        if settings.curve.0.is_empty() {
+                            return Err(anyhow!("Cannot use empty fan curve"));
+                        }
 
-        let fan_settings = gpu_config.and_then(|config| config.fan_control_settings.as_ref());
-        DeviceStats {
-            fan: FanStats {
-                control_enabled: gpu_config.is_some_and(|config| config.fan_control_enabled),
-                control_mode: fan_settings.map(|settings| settings.mode),
-                static_speed: fan_settings.map(|settings| settings.static_speed),
-                curve: fan_settings.map(|settings| settings.curve.0.clone()),
-                spindown_delay_ms: fan_settings.and_then(|settings| settings.spindown_delay_ms),
-                change_threshold: fan_settings.and_then(|settings| settings.change_threshold),
-                temperature_key: fan_settings.map(|settings| settings.temperature_key.clone()),
-                auto_threshold: None,
-                speed_current: self.hw_mon_and_then(HwMon::get_fan_current).or_else(|| {
-                    metrics
-                        .and_then(MetricsInfo::get_current_fan_speed)
-                        .map(u32::from)
-                }),
-                speed_max: self.hw_mon_and_then(HwMon::get_fan_max),
-                speed_min: self.hw_mon_and_then(HwMon::get_fan_min),
-                pwm_current: self.hw_mon_and_then(HwMon::get_fan_pwm).or_else(|| {
-                    metrics
-                        .and_then(MetricsInfo::get_fan_pwm)
-                        .and_then(|pwm| u8::try_from(pwm).ok())
-                }),
-                pwm_max,
-                pwm_min,
-                temperature_range: pmfw_curve
-                    .as_ref()
-                    .and_then(|curve| curve.allowed_ranges.as_ref())
-                    .map(|range| {
-                        (
-                            *range.temperature_range.start(),
-                            *range.temperature_range.end(),
-                        )
+                        if let Some(commit_handle) = self
+                            .start_curve_fan_control(settings.curve.clone(), settings.clone())
+                            .await
+                            .context("Failed to set curve fan control")?
+                        {
+                            commit_handles.push_front(commit_handle);
+                        }
+                    }
+                    lact_schema::FanControlMode::OffsetCurve => {
+                        validate_pwm_limits(settings)?;
+                        let firmware_curve = self
+                            .get_firmware_fan_curve()
+                            .context("The offset curve is based on the firmware fan curve")?;
+
+                        let settings = FanControlSettings {
+                            curve_unit: FanCurveUnit::Percentage,
+                            ..settings.clone()
+                        };
+                        self.start_curve_fan_control_task(firmware_curve, settings)
+                            .await
+                            .context("Failed to set offset curve fan control")?;
+                    }
+                }
+            } else {
+                return Err(anyhow!(
+                    "Trying to enable fan control with no settings provided"
+                ));
+            }
+        } else {
+            self.stop_fan_control(true)
+                .await
+                .context("Failed to stop fan control")?;
+
+            let pmfw = &config.pmfw_options;
+            if let Some(acoustic_limit) = pmfw.acoustic_limit {
+                if self
+                    .handle
+                    .get_fan_acoustic_limit()
+                    .context("Could not get acoustic limit")?
+                    .current
+                    != acoustic_limit
+                {
+                    if let Some(commit_handle) = self
+                        .write_pmfw(
+                            "acoustic_limit_rpm_threshold",
+                            &[acoustic_limit.to_string()],
+                            || self.handle.set_fan_acoustic_limit(acoustic_limit),
+                        )
+                        .context("Could not set acoustic limit")?
+                    {
+                        commit_handles.push_front(commit_handle);
+                    }
+                }
+            }
+            if let Some(acoustic_target) = pmfw.acoustic_target {
+                if self
+                    .handle
+                    .get_fan_acoustic_target()
+                    .context("Could not get acoustic target")?
+                    .current
+                    != acoustic_target
+                {
+                    if let Some(commit_handle) = self
+                        .write_pmfw(
+                            "acoustic_target_rpm_threshold",
+                            &[acoustic_target.to_string()],
+                            || self.handle.set_fan_acoustic_target(acoustic_target),
+                        )
+                        .context("Could not set acoustic target")?
+                    {
+                        commit_handles.push_front(commit_handle);
+                    }
+                }
+            }
+            if let Some(target_temperature) = pmfw.target_temperature {
+                if self
+                    .handle
+                    .get_fan_target_temperature()
+                    .context("Could not get target temperature")?
+                    .current
+                    != target_temperature
+                {
+                    if let Some(commit_handle) = self
+                        .write_pmfw(
+                            "fan_target_temperature",
+                            &[target_temperature.to_string()],
+                            || self.handle.set_fan_target_temperature(target_temperature),
+                        )
+                        .context("Could not set target temperature")?
+                    {
+                        commit_handles.push_front(commit_handle);
+                    }
+                }
+            }
+            if let Some(minimum_pwm) = pmfw.minimum_pwm {
+                if self
+                    .handle
+                    .get_fan_minimum_pwm()
+                    .context("Could not get minimum pwm")?
+                    .current
+                    != minimum_pwm
+                {
+                    if let Some(commit_handle) = self
+                        .write_pmfw("fan_minimum_pwm", &[minimum_pwm.to_string()], || {
+                            self.handle.set_fan_minimum_pwm(minimum_pwm)
+                        })
+                        .context("Could not set minimum pwm")?
+                    {
+                        commit_handles.push_front(commit_handle);
+                    }
+                }
+            }
+        }
+
+        // Unlike the other PMFW options, zero rpm should be functional with a custom curve
+        if let Some(zero_rpm) = config.pmfw_options.zero_rpm {
+            match self.handle.get_fan_zero_rpm_enable() {
+                Ok(current_zero_rpm) => {
+                    if current_zero_rpm != zero_rpm {
+                        if let Some(commit_handle) = self
+                            .write_pmfw(
+                                "fan_zero_rpm_enable",
+                                &[u8::from(zero_rpm).to_string()],
+                                || self.handle.set_fan_zero_rpm_enable(zero_rpm),
+                            )
+                            .context("Could not set zero RPM mode")?
+                        {
+                            commit_handles.push_front(commit_handle);
+                        }
+                    }
+                }
+                Err(err) => {
+                    error!(
+                        "zero RPM is present in the config, but not available on the GPU: {err}"
+                    );
+                }
+            }
+        }
+
+        if let Some(zero_rpm_threshold) = config.pmfw_options.zero_rpm_threshold {
+            match self.handle.get_fan_zero_rpm_stop_temperature() {
+                Ok(current_threshold) => {
+                    if current_threshold.current != zero_rpm_threshold {
+                        if let Some(commit_handle) = self
+                            .write_pmfw(
+                                "fan_zero_rpm_stop_temperature",
+                                &[zero_rpm_threshold.to_string()],
+                                || {
+                                    self.handle
+                                        .set_fan_zero_rpm_stop_temperature(zero_rpm_threshold)
+                                },
+                            )
+                            .context("Could not set zero RPM temperature")?
+                        {
+                            commit_handles.push_front(commit_handle);
+                        }
+                    }
+                }
+                Err(err) => {
+                    error!("zero RPM threshold is present in the config, but not available on the GPU: {err}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// With the idle lock enabled, the lowest core and memory clock levels are forced in place of
+    /// the configured power states. Those stay in the config, so they are restored after unlocking.
+    fn idle_lock_config<'a>(&self, config: &'a GpuConfig) -> Cow<'a, GpuConfig> {
+        if !config.idle_lock {
+            return Cow::Borrowed(config);
+        }
+
+        let mut config = config.clone();
+        config.performance_level = Some(PerformanceLevel::Manual);
+        config.memory_clock_level = None;
+        config.power_states.clear();
+        for (kind, file_name) in [
+            (PowerLevelKind::CoreClock, "pp_dpm_sclk"),
+            (PowerLevelKind::MemoryClock, "pp_dpm_mclk"),
+        ] {
+            if self.handle.get_path().join(file_name).exists() {
+                config.power_states.insert(kind, vec![0]);
+            }
+        }
+        Cow::Owned(config)
+    }
+
+    /// Power states and forced DPM levels, these need the manual performance level to be committed
+    async fn apply_power_states(&self, config: &GpuConfig) -> anyhow::Result<()> {
+        for (kind, states) in &config.power_states {
+            if config.performance_level != Some(PerformanceLevel::Manual) {
+                return Err(anyhow!(
+                    "Performance level has to be set to `manual` to configure power states"
+                ));
+            }
+
+            self.common
+                .write_with(
+                    &power_levels_path(self.handle.get_path(), *kind),
+                    &[&join_levels(states)],
+                    || {
+                        self.handle
+                            .set_enabled_power_levels(*kind, states)
+                            .with_context(|| format!("Could not set {kind:?} power states"))
+                    },
+                )
+                .await?;
+            // The configured states replace any level that was forced before
+            self.forced_levels.borrow_mut().remove(kind);
+        }
+
+        if let Some(level) = config.memory_clock_level {
+            if config.performance_level != Some(PerformanceLevel::Manual) {
+                return Err(anyhow!(
+                    "Performance level has to be set to `manual` to force a memory clock level"
+                ));
+            }
+            if config
+                .power_states
+                .contains_key(&PowerLevelKind::MemoryClock)
+            {
+                return Err(anyhow!(
+                    "Memory clock level cannot be forced together with memory clock power states"
+                ));
+            }
+
+            self.set_memory_clock_level(Some(level))
+                .await
+                .context("Could not set memory clock level")?;
+        } else if config.performance_level == Some(PerformanceLevel::Manual) {
+            if self.handle.get_path().join("pp_dpm_mclk").exists() {
+                self.set_memory_clock_level(None)
+                    .await
+                    .context("Could not reset memory clock levels")?;
+            }
+        } else {
+            self.forced_levels
+                .borrow_mut()
+                .remove(&PowerLevelKind::MemoryClock);
+        }
+
+        if let Some(level) = config.pcie_level {
+            if config.performance_level != Some(PerformanceLevel::Manual) {
+                return Err(anyhow!(
+                    "Performance level has to be set to `manual` to force a PCIe level"
+                ));
+            }
+
+            self.set_pcie_level(Some(level))
+                .await
+                .context("Could not set PCIe level")?;
+        } else if config.performance_level == Some(PerformanceLevel::Manual) {
+            if self.handle.get_path().join("pp_dpm_pcie").exists() {
+                self.set_pcie_level(None)
+                    .await
+                    .context("Could not reset PCIe levels")?;
+            }
+        } else {
+            // The driver re-enables all levels when leaving the manual performance level
+            self.forced_levels
+                .borrow_mut()
+                .remove(&PowerLevelKind::PcieSpeed);
+        }
+
+        Ok(())
+    }
+
+    fn performance_level_path(&self) -> PathBuf {
+        self.handle
+            .get_path()
+            .join("power_dpm_force_performance_level")
+    }
+
+    fn is_steam_deck(&self) -> bool {
+        self.common.pci_info.device_pci_info.vendor_id == VENDOR_AMD
+            && STEAM_DECK_IDS.contains(&self.common.pci_info.device_pci_info.model_id.as_str())
+    }
+}
+
+impl GpuController for AmdGpuController {
+    fn controller_info(&self) -> &CommonControllerInfo {
+        &self.common
+    }
+
+    fn device_type(&self) -> DeviceType {
+        self.drm_handle
+            .as_ref()
+            .and_then(|drm| drm.device_info().ok())
+            .map_or(DeviceType::Dedicated, |info| {
+                if (info.ids_flags & AMDGPU_IDS_FLAGS_FUSION) > 0 {
+                    DeviceType::Integrated
+                } else {
+                    DeviceType::Dedicated
+                }
+            })
+    }
+
+    fn get_info(&self) -> LocalBoxFuture<'_, DeviceInfo> {
+        Box::pin(async move {
+            let vulkan_instances = get_vulkan_info(&self.common).await.unwrap_or_else(|err| {
+                warn!("could not load vulkan info: {err:#}");
+                vec![]
+            });
+            let pci_info = Some(self.common.pci_info.clone());
+            let driver = self.handle.get_driver().to_owned();
+            let vbios_version = self.get_full_vbios_version();
+            let vbios = self.get_vbios_info();
+            let link_info = self.get_link_info();
+            let drm_info = self.get_drm_info();
+            let opencl_info = get_opencl_info(&self.common);
+
+            DeviceInfo {
+                pci_info,
+                vulkan_instances,
+                driver,
+                vbios_version,
+                vbios,
+                link_info,
+                opencl_info,
+                drm_info,
+                resizable_bar: read_resizable_bar(&self.common.sysfs_path),
+                // The kernel reports `unknown` when the VBIOS does not say which memory is used
+                memory_vendor: self
+                    .handle
+                    .get_vram_vendor()
+                    .ok()
+                    .map(|vendor| vendor.trim_matches('\0').to_owned())
+                    .filter(|vendor| !vendor.is_empty() && vendor != "unknown"),
+                vram_total: self.handle.get_total_vram().ok(),
+                thermal_limits: self
+                    .handle
+                    .hw_monitors
+                    .first()
+                    .map(|hw_mon| read_thermal_limits(hw_mon.get_path()))
+                    .unwrap_or_default(),
+                displays: read_displays(&self.common.sysfs_path),
+            }
+        })
+    }
+
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    fn get_stats(&self, gpu_config: Option<&GpuConfig>) -> DeviceStats {
+        let metrics_table = match gpu_metrics::read_from_sysfs(self.handle.get_path()) {
+            Ok(table) => Some(table),
+            Err(err) => {
+                trace!("could not decode gpu_metrics: {err:#}");
+                None
+            }
+        };
+        // The table is only read again by libdrm for the versions which are not decoded here,
+        // so that the values come from a single read
+        let metrics = metrics_table
+            .is_none()
+            .then(|| GpuMetrics::get_from_sysfs_path(self.handle.get_path()).ok())
+            .flatten();
+        let metrics = metrics.as_ref();
+        let busy_percent = self.handle.get_busy_percent().ok();
+        let activity = gpu_metrics::activity(busy_percent, metrics_table.as_ref());
+        // Read once for every value that comes from it
+        let od_table = self.read_od_table();
+        let clocks_table = od_table
+            .as_deref()
+            .and_then(|od_table| od_table.parse::<ClocksTableGen>().ok());
+        let power_profile_modes = self.handle.get_power_profile_modes().ok();
+
+        let pmfw_curve = self.handle.get_fan_curve().ok();
+        let pmfw_curve_range = pmfw_curve
+            .as_ref()
+            .and_then(|curve| curve.allowed_ranges.as_ref())
+            .map(|range| &range.speed_range);
+
+        let pwm_max = pmfw_curve_range
+            .map(|range| *range.end())
+            .map(|percent| (f64::from(percent) * 2.55) as u32)
+            .or_else(|| self.hw_mon_and_then(HwMon::get_fan_max_pwm).map(u32::from));
+        let pwm_min = pmfw_curve_range
+            .map(|range| *range.start())
+            .map(|percent| (f64::from(percent) * 2.55) as u32)
+            .or_else(|| self.hw_mon_and_then(HwMon::get_fan_min_pwm).map(u32::from));
+
+        let memory_power_state = self
+            .handle
+            .get_memory_clock_levels()
+            .ok()
+            .and_then(|levels| levels.active);
+        let pcie_power_state = self
+            .handle
+            .get_pcie_clock_levels()
+            .ok()
+            .and_then(|levels| levels.active);
+
+        let fan_settings = gpu_config.and_then(|config| config.fan_control_settings.as_ref());
+        let speed_current = self
+            .hw_mon_and_then(HwMon::get_fan_current)
+            .or_else(|| {
+                metrics_table
+                    .as_ref()
+                    .and_then(|table| table.current_fan_speed)
+                    .map(u32::from)
+            })
+            .or_else(|| {
+                metrics
+                    .and_then(MetricsInfo::get_current_fan_speed)
+                    .map(u32::from)
+            });
+
+        let mut power = PowerStats {
+            average: metrics_table
+                .as_ref()
+                .and_then(|table| table.average_socket_power)
+                .map(f64::from)
+                .or_else(|| self.hw_mon_and_then(HwMon::get_power_average)),
+            current: self.hw_mon_and_then(HwMon::get_power_input),
+            chip: metrics_table
+                .as_ref()
+                .and_then(|table| table.average_soc_power)
+                .map(f64::from),
+            cap: self.read_power_cap(),
+            cap_percent: None,
+        };
+        power.cap_percent = power.cap.current_percent();
+
+        DeviceStats {
+            fan: FanStats {
+                control_enabled: gpu_config.is_some_and(|config| config.fan_control_enabled),
+                control_mode: fan_settings.map(|settings| settings.mode),
+                hardware_mode: self.read_hardware_fan_mode(),
+                static_speed: fan_settings.map(|settings| settings.static_speed),
+                curve: fan_settings.map(|settings| settings.curve.0.clone()),
+                spindown_delay_ms: fan_settings.and_then(|settings| settings.spindown_delay_ms),
+                change_threshold: fan_settings.and_then(|settings| settings.change_threshold),
+                temperature_key: fan_settings.map(|settings| settings.temperature_key.clone()),
+                auto_threshold: None,
+                monitor_only: speed_current.is_some() && !self.is_fan_control_supported(),
+                speed_current,
+                speed_max: self.hw_mon_and_then(HwMon::get_fan_max),
+                speed_min: self.hw_mon_and_then(HwMon::get_fan_min),
+                pwm_current: self
+                    .hw_mon_and_then(HwMon::get_fan_pwm)
+                    .or_else(|| {
+                        metrics_table
+                            .as_ref()
+                            .and_then(|table| table.fan_pwm)
+                            .and_then(|pwm| u8::try_from(pwm).ok())
+                    })
+                    .or_else(|| {
+                        metrics
+                            .and_then(MetricsInfo::get_fan_pwm)
+                            .and_then(|pwm| u8::try_from(pwm).ok())
+                    }),
+                pwm_max,
+                pwm_min,
+                temperature_range: pmfw_curve
+                    .as_ref()
+                    .and_then(|curve| curve.allowed_ranges.as_ref())
+                    .map(|range| {
+                        (
+                            *range.temperature_range.start(),
+                            *range.temperature_range.end(),
+                        )
                     }),
                 pmfw_info: PmfwInfo {
                     acoustic_limit: self.handle.get_fan_acoustic_limit().ok(),
                     acoustic_target: self.handle.get_fan_acoustic_target().ok(),
                     target_temp: self.handle.get_fan_target_temperature().ok(),
                     minimum_pwm: self.handle.get_fan_minimum_pwm().ok(),
-                    zero_rpm_enable: self.handle.get_fan_zero_rpm_enable().ok(),
+                    zero_rpm_enable: self
+                        .is_pmfw_supported()
+                        .then(|| self.handle.get_fan_zero_rpm_enable().ok())
+                        .flatten(),
                     zero_rpm_temperature: self.handle.get_fan_zero_rpm_stop_temperature().ok(),
                 },
             },
-            clockspeed: self.get_clockspeed(),
+            clockspeed: self.get_clockspeed(metrics_table.as_ref(), clocks_table.as_ref()),
             voltage: VoltageStats {
-                gpu: self.hw_mon_and_then(HwMon::get_gpu_voltage),
+                gpu: metrics_table
+                    .as_ref()
+                    .and_then(|table| table.voltage_gfx)
+                    .map(u64::from)
+                    .or_else(|| self.hw_mon_and_then(HwMon::get_gpu_voltage)),
                 northbridge: self.hw_mon_and_then(HwMon::get_northbridge_voltage),
+                offset: match &clocks_table {
+                    Some(ClocksTableGen::Rdna(table)) => table.voltage_offset,
+                    _ => None,
+                },
             },
             vram: VramStats {
                 total: self.handle.get_total_vram().ok(),
                 used: self.handle.get_used_vram().ok(),
+                ecc_errors: self.get_ecc_errors(),
             },
-            power: PowerStats {
-                average: self.hw_mon_and_then(HwMon::get_power_average),
-                current: self.hw_mon_and_then(HwMon::get_power_input),
-                cap_current: self.hw_mon_and_then(HwMon::get_power_cap),
-                cap_max: self.hw_mon_and_then(HwMon::get_power_cap_max),
-                cap_min: self.hw_mon_and_then(HwMon::get_power_cap_min),
-                cap_default: self.hw_mon_and_then(HwMon::get_power_cap_default),
-            },
+            power,
             temps: self.hw_mon_map(HwMon::get_temps).unwrap_or_default(),
-            busy_percent: self.handle.get_busy_percent().ok(),
+            busy_percent,
+            activity,
             performance_level: self.handle.get_power_force_performance_level().ok(),
+            profiling_performance_level: self
+                .read_performance_level()
+                .filter(|level| level.starts_with("profile_")),
             core_power_state: self
                 .handle
                 .get_core_clock_levels()
                 .ok()
                 .and_then(|levels| levels.active),
-            memory_power_state: self
-                .handle
-                .get_memory_clock_levels()
-                .ok()
-                .and_then(|levels| levels.active),
-            pcie_power_state: self
-                .handle
-                .get_pcie_clock_levels()
-                .ok()
-                .and_then(|levels| levels.active),
+            memory_power_state,
+            pcie_power_state,
             throttle_info: self.get_throttle_info(),
+            throttle_reasons: metrics_table
+                .as_ref()
+                .and_then(|table| table.indep_throttle_status)
+                .or_else(|| metrics.and_then(MetricsInfo::get_indep_throttle_status))
+                .map(decode_throttle_reasons)
+                .unwrap_or_default(),
+            gpu_metrics: metrics_table,
+            pcie_link: Some(self.get_link_info()).filter(|link| !link.is_empty()),
+            memory_clock_level: self
+                .active_forced_level(PowerLevelKind::MemoryClock, memory_power_state),
+            pcie_level: self.active_forced_level(PowerLevelKind::PcieSpeed, pcie_power_state),
+            power_profile_mode: power_profile_modes
+                .and_then(|table| table.modes.get(&table.active).map(|mode| mode.name.clone())),
+            vf_curve: od_table
+                .as_deref()
+                .and_then(|od_table| vf_curve::parse(od_table, self.family_id)),
+            temperature_unit: TemperatureUnit::default(),
         }
     }
 
@@ -795,6 +1847,49 @@ impl GpuController for AmdGpuController {
         Ok(self.handle.get_power_profile_modes()?)
     }
 
+    fn get_capabilities(&self) -> GpuCapabilities {
+        let clocks_info = self.get_clocks_info(None).ok();
+        let stats = self.get_stats(None);
+        let mut capabilities = common::base_capabilities(&stats, clocks_info.as_ref());
+
+        capabilities.fan_control = self.is_fan_control_supported();
+        capabilities.performance_level = self.read_performance_level().is_some();
+        if capabilities.performance_level {
+            capabilities.performance_levels = self.read_performance_levels();
+        }
+        // Only reported when the power profile modes can be read
+        capabilities.power_profile_mode = stats.power_profile_mode.is_some();
+        capabilities.leds = read_leds(self.handle.get_path());
+
+        if let Some(lact_schema::ClocksTable::Amd(table)) =
+            clocks_info.as_ref().and_then(|info| info.table.as_ref())
+        {
+            // The offset is applied to the maximum memory clock, so its range follows from the range of that
+            let stock = self.stock_max_mclk(table);
+            if let Some((stock, range)) = stock.zip(capabilities.max_memory_clock) {
+                capabilities
+                    .mem_clock_offsets
+                    .insert(0, ValueRange::from((range.min - stock, range.max - stock)));
+            }
+        }
+
+        if let Ok(curve) = self.handle.get_fan_curve() {
+            if let Some(ranges) = &curve.allowed_ranges {
+                capabilities.fan_curve_temperature = Some(ValueRange::from((
+                    *ranges.temperature_range.start(),
+                    *ranges.temperature_range.end(),
+                )));
+                capabilities.fan_curve_speed = Some(ValueRange::from((
+                    u32::from(*ranges.speed_range.start()),
+                    u32::from(*ranges.speed_range.end()),
+                )));
+                capabilities.fan_curve_points = u32::try_from(curve.points.len()).ok();
+            }
+        }
+
+        capabilities
+    }
+
     fn reset_pmfw_settings(&self) {
         let handle = &self.handle;
         if self.handle.get_fan_target_temperature().is_ok() {
@@ -824,258 +1919,196 @@ impl GpuController for AmdGpuController {
         fs::read(debugfs.join("amdgpu_vbios")).context("Could not read VBIOS file")
     }
 
-    #[allow(clippy::too_many_lines)]
-    fn apply_config<'a>(&'a self, config: &'a GpuConfig) -> LocalBoxFuture<'a, anyhow::Result<()>> {
-        Box::pin(async {
-            let mut commit_handles = VecDeque::new();
+    fn get_versions(&self) -> VersionsInfo {
+        let firmware_versions = firmware::read_versions(&self.common.sysfs_path);
+        // The SMC firmware only provides the PMFW interface on RDNA3 and newer
+        let pmfw_version = if self.is_pmfw_supported() {
+            firmware_versions
+                .get("smc")
+                .and_then(|version| firmware::pmfw_version(version))
+        } else {
+            None
+        };
 
-            // Reset the clocks table in case the settings get reverted back to not having a clocks value configured
-            self.handle.reset_clocks_table().ok();
+        VersionsInfo {
+            driver: self.common.driver.clone(),
+            driver_version: common::read_driver_version(&self.common.sysfs_path),
+            vbios_version: self.get_full_vbios_version(),
+            pmfw_version,
+            firmware: firmware_versions,
+            ..Default::default()
+        }
+    }
 
-            if !config.fan_control_enabled {
-                self.stop_fan_control(true)
-                    .await
-                    .context("Failed to stop fan control")?;
-            }
+    fn get_firmware_fan_curve(&self) -> anyhow::Result<FanCurve> {
+        let pmfw_curve = self.handle.get_fan_curve().map_err(|_| {
+            ErrorKind::Unsupported.error("The GPU does not have a firmware fan curve")
+        })?;
+        Ok(from_pmfw_curve(&pmfw_curve))
+    }
 
-            if self.is_steam_deck() {
-                // Van Gogh/Sephiroth only allow clock settings to be used with manual performance mode
-                self.handle
-                    .set_power_force_performance_level(PerformanceLevel::Manual)
-                    .ok();
-            }
+    fn validate_fan_curve(&self, curve: &FanCurve, unit: FanCurveUnit) -> FanCurveValidation {
+        // GPUs with a firmware fan curve apply custom curves through it
+        let pmfw_curve = self.handle.get_fan_curve().ok();
+        validate_curve(curve, unit, pmfw_curve.as_ref())
+    }
 
-            if config.is_core_clocks_used() {
-                match self.handle.get_clocks_table() {
-                    Ok(original_table) => {
-                        let mut table = original_table.clone();
-                        apply_clocks_config_to_table(&config.clocks_configuration, &mut table)
-                            .context("Failed to apply clocks configuration to table")?;
-
-                        debug!(
-                            "writing clocks commands: {:#?}",
-                            table
-                                .get_commands(&original_table)
-                                .context("Failed to get table commands")?
-                        );
+    fn temperature_reader(&self) -> Option<TemperatureReader> {
+        let hw_mon = self.handle.hw_monitors.first().cloned()?;
+        Some(Rc::new(move |key: &str| {
+            let mut temps = hw_mon.get_temps();
+            let temp = if temps.len() == 1 {
+                temps.into_values().next()
+            } else {
+                temps.remove(key)
+            };
+            temp.and_then(|temp| temp.current)
+        }))
+    }
 
-                        let handle = self
-                            .handle
-                            .set_clocks_table(&table)
-                            .context("Could not write clocks table")
-                            .with_context(|| {
-                                format!(
-                                    "Clocks table commands: {:?}",
-                                    table.get_commands(&original_table)
-                                )
-                            })?;
-                        commit_handles.push_back(handle);
+    fn set_temperature_sources(&self, sources: TemperatureSources) {
+        *self.temperature_sources.borrow_mut() = sources;
+    }
+
+    fn self_test(&self) -> anyhow::Result<Vec<SelfTestEntry>> {
+        let hw_mon_path = self.handle.hw_monitors.first().map(HwMon::get_path);
+        Ok(self_test::run(self.handle.get_path(), hw_mon_path))
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn apply_config<'a>(&'a self, config: &'a GpuConfig) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async {
+            let config = self.idle_lock_config(config);
+            let config = &*config;
+            let mut commit_handles = VecDeque::new();
+            let mut commit_clocks_table = false;
+            let mut completed_steps = Vec::with_capacity(ApplyStep::ORDER.len());
+
+            for step in ApplyStep::ORDER {
+                debug!("applying {step} settings");
+                let result = match step {
+                    ApplyStep::PerformanceLevel => self.apply_performance_level(config).await,
+                    ApplyStep::Clocks => self.apply_clocks(config, &mut commit_clocks_table).await,
+                    ApplyStep::PowerCap => self.apply_power_cap(config).await,
+                    ApplyStep::FanControl => {
+                        self.apply_fan_control(config, &mut commit_handles).await
                     }
-                    Err(err) => {
-                        error!("custom clock settings are present but will be ignored, could not get clocks table: {err}");
+                    ApplyStep::Commit => {
+                        let result = commit_handles
+                            .drain(..)
+                            .try_for_each(|handle| handle.commit().map_err(anyhow::Error::from));
+                        match result {
+                            // The clocks table is committed after the firmware fan settings
+                            Ok(()) if commit_clocks_table => self.commit_clocks_table().await,
+                            result => result,
+                        }
                     }
-                }
-            }
-
-            match self.handle.get_power_force_performance_level() {
-                Ok(_) => {
-                    let performance_level =
-                        config.performance_level.unwrap_or(PerformanceLevel::Auto);
+                    ApplyStep::PowerStates => self.apply_power_states(config).await,
+                    ApplyStep::Leds => self.apply_leds(config).await,
+                };
 
-                    self.handle
-                        .set_power_force_performance_level(performance_level)
-                        .context("Failed to set power performance level")?;
-                }
-                Err(err) => {
-                    error!("could not get current performance level: {err}");
+                if let Err(err) = result {
+                    let completed = completed_steps
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    return Err(err.context(format!(
+                        "Could not apply {step} settings, completed steps: [{completed}]"
+                    )));
                 }
+                completed_steps.push(step);
             }
 
-            if let Some(mode_index) = config.power_profile_mode_index {
-                if config.performance_level != Some(PerformanceLevel::Manual) {
-                    return Err(anyhow!(
-                        "Performance level has to be set to `manual` to use power profile modes"
-                    ));
-                }
-
-                if config.custom_power_profile_mode_hueristics.is_empty() {
-                    self.handle
-                        .set_active_power_profile_mode(mode_index)
-                        .context("Failed to set active power profile mode")?;
-                } else {
-                    self.handle
-                        .set_custom_power_profile_mode_heuristics(
-                            &config.custom_power_profile_mode_hueristics,
-                        )
-                        .context("Failed to set custom power profile mode heuristics")?;
-                }
-            }
+            debug!("applied all settings steps");
+            Ok(())
+        })
+    }
 
-            if config.fan_control_enabled {
-                if let Some(ref settings) = config.fan_control_settings {
-                    match settings.mode {
-                        lact_schema::FanControlMode::Static => {
-                            let fan_handles = self
-                                .set_static_fan_control(settings.static_speed)
-                                .await
-                                .context("Failed to set static fan control")?;
-
-                            for handle in fan_handles {
-                                commit_handles.push_front(handle);
-                            }
-                        }
-                        lact_schema::FanControlMode::Curve => {
-                            if settings.curve.0.is_empty() {
-                                return Err(anyhow!("Cannot use empty fan curve"));
-                            }
+    fn verify_config(&self, config: &GpuConfig) -> Vec<ConfigDifference> {
+        // Compare against the settings which were actually applied
+        let config = self.idle_lock_config(config);
+        let config = &*config;
+        let mut drifted = vec![];
+        let mut push = |setting: &str, stored: String, current: String| {
+            drifted.push(ConfigDifference {
+                setting: setting.to_owned(),
+                stored,
+                current,
+            });
+        };
 
-                            if let Some(commit_handle) = self
-                                .start_curve_fan_control(settings.curve.clone(), settings.clone())
-                                .await
-                                .context("Failed to set curve fan control")?
-                            {
-                                commit_handles.push_front(commit_handle);
-                            }
-                        }
-                    }
-                } else {
-                    return Err(anyhow!(
-                        "Trying to enable fan control with no settings provided"
-                    ));
-                }
-            } else {
-                let pmfw = &config.pmfw_options;
-                if let Some(acoustic_limit) = pmfw.acoustic_limit {
-                    if self
-                        .handle
-                        .get_fan_acoustic_limit()
-                        .context("Could not get acoustic limit")?
-                        .current
-                        != acoustic_limit
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_acoustic_limit(acoustic_limit)
-                            .context("Could not set acoustic limit")?;
-                        commit_handles.push_front(commit_handle);
-                    }
-                }
-                if let Some(acoustic_target) = pmfw.acoustic_target {
-                    if self
-                        .handle
-                        .get_fan_acoustic_target()
-                        .context("Could not get acoustic target")?
-                        .current
-                        != acoustic_target
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_acoustic_target(acoustic_target)
-                            .context("Could not set acoustic target")?;
-                        commit_handles.push_front(commit_handle);
-                    }
-                }
-                if let Some(target_temperature) = pmfw.target_temperature {
-                    if self
-                        .handle
-                        .get_fan_target_temperature()
-                        .context("Could not get target temperature")?
-                        .current
-                        != target_temperature
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_target_temperature(target_temperature)
-                            .context("Could not set target temperature")?;
-                        commit_handles.push_front(commit_handle);
-                    }
-                }
-                if let Some(minimum_pwm) = pmfw.minimum_pwm {
-                    if self
-                        .handle
-                        .get_fan_minimum_pwm()
-                        .context("Could not get minimum pwm")?
-                        .current
-                        != minimum_pwm
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_minimum_pwm(minimum_pwm)
-                            .context("Could not set minimum pwm")?;
-                        commit_handles.push_front(commit_handle);
-                    }
-                }
+        if let (Some(expected_cap), Some(current_cap)) =
+            (config.power_cap, self.hw_mon_and_then(HwMon::get_power_cap))
+        {
+            if (expected_cap - current_cap).abs() >= 1.0 {
+                push(
+                    "power_cap",
+                    format!("{expected_cap}W"),
+                    format!("{current_cap}W"),
+                );
             }
+        }
 
-            // Unlike the other PMFW options, zero rpm should be functional with a custom curve
-            if let Some(zero_rpm) = config.pmfw_options.zero_rpm {
-                match self.handle.get_fan_zero_rpm_enable() {
-                    Ok(current_zero_rpm) => {
-                        if current_zero_rpm != zero_rpm {
-                            let commit_handle = self
-                                .handle
-                                .set_fan_zero_rpm_enable(zero_rpm)
-                                .context("Could not set zero RPM mode")?;
-                            commit_handles.push_front(commit_handle);
-                        }
-                    }
-                    Err(err) => {
-                        error!("zero RPM is present in the config, but not available on the GPU: {err}");
-                    }
-                }
+        if let (Some(expected_level), Ok(current_level)) = (
+            config.performance_level,
+            self.handle.get_power_force_performance_level(),
+        ) {
+            if expected_level != current_level {
+                push(
+                    "performance_level",
+                    expected_level.to_string(),
+                    current_level.to_string(),
+                );
             }
+        }
 
-            if let Some(zero_rpm_threshold) = config.pmfw_options.zero_rpm_threshold {
-                match self.handle.get_fan_zero_rpm_stop_temperature() {
-                    Ok(current_threshold) => {
-                        if current_threshold.current != zero_rpm_threshold {
-                            let commit_handle = self
-                                .handle
-                                .set_fan_zero_rpm_stop_temperature(zero_rpm_threshold)
-                                .context("Could not set zero RPM temperature")?;
-                            commit_handles.push_front(commit_handle);
-                        }
-                    }
-                    Err(err) => {
-                        error!("zero RPM threshold is present in the config, but not available on the GPU: {err}");
-                    }
+        // The PMFW fan curve is not tied to the hwmon control method
+        if config.fan_control_enabled && self.handle.get_fan_curve().is_err() {
+            if let Some(method) = self.hw_mon_and_then(HwMon::get_fan_control_method) {
+                if !matches!(method, FanControlMethod::Manual) {
+                    push(
+                        "fan_control_method",
+                        format!("{:?}", FanControlMethod::Manual),
+                        format!("{method:?}"),
+                    );
                 }
             }
+        }
 
-            if let Some(configured_cap) = config.power_cap {
-                let hw_mon = self.first_hw_mon()?;
+        if config.is_core_clocks_used() {
+            if let Ok(current_table) = self.handle.get_clocks_table() {
+                let mut expected_table = current_table.clone();
+                // Both sides are the table entries which differ, written as clocks commands
+                let commands = apply_clocks_config_to_table(
+                    &config.clocks_configuration,
+                    self.stock_max_mclk(&current_table),
+                    &mut expected_table,
+                )
+                .and_then(|()| {
+                    Ok((
+                        expected_table.get_commands(&current_table)?,
+                        current_table.get_commands(&expected_table)?,
+                    ))
+                });
 
-                hw_mon
-                    .set_power_cap(configured_cap)
-                    .with_context(|| format!("Failed to set power cap: {configured_cap}"))?;
-            } else if let Ok(hw_mon) = self.first_hw_mon() {
-                if let Ok(default_cap) = hw_mon.get_power_cap_default() {
-                    if Ok(default_cap) != hw_mon.get_power_cap() {
-                        hw_mon.set_power_cap(default_cap).with_context(|| {
-                            format!("Failed to set power cap to default cap: {default_cap}")
-                        })?;
+                if let Ok((stored_commands, current_commands)) = commands {
+                    if !stored_commands.is_empty() {
+                        push(
+                            "clocks_table",
+                            stored_commands.join("; "),
+                            current_commands.join("; "),
+                        );
                     }
                 }
             }
+        }
 
-            for handle in commit_handles {
-                handle.commit()?;
-            }
-
-            for (kind, states) in &config.power_states {
-                if config.performance_level != Some(PerformanceLevel::Manual) {
-                    return Err(anyhow!(
-                        "Performance level has to be set to `manual` to configure power states"
-                    ));
-                }
-
-                self.handle
-                    .set_enabled_power_levels(*kind, states)
-                    .with_context(|| format!("Could not set {kind:?} power states"))?;
-            }
+        drifted
+    }
 
-            Ok(())
-        })
+    fn supports_dry_run(&self) -> bool {
+        true
     }
 
     fn reset_clocks(&self) -> anyhow::Result<()> {
@@ -1090,10 +2123,10 @@ impl GpuController for AmdGpuController {
 
     fn cleanup(&self) -> LocalBoxFuture<'_, ()> {
         async {
-            if let Some((fan_notify, fan_handle)) = self.fan_control_handle.take() {
+            if let Some(FanControlHandle { notify, task, .. }) = self.fan_control_handle.take() {
                 debug!("sending stop notification to old fan control task");
-                fan_notify.notify_one();
-                fan_handle.await.unwrap();
+                notify.notify_one();
+                task.await.unwrap();
                 debug!("finished controller cleanup");
             }
         }
@@ -1111,6 +2144,48 @@ impl GpuController for AmdGpuController {
     }
 }
 
+/// Settings are applied in this order, as some of them depend on the ones before:
+/// clocks can only be changed with the right performance level, and power states
+/// can only be forced after the clocks table was committed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ApplyStep {
+    PerformanceLevel,
+    /// Includes the voltage settings, which are a part of the clocks table
+    Clocks,
+    PowerCap,
+    FanControl,
+    Commit,
+    PowerStates,
+    Leds,
+}
+
+impl ApplyStep {
+    const ORDER: [Self; 7] = [
+        Self::PerformanceLevel,
+        Self::Clocks,
+        Self::PowerCap,
+        Self::FanControl,
+        Self::Commit,
+        Self::PowerStates,
+        Self::Leds,
+    ];
+}
+
+impl fmt::Display for ApplyStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ApplyStep::PerformanceLevel => "performance level",
+            ApplyStep::Clocks => "clocks and voltage",
+            ApplyStep::PowerCap => "power cap",
+            ApplyStep::FanControl => "fan control",
+            ApplyStep::Commit => "commit",
+            ApplyStep::PowerStates => "power states",
+            ApplyStep::Leds => "LED",
+        };
+        s.fmt(f)
+    }
+}
+
 #[cfg(not(test))]
 fn get_drm_handle(
     common: &CommonControllerInfo,
@@ -1130,8 +2205,45 @@ fn get_drm_handle(
     Ok(handle)
 }
 
+/// The file which the enabled DPM levels of the given kind are written to
+fn power_levels_path(device_path: &Path, kind: PowerLevelKind) -> PathBuf {
+    let file_name = match kind {
+        PowerLevelKind::CoreClock => "pp_dpm_sclk",
+        PowerLevelKind::MemoryClock => "pp_dpm_mclk",
+        PowerLevelKind::PcieSpeed => "pp_dpm_pcie",
+        #[allow(unreachable_patterns)]
+        _ => return device_path.join(format!("pp_dpm_{kind:?}")),
+    };
+    device_path.join(file_name)
+}
+
+fn join_levels(levels: &[u8]) -> String {
+    levels
+        .iter()
+        .map(u8::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `power1_cap` is in microwatts
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn power_cap_value(cap: f64) -> String {
+    ((cap * 1_000_000.0) as u64).to_string()
+}
+
+/// The firmware fan curve is written one point at a time, as `<index> <temperature> <speed>`
+fn pmfw_curve_values(curve: &PmfwCurve) -> Vec<String> {
+    curve
+        .points
+        .iter()
+        .enumerate()
+        .map(|(index, (temperature, speed))| format!("{index} {temperature} {speed}"))
+        .collect()
+}
+
 fn apply_clocks_config_to_table(
     config: &ClocksConfiguration,
+    stock_max_mclk: Option<i32>,
     table: &mut ClocksTableGen,
 ) -> anyhow::Result<()> {
     if let ClocksTableGen::Rdna(ref mut table) = table {
@@ -1173,5 +2285,385 @@ fn apply_clocks_config_to_table(
         table.set_max_voltage(voltage)?;
     }
 
+    // The memory clock offset moves the maximum memory clock, so it takes precedence over it
+    if let Some(offset) = config.mem_clock_offsets.get(&0) {
+        let stock_max_mclk = stock_max_mclk.context("Memory clock is not available")?;
+        let clockspeed = stock_max_mclk
+            .checked_add(*offset)
+            .filter(|clockspeed| *clockspeed >= 0)
+            .context("Invalid memory clock offset")?;
+        table.set_max_mclk(clockspeed)?;
+    }
+
+    if let ClocksTableGen::Gcn(ref mut table) = table {
+        let sclk_range = table
+            .get_max_sclk_range()
+            .and_then(|range| range.into_full());
+        let mclk_range = table
+            .get_max_mclk_range()
+            .and_then(|range| range.into_full());
+        let voltage_range = table
+            .get_max_voltage_range()
+            .and_then(|range| range.into_full());
+
+        let state_kinds = [
+            (
+                "core",
+                &config.core_clock_states,
+                &mut table.sclk_levels,
+                sclk_range,
+            ),
+            (
+                "memory",
+                &config.memory_clock_states,
+                &mut table.mclk_levels,
+                mclk_range,
+            ),
+        ];
+        for (name, states, levels, clock_range) in state_kinds {
+            for (index, point) in states {
+                let level = levels
+                    .get_mut(usize::from(*index))
+                    .with_context(|| format!("{name} clock state {index} does not exist"))?;
+
+                check_clock_state_value(point.clockspeed, clock_range)
+                    .with_context(|| format!("Invalid {name} clock state {index} clockspeed"))?;
+                check_clock_state_value(point.voltage, voltage_range)
+                    .with_context(|| format!("Invalid {name} clock state {index} voltage"))?;
+
+                level.clockspeed = point.clockspeed;
+                level.voltage = point.voltage;
+            }
+        }
+    } else if !config.core_clock_states.is_empty() || !config.memory_clock_states.is_empty() {
+        return Err(anyhow!(
+            "Per-state clocks and voltages can only be configured on GCN-based GPUs"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Makes sure the heuristics match the layout described by the header of `pp_power_profile_mode`,
+/// as the amount of columns and clock types differs between GPU generations
+fn check_custom_heuristics(
+    table: &PowerProfileModesTable,
+    heuristics: &[Vec<Option<i32>>],
+) -> anyhow::Result<()> {
+    let custom_mode = table
+        .modes
+        .values()
+        .find(|mode| mode.name == "CUSTOM")
+        .context("GPU does not have a custom power profile mode")?;
+
+    if heuristics.len() != custom_mode.components.len() {
+        return Err(anyhow!(
+            "Custom power profile mode expects {} heuristics components, got {}",
+            custom_mode.components.len(),
+            heuristics.len()
+        ));
+    }
+
+    for (i, values) in heuristics.iter().enumerate() {
+        if values.len() != table.value_names.len() {
+            return Err(anyhow!(
+                "Custom power profile mode heuristics component {i} expects {} values ({}), got {}",
+                table.value_names.len(),
+                table.value_names.join(", "),
+                values.len()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_clock_state_value(value: i32, range: Option<(i32, i32)>) -> anyhow::Result<()> {
+    if let Some((min, max)) = range {
+        if !(min..=max).contains(&value) {
+            return Err(anyhow!(
+                "Value {value} is outside of the allowed range {min}-{max}"
+            ));
+        }
+    }
     Ok(())
 }
+
+/// Parses the error counts of a RAS block, e.g. `ue: 0\nce: 2`
+fn parse_corrected_errors(contents: &str) -> Option<u64> {
+    contents
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("ce:"))
+        .and_then(|count| count.trim().parse().ok())
+}
+
+/// Parses the contents of `pp_dpm_pcie`, e.g. `1: 16.0GT/s, x16 619Mhz *`
+fn parse_pcie_levels(contents: &str) -> anyhow::Result<Vec<String>> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            let (index, level) = line
+                .split_once(':')
+                .with_context(|| format!("Invalid PCIe level line '{line}'"))?;
+            if index.trim().parse::<usize>().ok() != Some(i) {
+                return Err(anyhow!("Unexpected PCIe level index in line '{line}'"));
+            }
+            Ok(level.trim().trim_end_matches('*').trim_end().to_owned())
+        })
+        .collect()
+}
+
+/// Decodes the ASIC-independent throttle status from `gpu_metrics`,
+/// which uses the `SMU_THROTTLER_*` bit layout from the kernel
+fn decode_throttle_reasons(status: u64) -> Vec<ThrottleReason> {
+    let reasons: BTreeSet<ThrottleReason> = (0..u64::BITS)
+        .filter(|bit| status & (1 << bit) != 0)
+        .map(|bit| match bit {
+            0..=15 => ThrottleReason::Power,
+            16..=31 => ThrottleReason::Current,
+            // VRHOT0/VRHOT1
+            44 | 45 => ThrottleReason::VoltageRegulator,
+            32..=55 => ThrottleReason::Thermal,
+            _ => ThrottleReason::Other,
+        })
+        .collect();
+    reasons.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_clocks_config_to_table, decode_throttle_reasons, parse_corrected_errors};
+    use amdgpu_sysfs::gpu_handle::overdrive::{ClocksTable, ClocksTableGen};
+    use indexmap::IndexMap;
+    use lact_schema::{
+        config::{ClockVoltagePoint, ClocksConfiguration},
+        ThrottleReason,
+    };
+    use pretty_assertions::assert_eq;
+
+    const POLARIS10: &str = include_str!("../../tests/pp_od_clk_voltage/polaris10");
+    const VEGA20: &str = include_str!("../../tests/pp_od_clk_voltage/vega20");
+    const NAVI31: &str = include_str!("../../tests/pp_od_clk_voltage/navi31");
+
+    /// Applies the commands to the contents of `pp_od_clk_voltage` the way the driver does,
+    /// so the table can be parsed again after writing it
+    fn write_commands(od_table: &str, commands: &[String]) -> String {
+        let mut lines: Vec<String> = od_table.lines().map(str::to_owned).collect();
+
+        for command in commands {
+            let mut args = command.split_whitespace();
+            let header = match args.next() {
+                Some("s") => "OD_SCLK:",
+                Some("m") => "OD_MCLK:",
+                Some("vc") => "OD_VDDC_CURVE:",
+                Some("vo") => "OD_VDDGFX_OFFSET:",
+                Some("r" | "c") => continue,
+                _ => panic!("unexpected command {command:?}"),
+            };
+            let args: Vec<&str> = args.collect();
+            let start = lines
+                .iter()
+                .position(|line| line == header)
+                .unwrap_or_else(|| panic!("no section for command {command:?}"))
+                + 1;
+
+            if header == "OD_VDDGFX_OFFSET:" {
+                lines[start] = format!("{}mV", args[0]);
+                continue;
+            }
+
+            let (index, values) = args.split_first().unwrap();
+            let prefix = format!("{index}:");
+            let line = lines[start..]
+                .iter_mut()
+                .take_while(|line| !line.ends_with(':'))
+                .find(|line| line.starts_with(&prefix))
+                .unwrap_or_else(|| panic!("no entry for command {command:?}"));
+            let values: Vec<String> = values
+                .iter()
+                .zip(["MHz", "mV"])
+                .map(|(value, unit)| format!("{value}{unit}"))
+                .collect();
+            *line = format!("{index}: {}", values.join(" "));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Parses the table, applies the config to it and parses it again after writing the commands
+    fn round_trip(od_table: &str, config: &ClocksConfiguration) -> ClocksTableGen {
+        let original: ClocksTableGen = od_table.parse().unwrap();
+        let mut table = original.clone();
+        apply_clocks_config_to_table(config, original.get_max_mclk(), &mut table).unwrap();
+
+        let commands = table.get_commands(&original).unwrap();
+        assert!(!commands.is_empty());
+        write_commands(od_table, &commands).parse().unwrap()
+    }
+
+    #[test]
+    fn gcn_clock_states_round_trip() {
+        let config = ClocksConfiguration {
+            core_clock_states: IndexMap::from([(
+                7,
+                ClockVoltagePoint {
+                    clockspeed: 1300,
+                    voltage: 1100,
+                },
+            )]),
+            memory_clock_states: IndexMap::from([(
+                1,
+                ClockVoltagePoint {
+                    clockspeed: 2100,
+                    voltage: 975,
+                },
+            )]),
+            ..Default::default()
+        };
+
+        let ClocksTableGen::Gcn(table) = round_trip(POLARIS10, &config) else {
+            panic!("expected a GCN table");
+        };
+        let sclk_levels: Vec<_> = table
+            .sclk_levels
+            .iter()
+            .map(|level| (level.clockspeed, level.voltage))
+            .collect();
+        assert_eq!(
+            vec![
+                (300, 750),
+                (608, 768),
+                (910, 912),
+                (1077, 1018),
+                (1145, 1062),
+                (1191, 1093),
+                (1236, 1125),
+                (1300, 1100),
+            ],
+            sclk_levels
+        );
+        let mclk_levels: Vec<_> = table
+            .mclk_levels
+            .iter()
+            .map(|level| (level.clockspeed, level.voltage))
+            .collect();
+        assert_eq!(vec![(300, 750), (2100, 975)], mclk_levels);
+    }
+
+    #[test]
+    fn gcn_clock_state_out_of_range() {
+        let config = ClocksConfiguration {
+            core_clock_states: IndexMap::from([(
+                7,
+                ClockVoltagePoint {
+                    clockspeed: 2100,
+                    voltage: 1100,
+                },
+            )]),
+            ..Default::default()
+        };
+        let mut table: ClocksTableGen = POLARIS10.parse().unwrap();
+        assert!(apply_clocks_config_to_table(&config, None, &mut table).is_err());
+    }
+
+    #[test]
+    fn vega20_max_clocks_round_trip() {
+        let config = ClocksConfiguration {
+            max_core_clock: Some(1900),
+            max_memory_clock: Some(1100),
+            ..Default::default()
+        };
+
+        let table = round_trip(VEGA20, &config);
+        assert!(matches!(table, ClocksTableGen::Rdna(_)));
+        assert_eq!(Some(1900), table.get_max_sclk());
+        assert_eq!(Some(1100), table.get_max_mclk());
+    }
+
+    #[test]
+    fn rdna3_offsets_round_trip() {
+        let config = ClocksConfiguration {
+            max_core_clock: Some(2800),
+            voltage_offset: Some(-50),
+            mem_clock_offsets: IndexMap::from([(0, 50)]),
+            ..Default::default()
+        };
+
+        let table = round_trip(NAVI31, &config);
+        assert_eq!(Some(2800), table.get_max_sclk());
+        assert_eq!(Some(1300), table.get_max_mclk());
+        let ClocksTableGen::Rdna(table) = table else {
+            panic!("expected an RDNA table");
+        };
+        assert_eq!(Some(-50), table.voltage_offset);
+    }
+
+    #[test]
+    fn per_state_clocks_need_gcn_table() {
+        let config = ClocksConfiguration {
+            core_clock_states: IndexMap::from([(
+                1,
+                ClockVoltagePoint {
+                    clockspeed: 2000,
+                    voltage: 1000,
+                },
+            )]),
+            ..Default::default()
+        };
+        let mut table: ClocksTableGen = NAVI31.parse().unwrap();
+        assert!(apply_clocks_config_to_table(&config, None, &mut table).is_err());
+    }
+
+    #[test]
+    fn corrected_memory_errors() {
+        assert_eq!(Some(2), parse_corrected_errors("ue: 0\nce: 2\n"));
+        assert_eq!(None, parse_corrected_errors("ue: 0\n"));
+    }
+
+    #[test]
+    fn decode_no_throttling() {
+        assert_eq!(Vec::<ThrottleReason>::new(), decode_throttle_reasons(0));
+    }
+
+    #[test]
+    fn decode_single_reasons() {
+        // PPT0
+        assert_eq!(vec![ThrottleReason::Power], decode_throttle_reasons(1));
+        // TDC_GFX
+        assert_eq!(
+            vec![ThrottleReason::Current],
+            decode_throttle_reasons(1 << 16)
+        );
+        // TEMP_HOTSPOT
+        assert_eq!(
+            vec![ThrottleReason::Thermal],
+            decode_throttle_reasons(0x10_0000_0000)
+        );
+        // VRHOT0
+        assert_eq!(
+            vec![ThrottleReason::VoltageRegulator],
+            decode_throttle_reasons(1 << 44)
+        );
+        // PPM
+        assert_eq!(
+            vec![ThrottleReason::Other],
+            decode_throttle_reasons(1 << 56)
+        );
+    }
+
+    #[test]
+    fn decode_combined_reasons() {
+        // SPL, FPPT, TDC_SOC, TEMP_EDGE and TEMP_MEM
+        let status = (1 << 4) | (1 << 5) | (1 << 17) | (1 << 35) | (1 << 34);
+        assert_eq!(
+            vec![
+                ThrottleReason::Power,
+                ThrottleReason::Current,
+                ThrottleReason::Thermal
+            ],
+            decode_throttle_reasons(status)
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Contains a `<name>_fw_version` file for each firmware component
+const FW_VERSION_DIR: &str = "fw_version";
+const FW_VERSION_SUFFIX: &str = "_fw_version";
+
+/// Reads the versions of the firmware components from the `fw_version` directory of the device.
+/// Components which are not used by the GPU are reported with a version of 0 and get left out.
+pub fn read_versions(device_path: &Path) -> BTreeMap<String, String> {
+    let Ok(entries) = fs::read_dir(device_path.join(FW_VERSION_DIR)) else {
+        return BTreeMap::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name();
+            let name = file_name
+                .to_str()?
+                .strip_suffix(FW_VERSION_SUFFIX)?
+                .to_owned();
+            let version = fs::read_to_string(entry.path()).ok()?.trim().to_owned();
+            (parse_hex(&version)? != 0).then_some((name, version))
+        })
+        .collect()
+}
+
+/// The power management firmware runs on the SMC, its version is made up of the lower three bytes
+/// of the SMC firmware version. Formatted the same way as in `amdgpu_firmware_info`.
+pub fn pmfw_version(smc_version: &str) -> Option<String> {
+    let [_, major, minor, patch] = parse_hex(smc_version)?.to_be_bytes();
+    Some(format!("{major}.{minor}.{patch}"))
+}
+
+fn parse_hex(value: &str) -> Option<u32> {
+    u32::from_str_radix(value.strip_prefix("0x")?, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pmfw_version, read_versions};
+    use pretty_assertions::assert_eq;
+    use std::{collections::BTreeMap, fs};
+
+    #[test]
+    fn firmware_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let fw_dir = dir.path().join("fw_version");
+        fs::create_dir(&fw_dir).unwrap();
+        fs::write(fw_dir.join("smc_fw_version"), "0x00504500\n").unwrap();
+        fs::write(fw_dir.join("mec_fw_version"), "0x000001f4\n").unwrap();
+        fs::write(fw_dir.join("vce_fw_version"), "0x00000000\n").unwrap();
+        fs::write(fw_dir.join("unrelated"), "0x00000001\n").unwrap();
+
+        let expected = BTreeMap::from([
+            ("mec".to_owned(), "0x000001f4".to_owned()),
+            ("smc".to_owned(), "0x00504500".to_owned()),
+        ]);
+        assert_eq!(expected, read_versions(dir.path()));
+
+        assert!(read_versions(&dir.path().join("missing")).is_empty());
+    }
+
+    #[test]
+    fn smc_version_to_pmfw() {
+        assert_eq!(Some("80.69.0".to_owned()), pmfw_version("0x00504500"));
+        assert_eq!(Some("78.129.2".to_owned()), pmfw_version("0x004e8102"));
+        assert_eq!(None, pmfw_version("80.69.0"));
+    }
+}
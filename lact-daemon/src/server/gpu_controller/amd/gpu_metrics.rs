@@ -0,0 +1,665 @@
+use anyhow::{bail, Context};
+use lact_schema::{ActivityStats, GpuDieStats, GpuMetricsStats};
+use std::{collections::BTreeMap, fs, path::Path};
+
+const HEADER_SIZE: usize = 4;
+/// Marker used by the firmware for fields that are not reported
+const UNAVAILABLE: u16 = u16::MAX;
+
+/// Offsets of the fields shared by the dGPU table versions 1.1 to 1.3
+mod v1 {
+    pub const TEMPERATURE_EDGE: usize = 4;
+    pub const TEMPERATURE_HOTSPOT: usize = 6;
+    pub const TEMPERATURE_MEM: usize = 8;
+    pub const TEMPERATURE_VRGFX: usize = 10;
+    pub const TEMPERATURE_VRSOC: usize = 12;
+    pub const TEMPERATURE_VRMEM: usize = 14;
+    pub const AVERAGE_GFX_ACTIVITY: usize = 16;
+    pub const AVERAGE_UMC_ACTIVITY: usize = 18;
+    pub const AVERAGE_MM_ACTIVITY: usize = 20;
+    pub const AVERAGE_SOCKET_POWER: usize = 22;
+    pub const ENERGY_ACCUMULATOR: usize = 24;
+    pub const AVERAGE_GFXCLK_FREQUENCY: usize = 40;
+    pub const AVERAGE_UCLK_FREQUENCY: usize = 44;
+    pub const CURRENT_GFXCLK: usize = 54;
+    pub const CURRENT_UCLK: usize = 58;
+    pub const CURRENT_FAN_SPEED: usize = 72;
+    pub const PCIE_LINK_WIDTH: usize = 74;
+    pub const PCIE_LINK_SPEED: usize = 76;
+    // Only present since version 1.3
+    pub const VOLTAGE_SOC: usize = 104;
+    pub const VOLTAGE_GFX: usize = 106;
+    pub const VOLTAGE_MEM: usize = 108;
+    pub const INDEP_THROTTLE_STATUS: usize = 112;
+
+    pub const V1_1_SIZE: usize = 96;
+    pub const V1_2_SIZE: usize = 104;
+    pub const V1_3_SIZE: usize = 120;
+}
+
+/// Offsets of the table versions 1.4 to 1.6, which are used by the multi-die MI300 series.
+/// Apart from the header they have a different layout than the earlier dGPU tables.
+///
+/// The temperatures, power and activity at the start are in the same place in all of them,
+/// the later versions move the rest of the fields around. All of them have a clock for each die,
+/// version 1.6 also has the activity of each die in the stats of the partitions.
+/// None of them has a temperature for each die, the temperatures cover the whole GPU.
+mod multi_die {
+    pub const TEMPERATURE_HOTSPOT: usize = 4;
+    pub const TEMPERATURE_MEM: usize = 6;
+    pub const TEMPERATURE_VRSOC: usize = 8;
+    pub const CURRENT_SOCKET_POWER: usize = 10;
+    pub const AVERAGE_GFX_ACTIVITY: usize = 12;
+    pub const AVERAGE_UMC_ACTIVITY: usize = 14;
+    /// Length of the arrays with a value for each die, dies which are not present
+    /// are marked as unavailable
+    pub const MAX_DIES: u8 = 8;
+
+    pub struct Layout {
+        pub energy_accumulator: usize,
+        pub pcie_link_width: usize,
+        pub pcie_link_speed: usize,
+        /// An array with the clock of each die
+        pub current_gfxclk: usize,
+        pub current_uclk: usize,
+        /// Smallest table which contains all of the decoded fields
+        pub min_size: usize,
+    }
+
+    pub const V1_4: Layout = Layout {
+        energy_accumulator: 24,
+        pcie_link_width: 48,
+        pcie_link_speed: 50,
+        current_gfxclk: 240,
+        current_uclk: 280,
+        min_size: 282,
+    };
+
+    /// Adds the activity of the JPEG engines in front of the energy accumulator
+    pub const V1_5: Layout = Layout {
+        energy_accumulator: 88,
+        pcie_link_width: 112,
+        pcie_link_speed: 114,
+        current_gfxclk: 312,
+        current_uclk: 352,
+        min_size: 354,
+    };
+
+    /// Moves the engine activity into the stats of each partition, which follow the clocks
+    pub const V1_6: Layout = Layout {
+        energy_accumulator: 16,
+        pcie_link_width: 60,
+        pcie_link_speed: 62,
+        current_gfxclk: 264,
+        current_uclk: 304,
+        min_size: XCP_STATS + XCP_STATS_SIZE * MAX_PARTITIONS,
+    };
+
+    // Only present since version 1.6
+    pub const NUM_PARTITION: usize = 306;
+    /// Stats of each partition, starting with a 32-bit activity of each of its dies.
+    /// The dies are numbered across the partitions in order.
+    pub const XCP_STATS: usize = 312;
+    pub const XCP_STATS_SIZE: usize = 168;
+    pub const MAX_PARTITIONS: usize = 8;
+}
+
+/// Offsets of the APU table versions 2.1 to 2.4. Only the fields which have the same meaning
+/// as in the dGPU tables are decoded, along with the separate `SoC` power and the fan PWM.
+mod v2 {
+    pub const AVERAGE_GFX_ACTIVITY: usize = 28;
+    pub const AVERAGE_MM_ACTIVITY: usize = 30;
+    pub const AVERAGE_SOCKET_POWER: usize = 40;
+    pub const AVERAGE_SOC_POWER: usize = 44;
+    pub const CURRENT_GFXCLK: usize = 76;
+    pub const FAN_PWM: usize = 112;
+    // Only present since version 2.2
+    pub const INDEP_THROTTLE_STATUS: usize = 120;
+
+    /// Smallest table which is accepted, the one that ends after the power fields.
+    /// The fields past it are checked one by one, and left out when the table ends before them.
+    pub const MIN_SIZE: usize = 48;
+}
+
+pub fn read_from_sysfs(device_path: &Path) -> anyhow::Result<GpuMetricsStats> {
+    let data = fs::read(device_path.join("gpu_metrics")).context("Could not read gpu_metrics")?;
+    parse(&data)
+}
+
+/// Decodes the table based on the version in its header.
+/// The layouts follow the `gpu_metrics_v*` structs in the kernel's `kgd_pp_interface.h`.
+pub fn parse(data: &[u8]) -> anyhow::Result<GpuMetricsStats> {
+    let header = data
+        .get(..HEADER_SIZE)
+        .context("gpu_metrics table is too short to contain a header")?;
+    let structure_size = usize::from(u16::from_le_bytes([header[0], header[1]]));
+    let format_revision = header[2];
+    let content_revision = header[3];
+
+    if data.len() < structure_size {
+        bail!(
+            "gpu_metrics table is truncated, expected {structure_size} bytes but got {}",
+            data.len()
+        );
+    }
+    let data = &data[..structure_size];
+
+    let min_size = match (format_revision, content_revision) {
+        (1, 1) => v1::V1_1_SIZE,
+        (1, 2) => v1::V1_2_SIZE,
+        (1, 3) => v1::V1_3_SIZE,
+        (1, 4) => multi_die::V1_4.min_size,
+        (1, 5) => multi_die::V1_5.min_size,
+        (1, 6) => multi_die::V1_6.min_size,
+        (2, 1..=4) => v2::MIN_SIZE,
+        _ => bail!("Unsupported gpu_metrics version {format_revision}.{content_revision}"),
+    };
+    if structure_size < min_size {
+        bail!(
+            "gpu_metrics table version {format_revision}.{content_revision} \
+            is too small: {structure_size} bytes"
+        );
+    }
+
+    let field = |offset| read_u16(data, offset);
+
+    if format_revision == 2 {
+        let die = GpuDieStats {
+            gfx_activity: field(v2::AVERAGE_GFX_ACTIVITY),
+            current_gfxclk: field(v2::CURRENT_GFXCLK),
+            ..Default::default()
+        };
+        return Ok(GpuMetricsStats {
+            format_revision,
+            content_revision,
+            average_gfx_activity: field(v2::AVERAGE_GFX_ACTIVITY),
+            average_mm_activity: field(v2::AVERAGE_MM_ACTIVITY),
+            average_socket_power: field(v2::AVERAGE_SOCKET_POWER).map(milliwatts_to_watts),
+            average_soc_power: field(v2::AVERAGE_SOC_POWER).map(milliwatts_to_watts),
+            current_gfxclk: field(v2::CURRENT_GFXCLK),
+            fan_pwm: field(v2::FAN_PWM),
+            indep_throttle_status: read_u64(data, v2::INDEP_THROTTLE_STATUS)
+                .filter(|_| content_revision >= 2),
+            dies: BTreeMap::from([(0, die)]),
+            ..Default::default()
+        });
+    }
+
+    let multi_die_layout = match content_revision {
+        4 => Some(multi_die::V1_4),
+        5 => Some(multi_die::V1_5),
+        6 => Some(multi_die::V1_6),
+        _ => None,
+    };
+    if let Some(layout) = multi_die_layout {
+        // Dies which are not present are left out
+        let mut dies: BTreeMap<u8, GpuDieStats> = (0..multi_die::MAX_DIES)
+            .filter_map(|index| {
+                let current_gfxclk = field(layout.current_gfxclk + usize::from(index) * 2)?;
+                let die = GpuDieStats {
+                    current_gfxclk: Some(current_gfxclk),
+                    ..Default::default()
+                };
+                Some((index, die))
+            })
+            .collect();
+        if content_revision >= 6 {
+            for (index, gfx_activity) in (0..multi_die::MAX_DIES).zip(die_activity(data)) {
+                dies.entry(index).or_default().gfx_activity = Some(gfx_activity);
+            }
+        }
+
+        return Ok(GpuMetricsStats {
+            format_revision,
+            content_revision,
+            temperature_hotspot: field(multi_die::TEMPERATURE_HOTSPOT),
+            temperature_mem: field(multi_die::TEMPERATURE_MEM),
+            temperature_vrsoc: field(multi_die::TEMPERATURE_VRSOC),
+            average_gfx_activity: field(multi_die::AVERAGE_GFX_ACTIVITY),
+            average_umc_activity: field(multi_die::AVERAGE_UMC_ACTIVITY),
+            average_socket_power: field(multi_die::CURRENT_SOCKET_POWER),
+            energy_accumulator: read_u64(data, layout.energy_accumulator),
+            current_uclk: field(layout.current_uclk),
+            pcie_link_width: field(layout.pcie_link_width),
+            pcie_link_speed: field(layout.pcie_link_speed),
+            dies,
+            ..Default::default()
+        });
+    }
+
+    let has_voltages = content_revision >= 3;
+    let die = GpuDieStats {
+        temperature_hotspot: field(v1::TEMPERATURE_HOTSPOT),
+        gfx_activity: field(v1::AVERAGE_GFX_ACTIVITY),
+        current_gfxclk: field(v1::CURRENT_GFXCLK),
+    };
+
+    Ok(GpuMetricsStats {
+        format_revision,
+        content_revision,
+        temperature_edge: field(v1::TEMPERATURE_EDGE),
+        temperature_hotspot: field(v1::TEMPERATURE_HOTSPOT),
+        temperature_mem: field(v1::TEMPERATURE_MEM),
+        temperature_vrgfx: field(v1::TEMPERATURE_VRGFX),
+        temperature_vrsoc: field(v1::TEMPERATURE_VRSOC),
+        temperature_vrmem: field(v1::TEMPERATURE_VRMEM),
+        average_gfx_activity: field(v1::AVERAGE_GFX_ACTIVITY),
+        average_umc_activity: field(v1::AVERAGE_UMC_ACTIVITY),
+        average_mm_activity: field(v1::AVERAGE_MM_ACTIVITY),
+        average_socket_power: field(v1::AVERAGE_SOCKET_POWER),
+        // The dGPU tables only report the power of the whole board
+        average_soc_power: None,
+        average_gfxclk_frequency: field(v1::AVERAGE_GFXCLK_FREQUENCY),
+        average_uclk_frequency: field(v1::AVERAGE_UCLK_FREQUENCY),
+        current_gfxclk: field(v1::CURRENT_GFXCLK),
+        current_uclk: field(v1::CURRENT_UCLK),
+        current_fan_speed: field(v1::CURRENT_FAN_SPEED),
+        pcie_link_width: field(v1::PCIE_LINK_WIDTH),
+        pcie_link_speed: field(v1::PCIE_LINK_SPEED),
+        voltage_soc: field(v1::VOLTAGE_SOC).filter(|_| has_voltages),
+        voltage_gfx: field(v1::VOLTAGE_GFX).filter(|_| has_voltages),
+        voltage_mem: field(v1::VOLTAGE_MEM).filter(|_| has_voltages),
+        energy_accumulator: read_u64(data, v1::ENERGY_ACCUMULATOR),
+        // The dGPU tables have no fan PWM, only the fan speed
+        fan_pwm: None,
+        indep_throttle_status: read_u64(data, v1::INDEP_THROTTLE_STATUS)
+            .filter(|_| content_revision >= 3),
+        dies: BTreeMap::from([(0, die)]),
+    })
+}
+
+/// Activity of each die in the partition stats of the version 1.6, in the order of the dies
+fn die_activity(data: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    let partitions = read_u16(data, multi_die::NUM_PARTITION)
+        .map_or(1, usize::from)
+        .min(multi_die::MAX_PARTITIONS);
+    (0..partitions).flat_map(move |partition| {
+        let stats = multi_die::XCP_STATS + partition * multi_die::XCP_STATS_SIZE;
+        (0..usize::from(multi_die::MAX_DIES))
+            .filter_map(move |die| read_u32(data, stats + die * 4))
+            .filter_map(|activity| u16::try_from(activity).ok())
+    })
+}
+
+/// Splits the usage by engine when the table is available
+pub fn activity(busy_percent: Option<u8>, metrics: Option<&GpuMetricsStats>) -> ActivityStats {
+    let percent = |value: Option<u16>| value.and_then(|value| u8::try_from(value).ok());
+    ActivityStats {
+        busy: busy_percent,
+        graphics: metrics.and_then(|metrics| percent(metrics.average_gfx_activity)),
+        media: metrics.and_then(|metrics| percent(metrics.average_mm_activity)),
+        memory_controller: metrics.and_then(|metrics| percent(metrics.average_umc_activity)),
+    }
+}
+
+/// The APU tables report power in mW, unlike the dGPU ones
+fn milliwatts_to_watts(value: u16) -> u16 {
+    value.saturating_add(500) / 1000
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+    (value != UNAVAILABLE).then_some(value)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    let value = u32::from_le_bytes(bytes.try_into().ok()?);
+    (value != u32::MAX).then_some(value)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    let value = u64::from_le_bytes(bytes.try_into().ok()?);
+    (value != u64::MAX).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{activity, multi_die, parse, v1, v2};
+    use lact_schema::{ActivityStats, GpuDieStats, GpuMetricsStats};
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeMap;
+
+    const V1_1: &[u8] = include_bytes!("../../../tests/gpu_metrics/v1_1.bin");
+    const V1_2: &[u8] = include_bytes!("../../../tests/gpu_metrics/v1_2.bin");
+    const V1_3: &[u8] = include_bytes!("../../../tests/gpu_metrics/v1_3.bin");
+    const V1_4: &[u8] = include_bytes!("../../../tests/gpu_metrics/v1_4.bin");
+    const V1_5: &[u8] = include_bytes!("../../../tests/gpu_metrics/v1_5.bin");
+    const V1_6: &[u8] = include_bytes!("../../../tests/gpu_metrics/v1_6.bin");
+    const V2_1: &[u8] = include_bytes!("../../../tests/gpu_metrics/v2_1.bin");
+
+    fn synthetic_stats(content_revision: u8) -> GpuMetricsStats {
+        GpuMetricsStats {
+            format_revision: 1,
+            content_revision,
+            temperature_edge: Some(45),
+            temperature_hotspot: Some(58),
+            temperature_mem: Some(62),
+            temperature_vrgfx: Some(50),
+            temperature_vrsoc: Some(47),
+            temperature_vrmem: None,
+            average_gfx_activity: Some(87),
+            average_umc_activity: Some(35),
+            average_mm_activity: Some(0),
+            average_socket_power: Some(212),
+            average_soc_power: None,
+            average_gfxclk_frequency: Some(2305),
+            average_uclk_frequency: Some(1000),
+            current_gfxclk: Some(2450),
+            current_uclk: Some(1000),
+            current_fan_speed: Some(1650),
+            pcie_link_width: Some(16),
+            pcie_link_speed: Some(160),
+            voltage_soc: None,
+            voltage_gfx: None,
+            voltage_mem: None,
+            energy_accumulator: Some(123_456_789),
+            fan_pwm: None,
+            indep_throttle_status: None,
+            dies: BTreeMap::from([(
+                0,
+                GpuDieStats {
+                    temperature_hotspot: Some(58),
+                    gfx_activity: Some(87),
+                    current_gfxclk: Some(2450),
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn decode_v1_1() {
+        assert_eq!(synthetic_stats(1), parse(V1_1).unwrap());
+    }
+
+    #[test]
+    fn decode_v1_2() {
+        assert_eq!(synthetic_stats(2), parse(V1_2).unwrap());
+    }
+
+    #[test]
+    fn decode_v1_3() {
+        let expected = GpuMetricsStats {
+            format_revision: 1,
+            content_revision: 3,
+            temperature_edge: Some(24),
+            temperature_hotspot: Some(31),
+            temperature_mem: Some(48),
+            temperature_vrgfx: Some(34),
+            temperature_vrsoc: Some(32),
+            temperature_vrmem: Some(34),
+            average_gfx_activity: Some(24),
+            average_umc_activity: Some(0),
+            average_mm_activity: Some(0),
+            average_socket_power: Some(32),
+            average_soc_power: None,
+            average_gfxclk_frequency: Some(2011),
+            average_uclk_frequency: Some(909),
+            current_gfxclk: Some(2011),
+            current_uclk: Some(456),
+            current_fan_speed: Some(868),
+            pcie_link_width: Some(16),
+            pcie_link_speed: Some(160),
+            voltage_soc: Some(768),
+            voltage_gfx: Some(985),
+            voltage_mem: Some(704),
+            energy_accumulator: Some(0),
+            fan_pwm: None,
+            indep_throttle_status: Some(0),
+            dies: BTreeMap::from([(
+                0,
+                GpuDieStats {
+                    temperature_hotspot: Some(31),
+                    gfx_activity: Some(24),
+                    current_gfxclk: Some(2011),
+                },
+            )]),
+        };
+        assert_eq!(expected, parse(V1_3).unwrap());
+    }
+
+    #[test]
+    fn decode_v2_1() {
+        let expected = GpuMetricsStats {
+            format_revision: 2,
+            content_revision: 1,
+            average_gfx_activity: Some(35),
+            average_mm_activity: Some(0),
+            average_socket_power: Some(15),
+            average_soc_power: Some(2),
+            dies: BTreeMap::from([(
+                0,
+                GpuDieStats {
+                    gfx_activity: Some(35),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        assert_eq!(expected, parse(V2_1).unwrap());
+    }
+
+    /// The multi-die fixtures all contain the same values
+    fn multi_die_stats(content_revision: u8, die_activity: &[u16]) -> GpuMetricsStats {
+        // Six dies are present, like on the MI300A
+        let dies = [2100, 2100, 2095, 2100, 1400, 2100]
+            .into_iter()
+            .zip(0..)
+            .map(|(current_gfxclk, index)| {
+                let die = GpuDieStats {
+                    current_gfxclk: Some(current_gfxclk),
+                    gfx_activity: die_activity.get(usize::from(index)).copied(),
+                    ..Default::default()
+                };
+                (index, die)
+            })
+            .collect();
+
+        GpuMetricsStats {
+            format_revision: 1,
+            content_revision,
+            temperature_hotspot: Some(61),
+            temperature_mem: Some(55),
+            temperature_vrsoc: Some(48),
+            average_gfx_activity: Some(73),
+            average_umc_activity: Some(21),
+            average_socket_power: Some(452),
+            current_uclk: Some(1300),
+            pcie_link_width: Some(16),
+            pcie_link_speed: Some(320),
+            energy_accumulator: Some(123_456_789),
+            dies,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decode_v1_4() {
+        assert_eq!(multi_die_stats(4, &[]), parse(V1_4).unwrap());
+    }
+
+    #[test]
+    fn decode_v1_5() {
+        assert_eq!(multi_die_stats(5, &[]), parse(V1_5).unwrap());
+    }
+
+    #[test]
+    fn decode_v1_6() {
+        let expected = multi_die_stats(6, &[70, 75, 72, 80, 60, 81]);
+        assert_eq!(expected, parse(V1_6).unwrap());
+    }
+
+    #[test]
+    fn die_activity_across_partitions() {
+        // Two partitions with three dies each
+        let mut data = V1_6.to_vec();
+        data[multi_die::NUM_PARTITION..multi_die::NUM_PARTITION + 2]
+            .copy_from_slice(&2u16.to_le_bytes());
+        let first = multi_die::XCP_STATS;
+        let second = multi_die::XCP_STATS + multi_die::XCP_STATS_SIZE;
+        data[first + 12..first + 24].fill(0xff);
+        for (index, activity) in [40u32, 41, 42].into_iter().enumerate() {
+            let offset = second + index * 4;
+            data[offset..offset + 4].copy_from_slice(&activity.to_le_bytes());
+        }
+
+        let metrics = parse(&data).unwrap();
+        let activity: Vec<Option<u16>> =
+            metrics.dies.values().map(|die| die.gfx_activity).collect();
+        assert_eq!(
+            vec![Some(70), Some(75), Some(72), Some(40), Some(41), Some(42)],
+            activity
+        );
+    }
+
+    #[test]
+    fn die_indices() {
+        // Dies which are not present are left out without changing the indices of the others
+        let mut data = V1_4.to_vec();
+        let offset = multi_die::V1_4.current_gfxclk + 2;
+        data[offset..offset + 2].fill(0xff);
+        let metrics = parse(&data).unwrap();
+        let indices: Vec<u8> = metrics.dies.into_keys().collect();
+        assert_eq!(vec![0, 2, 3, 4, 5], indices);
+
+        // Single die GPUs report the values of the whole GPU as the die 0
+        let metrics = parse(V1_3).unwrap();
+        assert_eq!(1, metrics.dies.len());
+        assert_eq!(metrics.current_gfxclk, metrics.dies[&0].current_gfxclk);
+        assert_eq!(
+            metrics.temperature_hotspot,
+            metrics.dies[&0].temperature_hotspot
+        );
+    }
+
+    #[test]
+    fn separate_soc_power() {
+        let mut data = V2_1.to_vec();
+        data[v2::AVERAGE_SOC_POWER..v2::AVERAGE_SOC_POWER + 2]
+            .copy_from_slice(&4800u16.to_le_bytes());
+        assert_eq!(Some(5), parse(&data).unwrap().average_soc_power);
+
+        // Not reported by the firmware
+        data[v2::AVERAGE_SOC_POWER..v2::AVERAGE_SOC_POWER + 2].fill(0xff);
+        let metrics = parse(&data).unwrap();
+        assert_eq!(None, metrics.average_soc_power);
+        assert_eq!(Some(15), metrics.average_socket_power);
+
+        // The board power is the only one in the dGPU tables
+        let metrics = parse(V1_3).unwrap();
+        assert_eq!(Some(32), metrics.average_socket_power);
+        assert_eq!(None, metrics.average_soc_power);
+    }
+
+    #[test]
+    fn apu_fan_and_throttle_status() {
+        let mut data = V2_1.to_vec();
+        data[v2::FAN_PWM..v2::FAN_PWM + 2].copy_from_slice(&120u16.to_le_bytes());
+        let metrics = parse(&data).unwrap();
+        assert_eq!(Some(120), metrics.fan_pwm);
+        assert_eq!(None, metrics.indep_throttle_status);
+
+        // The throttle status was added in version 2.2
+        data[..2].copy_from_slice(&128u16.to_le_bytes());
+        data[3] = 2;
+        data.resize(128, 0);
+        data[v2::INDEP_THROTTLE_STATUS..].copy_from_slice(&(1u64 << 33).to_le_bytes());
+        let metrics = parse(&data).unwrap();
+        assert_eq!(Some(1 << 33), metrics.indep_throttle_status);
+    }
+
+    #[test]
+    fn engine_activity() {
+        let mut data = V1_3.to_vec();
+        for (offset, value) in [
+            (v1::AVERAGE_GFX_ACTIVITY, 12u16),
+            (v1::AVERAGE_UMC_ACTIVITY, 30),
+            (v1::AVERAGE_MM_ACTIVITY, 85),
+        ] {
+            data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        }
+        let metrics = parse(&data).unwrap();
+
+        let expected = ActivityStats {
+            busy: Some(15),
+            graphics: Some(12),
+            media: Some(85),
+            memory_controller: Some(30),
+        };
+        assert_eq!(expected, activity(Some(15), Some(&metrics)));
+    }
+
+    #[test]
+    fn activity_without_metrics() {
+        let expected = ActivityStats {
+            busy: Some(40),
+            ..Default::default()
+        };
+        assert_eq!(expected, activity(Some(40), None));
+
+        // Engines which are not reported by the firmware
+        let mut data = V1_3.to_vec();
+        data[v1::AVERAGE_MM_ACTIVITY..v1::AVERAGE_MM_ACTIVITY + 2].fill(0xff);
+        let metrics = parse(&data).unwrap();
+        assert_eq!(None, activity(Some(40), Some(&metrics)).media);
+    }
+
+    #[test]
+    fn trailing_data_is_ignored() {
+        let mut data = V1_3.to_vec();
+        data.resize(4096, 0);
+        assert_eq!(parse(V1_3).unwrap(), parse(&data).unwrap());
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut data = V1_3.to_vec();
+        data[2] = 2;
+        data[3] = 0;
+        let err = parse(&data).unwrap_err();
+        assert_eq!("Unsupported gpu_metrics version 2.0", err.to_string());
+    }
+
+    #[test]
+    fn newer_multi_die_version_is_unsupported() {
+        let mut data = V1_6.to_vec();
+        data[3] = 7;
+        let err = parse(&data).unwrap_err();
+        assert_eq!("Unsupported gpu_metrics version 1.7", err.to_string());
+    }
+
+    #[test]
+    fn truncated_table() {
+        assert!(parse(&V1_3[..2]).is_err());
+        assert!(parse(&V1_3[..64]).is_err());
+    }
+
+    #[test]
+    fn fields_past_the_end_of_short_apu_table() {
+        let mut data = V2_1[..80].to_vec();
+        data[..2].copy_from_slice(&80u16.to_le_bytes());
+        data[v2::CURRENT_GFXCLK..v2::CURRENT_GFXCLK + 2].copy_from_slice(&1800u16.to_le_bytes());
+
+        let metrics = parse(&data).unwrap();
+        assert_eq!(Some(35), metrics.average_gfx_activity);
+        assert_eq!(Some(15), metrics.average_socket_power);
+        assert_eq!(Some(1800), metrics.current_gfxclk);
+        assert_eq!(None, metrics.fan_pwm);
+        assert_eq!(None, metrics.indep_throttle_status);
+
+        // Shorter than the power fields
+        let mut data = V2_1[..40].to_vec();
+        data[..2].copy_from_slice(&40u16.to_le_bytes());
+        assert!(parse(&data).is_err());
+    }
+
+    #[test]
+    fn size_too_small_for_version() {
+        // Header claiming version 1.3 with the size of a 1.1 table
+        let mut data = V1_1.to_vec();
+        data[3] = 3;
+        assert!(parse(&data).is_err());
+    }
+}
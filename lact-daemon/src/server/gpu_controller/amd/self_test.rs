@@ -0,0 +1,144 @@
+use lact_schema::{SelfTestControl, SelfTestEntry, SelfTestStatus};
+use nix::libc;
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::Path,
+};
+
+/// Writes the current value back into each control, so nothing gets changed.
+/// The clocks table can't be written back as-is, so it is only opened for writing,
+/// which sysfs refuses for files that the driver doesn't allow to be written.
+pub fn run(device_path: &Path, hw_mon_path: Option<&Path>) -> Vec<SelfTestEntry> {
+    let mut entries = Vec::new();
+
+    match hw_mon_path {
+        Some(hw_mon_path) => {
+            let fan_mode_path = hw_mon_path.join("pwm1_enable");
+            let fan_mode = fs::read_to_string(&fan_mode_path).ok();
+            entries.push(entry(SelfTestControl::FanControlMode, &fan_mode_path));
+
+            let fan_speed_path = hw_mon_path.join("pwm1");
+            if fan_mode.as_deref().map(str::trim) == Some("1") {
+                entries.push(entry(SelfTestControl::FanSpeed, &fan_speed_path));
+            } else {
+                entries.push(SelfTestEntry {
+                    control: SelfTestControl::FanSpeed,
+                    path: Some(fan_speed_path.display().to_string()),
+                    status: SelfTestStatus::Skipped {
+                        reason: "The fan speed can only be written with manual fan control"
+                            .to_owned(),
+                    },
+                });
+            }
+
+            entries.push(entry(
+                SelfTestControl::PowerCap,
+                &hw_mon_path.join("power1_cap"),
+            ));
+        }
+        None => {
+            for control in [
+                SelfTestControl::FanControlMode,
+                SelfTestControl::FanSpeed,
+                SelfTestControl::PowerCap,
+            ] {
+                entries.push(SelfTestEntry {
+                    control,
+                    path: None,
+                    status: SelfTestStatus::Unsupported,
+                });
+            }
+        }
+    }
+
+    entries.push(entry(
+        SelfTestControl::PerformanceLevel,
+        &device_path.join("power_dpm_force_performance_level"),
+    ));
+
+    let clocks_path = device_path.join("pp_od_clk_voltage");
+    let clocks_status = match OpenOptions::new().write(true).open(&clocks_path) {
+        Ok(_) => SelfTestStatus::Functional,
+        Err(err) => status_from_error(&err),
+    };
+    entries.push(SelfTestEntry {
+        control: SelfTestControl::Clocks,
+        path: Some(clocks_path.display().to_string()),
+        status: clocks_status,
+    });
+
+    entries
+}
+
+fn entry(control: SelfTestControl, path: &Path) -> SelfTestEntry {
+    SelfTestEntry {
+        control,
+        path: Some(path.display().to_string()),
+        status: write_back(path),
+    }
+}
+
+fn write_back(path: &Path) -> SelfTestStatus {
+    let current = match fs::read_to_string(path) {
+        Ok(current) => current,
+        Err(err) => return status_from_error(&err),
+    };
+    let current = current.trim();
+
+    if let Err(err) = fs::write(path, current) {
+        return status_from_error(&err);
+    }
+
+    match fs::read_to_string(path) {
+        Ok(value) if value.trim() == current => SelfTestStatus::Functional,
+        Ok(value) => SelfTestStatus::Failed {
+            message: format!("Wrote '{current}', but read back '{}'", value.trim()),
+        },
+        Err(err) => status_from_error(&err),
+    }
+}
+
+fn status_from_error(err: &io::Error) -> SelfTestStatus {
+    match err.kind() {
+        io::ErrorKind::NotFound => SelfTestStatus::Unsupported,
+        io::ErrorKind::PermissionDenied => SelfTestStatus::PermissionDenied,
+        _ if err.raw_os_error() == Some(libc::EOPNOTSUPP) => SelfTestStatus::Unsupported,
+        _ => SelfTestStatus::Failed {
+            message: err.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::status_from_error;
+    use lact_schema::SelfTestStatus;
+    use nix::libc;
+    use std::io;
+
+    #[test]
+    fn error_status() {
+        let status = |err: io::Error| status_from_error(&err);
+
+        assert_eq!(
+            SelfTestStatus::PermissionDenied,
+            status(io::Error::from_raw_os_error(libc::EACCES))
+        );
+        assert_eq!(
+            SelfTestStatus::Unsupported,
+            status(io::Error::from_raw_os_error(libc::ENOENT))
+        );
+        assert_eq!(
+            SelfTestStatus::Unsupported,
+            status(io::Error::from_raw_os_error(libc::EOPNOTSUPP))
+        );
+
+        // Firmware rejecting the value shows up as an invalid argument
+        let SelfTestStatus::Failed { message } = status(io::Error::from_raw_os_error(libc::EINVAL))
+        else {
+            panic!("invalid argument should be reported as a failure");
+        };
+        assert!(message.contains("Invalid argument"), "{message}");
+    }
+}
@@ -0,0 +1,119 @@
+use crate::AMDGPU_FAMILY_GC_11_0_0;
+use lact_schema::VfPoint;
+
+/// Explicit curve points, used on Navi 1x
+const VDDC_CURVE_SECTION: &str = "OD_VDDC_CURVE:";
+/// On older generations every core clock state has its own voltage
+const SCLK_SECTION: &str = "OD_SCLK:";
+
+/// Reads the voltage-frequency curve from the contents of `pp_od_clk_voltage`.
+///
+/// RDNA3 and newer only expose a voltage offset instead of the curve points,
+/// and RDNA2 lists the clocks without voltages, so there is no curve on those.
+pub fn parse(od_table: &str, family_id: Option<u32>) -> Option<Vec<VfPoint>> {
+    if family_id.is_some_and(|family| family >= AMDGPU_FAMILY_GC_11_0_0) {
+        return None;
+    }
+
+    parse_section(od_table, VDDC_CURVE_SECTION).or_else(|| parse_section(od_table, SCLK_SECTION))
+}
+
+/// Returns `None` if the section is missing or any of its entries don't have a voltage
+fn parse_section(od_table: &str, header: &str) -> Option<Vec<VfPoint>> {
+    let mut lines = od_table
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .skip_while(|line| *line != header);
+    lines.next()?;
+
+    let points = lines
+        .take_while(|line| !line.ends_with(':'))
+        .map(parse_point)
+        .collect::<Option<Vec<_>>>()?;
+    (!points.is_empty()).then_some(points)
+}
+
+/// Parses an entry such as `1:   1424MHz   805mV`
+fn parse_point(line: &str) -> Option<VfPoint> {
+    let (_, values) = line.split_once(':')?;
+    let mut values = values.split_whitespace();
+
+    let clockspeed = parse_value(values.next()?, "mhz")?;
+    let voltage = parse_value(values.next()?, "mv")?;
+    Some(VfPoint {
+        clockspeed,
+        voltage,
+    })
+}
+
+/// The unit capitalization is not consistent between generations
+fn parse_value(value: &str, unit: &str) -> Option<u32> {
+    value.to_ascii_lowercase().strip_suffix(unit)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use crate::AMDGPU_FAMILY_GC_11_0_0;
+    use lact_schema::VfPoint;
+    use pretty_assertions::assert_eq;
+
+    const DATA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/data/amd");
+
+    fn od_table(gpu: &str, card: &str) -> String {
+        let path = format!("{DATA_DIR}/{gpu}/{card}/device/pp_od_clk_voltage");
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    fn points(points: &[(u32, u32)]) -> Vec<VfPoint> {
+        points
+            .iter()
+            .map(|(clockspeed, voltage)| VfPoint {
+                clockspeed: *clockspeed,
+                voltage: *voltage,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn navi10_curve() {
+        let table = od_table("rx5700xt", "card1");
+        assert_eq!(
+            Some(points(&[(1351, 750), (1424, 805), (1900, 1100)])),
+            parse(&table, Some(143))
+        );
+    }
+
+    #[test]
+    fn polaris_states() {
+        let table = od_table("rx580", "card0");
+        let expected = points(&[
+            (300, 750),
+            (600, 769),
+            (900, 912),
+            (1145, 1125),
+            (1215, 1150),
+            (1257, 1150),
+            (1300, 1150),
+            (1366, 1150),
+        ]);
+        assert_eq!(Some(expected), parse(&table, Some(130)));
+    }
+
+    #[test]
+    fn rdna2_has_no_curve() {
+        let table = od_table("rx6900xt", "card0");
+        assert_eq!(None, parse(&table, Some(143)));
+    }
+
+    #[test]
+    fn rdna3_has_no_curve() {
+        let table = od_table("rx7900xtx", "card0");
+        assert_eq!(None, parse(&table, Some(AMDGPU_FAMILY_GC_11_0_0)));
+
+        // The family is checked before the table contents
+        let table = od_table("rx5700xt", "card1");
+        assert_eq!(None, parse(&table, Some(AMDGPU_FAMILY_GC_11_0_0)));
+    }
+}
@@ -1,8 +1,15 @@
+pub mod displays;
 pub mod fan_control;
 pub mod fdinfo;
+pub mod leds;
+pub mod resizable_bar;
+pub mod retry;
+pub mod thermal_limits;
 
+use amdgpu_sysfs::gpu_handle::overdrive::{ClocksTable as _, ClocksTableGen, Range};
+use lact_schema::{ClocksInfo, ClocksTable, DeviceStats, ErrorKind, GpuCapabilities, ValueRange};
 use libcopes::PID;
-use std::io;
+use std::{fmt::Display, fs, io, path::Path};
 use tracing::debug;
 
 pub fn resolve_process_name(pid: PID) -> io::Result<(String, String)> {
@@ -30,3 +37,149 @@ pub fn resolve_process_name(pid: PID) -> io::Result<(String, String)> {
 
     Ok((name, args))
 }
+
+/// Version of the kernel module used by the device.
+/// In-tree modules don't have a version, so this is only set for out of tree builds.
+pub fn read_driver_version(device_path: &Path) -> Option<String> {
+    let version = fs::read_to_string(device_path.join("driver/module/version")).ok()?;
+    Some(version.trim().to_owned()).filter(|version| !version.is_empty())
+}
+
+/// Checks a configured value against the limits reported by the GPU before it gets written,
+/// as the driver only rejects out of range values with a generic error
+pub fn validate_within_range<T: PartialOrd + Display>(
+    name: &str,
+    value: T,
+    min: Option<T>,
+    max: Option<T>,
+    unit: &str,
+) -> anyhow::Result<()> {
+    if let Some(min) = min {
+        if value < min {
+            return Err(ErrorKind::OutOfRange.error(format!(
+                "{name} {value}{unit} is below the minimum of {min}{unit}"
+            )));
+        }
+    }
+    if let Some(max) = max {
+        if value > max {
+            return Err(ErrorKind::OutOfRange.error(format!(
+                "{name} {value}{unit} is above the maximum of {max}{unit}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Fills in the capabilities that can be derived from the stats and the clocks table,
+/// which are reported the same way for all vendors
+pub fn base_capabilities(stats: &DeviceStats, clocks_info: Option<&ClocksInfo>) -> GpuCapabilities {
+    let mut capabilities = GpuCapabilities {
+        power_cap: stats.power.cap.range().map(ValueRange::from),
+        fan_curve_temperature: stats.fan.temperature_range.map(ValueRange::from),
+        ..Default::default()
+    };
+
+    match clocks_info.and_then(|info| info.table.as_ref()) {
+        Some(ClocksTable::Amd(table)) => {
+            let full_range = |range: Option<Range>| {
+                range
+                    .and_then(|range| range.into_full())
+                    .map(ValueRange::from)
+            };
+            capabilities.max_core_clock = full_range(table.get_max_sclk_range());
+            capabilities.min_core_clock = full_range(table.get_min_sclk_range());
+            capabilities.max_memory_clock = full_range(table.get_max_mclk_range());
+            capabilities.min_memory_clock = full_range(table.get_min_mclk_range());
+            capabilities.max_voltage = full_range(table.get_max_voltage_range());
+            capabilities.min_voltage = full_range(table.get_min_voltage_range());
+
+            if let ClocksTableGen::Rdna(table) = table {
+                // Offsets are only offered with the range reported by the driver
+                if table.voltage_offset.is_some() {
+                    capabilities.voltage_offset = full_range(table.od_range.voltage_offset);
+                }
+                if let Some(range) = full_range(table.od_range.sclk_offset) {
+                    capabilities.gpu_clock_offsets.insert(0, range);
+                }
+            }
+        }
+        Some(ClocksTable::Nvidia(table)) => {
+            capabilities.max_core_clock = table.gpu_clock_range.and_then(clock_range);
+            capabilities.min_core_clock = capabilities.max_core_clock;
+            capabilities.max_memory_clock = table.vram_clock_range.and_then(clock_range);
+            capabilities.min_memory_clock = capabilities.max_memory_clock;
+
+            let offset_ranges = [
+                (&table.gpu_offsets, &mut capabilities.gpu_clock_offsets),
+                (&table.mem_offsets, &mut capabilities.mem_clock_offsets),
+            ];
+            for (offsets, ranges) in offset_ranges {
+                ranges.extend(
+                    offsets.iter().map(|(pstate, offset)| {
+                        (*pstate, ValueRange::from((offset.min, offset.max)))
+                    }),
+                );
+            }
+        }
+        Some(ClocksTable::Intel(table)) => {
+            let range = table.rpn_freq.zip(table.rp0_freq).and_then(clock_range);
+            capabilities.max_core_clock = range;
+            capabilities.min_core_clock = range;
+        }
+        None => (),
+    }
+
+    capabilities
+}
+
+fn clock_range<T: TryInto<i32>>((min, max): (T, T)) -> Option<ValueRange<i32>> {
+    Some(ValueRange {
+        min: min.try_into().ok()?,
+        max: max.try_into().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_within_range;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn within_range() {
+        validate_within_range("Power cap", 150.0, Some(100.0), Some(200.0), "W").unwrap();
+        validate_within_range("Power cap", 100.0, Some(100.0), Some(200.0), "W").unwrap();
+        validate_within_range("Power cap", 200.0, Some(100.0), Some(200.0), "W").unwrap();
+        validate_within_range("Voltage offset", -500, None, None, "mV").unwrap();
+    }
+
+    #[test]
+    fn below_min() {
+        let err = validate_within_range("Power cap", 95.5, Some(100.0), Some(200.0), "W");
+        assert_eq!(
+            "Power cap 95.5W is below the minimum of 100W",
+            err.unwrap_err().to_string()
+        );
+
+        let err = validate_within_range("Voltage offset", -300, Some(-200), None, "mV");
+        assert_eq!(
+            "Voltage offset -300mV is below the minimum of -200mV",
+            err.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn above_max() {
+        let err = validate_within_range("Power cap", 250.0, Some(100.0), Some(200.0), "W");
+        assert_eq!(
+            "Power cap 250W is above the maximum of 200W",
+            err.unwrap_err().to_string()
+        );
+
+        let err = validate_within_range("Maximum core clock", 3500, None, Some(3000), "MHz");
+        assert_eq!(
+            "Maximum core clock 3500MHz is above the maximum of 3000MHz",
+            err.unwrap_err().to_string()
+        );
+    }
+}
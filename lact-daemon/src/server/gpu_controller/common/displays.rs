@@ -0,0 +1,136 @@
+use lact_schema::DisplayInfo;
+use std::{fs, path::Path};
+
+/// Reads the connectors of the device's DRM cards which have a display connected.
+/// Returns an empty list for headless GPUs, which don't have any connectors.
+pub fn read_displays(device_path: &Path) -> Vec<DisplayInfo> {
+    let mut displays = Vec::new();
+
+    let Ok(cards) = fs::read_dir(device_path.join("drm")) else {
+        return displays;
+    };
+
+    for card in cards.flatten() {
+        let card_name = card.file_name();
+        // Render nodes don't have connectors
+        let Some(card_name) = card_name.to_str().filter(|name| name.starts_with("card")) else {
+            continue;
+        };
+        let Ok(entries) = fs::read_dir(card.path()) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let Some(connector) = file_name
+                .to_str()
+                .and_then(|name| name.strip_prefix(card_name)?.strip_prefix('-'))
+            else {
+                continue;
+            };
+
+            let path = entry.path();
+            let read = |name: &str| {
+                fs::read_to_string(path.join(name))
+                    .ok()
+                    .map(|value| value.trim().to_owned())
+            };
+            if read("status").as_deref() != Some("connected") {
+                continue;
+            }
+
+            // Modes with the same resolution are listed once for every refresh rate
+            let mut modes: Vec<String> = Vec::new();
+            for mode in read("modes").unwrap_or_default().lines() {
+                if !modes.iter().any(|existing| existing == mode) {
+                    modes.push(mode.to_owned());
+                }
+            }
+
+            displays.push(DisplayInfo {
+                connector: connector.to_owned(),
+                enabled: read("enabled").as_deref() == Some("enabled"),
+                dpms: read("dpms"),
+                modes,
+            });
+        }
+    }
+
+    displays.sort_by(|a, b| a.connector.cmp(&b.connector));
+    displays
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_displays;
+    use lact_schema::DisplayInfo;
+    use pretty_assertions::assert_eq;
+    use std::{fs, path::Path};
+    use tempfile::tempdir;
+
+    fn write_connector(card_path: &Path, name: &str, files: &[(&str, &str)]) {
+        let connector_path = card_path.join(name);
+        fs::create_dir_all(&connector_path).unwrap();
+        for (file, contents) in files {
+            fs::write(connector_path.join(file), contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn connected_displays() {
+        let dir = tempdir().unwrap();
+        let card_path = dir.path().join("drm/card1");
+        write_connector(
+            &card_path,
+            "card1-DP-1",
+            &[
+                ("status", "connected\n"),
+                ("enabled", "enabled\n"),
+                ("dpms", "On\n"),
+                ("modes", "2560x1440\n2560x1440\n1920x1080\n2560x1440\n"),
+            ],
+        );
+        write_connector(
+            &card_path,
+            "card1-HDMI-A-1",
+            &[
+                ("status", "connected\n"),
+                ("enabled", "disabled\n"),
+                ("dpms", "Off\n"),
+                ("modes", "3840x2160\n"),
+            ],
+        );
+        write_connector(
+            &card_path,
+            "card1-DP-2",
+            &[("status", "disconnected\n"), ("enabled", "disabled\n")],
+        );
+        fs::create_dir_all(dir.path().join("drm/renderD128")).unwrap();
+
+        assert_eq!(
+            vec![
+                DisplayInfo {
+                    connector: "DP-1".to_owned(),
+                    enabled: true,
+                    dpms: Some("On".to_owned()),
+                    modes: vec!["2560x1440".to_owned(), "1920x1080".to_owned()],
+                },
+                DisplayInfo {
+                    connector: "HDMI-A-1".to_owned(),
+                    enabled: false,
+                    dpms: Some("Off".to_owned()),
+                    modes: vec!["3840x2160".to_owned()],
+                },
+            ],
+            read_displays(dir.path())
+        );
+    }
+
+    #[test]
+    fn headless() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("drm/card0")).unwrap();
+        assert!(read_displays(dir.path()).is_empty());
+        assert!(read_displays(&dir.path().join("missing")).is_empty());
+    }
+}
@@ -1,14 +1,99 @@
-use amdgpu_sysfs::{gpu_handle::fan_control::FanCurve as PmfwCurve, hw_mon::Temperature};
-use anyhow::{anyhow, bail, Context};
-use lact_schema::config::FanCurve;
-use tracing::warn;
+use amdgpu_sysfs::{
+    gpu_handle::fan_control::{FanCurve as PmfwCurve, FanCurveRanges},
+    hw_mon::Temperature,
+};
+use lact_schema::{
+    config::{ExternalTemperature, FanControlSettings, FanCurve, FanCurvePreset, FanCurveUnit},
+    CurveTemperature, ErrorKind, FanCurvePointValidation, FanCurveValidation, ValueRange,
+};
+use std::{collections::BTreeMap, rc::Rc, time::Duration};
+use tokio::time::{self, Instant, Interval, MissedTickBehavior};
+use tracing::{trace, warn};
+
+/// Temperatures that generated curves span, narrowed down to the range allowed by the GPU
+const PRESET_TEMPERATURE_RANGE: ValueRange<i32> = ValueRange { min: 40, max: 90 };
+const PRESET_SPEED_RANGE: ValueRange<u32> = ValueRange { min: 0, max: 100 };
+const PRESET_POINT_COUNT: u32 = 5;
 
 pub trait FanCurveExt {
-    fn pwm_at_temp(&self, temp: Temperature) -> u8;
+    fn pwm_at_temp(&self, temp: Temperature) -> u8 {
+        self.pwm_at_temp_with_unit(temp, FanCurveUnit::Percentage)
+    }
+
+    /// Interpolates the curve in the space of the given unit
+    fn pwm_at_temp_with_unit(&self, temp: Temperature, unit: FanCurveUnit) -> u8;
 
     fn into_pmfw_curve(self, current_pmfw_curve: PmfwCurve) -> anyhow::Result<PmfwCurve>;
 
-    fn validate(&self) -> anyhow::Result<()>;
+    fn validate(&self) -> anyhow::Result<()> {
+        self.validate_with_unit(FanCurveUnit::Percentage)
+    }
+
+    fn validate_with_unit(&self, unit: FanCurveUnit) -> anyhow::Result<()>;
+}
+
+pub fn validate_pwm_limits(settings: &FanControlSettings) -> anyhow::Result<()> {
+    if let (Some(min), Some(max)) = (settings.min_pwm, settings.max_pwm) {
+        if min > max {
+            return Err(ErrorKind::OutOfRange.error(format!(
+                "Minimum fan PWM {min} is higher than the maximum {max}"
+            )));
+        }
+    }
+    if let Some(step) = settings.spindown_step {
+        if step.is_nan() || step <= 0.0 || step > 1.0 {
+            return Err(ErrorKind::OutOfRange.error(format!(
+                "Fan spindown step {step} must be above 0 and at most 1"
+            )));
+        }
+    }
+    if let Some(offset) = settings.curve_offset {
+        if !(-1.0..=1.0).contains(&offset) {
+            return Err(ErrorKind::OutOfRange.error(format!(
+                "Fan curve offset {offset} must be between -1 and 1"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Ticks at which the fan curve gets evaluated, starting one interval from now.
+/// The ticks are at fixed deadlines, so when other tasks on the runtime delay an evaluation,
+/// the delay doesn't carry over to the following ones.
+pub fn evaluation_ticker(interval: Duration) -> Interval {
+    let mut ticker = time::interval_at(Instant::now() + interval, interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    ticker
+}
+
+/// Reads a temperature sensor of a GPU by its name
+pub type TemperatureReader = Rc<dyn Fn(&str) -> Option<f32>>;
+/// Temperature readers of all GPUs by their id, for fan curves which reference other GPUs
+pub type TemperatureSources = Rc<BTreeMap<String, TemperatureReader>>;
+
+/// Raises the temperature the curve gets evaluated at to the hottest of the referenced sensors.
+/// Sensors which can't be read, e.g. because their GPU was removed, are skipped.
+pub fn with_external_temperatures(
+    mut temp: Temperature,
+    external_temperatures: &[ExternalTemperature],
+    sources: &TemperatureSources,
+) -> Temperature {
+    for external in external_temperatures {
+        let reading = sources
+            .get(&external.gpu_id)
+            .and_then(|read| read(&external.temperature_key));
+        match reading {
+            Some(value) => {
+                temp.current = Some(temp.current.map_or(value, |current| current.max(value)));
+            }
+            None => trace!(
+                "could not read temperature {} of GPU {}, skipping it",
+                external.temperature_key,
+                external.gpu_id
+            ),
+        }
+    }
+    temp
 }
 
 #[allow(
@@ -17,7 +102,7 @@ pub trait FanCurveExt {
     clippy::cast_sign_loss
 )]
 impl FanCurveExt for FanCurve {
-    fn pwm_at_temp(&self, temp: Temperature) -> u8 {
+    fn pwm_at_temp_with_unit(&self, temp: Temperature, unit: FanCurveUnit) -> u8 {
         let current = temp.current.expect("No current temp");
 
         // This scenario is most likely unreachable as the kernel shuts down the GPU when it reaches critical temperature
@@ -26,82 +111,269 @@ impl FanCurveExt for FanCurve {
             return u8::MAX;
         }
 
-        let current = current as i32;
-        let maybe_lower = self.0.range(..current).next_back();
-        let maybe_higher = self.0.range(current..).next();
+        let key = CurveTemperature::from_celsius(current);
+        let maybe_lower = self.0.range(..key).next_back();
+        let maybe_higher = self.0.range(key..).next();
 
-        let percentage = match (maybe_lower, maybe_higher) {
+        let value = match (maybe_lower, maybe_higher) {
             (Some((lower_temp, lower_speed)), Some((higher_temp, higher_speed))) => {
-                let speed_ratio = (current - lower_temp) as f32 / (higher_temp - lower_temp) as f32;
-                lower_speed + (higher_speed - lower_speed) * speed_ratio
+                let lower_temp = lower_temp.celsius();
+                let speed_ratio = (current - lower_temp) / (higher_temp.celsius() - lower_temp);
+                (lower_speed + (higher_speed - lower_speed) * speed_ratio).clamp(
+                    lower_speed.min(*higher_speed),
+                    lower_speed.max(*higher_speed),
+                )
             }
             (Some((_, lower_speed)), None) => *lower_speed,
             (None, Some((_, higher_speed))) => *higher_speed,
             (None, None) => panic!("Could not find fan speed on the curve! This is a bug."),
         };
 
-        (f32::from(u8::MAX) * percentage) as u8
+        match unit {
+            FanCurveUnit::Percentage => (f32::from(u8::MAX) * value) as u8,
+            FanCurveUnit::Pwm => value as u8,
+        }
     }
 
     fn into_pmfw_curve(self, current_pmfw_curve: PmfwCurve) -> anyhow::Result<PmfwCurve> {
-        if current_pmfw_curve.points.len() != self.0.len() {
-            return Err(anyhow!(
-                "The GPU only supports {} curve points, given {}",
-                current_pmfw_curve.points.len(),
-                self.0.len()
-            ));
-        }
-        let allowed_ranges = current_pmfw_curve
-            .allowed_ranges
-            .context("The GPU does not allow fan curve modifications")?;
-        let min_percent = *allowed_ranges.speed_range.start();
-        let max_percent = *allowed_ranges.speed_range.end();
-        let min_temp = *allowed_ranges.temperature_range.start();
-        let max_temp = *allowed_ranges.temperature_range.end();
-
-        let points = self
+        check_pmfw_point_count(&current_pmfw_curve, self.0.len())?;
+        let allowed_ranges = current_pmfw_curve.allowed_ranges.ok_or_else(|| {
+            ErrorKind::Unsupported.error("The GPU does not allow fan curve modifications")
+        })?;
+
+        let points: Box<[(i32, u8)]> = self
             .0
             .into_iter()
             .map(|(temp, ratio)| {
-                let custom_percent = (ratio * 100.0) as u8;
-
-                if !(min_temp..=max_temp).contains(&temp) {
-                    bail!("Temperature {temp}℃ is outside of the allowed range {min_temp}℃ to {max_temp}℃");
-                }
-
-                if !(min_percent..=max_percent).contains(&custom_percent) {
-                    bail!("Speed {custom_percent}% is outside of the allowed range {min_percent}% to {max_percent}%");
-                }
-
-                Ok((temp, custom_percent))
+                Ok((
+                    pmfw_temperature(temp, &allowed_ranges)?,
+                    pmfw_speed(ratio, &allowed_ranges)?,
+                ))
             })
             .collect::<anyhow::Result<_>>()?;
 
+        if let Some(pair) = points.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(ErrorKind::OutOfRange.error(format!(
+                "Multiple curve points are at {}℃ after rounding to whole degrees",
+                pair[0].0
+            )));
+        }
+
         Ok(PmfwCurve {
             points,
             allowed_ranges: Some(allowed_ranges),
         })
     }
 
-    fn validate(&self) -> anyhow::Result<()> {
-        for percentage in self.0.values() {
-            if !(0.0..=1.0).contains(percentage) {
-                return Err(anyhow!("Fan speed percentage must be between 0 and 1"));
+    fn validate_with_unit(&self, unit: FanCurveUnit) -> anyhow::Result<()> {
+        for value in self.0.values() {
+            match unit {
+                FanCurveUnit::Percentage => {
+                    if !(0.0..=1.0).contains(value) {
+                        return Err(ErrorKind::OutOfRange
+                            .error("Fan speed percentage must be between 0 and 1"));
+                    }
+                }
+                FanCurveUnit::Pwm => {
+                    if !(0.0..=f32::from(u8::MAX)).contains(value) {
+                        return Err(
+                            ErrorKind::OutOfRange.error("Fan PWM value must be between 0 and 255")
+                        );
+                    }
+                }
             }
         }
         Ok(())
     }
 }
 
+fn check_pmfw_point_count(pmfw_curve: &PmfwCurve, count: usize) -> anyhow::Result<()> {
+    if pmfw_curve.points.len() != count {
+        return Err(ErrorKind::OutOfRange.error(format!(
+            "The GPU only supports {} curve points, given {count}",
+            pmfw_curve.points.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Converts the temperature of a point into the whole degrees accepted by the firmware
+fn pmfw_temperature(temp: CurveTemperature, ranges: &FanCurveRanges) -> anyhow::Result<i32> {
+    let min_temp = *ranges.temperature_range.start();
+    let max_temp = *ranges.temperature_range.end();
+    let firmware_temp = temp.rounded();
+
+    if !(min_temp..=max_temp).contains(&firmware_temp) {
+        return Err(ErrorKind::OutOfRange.error(format!(
+            "Temperature {temp}℃ is outside of the allowed range {min_temp}℃ to {max_temp}℃"
+        )));
+    }
+    Ok(firmware_temp)
+}
+
+/// Converts a speed ratio into the percentage used by the firmware
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn pmfw_speed(ratio: f32, ranges: &FanCurveRanges) -> anyhow::Result<u8> {
+    let min_percent = *ranges.speed_range.start();
+    let max_percent = *ranges.speed_range.end();
+    // Whole percentages stored as ratios, such as 0.53, end up slightly below them when scaled back
+    let custom_percent = (ratio * 100.0).round() as u8;
+
+    if !(min_percent..=max_percent).contains(&custom_percent) {
+        return Err(ErrorKind::OutOfRange.error(format!(
+            "Speed {custom_percent}% is outside of the allowed range {min_percent}% to {max_percent}%"
+        )));
+    }
+    Ok(custom_percent)
+}
+
+/// Checks every point of the curve separately, so that all of the invalid points can be shown.
+/// When the GPU has a firmware curve, the points also have to fit into its allowed ranges,
+/// and the curve gets converted the same way as when it's applied.
+pub fn validate_curve(
+    curve: &FanCurve,
+    unit: FanCurveUnit,
+    pmfw_curve: Option<&PmfwCurve>,
+) -> FanCurveValidation {
+    let percentage_curve = match unit {
+        FanCurveUnit::Percentage => curve.clone(),
+        FanCurveUnit::Pwm => curve.to_percentage_curve(),
+    };
+    let ranges = pmfw_curve.and_then(|pmfw_curve| pmfw_curve.allowed_ranges.as_ref());
+
+    let points = curve
+        .0
+        .iter()
+        .zip(percentage_curve.0.values())
+        .map(|((temp, speed), ratio)| {
+            let point = FanCurve(BTreeMap::from([(*temp, *speed)]));
+            let mut speed_error = point.validate_with_unit(unit).err();
+            let mut temperature_error = None;
+
+            if let Some(ranges) = ranges {
+                temperature_error = pmfw_temperature(*temp, ranges).err();
+                speed_error = speed_error.or_else(|| pmfw_speed(*ratio, ranges).err());
+            }
+
+            FanCurvePointValidation {
+                temperature: *temp,
+                speed: *speed,
+                temperature_error: temperature_error.map(|err| err.to_string()),
+                speed_error: speed_error.map(|err| err.to_string()),
+            }
+        })
+        .collect();
+    let mut validation = FanCurveValidation {
+        errors: vec![],
+        points,
+    };
+
+    if let Some(pmfw_curve) = pmfw_curve {
+        if let Err(err) = check_pmfw_point_count(pmfw_curve, curve.0.len()) {
+            validation.errors.push(err.to_string());
+        } else if validation.is_valid() {
+            // Problems which only show up after the conversion,
+            // such as points which end up at the same temperature after rounding
+            if let Err(err) = percentage_curve.into_pmfw_curve(pmfw_curve.clone()) {
+                validation.errors.push(err.to_string());
+            }
+        }
+    }
+
+    validation
+}
+
+/// Converts a firmware curve into a software one, the inverse of [`FanCurveExt::into_pmfw_curve`].
+/// Points outside of the allowed ranges get moved into them, so that the curve can be applied back.
+pub fn from_pmfw_curve(pmfw_curve: &PmfwCurve) -> FanCurve {
+    let ranges = pmfw_curve.allowed_ranges.as_ref();
+
+    let points = pmfw_curve
+        .points
+        .iter()
+        .map(|(temp, percent)| {
+            let (temp, percent) = match ranges {
+                Some(ranges) => (
+                    (*temp).clamp(
+                        *ranges.temperature_range.start(),
+                        *ranges.temperature_range.end(),
+                    ),
+                    (*percent).clamp(*ranges.speed_range.start(), *ranges.speed_range.end()),
+                ),
+                None => (*temp, *percent),
+            };
+            let ratio = (f32::from(percent) / 100.0).clamp(0.0, 1.0);
+            (CurveTemperature::from(temp), ratio)
+        })
+        .collect();
+
+    FanCurve(points)
+}
+
+/// Generates a curve with points spread evenly over the temperature range.
+/// The ranges and the point count come from the firmware curve when the GPU has one.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+pub fn generate_curve(
+    preset: FanCurvePreset,
+    temperature_range: Option<ValueRange<i32>>,
+    speed_range: Option<ValueRange<u32>>,
+    point_count: Option<u32>,
+) -> FanCurve {
+    // Share of the speed range used at the lowest and highest points,
+    // and how sharply the speed rises in between
+    let (low, high, exponent) = match preset {
+        FanCurvePreset::Silent => (0.0, 0.7, 2.0),
+        FanCurvePreset::Balanced => (0.15, 0.85, 1.5),
+        FanCurvePreset::Performance => (0.3, 1.0, 1.0),
+    };
+
+    let allowed_temps = temperature_range.unwrap_or(PRESET_TEMPERATURE_RANGE);
+    let min_temp = allowed_temps.min.max(PRESET_TEMPERATURE_RANGE.min);
+    let max_temp = allowed_temps.max.min(PRESET_TEMPERATURE_RANGE.max);
+    let (min_temp, max_temp) = if min_temp < max_temp {
+        (min_temp, max_temp)
+    } else {
+        (allowed_temps.min, allowed_temps.max)
+    };
+
+    let speeds = speed_range.unwrap_or(PRESET_SPEED_RANGE);
+    let steps = point_count.unwrap_or(PRESET_POINT_COUNT).max(2) - 1;
+
+    let points = (0..=steps)
+        .map(|step| {
+            let position = step as f32 / steps as f32;
+            let temp = min_temp + ((max_temp - min_temp) as f32 * position).round() as i32;
+
+            let share = low + (high - low) * position.powf(exponent);
+            let percent = speeds.min as f32 + (speeds.max - speeds.min) as f32 * share;
+            (CurveTemperature::from(temp), percent.round() / 100.0)
+        })
+        .collect();
+
+    FanCurve(points)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{FanCurve, PmfwCurve};
+    use super::{
+        evaluation_ticker, from_pmfw_curve, generate_curve, validate_curve, validate_pwm_limits,
+        with_external_temperatures, FanCurve, PmfwCurve, TemperatureReader, TemperatureSources,
+    };
     use crate::server::gpu_controller::common::fan_control::FanCurveExt;
     use amdgpu_sysfs::{gpu_handle::fan_control::FanCurveRanges, hw_mon::Temperature};
-    use anyhow::anyhow;
+    use anyhow::{anyhow, Context};
+    use lact_schema::{
+        config::{ExternalTemperature, FanControlSettings, FanCurvePreset, FanCurveUnit},
+        CurveTemperature, ErrorKind, FanControlMode, Response, ValueRange,
+    };
+    use std::{collections::BTreeMap, rc::Rc, time::Duration};
+    use tokio::{
+        task::LocalSet,
+        time::{self, Instant},
+    };
 
     fn simple_pwm(temp: f32) -> u8 {
-        let curve = FanCurve([(0, 0.0), (100, 1.0)].into());
+        let curve = FanCurve::from([(0, 0.0), (100, 1.0)]);
         let temp = Temperature {
             current: Some(temp),
             crit: Some(150.0),
@@ -142,7 +414,7 @@ mod tests {
 
     #[test]
     fn curve_crit() {
-        let curve = FanCurve([(20, 0.0), (80, 100.0)].into());
+        let curve = FanCurve::from([(20, 0.0), (80, 100.0)]);
         let temp = Temperature {
             current: Some(100.0),
             crit: Some(90.0),
@@ -154,7 +426,7 @@ mod tests {
 
     #[test]
     fn uneven_curve() {
-        let curve = FanCurve([(30, 0.0), (40, 0.1), (55, 0.9), (61, 1.0)].into());
+        let curve = FanCurve::from([(30, 0.0), (40, 0.1), (55, 0.9), (61, 1.0)]);
         let pwm_at_temp = |current: f32| {
             let temp = Temperature {
                 current: Some(current),
@@ -208,12 +480,28 @@ mod tests {
         assert_eq!(&expected_points, pmfw_curve.points.as_ref());
     }
 
+    #[test]
+    fn curve_to_pmfw_keeps_whole_percentages() {
+        // 0.53 * 100 and 0.59 * 100 are just below 53 and 59 as `f32`
+        let curve = FanCurve::from([(40, 0.3), (50, 0.35), (60, 0.53), (70, 0.59), (80, 1.0)]);
+        let current_pmfw_curve = PmfwCurve {
+            points: Box::new([(0, 0); 5]),
+            allowed_ranges: Some(FanCurveRanges {
+                temperature_range: 25..=100,
+                speed_range: 15..=100,
+            }),
+        };
+        let pmfw_curve = curve.into_pmfw_curve(current_pmfw_curve).unwrap();
+        let expected_points = [(40, 30), (50, 35), (60, 53), (70, 59), (80, 100)];
+        assert_eq!(&expected_points, pmfw_curve.points.as_ref());
+    }
+
     #[test]
     fn curve_outside_of_limits_to_pmfw() {
         let curve_invalid_temp =
-            FanCurve([(20, 0.4), (50, 0.35), (60, 0.5), (70, 0.75), (80, 1.0)].into());
+            FanCurve::from([(20, 0.4), (50, 0.35), (60, 0.5), (70, 0.75), (80, 1.0)]);
         let curve_invalid_speed =
-            FanCurve([(40, 0.1), (50, 0.35), (60, 0.5), (70, 0.75), (80, 1.0)].into());
+            FanCurve::from([(40, 0.1), (50, 0.35), (60, 0.5), (70, 0.75), (80, 1.0)]);
 
         let current_pmfw_curve = PmfwCurve {
             points: Box::new([(0, 0); 5]),
@@ -237,4 +525,585 @@ mod tests {
                 .to_string()
         );
     }
+
+    fn validation_pmfw_curve() -> PmfwCurve {
+        PmfwCurve {
+            points: Box::new([(0, 0); 5]),
+            allowed_ranges: Some(FanCurveRanges {
+                temperature_range: 25..=100,
+                speed_range: 30..=100,
+            }),
+        }
+    }
+
+    /// The points with errors, along with the temperature and speed errors
+    fn invalid_points(
+        curve: &FanCurve,
+        unit: FanCurveUnit,
+        pmfw_curve: Option<&PmfwCurve>,
+    ) -> Vec<(usize, Option<String>, Option<String>)> {
+        validate_curve(curve, unit, pmfw_curve)
+            .points
+            .into_iter()
+            .enumerate()
+            .filter(|(_, point)| point.temperature_error.is_some() || point.speed_error.is_some())
+            .map(|(i, point)| (i, point.temperature_error, point.speed_error))
+            .collect()
+    }
+
+    #[test]
+    fn validate_valid_curve() {
+        let pmfw_curve = validation_pmfw_curve();
+        let curve = FanCurve::from([(40, 0.3), (50, 0.35), (60, 0.5), (70, 0.75), (80, 1.0)]);
+
+        let validation = validate_curve(&curve, FanCurveUnit::Percentage, Some(&pmfw_curve));
+        assert!(validation.is_valid(), "{validation:?}");
+        assert_eq!(5, validation.points.len());
+        assert_eq!(CurveTemperature::from(60), validation.points[2].temperature);
+        assert_eq!(0.5, validation.points[2].speed);
+
+        // The same curve in PWM values
+        let pwm_curve = FanCurve::from([
+            (40, 77.0),
+            (50, 90.0),
+            (60, 128.0),
+            (70, 191.0),
+            (80, 255.0),
+        ]);
+        let validation = validate_curve(&pwm_curve, FanCurveUnit::Pwm, Some(&pmfw_curve));
+        assert!(validation.is_valid(), "{validation:?}");
+
+        // Without a firmware curve any temperature can be used
+        let curve = FanCurve::from([(10, 0.1), (105, 1.0)]);
+        assert!(validate_curve(&curve, FanCurveUnit::Percentage, None).is_valid());
+    }
+
+    #[test]
+    fn validate_temperature_out_of_range() {
+        let pmfw_curve = validation_pmfw_curve();
+        let curve = FanCurve::from([(20, 0.4), (50, 0.35), (60, 0.5), (70, 0.75), (110, 1.0)]);
+
+        assert_eq!(
+            vec![
+                (
+                    0,
+                    Some("Temperature 20℃ is outside of the allowed range 25℃ to 100℃".to_owned()),
+                    None,
+                ),
+                (
+                    4,
+                    Some("Temperature 110℃ is outside of the allowed range 25℃ to 100℃".to_owned()),
+                    None,
+                ),
+            ],
+            invalid_points(&curve, FanCurveUnit::Percentage, Some(&pmfw_curve))
+        );
+        let validation = validate_curve(&curve, FanCurveUnit::Percentage, Some(&pmfw_curve));
+        assert!(!validation.is_valid());
+        assert!(validation.errors.is_empty());
+    }
+
+    #[test]
+    fn validate_speed_out_of_range() {
+        let pmfw_curve = validation_pmfw_curve();
+        let curve = FanCurve::from([(40, 0.1), (50, 0.35), (60, 0.5), (70, 0.75), (80, 1.0)]);
+        assert_eq!(
+            vec![(
+                0,
+                None,
+                Some("Speed 10% is outside of the allowed range 30% to 100%".to_owned()),
+            )],
+            invalid_points(&curve, FanCurveUnit::Percentage, Some(&pmfw_curve))
+        );
+
+        // Speeds above 100% are refused before the range of the firmware gets checked
+        let curve = FanCurve::from([(40, 0.3), (80, 1.5)]);
+        assert_eq!(
+            vec![(
+                1,
+                None,
+                Some("Fan speed percentage must be between 0 and 1".to_owned()),
+            )],
+            invalid_points(&curve, FanCurveUnit::Percentage, None)
+        );
+
+        // The point count only gets reported for the whole curve
+        let validation = validate_curve(&curve, FanCurveUnit::Percentage, Some(&pmfw_curve));
+        assert_eq!(
+            vec!["The GPU only supports 5 curve points, given 2".to_owned()],
+            validation.errors
+        );
+    }
+
+    fn fractional_curve(points: &[(f32, f32)]) -> FanCurve {
+        FanCurve(
+            points
+                .iter()
+                .map(|(temp, speed)| (CurveTemperature::from_celsius(*temp), *speed))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn fractional_curve_points() {
+        let curve = fractional_curve(&[(40.5, 0.0), (41.5, 1.0)]);
+        assert_eq!(0, curve.pwm_at_temp(temp(40.0)));
+        assert_eq!(0, curve.pwm_at_temp(temp(40.5)));
+        assert_eq!(127, curve.pwm_at_temp(temp(41.0)));
+        assert_eq!(191, curve.pwm_at_temp(temp(41.25)));
+        assert_eq!(255, curve.pwm_at_temp(temp(41.5)));
+        assert_eq!(255, curve.pwm_at_temp(temp(42.0)));
+    }
+
+    #[test]
+    fn fractional_curve_to_pmfw() {
+        let current_pmfw_curve = PmfwCurve {
+            points: Box::new([(0, 0); 3]),
+            allowed_ranges: Some(FanCurveRanges {
+                temperature_range: 25..=100,
+                speed_range: 0..=100,
+            }),
+        };
+
+        let curve = fractional_curve(&[(40.4, 0.3), (50.5, 0.5), (61.0, 0.8)]);
+        let pmfw_curve = curve.into_pmfw_curve(current_pmfw_curve.clone()).unwrap();
+        assert_eq!(&[(40, 30), (51, 50), (61, 80)], pmfw_curve.points.as_ref());
+
+        let curve = fractional_curve(&[(40.2, 0.3), (40.4, 0.5), (61.0, 0.8)]);
+        assert_eq!(
+            "Multiple curve points are at 40℃ after rounding to whole degrees",
+            curve
+                .into_pmfw_curve(current_pmfw_curve)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn pmfw_curve_to_software() {
+        let pmfw_curve = PmfwCurve {
+            points: Box::new([(40, 25), (50, 40), (60, 60), (70, 80), (80, 100)]),
+            allowed_ranges: Some(FanCurveRanges {
+                temperature_range: 25..=100,
+                speed_range: 15..=100,
+            }),
+        };
+
+        let curve = from_pmfw_curve(&pmfw_curve);
+        assert_eq!(
+            FanCurve::from([(40, 0.25), (50, 0.4), (60, 0.6), (70, 0.8), (80, 1.0)]),
+            curve
+        );
+
+        let converted_back = curve.into_pmfw_curve(pmfw_curve.clone()).unwrap();
+        assert_eq!(pmfw_curve.points, converted_back.points);
+    }
+
+    #[test]
+    fn pmfw_curve_outside_of_limits_to_software() {
+        // Some firmwares report an empty curve before it has been changed
+        let pmfw_curve = PmfwCurve {
+            points: Box::new([(0, 0), (50, 40), (110, 120)]),
+            allowed_ranges: Some(FanCurveRanges {
+                temperature_range: 25..=100,
+                speed_range: 15..=100,
+            }),
+        };
+        let curve = from_pmfw_curve(&pmfw_curve);
+        assert_eq!(FanCurve::from([(25, 0.15), (50, 0.4), (100, 1.0)]), curve);
+        assert!(curve.into_pmfw_curve(pmfw_curve).is_ok());
+
+        let unrestricted_curve = PmfwCurve {
+            points: Box::new([(30, 150)]),
+            allowed_ranges: None,
+        };
+        assert_eq!(
+            FanCurve::from([(30, 1.0)]),
+            from_pmfw_curve(&unrestricted_curve)
+        );
+    }
+
+    #[test]
+    fn software_curve_pmfw_round_trip() {
+        let current_pmfw_curve = PmfwCurve {
+            points: Box::new([(0, 0); 5]),
+            allowed_ranges: Some(FanCurveRanges {
+                temperature_range: 25..=100,
+                speed_range: 0..=100,
+            }),
+        };
+        let curve = fractional_curve(&[
+            (40.0, 0.333),
+            (50.2, 0.456),
+            (60.0, 0.5),
+            (69.8, 0.751),
+            (80.0, 0.999),
+        ]);
+
+        let pmfw_curve = curve.clone().into_pmfw_curve(current_pmfw_curve).unwrap();
+        let round_tripped = from_pmfw_curve(&pmfw_curve);
+        assert_eq!(curve.0.len(), round_tripped.0.len());
+        for ((temp, speed), (new_temp, new_speed)) in curve.0.iter().zip(&round_tripped.0) {
+            assert!((temp.celsius() - new_temp.celsius()).abs() <= 0.5);
+            assert!((speed - new_speed).abs() <= 0.005, "{speed} -> {new_speed}");
+        }
+
+        // Converting the firmware curve again does not change it any further
+        let pmfw_curve_again = round_tripped.into_pmfw_curve(pmfw_curve.clone()).unwrap();
+        assert_eq!(pmfw_curve.points, pmfw_curve_again.points);
+    }
+
+    fn temp(current: f32) -> Temperature {
+        Temperature {
+            current: Some(current),
+            crit: Some(150.0),
+            crit_hyst: Some(-100.0),
+        }
+    }
+
+    #[test]
+    fn pwm_curve_matches_percentage_curve() {
+        let percentage_curve =
+            FanCurve::from([(30, 0.0), (40, 0.1), (50, 0.6), (60, 0.8), (80, 1.0)]);
+        let pwm_curve = percentage_curve.to_pwm_curve();
+        assert_eq!(
+            FanCurve::from([(30, 0.0), (40, 25.5), (50, 153.0), (60, 204.0), (80, 255.0)]),
+            pwm_curve
+        );
+
+        for current in [20.0, 30.0, 35.0, 45.0, 52.0, 61.0, 79.0, 90.0] {
+            assert_eq!(
+                percentage_curve.pwm_at_temp(temp(current)),
+                pwm_curve.pwm_at_temp_with_unit(temp(current), FanCurveUnit::Pwm),
+                "{current}°C"
+            );
+        }
+    }
+
+    #[test]
+    fn pwm_curve_interpolates_raw_values() {
+        let curve = FanCurve::from([(40, 50.0), (60, 150.0)]);
+        assert_eq!(
+            50,
+            curve.pwm_at_temp_with_unit(temp(30.0), FanCurveUnit::Pwm)
+        );
+        assert_eq!(
+            100,
+            curve.pwm_at_temp_with_unit(temp(50.0), FanCurveUnit::Pwm)
+        );
+        assert_eq!(
+            150,
+            curve.pwm_at_temp_with_unit(temp(70.0), FanCurveUnit::Pwm)
+        );
+    }
+
+    #[test]
+    fn pwm_curve_round_trip() {
+        let curve = FanCurve::from([(40, 0.2), (60, 0.5), (80, 1.0)]);
+        assert_eq!(curve, curve.to_pwm_curve().to_percentage_curve());
+    }
+
+    #[test]
+    fn validate_pwm_curve() {
+        let curve = FanCurve::from([(40, 100.0), (80, 255.0)]);
+        curve.validate_with_unit(FanCurveUnit::Pwm).unwrap();
+        assert!(curve.validate().is_err());
+
+        let curve = FanCurve::from([(40, 100.0), (80, 256.0)]);
+        assert_eq!(
+            "Fan PWM value must be between 0 and 255",
+            curve
+                .validate_with_unit(FanCurveUnit::Pwm)
+                .unwrap_err()
+                .to_string()
+        );
+        let curve = FanCurve::from([(40, -1.0), (80, 255.0)]);
+        assert!(curve.validate_with_unit(FanCurveUnit::Pwm).is_err());
+    }
+
+    #[test]
+    fn pwm_limits_override_curve() {
+        let curve = FanCurve::from([(40, 0.0), (60, 0.5), (80, 1.0)]);
+        let settings = FanControlSettings {
+            min_pwm: Some(51),
+            max_pwm: Some(204),
+            ..Default::default()
+        };
+        let pwm_at_temp = |current: f32| settings.clamp_pwm(curve.pwm_at_temp(temp(current)));
+
+        assert_eq!(51, pwm_at_temp(30.0));
+        assert_eq!(51, pwm_at_temp(44.0));
+        assert_eq!(127, pwm_at_temp(60.0));
+        assert_eq!(204, pwm_at_temp(75.0));
+        assert_eq!(204, pwm_at_temp(90.0));
+    }
+
+    #[test]
+    fn pwm_limits_unset() {
+        let curve = FanCurve::from([(40, 0.0), (80, 1.0)]);
+        let settings = FanControlSettings::default();
+
+        assert_eq!(0, settings.clamp_pwm(curve.pwm_at_temp(temp(30.0))));
+        assert_eq!(255, settings.clamp_pwm(curve.pwm_at_temp(temp(90.0))));
+    }
+
+    #[test]
+    fn invalid_pwm_limits() {
+        let settings = FanControlSettings {
+            min_pwm: Some(200),
+            max_pwm: Some(100),
+            ..Default::default()
+        };
+        assert!(validate_pwm_limits(&settings).is_err());
+    }
+
+    #[test]
+    fn curve_offset() {
+        let curve = FanCurve::from([(40, 0.2), (60, 0.5), (80, 1.0)]);
+        let pwm_at_temp = |settings: &FanControlSettings, current: f32| {
+            settings.clamp_pwm(settings.apply_curve_offset(curve.pwm_at_temp(temp(current))))
+        };
+
+        let settings = FanControlSettings {
+            mode: FanControlMode::OffsetCurve,
+            curve_offset: Some(0.1),
+            ..Default::default()
+        };
+        assert_eq!(77, pwm_at_temp(&settings, 30.0));
+        assert_eq!(153, pwm_at_temp(&settings, 60.0));
+        // Clamped at the full speed
+        assert_eq!(255, pwm_at_temp(&settings, 80.0));
+
+        let settings = FanControlSettings {
+            curve_offset: Some(-0.3),
+            min_pwm: Some(20),
+            ..settings
+        };
+        assert_eq!(20, pwm_at_temp(&settings, 30.0));
+        assert_eq!(50, pwm_at_temp(&settings, 60.0));
+        assert_eq!(178, pwm_at_temp(&settings, 80.0));
+
+        let settings = FanControlSettings {
+            min_pwm: None,
+            ..settings
+        };
+        assert_eq!(0, pwm_at_temp(&settings, 30.0));
+
+        // The offset is only used by the offset curve mode
+        let settings = FanControlSettings {
+            mode: FanControlMode::Curve,
+            ..settings
+        };
+        assert_eq!(51, pwm_at_temp(&settings, 30.0));
+    }
+
+    #[test]
+    fn invalid_curve_offset() {
+        for offset in [-1.5, 1.01, f32::NAN] {
+            let settings = FanControlSettings {
+                curve_offset: Some(offset),
+                ..Default::default()
+            };
+            assert!(validate_pwm_limits(&settings).is_err(), "offset {offset}");
+        }
+    }
+
+    #[test]
+    fn gradual_spindown() {
+        let curve = FanCurve::from([(40, 0.0), (80, 1.0)]);
+        let settings = FanControlSettings {
+            spindown_step: Some(0.1),
+            ..Default::default()
+        };
+
+        let mut pwm = 0;
+        let mut set_pwms = vec![];
+        for current in [40.0, 80.0, 85.0, 40.0, 40.0, 40.0, 60.0, 40.0] {
+            let curve_pwm = settings.clamp_pwm(curve.pwm_at_temp(temp(current)));
+            pwm = settings.limit_spindown(pwm, curve_pwm);
+            set_pwms.push(pwm);
+        }
+
+        // The spike is followed right away, but the drop is limited to 10% (26) per evaluation
+        assert_eq!(vec![0, 255, 255, 229, 203, 177, 151, 125], set_pwms);
+    }
+
+    #[test]
+    fn spindown_step_unset() {
+        let settings = FanControlSettings::default();
+        assert_eq!(0, settings.limit_spindown(255, 0));
+        assert_eq!(255, settings.limit_spindown(0, 255));
+    }
+
+    #[test]
+    fn invalid_spindown_step() {
+        for step in [0.0, -0.1, 1.5, f32::NAN] {
+            let settings = FanControlSettings {
+                spindown_step: Some(step),
+                ..Default::default()
+            };
+            assert!(validate_pwm_limits(&settings).is_err(), "step {step}");
+        }
+    }
+
+    #[test]
+    fn curve_out_of_range_error_kind() {
+        let curve = FanCurve::from([(40, 0.5), (80, 1.5)]);
+        let err = curve
+            .validate()
+            .context("Could not set fan control")
+            .unwrap_err();
+        assert_eq!(Some(ErrorKind::OutOfRange), ErrorKind::from_error(&err));
+
+        let Response::<()>::Error(response_err) = Response::from(err) else {
+            panic!("expected an error response");
+        };
+        assert_eq!(Some(ErrorKind::OutOfRange), response_err.kind);
+        assert_eq!("Could not set fan control", response_err.to_string());
+    }
+
+    #[test]
+    fn pmfw_curve_error_kinds() {
+        let curve = FanCurve::from([(20, 0.4), (50, 0.35), (60, 0.5), (70, 0.75), (80, 1.0)]);
+        let mut current_pmfw_curve = PmfwCurve {
+            points: Box::new([(0, 0); 5]),
+            allowed_ranges: Some(FanCurveRanges {
+                temperature_range: 25..=100,
+                speed_range: 30..=100,
+            }),
+        };
+
+        let err = curve
+            .clone()
+            .into_pmfw_curve(current_pmfw_curve.clone())
+            .unwrap_err();
+        assert_eq!(Some(ErrorKind::OutOfRange), ErrorKind::from_error(&err));
+
+        current_pmfw_curve.allowed_ranges = None;
+        let err = curve.into_pmfw_curve(current_pmfw_curve).unwrap_err();
+        assert_eq!(Some(ErrorKind::Unsupported), ErrorKind::from_error(&err));
+    }
+
+    #[test]
+    fn generated_curves_fit_pmfw_ranges() {
+        let current_pmfw_curve = PmfwCurve {
+            points: Box::new([(0, 0); 5]),
+            allowed_ranges: Some(FanCurveRanges {
+                temperature_range: 25..=100,
+                speed_range: 15..=100,
+            }),
+        };
+
+        for preset in FanCurvePreset::ALL {
+            let curve = generate_curve(
+                preset,
+                Some(ValueRange::from((25, 100))),
+                Some(ValueRange::from((15, 100))),
+                Some(5),
+            );
+            assert_eq!(5, curve.0.len(), "{preset:?}");
+            curve.validate().unwrap();
+
+            let speeds: Vec<f32> = curve.0.values().copied().collect();
+            assert!(
+                speeds.windows(2).all(|pair| pair[0] <= pair[1]),
+                "{preset:?} is not monotonic: {speeds:?}"
+            );
+
+            let pmfw_curve = curve.into_pmfw_curve(current_pmfw_curve.clone()).unwrap();
+            for (temp, speed) in pmfw_curve.points.as_ref() {
+                assert!((25..=100).contains(temp), "{preset:?}");
+                assert!((15..=100).contains(speed), "{preset:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn presets_are_ordered_by_speed() {
+        let generate = |preset| generate_curve(preset, None, None, None);
+        let curves = FanCurvePreset::ALL.map(generate);
+        for curve in &curves {
+            let temps: Vec<i32> = curve.0.keys().map(|temp| temp.rounded()).collect();
+            assert_eq!(vec![40, 53, 65, 78, 90], temps);
+        }
+
+        // Every point of a louder preset is at least as fast as the one of a quieter preset
+        for pair in curves.windows(2) {
+            let (quieter, louder) = (&pair[0].0, &pair[1].0);
+            assert!(quieter.values().zip(louder.values()).all(|(a, b)| a <= b));
+        }
+    }
+
+    #[test]
+    fn hottest_external_temperature() {
+        let reader = |junction: f32| -> TemperatureReader {
+            Rc::new(move |key: &str| match key {
+                "edge" => Some(50.0),
+                "junction" => Some(junction),
+                _ => None,
+            })
+        };
+        let sources: TemperatureSources = Rc::new(BTreeMap::from([
+            ("gpu-a".to_owned(), reader(70.0)),
+            ("gpu-b".to_owned(), reader(55.0)),
+        ]));
+        let external = |gpu_id: &str, temperature_key: &str| ExternalTemperature {
+            gpu_id: gpu_id.to_owned(),
+            temperature_key: temperature_key.to_owned(),
+        };
+
+        let evaluated = with_external_temperatures(
+            temp(60.0),
+            &[external("gpu-a", "junction"), external("gpu-b", "junction")],
+            &sources,
+        );
+        assert_eq!(Some(70.0), evaluated.current);
+
+        // Cooler GPUs don't lower the temperature, and missing ones are skipped
+        let evaluated = with_external_temperatures(
+            temp(60.0),
+            &[external("gpu-b", "edge"), external("gpu-c", "edge")],
+            &sources,
+        );
+        assert_eq!(Some(60.0), evaluated.current);
+        let evaluated =
+            with_external_temperatures(temp(60.0), &[external("gpu-a", "mem")], &sources);
+        assert_eq!(Some(60.0), evaluated.current);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn evaluation_ticker_under_load() {
+        LocalSet::new()
+            .run_until(async {
+                // Keeps the runtime busy most of the time, like slow reads in other tasks would.
+                // The blocking is simulated by advancing the paused clock, so the timing is exact.
+                let load = tokio::task::spawn_local(async {
+                    loop {
+                        time::advance(Duration::from_millis(30)).await;
+                        time::sleep(Duration::from_millis(5)).await;
+                    }
+                });
+
+                let interval = Duration::from_millis(50);
+                let mut ticker = evaluation_ticker(interval);
+                let start = Instant::now();
+                let mut previous_tick = start;
+                for _ in 0..10 {
+                    ticker.tick().await;
+                    let gap = previous_tick.elapsed();
+                    assert!(gap < interval + Duration::from_millis(50), "{gap:?}");
+                    previous_tick = Instant::now();
+                }
+                load.abort();
+
+                // Each evaluation is late by at most one blocking period, which doesn't add up
+                let elapsed = start.elapsed();
+                assert!(elapsed >= interval * 10, "{elapsed:?}");
+                assert!(
+                    elapsed < interval * 10 + Duration::from_millis(80),
+                    "{elapsed:?}"
+                );
+            })
+            .await;
+    }
 }
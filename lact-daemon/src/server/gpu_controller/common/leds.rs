@@ -0,0 +1,251 @@
+use lact_schema::{config::LedSettings, ErrorKind, LedInfo};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Reads the LEDs which the kernel exposes for the device through the LED class.
+/// LEDs are only listed when both their brightness and maximum brightness can be read.
+pub fn read_leds(device_path: &Path) -> Vec<LedInfo> {
+    let Ok(entries) = fs::read_dir(device_path.join("leds")) else {
+        return vec![];
+    };
+
+    let mut leds: Vec<LedInfo> = entries
+        .flatten()
+        .filter_map(|entry| read_led(&entry.path()))
+        .collect();
+    leds.sort_by(|a, b| a.name.cmp(&b.name));
+    leds
+}
+
+fn read_led(path: &Path) -> Option<LedInfo> {
+    let name = path.file_name()?.to_str()?.to_owned();
+    let read = |file_name: &str| fs::read_to_string(path.join(file_name)).ok();
+
+    let brightness = read("brightness")?.trim().parse().ok()?;
+    let max_brightness = read("max_brightness")?.trim().parse().ok()?;
+    let color_channels = read("multi_index")
+        .map(|contents| contents.split_whitespace().map(str::to_owned).collect())
+        .unwrap_or_default();
+    let color = read("multi_intensity")
+        .and_then(|contents| {
+            contents
+                .split_whitespace()
+                .map(|value| value.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Some(LedInfo {
+        name,
+        brightness,
+        max_brightness,
+        color_channels,
+        color,
+    })
+}
+
+/// Checks the settings against what the LED supports.
+/// Only LEDs which were read from the device can be set,
+/// so the name can't point outside of its `leds` directory.
+pub fn check_led_settings(
+    leds: &[LedInfo],
+    name: &str,
+    settings: &LedSettings,
+) -> anyhow::Result<()> {
+    let led = leds.iter().find(|led| led.name == name).ok_or_else(|| {
+        ErrorKind::Unsupported.error(format!("The GPU does not have an LED named `{name}`"))
+    })?;
+
+    if settings
+        .brightness
+        .is_some_and(|brightness| brightness > led.max_brightness)
+    {
+        return Err(ErrorKind::OutOfRange.error(format!(
+            "The brightness of `{name}` can be at most {}",
+            led.max_brightness
+        )));
+    }
+
+    if let Some(color) = &settings.color {
+        if led.color_channels.is_empty() {
+            return Err(
+                ErrorKind::Unsupported.error(format!("The LED `{name}` only has a single color"))
+            );
+        }
+        if color.len() != led.color_channels.len() {
+            return Err(ErrorKind::OutOfRange.error(format!(
+                "The LED `{name}` has {} color channels: {}",
+                led.color_channels.len(),
+                led.color_channels.join(", ")
+            )));
+        }
+        if color
+            .iter()
+            .any(|intensity| *intensity > led.max_brightness)
+        {
+            return Err(ErrorKind::OutOfRange.error(format!(
+                "The color intensities of `{name}` can be at most {}",
+                led.max_brightness
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// The current state of the LED, in the form of settings which restore it
+pub fn led_state(led: &LedInfo) -> LedSettings {
+    LedSettings {
+        brightness: Some(led.brightness),
+        color: (!led.color.is_empty()).then(|| led.color.clone()),
+    }
+}
+
+/// Files to write for changing an LED to the given settings.
+/// The color comes first, as the kernel scales the channel intensities by the brightness.
+pub fn led_writes(
+    device_path: &Path,
+    name: &str,
+    settings: &LedSettings,
+) -> Vec<(PathBuf, String)> {
+    let led_path = device_path.join("leds").join(name);
+    let mut writes = Vec::new();
+
+    if let Some(color) = &settings.color {
+        let value = color
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        writes.push((led_path.join("multi_intensity"), value));
+    }
+    if let Some(brightness) = settings.brightness {
+        writes.push((led_path.join("brightness"), brightness.to_string()));
+    }
+
+    writes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_led_settings, led_writes, read_leds};
+    use lact_schema::{config::LedSettings, ErrorKind, LedInfo};
+    use pretty_assertions::assert_eq;
+    use std::{fs, path::Path};
+    use tempfile::tempdir;
+
+    fn create_led(device_path: &Path, name: &str, files: &[(&str, &str)]) {
+        let led_path = device_path.join("leds").join(name);
+        fs::create_dir_all(&led_path).unwrap();
+        for (file_name, contents) in files {
+            fs::write(led_path.join(file_name), contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn read_single_color_and_multicolor_leds() {
+        let dir = tempdir().unwrap();
+        create_led(
+            dir.path(),
+            "amdgpu:rgb:status",
+            &[
+                ("brightness", "128\n"),
+                ("max_brightness", "255\n"),
+                ("multi_index", "red green blue\n"),
+                ("multi_intensity", "255 0 64\n"),
+            ],
+        );
+        create_led(
+            dir.path(),
+            "amdgpu:white:logo",
+            &[("brightness", "0\n"), ("max_brightness", "1\n")],
+        );
+        // Not readable as an LED
+        create_led(dir.path(), "amdgpu::broken", &[("brightness", "1\n")]);
+
+        assert_eq!(
+            vec![
+                LedInfo {
+                    name: "amdgpu:rgb:status".to_owned(),
+                    brightness: 128,
+                    max_brightness: 255,
+                    color_channels: vec!["red".to_owned(), "green".to_owned(), "blue".to_owned()],
+                    color: vec![255, 0, 64],
+                },
+                LedInfo {
+                    name: "amdgpu:white:logo".to_owned(),
+                    brightness: 0,
+                    max_brightness: 1,
+                    color_channels: vec![],
+                    color: vec![],
+                },
+            ],
+            read_leds(dir.path())
+        );
+
+        let settings = LedSettings {
+            brightness: Some(200),
+            color: Some(vec![0, 255, 0]),
+        };
+        let led_path = dir.path().join("leds/amdgpu:rgb:status");
+        assert_eq!(
+            vec![
+                (led_path.join("multi_intensity"), "0 255 0".to_owned()),
+                (led_path.join("brightness"), "200".to_owned()),
+            ],
+            led_writes(dir.path(), "amdgpu:rgb:status", &settings)
+        );
+    }
+
+    #[test]
+    fn check_settings() {
+        let dir = tempdir().unwrap();
+        create_led(
+            dir.path(),
+            "amdgpu:rgb:status",
+            &[
+                ("brightness", "0\n"),
+                ("max_brightness", "255\n"),
+                ("multi_index", "red green blue\n"),
+                ("multi_intensity", "255 255 255\n"),
+            ],
+        );
+        let leds = read_leds(dir.path());
+        let check = |name: &str, brightness, color: Option<Vec<u32>>| {
+            let settings = LedSettings {
+                brightness: Some(brightness),
+                color,
+            };
+            check_led_settings(&leds, name, &settings).map_err(|err| ErrorKind::from_error(&err))
+        };
+
+        assert_eq!(
+            Ok(()),
+            check("amdgpu:rgb:status", 255, Some(vec![0, 0, 255]))
+        );
+        assert_eq!(
+            Err(Some(ErrorKind::OutOfRange)),
+            check("amdgpu:rgb:status", 256, None)
+        );
+        assert_eq!(
+            Err(Some(ErrorKind::OutOfRange)),
+            check("amdgpu:rgb:status", 255, Some(vec![255, 0]))
+        );
+        // Names which are not an LED of the device can't be used to reach other files
+        for name in [
+            "amdgpu:white:logo",
+            "../../power_dpm_force_performance_level",
+            ".",
+        ] {
+            assert_eq!(Err(Some(ErrorKind::Unsupported)), check(name, 1, None));
+        }
+    }
+
+    #[test]
+    fn no_leds() {
+        let dir = tempdir().unwrap();
+        assert_eq!(Vec::<LedInfo>::new(), read_leds(dir.path()));
+    }
+}
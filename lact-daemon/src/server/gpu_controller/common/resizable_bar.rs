@@ -0,0 +1,136 @@
+use lact_schema::ResizableBarInfo;
+use std::{fs, path::Path};
+
+/// Amount of standard BARs in the PCI config space
+const BAR_COUNT: usize = 6;
+/// Bit `n` of the supported sizes mask stands for a size of `1 MiB << n`
+const MIN_BAR_SIZE: u64 = 1024 * 1024;
+const MAX_SIZE_BITS: u32 = 44;
+
+/// Reads the configuration of the first resizable BAR of the device.
+/// Returns `None` when the device or the platform doesn't expose resizable BARs.
+pub fn read_resizable_bar(device_path: &Path) -> Option<ResizableBarInfo> {
+    let resources = fs::read_to_string(device_path.join("resource")).ok()?;
+    let current_sizes: Vec<Option<u64>> = resources
+        .lines()
+        .take(BAR_COUNT)
+        .map(parse_resource_size)
+        .collect();
+
+    (0..BAR_COUNT).find_map(|index| {
+        let resize_path = device_path.join(format!("resource{index}_resize"));
+        let raw_mask = fs::read_to_string(resize_path).ok()?;
+        let mask = u64::from_str_radix(raw_mask.trim().trim_start_matches("0x"), 16).ok()?;
+
+        let current_size = current_sizes.get(index).copied().flatten()?;
+        let supported_sizes = (0..MAX_SIZE_BITS)
+            .filter(|bit| mask & (1 << bit) != 0)
+            .map(|bit| MIN_BAR_SIZE << bit)
+            .collect();
+
+        Some(ResizableBarInfo {
+            bar_index: u8::try_from(index).ok()?,
+            current_size,
+            supported_sizes,
+        })
+    })
+}
+
+/// Parses a `start end flags` line of the `resource` file, returning `None` for unused BARs
+fn parse_resource_size(line: &str) -> Option<u64> {
+    let mut parts = line.split_whitespace();
+    let start = parse_hex(parts.next()?)?;
+    let end = parse_hex(parts.next()?)?;
+
+    if end == 0 || end < start {
+        None
+    } else {
+        Some(end - start + 1)
+    }
+}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.trim_start_matches("0x"), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_resizable_bar;
+    use lact_schema::ResizableBarInfo;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use tempfile::tempdir;
+
+    const MIB: u64 = 1024 * 1024;
+
+    const RESOURCE_256M: &str = "\
+0x00000000d0000000 0x00000000dfffffff 0x000000000014220c
+0x0000000000000000 0x0000000000000000 0x0000000000000000
+0x00000000e0000000 0x00000000e01fffff 0x000000000014220c
+0x0000000000000000 0x0000000000000000 0x0000000000000000
+0x000000000000d000 0x000000000000d0ff 0x0000000000040101
+0x00000000fce00000 0x00000000fce3ffff 0x0000000000040200
+0x00000000000c0000 0x00000000000dffff 0x0000000000000212
+";
+
+    const RESOURCE_16G: &str = "\
+0x0000007c00000000 0x0000007fffffffff 0x000000000014220c
+0x0000000000000000 0x0000000000000000 0x0000000000000000
+0x0000007800000000 0x00000078001fffff 0x000000000014220c
+0x0000000000000000 0x0000000000000000 0x0000000000000000
+0x000000000000e000 0x000000000000e0ff 0x0000000000040101
+0x00000000fcd00000 0x00000000fcdfffff 0x0000000000040200
+";
+
+    #[test]
+    fn rebar_disabled() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("resource"), RESOURCE_256M).unwrap();
+        // 256 MiB up to 16 GiB
+        fs::write(dir.path().join("resource0_resize"), "0000000000007f00\n").unwrap();
+
+        let info = read_resizable_bar(dir.path()).unwrap();
+        assert_eq!(
+            ResizableBarInfo {
+                bar_index: 0,
+                current_size: 256 * MIB,
+                supported_sizes: (8..=14).map(|bit| MIB << bit).collect(),
+            },
+            info
+        );
+        assert_eq!(Some(16 * 1024 * MIB), info.max_size());
+        assert!(!info.is_max_size());
+    }
+
+    #[test]
+    fn rebar_enabled() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("resource"), RESOURCE_16G).unwrap();
+        fs::write(dir.path().join("resource0_resize"), "0000000000007f00\n").unwrap();
+
+        let info = read_resizable_bar(dir.path()).unwrap();
+        assert_eq!(16 * 1024 * MIB, info.current_size);
+        assert!(info.is_max_size());
+    }
+
+    #[test]
+    fn rebar_on_other_bar() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("resource"), RESOURCE_256M).unwrap();
+        fs::write(dir.path().join("resource2_resize"), "0000000000000003\n").unwrap();
+
+        let info = read_resizable_bar(dir.path()).unwrap();
+        assert_eq!(2, info.bar_index);
+        assert_eq!(2 * MIB, info.current_size);
+        assert_eq!(vec![MIB, 2 * MIB], info.supported_sizes);
+    }
+
+    #[test]
+    fn rebar_unsupported() {
+        let dir = tempdir().unwrap();
+        assert_eq!(None, read_resizable_bar(dir.path()));
+
+        fs::write(dir.path().join("resource"), RESOURCE_256M).unwrap();
+        assert_eq!(None, read_resizable_bar(dir.path()));
+    }
+}
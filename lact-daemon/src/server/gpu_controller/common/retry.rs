@@ -0,0 +1,115 @@
+use anyhow::Context;
+use nix::libc;
+use std::{fs, io, path::Path, time::Duration};
+use tokio::time;
+use tracing::warn;
+
+/// Errors which the driver returns while the GPU is momentarily busy, such as during a power state
+/// transition. Anything else (like `EINVAL` for a rejected value) is reported right away.
+const TRANSIENT_ERRORS: [i32; 3] = [libc::EBUSY, libc::EAGAIN, libc::EINTR];
+
+/// How failed sysfs writes get retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteRetry {
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on each further attempt
+    pub backoff: Duration,
+}
+
+impl WriteRetry {
+    /// Runs the given write, retrying it when it fails with a transient error.
+    /// Waiting for the next attempt doesn't block the runtime, so fan control keeps running.
+    pub async fn run<T>(&self, mut write: impl FnMut() -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let mut delay = self.backoff;
+        let mut attempt = 0;
+
+        loop {
+            match write() {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt < self.max_retries && is_transient(&err) => {
+                    attempt += 1;
+                    warn!(
+                        "sysfs write failed: {err:#}, retrying in {delay:?} (attempt {attempt}/{})",
+                        self.max_retries
+                    );
+                    time::sleep(delay).await;
+                    delay = delay.saturating_mul(2);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub async fn write(&self, path: &Path, contents: &str) -> anyhow::Result<()> {
+        self.run(|| {
+            fs::write(path, contents).with_context(|| format!("Could not write {}", path.display()))
+        })
+        .await
+    }
+}
+
+fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .filter_map(io::Error::raw_os_error)
+        .any(|code| TRANSIENT_ERRORS.contains(&code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WriteRetry;
+    use anyhow::Context;
+    use nix::libc;
+    use std::{io, time::Duration};
+
+    const RETRY: WriteRetry = WriteRetry {
+        max_retries: 3,
+        backoff: Duration::from_millis(1),
+    };
+
+    /// Runs a write which fails with the given errors in order before succeeding,
+    /// returns the result and the amount of attempts
+    async fn run_failing(errors: &[i32]) -> (anyhow::Result<()>, usize) {
+        let mut attempts = 0;
+        let result = RETRY
+            .run(|| {
+                attempts += 1;
+                match errors.get(attempts - 1) {
+                    Some(code) => {
+                        Err(io::Error::from_raw_os_error(*code)).context("Could not write")
+                    }
+                    None => Ok(()),
+                }
+            })
+            .await;
+        (result, attempts)
+    }
+
+    #[tokio::test]
+    async fn busy_then_success() {
+        let (result, attempts) = run_failing(&[libc::EBUSY, libc::EBUSY]).await;
+        result.unwrap();
+        assert_eq!(3, attempts);
+    }
+
+    #[tokio::test]
+    async fn permanent_error_fails_fast() {
+        for code in [libc::EINVAL, libc::EACCES] {
+            let (result, attempts) = run_failing(&[code]).await;
+            assert!(result.is_err());
+            assert_eq!(1, attempts);
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_exhausted() {
+        let (result, attempts) = run_failing(&[libc::EAGAIN; 5]).await;
+        let err = result.unwrap_err();
+        assert_eq!(4, attempts);
+        assert_eq!(
+            Some(libc::EAGAIN),
+            err.downcast_ref::<io::Error>()
+                .and_then(io::Error::raw_os_error)
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use lact_schema::ThermalLimits;
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Reads the temperature thresholds of the sensors of a hwmon device.
+/// Sensors without a label are named by their index, the same as in the stats.
+/// Sensors which have none of the thresholds are skipped.
+pub fn read_thermal_limits(hw_mon_path: &Path) -> BTreeMap<String, ThermalLimits> {
+    let mut limits = BTreeMap::new();
+
+    let Ok(entries) = fs::read_dir(hw_mon_path) else {
+        return limits;
+    };
+
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let Some(sensor) = file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix("_input"))
+            .filter(|name| name.starts_with("temp"))
+        else {
+            continue;
+        };
+
+        let read = |suffix: &str| read_temperature(&hw_mon_path.join(format!("{sensor}_{suffix}")));
+        let sensor_limits = ThermalLimits {
+            max: read("max"),
+            crit: read("crit"),
+            emergency: read("emergency"),
+        };
+        if sensor_limits == ThermalLimits::default() {
+            continue;
+        }
+
+        let name = match fs::read_to_string(hw_mon_path.join(format!("{sensor}_label"))) {
+            Ok(label) => trim_value(&label).to_owned(),
+            Err(_) => sensor.trim_start_matches("temp").to_owned(),
+        };
+        limits.insert(name, sensor_limits);
+    }
+
+    limits
+}
+
+/// Reads a temperature file in millidegrees
+fn read_temperature(path: &Path) -> Option<f32> {
+    let value: i32 = trim_value(&fs::read_to_string(path).ok()?).parse().ok()?;
+    #[allow(clippy::cast_precision_loss)]
+    let celsius = value as f32 / 1000.0;
+    Some(celsius)
+}
+
+/// Some drivers pad the values with null bytes
+fn trim_value(value: &str) -> &str {
+    value.trim_matches(|c: char| c.is_whitespace() || c == '\0')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_thermal_limits;
+    use lact_schema::ThermalLimits;
+    use pretty_assertions::assert_eq;
+    use std::{collections::BTreeMap, fs};
+    use tempfile::tempdir;
+
+    #[test]
+    fn unlabeled_sensor() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("temp1_input"), "45000\n").unwrap();
+        fs::write(dir.path().join("temp1_crit"), "120000\n").unwrap();
+        // Sensors without any thresholds are skipped
+        fs::write(dir.path().join("temp2_input"), "50000\n").unwrap();
+
+        let limits = read_thermal_limits(dir.path());
+        assert_eq!(
+            BTreeMap::from([(
+                "1".to_owned(),
+                ThermalLimits {
+                    crit: Some(120.0),
+                    ..Default::default()
+                }
+            )]),
+            limits
+        );
+
+        assert!(read_thermal_limits(&dir.path().join("missing")).is_empty());
+    }
+}
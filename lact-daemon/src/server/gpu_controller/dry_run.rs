@@ -0,0 +1,129 @@
+use super::{
+    common::fan_control::{TemperatureReader, TemperatureSources},
+    CommonControllerInfo, DynGpuController, GpuController,
+};
+use amdgpu_sysfs::gpu_handle::power_profile_mode::PowerProfileModesTable;
+use futures::{future::LocalBoxFuture, FutureExt};
+use lact_schema::{
+    config::{FanCurve, FanCurveUnit, GpuConfig},
+    ClocksInfo, ConfigDifference, DeviceInfo, DeviceStats, DeviceType, ErrorKind,
+    FanCurveValidation, GpuCapabilities, PowerStates, ProcessList, SelfTestEntry, SysfsWrite,
+    VersionsInfo,
+};
+use tracing::warn;
+
+/// Wraps a controller so that configuration changes go through the regular apply code,
+/// with its writes only being recorded and logged. All of the reads still go to the real device.
+pub struct DryRunController {
+    inner: DynGpuController,
+}
+
+impl DryRunController {
+    pub fn new(inner: DynGpuController) -> Self {
+        *inner.controller_info().dry_run_writes.borrow_mut() = Some(Vec::new());
+        Self { inner }
+    }
+}
+
+impl GpuController for DryRunController {
+    fn controller_info(&self) -> &CommonControllerInfo {
+        self.inner.controller_info()
+    }
+
+    fn device_type(&self) -> DeviceType {
+        self.inner.device_type()
+    }
+
+    fn get_info(&self) -> LocalBoxFuture<'_, DeviceInfo> {
+        self.inner.get_info()
+    }
+
+    fn apply_config<'a>(&'a self, config: &'a GpuConfig) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        // Controllers which can't record their writes would change the device
+        if !self.inner.supports_dry_run() {
+            return async {
+                Err(ErrorKind::Unsupported.error("Dry run mode is not supported on this GPU"))
+            }
+            .boxed_local();
+        }
+        self.inner.apply_config(config)
+    }
+
+    fn supports_dry_run(&self) -> bool {
+        self.inner.supports_dry_run()
+    }
+
+    fn dry_run_log(&self) -> Option<Vec<SysfsWrite>> {
+        self.inner.controller_info().dry_run_writes.borrow().clone()
+    }
+
+    fn verify_config(&self, _config: &GpuConfig) -> Vec<ConfigDifference> {
+        // Nothing gets written in dry run mode, so the device state is not expected to match the config
+        vec![]
+    }
+
+    fn get_stats(&self, gpu_config: Option<&GpuConfig>) -> DeviceStats {
+        self.inner.get_stats(gpu_config)
+    }
+
+    fn get_clocks_info(&self, gpu_config: Option<&GpuConfig>) -> anyhow::Result<ClocksInfo> {
+        self.inner.get_clocks_info(gpu_config)
+    }
+
+    fn get_power_states(&self, gpu_config: Option<&GpuConfig>) -> PowerStates {
+        self.inner.get_power_states(gpu_config)
+    }
+
+    fn get_capabilities(&self) -> GpuCapabilities {
+        self.inner.get_capabilities()
+    }
+
+    fn reset_pmfw_settings(&self) {
+        warn!("dry run: skipping PMFW settings reset");
+    }
+
+    fn cleanup(&self) -> LocalBoxFuture<'_, ()> {
+        self.inner.cleanup()
+    }
+
+    fn reset_clocks(&self) -> anyhow::Result<()> {
+        warn!("dry run: skipping clocks reset");
+        Ok(())
+    }
+
+    fn get_power_profile_modes(&self) -> anyhow::Result<PowerProfileModesTable> {
+        self.inner.get_power_profile_modes()
+    }
+
+    fn get_versions(&self) -> VersionsInfo {
+        self.inner.get_versions()
+    }
+
+    fn get_firmware_fan_curve(&self) -> anyhow::Result<FanCurve> {
+        self.inner.get_firmware_fan_curve()
+    }
+
+    fn validate_fan_curve(&self, curve: &FanCurve, unit: FanCurveUnit) -> FanCurveValidation {
+        self.inner.validate_fan_curve(curve, unit)
+    }
+
+    fn temperature_reader(&self) -> Option<TemperatureReader> {
+        self.inner.temperature_reader()
+    }
+
+    fn set_temperature_sources(&self, sources: TemperatureSources) {
+        self.inner.set_temperature_sources(sources);
+    }
+
+    fn self_test(&self) -> anyhow::Result<Vec<SelfTestEntry>> {
+        Err(ErrorKind::Unsupported.error("The self-test can't be run in dry run mode"))
+    }
+
+    fn vbios_dump(&self) -> anyhow::Result<Vec<u8>> {
+        self.inner.vbios_dump()
+    }
+
+    fn process_list(&self) -> anyhow::Result<ProcessList> {
+        self.inner.process_list()
+    }
+}
@@ -7,7 +7,12 @@ use crate::{
         drm_xe_memory_class_DRM_XE_MEM_REGION_CLASS_VRAM, IntelDrm,
     },
     server::{
-        gpu_controller::common::fdinfo::{self, DrmUtilMap},
+        gpu_controller::common::{
+            displays::read_displays,
+            fdinfo::{self, DrmUtilMap},
+            resizable_bar::read_resizable_bar,
+            thermal_limits::read_thermal_limits,
+        },
         opencl::get_opencl_info,
         vulkan::get_vulkan_info,
     },
@@ -16,10 +21,10 @@ use amdgpu_sysfs::{gpu_handle::power_profile_mode::PowerProfileModesTable, hw_mo
 use anyhow::{anyhow, Context};
 use futures::future::LocalBoxFuture;
 use lact_schema::{
-    config::GpuConfig, ClocksInfo, ClocksTable, ClockspeedStats, DeviceInfo, DeviceStats,
-    DeviceType, DrmInfo, DrmMemoryInfo, FanStats, IntelClocksTable, IntelDrmInfo, LinkInfo,
-    PowerState, PowerStates, PowerStats, ProcessList, ProcessUtilizationType, VoltageStats,
-    VramStats,
+    config::GpuConfig, ActivityStats, ClocksInfo, ClocksTable, ClockspeedStats, DeviceInfo,
+    DeviceStats, DeviceType, DrmInfo, DrmMemoryInfo, FanStats, IntelClocksTable, IntelDrmInfo,
+    LinkInfo, PowerCap, PowerState, PowerStates, PowerStats, ProcessList, ProcessUtilizationType,
+    VoltageStats, VramStats,
 };
 use std::{
     cell::{Cell, RefCell},
@@ -136,7 +141,7 @@ impl IntelGpuController {
         };
 
         let stats = controller.get_stats(None);
-        controller.initial_power_cap = stats.power.cap_current.filter(|cap| *cap != 0.0);
+        controller.initial_power_cap = stats.power.cap.current.filter(|cap| *cap != 0.0);
 
         Ok(controller)
     }
@@ -609,9 +614,19 @@ impl GpuController for IntelGpuController {
                 vulkan_instances,
                 driver: self.common.driver.clone(),
                 vbios_version: None,
+                vbios: None,
                 link_info: LinkInfo::default(),
                 drm_info: Some(drm_info),
                 opencl_info: get_opencl_info(&self.common),
+                resizable_bar: read_resizable_bar(&self.common.sysfs_path),
+                memory_vendor: None,
+                vram_total: None,
+                thermal_limits: self
+                    .hwmon_path
+                    .as_deref()
+                    .map(read_thermal_limits)
+                    .unwrap_or_default(),
+                displays: read_displays(&self.common.sysfs_path),
             }
         })
     }
@@ -649,6 +664,7 @@ impl GpuController for IntelGpuController {
             gpu_clockspeed,
             current_gfxclk,
             vram_clockspeed: None,
+            vram_clock_offset: None,
         };
 
         let cap_current = self
@@ -656,26 +672,35 @@ impl GpuController for IntelGpuController {
             .map(|value: f64| value / 1_000_000.0)
             .map(|cap| if cap == 0.0 { 100.0 } else { cap }); // Placeholder max value
 
-        let power = PowerStats {
+        let mut power = PowerStats {
             average: None,
             current: self.get_power_usage(),
-            cap_current,
-            cap_min: Some(0.0),
-            cap_max: self
-                .read_hwmon_file::<f64>("power", "_rated_max")
-                .filter(|max| *max != 0.0)
-                .map(|cap| cap / 1_000_000.0)
-                .or_else(|| cap_current.map(|current| current * 2.0)),
-            cap_default: self.initial_power_cap,
+            chip: None,
+            cap: PowerCap {
+                current: cap_current,
+                min: Some(0.0),
+                max: self
+                    .read_hwmon_file::<f64>("power", "_rated_max")
+                    .filter(|max| *max != 0.0)
+                    .map(|cap| cap / 1_000_000.0)
+                    .or_else(|| cap_current.map(|current| current * 2.0)),
+                default: self.initial_power_cap,
+            },
+            cap_percent: None,
         };
+        power.cap_percent = power.cap.current_percent();
 
         let voltage = VoltageStats {
             gpu: self.read_hwmon_file("in", "_input"),
             northbridge: None,
+            offset: None,
         };
 
+        let speed_current = self.read_hwmon_file("fan", "_input");
         let fan = FanStats {
-            speed_current: self.read_hwmon_file("fan", "_input"),
+            // Fan control is not implemented for Intel GPUs
+            monitor_only: speed_current.is_some(),
+            speed_current,
             ..Default::default()
         };
 
@@ -689,12 +714,19 @@ impl GpuController for IntelGpuController {
                 0 => None,
                 used => Some(used),
             },
+            ecc_errors: None,
         };
 
+        let busy_percent = self.get_busy_percent();
+
         DeviceStats {
             clockspeed,
             vram,
-            busy_percent: self.get_busy_percent(),
+            busy_percent,
+            activity: ActivityStats {
+                busy: busy_percent,
+                ..Default::default()
+            },
             power,
             temps: self.get_temperatures(),
             voltage,
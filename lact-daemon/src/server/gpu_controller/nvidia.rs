@@ -5,7 +5,16 @@ use super::{CommonControllerInfo, FanControlHandle, GpuController};
 use crate::{
     bindings::nvidia::NvPhysicalGpuHandle,
     server::{
-        gpu_controller::{common::fan_control::FanCurveExt, common::resolve_process_name, NvApi},
+        gpu_controller::{
+            common::{
+                self,
+                displays::read_displays,
+                fan_control::{evaluation_ticker, validate_pwm_limits, FanCurveExt},
+                resizable_bar::read_resizable_bar,
+                resolve_process_name,
+            },
+            NvApi,
+        },
         opencl::get_opencl_info,
         vulkan::get_vulkan_info,
     },
@@ -17,14 +26,17 @@ use futures::{future::LocalBoxFuture, FutureExt};
 use indexmap::IndexMap;
 use lact_schema::{
     config::{FanControlSettings, FanCurve, GpuConfig},
-    ClocksInfo, ClocksTable, ClockspeedStats, DeviceInfo, DeviceStats, DeviceType, DrmInfo,
-    DrmMemoryInfo, FanControlMode, FanStats, IntelDrmInfo, LinkInfo, NvidiaClockOffset,
-    NvidiaClocksTable, PmfwInfo, PowerState, PowerStates, PowerStats, ProcessInfo, ProcessList,
-    ProcessType, ProcessUtilizationType, VoltageStats, VramStats,
+    ActivityStats, ClocksInfo, ClocksTable, ClockspeedStats, DeviceInfo, DeviceStats, DeviceType,
+    DrmInfo, DrmMemoryInfo, ErrorKind, FanControlMode, FanStats, GpuCapabilities, IntelDrmInfo,
+    LinkInfo, NvidiaClockOffset, NvidiaClocksTable, PmfwInfo, PowerCap, PowerState, PowerStates,
+    PowerStats, ProcessInfo, ProcessList, ProcessType, ProcessUtilizationType, TemperatureUnit,
+    ThermalLimits, ThrottleReason, VbiosInfo, VersionsInfo, VoltageStats, VramStats,
 };
 use nvml_wrapper::{
     bitmasks::device::ThrottleReasons,
-    enum_wrappers::device::{Clock, PerformanceState, TemperatureSensor, TemperatureThreshold},
+    enum_wrappers::device::{
+        Clock, EccCounter, MemoryError, PerformanceState, TemperatureSensor, TemperatureThreshold,
+    },
     enums::device::{GpuLockedClocksSetting, UsedGpuMemory},
     error::NvmlError,
     Device, Nvml,
@@ -37,8 +49,8 @@ use std::{
     rc::Rc,
     time::{Duration, Instant},
 };
-use tokio::{select, sync::Notify, time::sleep};
-use tracing::{debug, error, trace, warn};
+use tokio::{select, sync::Notify};
+use tracing::{debug, error, trace, warn, Instrument};
 
 const SUPPORTED_UTIL_TYPES: &[ProcessUtilizationType] = &[
     ProcessUtilizationType::Graphics,
@@ -145,7 +157,22 @@ impl NvidiaGpuController {
         curve: FanCurve,
         settings: FanControlSettings,
     ) -> anyhow::Result<()> {
-        // Stop existing task to re-apply new curve
+        curve
+            .validate_with_unit(settings.curve_unit)
+            .context("Invalid fan curve")?;
+        validate_pwm_limits(&settings)?;
+
+        let curve_updated = self
+            .fan_control_handle
+            .borrow()
+            .as_ref()
+            .is_some_and(|running| running.update_curve(&curve, &settings));
+        if curve_updated {
+            debug!("updated the curve of the running fan control task");
+            return Ok(());
+        }
+
+        // Stop existing task to re-apply new settings
         self.stop_fan_control().await?;
 
         let device = self.device();
@@ -165,20 +192,32 @@ impl NvidiaGpuController {
 
         let notify = Rc::new(Notify::new());
         let task_notify = notify.clone();
+        let reevaluate = Rc::new(Notify::new());
+        let task_reevaluate = reevaluate.clone();
+        let curve = Rc::new(RefCell::new(curve));
+        let task_curve = curve.clone();
+        let running_settings = settings.clone();
 
         let nvml = self.nvml.clone();
         let pci_slot_id = self.common.pci_slot_name.clone();
         debug!("spawning new fan control task");
 
-        let handle = tokio::task::spawn_local(async move {
+        let interval = self
+            .common
+            .fan_control_interval
+            .unwrap_or(Duration::from_millis(settings.interval_ms));
+        let fan_task = async move {
             let mut device = nvml
                 .device_by_pci_bus_id(pci_slot_id.as_str())
                 .expect("Can no longer get device");
 
             let mut last_pwm = (None, Instant::now());
             let mut last_temp = 0;
+            // The fan is being slowed down in steps, which continues even if the temperature
+            // doesn't change anymore
+            let mut spindown_pending = false;
 
-            let interval = Duration::from_millis(settings.interval_ms);
+            let mut ticker = evaluation_ticker(interval);
             let spindown_delay = Duration::from_millis(settings.spindown_delay_ms.unwrap_or(0));
             #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
             let change_threshold = settings.change_threshold.unwrap_or(0) as i32;
@@ -188,9 +227,16 @@ impl NvidiaGpuController {
             let mut manual_mode = true;
 
             loop {
-                select! {
-                    () = sleep(interval) => (),
+                // The interval starts over after a curve change gets applied, so the next tick
+                // doesn't write the same value again right after it.
+                // Multiple changes before the task wakes up are only evaluated once.
+                let reevaluating = select! {
+                    _ = ticker.tick() => false,
+                    () = task_reevaluate.notified() => true,
                     () = task_notify.notified() => break,
+                };
+                if reevaluating {
+                    ticker.reset();
                 }
 
                 #[allow(clippy::cast_possible_wrap)]
@@ -198,7 +244,10 @@ impl NvidiaGpuController {
                     .temperature(TemperatureSensor::Gpu)
                     .expect("Could not read temperature") as i32;
 
-                if (last_temp - current_temp).abs() < change_threshold {
+                if !reevaluating
+                    && !spindown_pending
+                    && (last_temp - current_temp).abs() < change_threshold
+                {
                     trace!("temperature changed from {last_temp}°C to {current_temp}°C, which is less than the {change_threshold}°C threshold, skipping speed adjustment");
                     continue;
                 }
@@ -222,17 +271,22 @@ impl NvidiaGpuController {
                     continue;
                 }
 
-                let target_pwm = curve.pwm_at_temp(Temperature {
+                let temp = Temperature {
                     #[allow(clippy::cast_precision_loss)]
                     current: Some(current_temp as f32),
                     crit: None,
                     crit_hyst: None,
-                });
+                };
+                let curve_pwm = settings.clamp_pwm(
+                    task_curve
+                        .borrow()
+                        .pwm_at_temp_with_unit(temp, settings.curve_unit),
+                );
                 let now = Instant::now();
 
                 if let (Some(previous_pwm), previous_timestamp) = last_pwm {
                     let diff = now - previous_timestamp;
-                    if target_pwm < previous_pwm && diff < spindown_delay {
+                    if curve_pwm < previous_pwm && !spindown_pending && diff < spindown_delay {
                         trace!(
                             "delaying fan spindown ({}ms left)",
                             (spindown_delay - diff).as_millis()
@@ -241,6 +295,11 @@ impl NvidiaGpuController {
                     }
                 }
 
+                let target_pwm = last_pwm.0.map_or(curve_pwm, |previous_pwm| {
+                    settings.limit_spindown(previous_pwm, curve_pwm)
+                });
+                spindown_pending = target_pwm != curve_pwm;
+
                 last_pwm = (Some(target_pwm), now);
                 last_temp = current_temp;
 
@@ -258,13 +317,20 @@ impl NvidiaGpuController {
                 manual_mode = true;
             }
             debug!("exited fan control task");
+        };
+        let task = tokio::task::spawn_local(fan_task.instrument(self.common.span()));
+
+        *notify_guard = Some(FanControlHandle {
+            notify,
+            task,
+            settings: running_settings,
+            curve,
+            reevaluate,
         });
 
-        *notify_guard = Some((notify, handle));
-
         debug!(
             "started fan control with interval {}ms",
-            settings.interval_ms
+            interval.as_millis()
         );
 
         Ok(())
@@ -278,9 +344,9 @@ impl NvidiaGpuController {
             .try_borrow_mut()
             .map_err(|err| anyhow!("Lock error: {err}"))?
             .take();
-        if let Some((notify, handle)) = maybe_notify {
+        if let Some(FanControlHandle { notify, task, .. }) = maybe_notify {
             notify.notify_one();
-            handle.await?;
+            task.await?;
             fail_on_error = true;
         }
 
@@ -367,6 +433,36 @@ impl GpuController for NvidiaGpuController {
             let device = self.device();
             let driver_handle = self.driver_handle.as_ref();
 
+            let vbios_version = device
+                .vbios_version()
+                .map_err(|err| error!("could not get VBIOS version: {err}"))
+                .ok();
+            let subsystem = &self.common.pci_info.subsystem_pci_info;
+            let vbios = vbios_version.clone().map(|version| VbiosInfo {
+                version,
+                part_number: None,
+                build_date: None,
+                subsystem_vendor_id: subsystem.vendor_id.clone(),
+                subsystem_device_id: subsystem.model_id.clone(),
+            });
+
+            #[allow(clippy::cast_precision_loss)]
+            let threshold = |threshold: TemperatureThreshold| {
+                device
+                    .temperature_threshold(threshold)
+                    .ok()
+                    .map(|value| value as f32)
+            };
+            let gpu_limits = ThermalLimits {
+                max: threshold(TemperatureThreshold::GpuMax),
+                crit: threshold(TemperatureThreshold::Slowdown),
+                emergency: threshold(TemperatureThreshold::Shutdown),
+            };
+            let mut thermal_limits = BTreeMap::new();
+            if gpu_limits != ThermalLimits::default() {
+                thermal_limits.insert("GPU".to_owned(), gpu_limits);
+            }
+
             DeviceInfo {
                 pci_info: Some(self.common.pci_info.clone()),
                 vulkan_instances,
@@ -374,10 +470,8 @@ impl GpuController for NvidiaGpuController {
                     "nvidia {}",
                     self.nvml.sys_driver_version().unwrap_or_default()
                 ), // NVML should always be "nvidia"
-                vbios_version: device
-                    .vbios_version()
-                    .map_err(|err| error!("could not get VBIOS version: {err}"))
-                    .ok(),
+                vbios_version,
+                vbios,
                 link_info: LinkInfo {
                     current_width: device.current_pcie_link_width().map(|v| v.to_string()).ok(),
                     current_speed: device
@@ -444,6 +538,11 @@ impl GpuController for NvidiaGpuController {
                         .ok(),
                     intel: IntelDrmInfo::default(),
                 }),
+                resizable_bar: read_resizable_bar(&self.common.sysfs_path),
+                memory_vendor: None,
+                vram_total: None,
+                thermal_limits,
+                displays: read_displays(&self.common.sysfs_path),
             }
         })
     }
@@ -547,27 +646,69 @@ impl GpuController for NvidiaGpuController {
             .map(|info| VramStats {
                 total: Some(info.total),
                 used: Some(info.used),
+                ecc_errors: device
+                    .total_ecc_errors(MemoryError::Corrected, EccCounter::Volatile)
+                    .ok(),
             })
             .unwrap_or_default();
 
-        let active_pstate = device
-            .performance_state()
-            .map(|pstate| pstate.as_c() as usize)
-            .ok();
+        let performance_state = device.performance_state().ok();
+        let active_pstate = performance_state.map(|pstate| pstate.as_c() as usize);
+        let vram_clock_offset = performance_state.and_then(|pstate| {
+            device
+                .clock_offset(Clock::Memory, pstate)
+                .map(|offset| offset.clock_offset_mhz)
+                .ok()
+        });
 
         let fan_range = device.min_max_fan_speed().ok();
 
+        let throttle_reasons = device.current_throttle_reasons().ok();
+
+        let mut power = PowerStats {
+            average: None,
+            current: device.power_usage().map(|mw| f64::from(mw) / 1000.0).ok(),
+            chip: None,
+            cap: PowerCap {
+                current: device
+                    .power_management_limit()
+                    .map(|mw| f64::from(mw) / 1000.0)
+                    .ok(),
+                max: device
+                    .power_management_limit_constraints()
+                    .map(|constraints| f64::from(constraints.max_limit) / 1000.0)
+                    .ok(),
+                min: device
+                    .power_management_limit_constraints()
+                    .map(|constraints| f64::from(constraints.min_limit) / 1000.0)
+                    .ok(),
+                default: device
+                    .power_management_limit_default()
+                    .map(|mw| f64::from(mw) / 1000.0)
+                    .ok(),
+            },
+            cap_percent: None,
+        };
+        power.cap_percent = power.cap.current_percent();
+
+        let busy_percent = device
+            .utilization_rates()
+            .map(|utilization| u8::try_from(utilization.gpu).expect("Invalid percentage"))
+            .ok();
+
         DeviceStats {
             temps,
             fan: FanStats {
                 control_enabled: gpu_config.is_some_and(|config| config.fan_control_enabled),
                 control_mode: fan_settings.map(|settings| settings.mode),
+                hardware_mode: None,
                 static_speed: fan_settings.map(|settings| settings.static_speed),
                 curve: fan_settings.map(|settings| settings.curve.0.clone()),
                 spindown_delay_ms: fan_settings.and_then(|settings| settings.spindown_delay_ms),
                 change_threshold: fan_settings.and_then(|settings| settings.change_threshold),
                 auto_threshold: fan_settings.and_then(|settings| settings.auto_threshold),
                 temperature_key: None,
+                monitor_only: false,
                 speed_current,
                 speed_max: None,
                 speed_min: None,
@@ -577,37 +718,20 @@ impl GpuController for NvidiaGpuController {
                 temperature_range: None,
                 pmfw_info: PmfwInfo::default(),
             },
-            power: PowerStats {
-                average: None,
-                current: device.power_usage().map(|mw| f64::from(mw) / 1000.0).ok(),
-                cap_current: device
-                    .power_management_limit()
-                    .map(|mw| f64::from(mw) / 1000.0)
-                    .ok(),
-                cap_max: device
-                    .power_management_limit_constraints()
-                    .map(|constraints| f64::from(constraints.max_limit) / 1000.0)
-                    .ok(),
-                cap_min: device
-                    .power_management_limit_constraints()
-                    .map(|constraints| f64::from(constraints.min_limit) / 1000.0)
-                    .ok(),
-                cap_default: device
-                    .power_management_limit_default()
-                    .map(|mw| f64::from(mw) / 1000.0)
-                    .ok(),
+            power,
+            busy_percent,
+            activity: ActivityStats {
+                busy: busy_percent,
+                ..Default::default()
             },
-            busy_percent: device
-                .utilization_rates()
-                .map(|utilization| u8::try_from(utilization.gpu).expect("Invalid percentage"))
-                .ok(),
             vram,
             clockspeed: ClockspeedStats {
                 gpu_clockspeed: device.clock_info(Clock::Graphics).map(Into::into).ok(),
                 vram_clockspeed: device.clock_info(Clock::Memory).map(Into::into).ok(),
                 current_gfxclk: None,
+                vram_clock_offset,
             },
-            throttle_info: device.current_throttle_reasons().ok().map(|reasons| {
+            throttle_info: throttle_reasons.map(|reasons| {
                 reasons
                     .iter()
                     .filter(|reason| *reason != ThrottleReasons::GPU_IDLE)
@@ -621,11 +745,23 @@ impl GpuController for NvidiaGpuController {
             voltage: VoltageStats {
                 gpu: voltage,
                 northbridge: None,
+                offset: None,
             },
             performance_level: None,
+            profiling_performance_level: None,
             core_power_state: active_pstate,
             memory_power_state: active_pstate,
             pcie_power_state: None,
+            throttle_reasons: throttle_reasons
+                .map(decode_throttle_reasons)
+                .unwrap_or_default(),
+            gpu_metrics: None,
+            pcie_link: None,
+            memory_clock_level: None,
+            pcie_level: None,
+            power_profile_mode: None,
+            vf_curve: None,
+            temperature_unit: TemperatureUnit::default(),
         }
     }
 
@@ -708,12 +844,29 @@ impl GpuController for NvidiaGpuController {
         })
     }
 
+    fn get_capabilities(&self) -> GpuCapabilities {
+        let clocks_info = self.get_clocks_info(None).ok();
+        let mut capabilities =
+            common::base_capabilities(&self.get_stats(None), clocks_info.as_ref());
+        capabilities.fan_control = self.device().num_fans().is_ok_and(|count| count > 0);
+        capabilities
+    }
+
     fn get_power_profile_modes(&self) -> anyhow::Result<PowerProfileModesTable> {
         Err(anyhow!("Not supported on Nvidia"))
     }
 
     fn reset_pmfw_settings(&self) {}
 
+    fn get_versions(&self) -> VersionsInfo {
+        VersionsInfo {
+            driver: self.common.driver.clone(),
+            driver_version: self.nvml.sys_driver_version().ok(),
+            vbios_version: self.device().vbios_version().ok(),
+            ..Default::default()
+        }
+    }
+
     fn vbios_dump(&self) -> anyhow::Result<Vec<u8>> {
         Err(anyhow!("Not supported on Nvidia"))
     }
@@ -855,6 +1008,11 @@ impl GpuController for NvidiaGpuController {
                         self.start_curve_fan_control_task(settings.curve.clone(), settings.clone())
                             .await?;
                     }
+                    FanControlMode::OffsetCurve => {
+                        return Err(ErrorKind::Unsupported.error(
+                            "Nvidia GPUs don't have a firmware fan curve to apply an offset to",
+                        ));
+                    }
                 }
             } else {
                 self.stop_fan_control()
@@ -918,10 +1076,10 @@ impl GpuController for NvidiaGpuController {
 
     fn cleanup(&self) -> LocalBoxFuture<'_, ()> {
         async {
-            if let Some((fan_notify, fan_handle)) = self.fan_control_handle.take() {
+            if let Some(FanControlHandle { notify, task, .. }) = self.fan_control_handle.take() {
                 debug!("sending stop notification to old fan control task");
-                fan_notify.notify_one();
-                fan_handle.await.unwrap();
+                notify.notify_one();
+                task.await.unwrap();
                 debug!("finished controller cleanup");
             }
         }
@@ -1004,3 +1162,25 @@ impl GpuController for NvidiaGpuController {
         })
     }
 }
+
+fn decode_throttle_reasons(reasons: ThrottleReasons) -> Vec<ThrottleReason> {
+    let mut decoded = vec![];
+    if reasons.intersects(ThrottleReasons::SW_POWER_CAP | ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN)
+    {
+        decoded.push(ThrottleReason::Power);
+    }
+    if reasons
+        .intersects(ThrottleReasons::SW_THERMAL_SLOWDOWN | ThrottleReasons::HW_THERMAL_SLOWDOWN)
+    {
+        decoded.push(ThrottleReason::Thermal);
+    }
+    if reasons.intersects(
+        ThrottleReasons::HW_SLOWDOWN
+            | ThrottleReasons::SYNC_BOOST
+            | ThrottleReasons::APPLICATIONS_CLOCKS_SETTING
+            | ThrottleReasons::DISPLAY_CLOCK_SETTING,
+    ) {
+        decoded.push(ThrottleReason::Other);
+    }
+    decoded
+}
@@ -0,0 +1,142 @@
+use super::{
+    common::fan_control::{TemperatureReader, TemperatureSources},
+    CommonControllerInfo, DynGpuController, GpuController,
+};
+use amdgpu_sysfs::gpu_handle::power_profile_mode::PowerProfileModesTable;
+use futures::{future::LocalBoxFuture, FutureExt};
+use lact_schema::{
+    config::{FanCurve, FanCurveUnit, GpuConfig},
+    ClocksInfo, ConfigDifference, DeviceInfo, DeviceStats, DeviceType, FanCurveValidation,
+    GpuCapabilities, PowerStates, ProcessList, SelfTestEntry, SysfsWrite, VersionsInfo,
+};
+use tracing::{Instrument, Span};
+
+/// Wraps a controller so that everything it logs is recorded in the span of its GPU,
+/// which tells apart the messages of different GPUs
+pub struct SpannedController {
+    inner: DynGpuController,
+    span: Span,
+}
+
+impl SpannedController {
+    pub fn new(inner: DynGpuController, span: Span) -> Self {
+        Self { inner, span }
+    }
+}
+
+impl GpuController for SpannedController {
+    fn controller_info(&self) -> &CommonControllerInfo {
+        self.inner.controller_info()
+    }
+
+    fn device_type(&self) -> DeviceType {
+        self.inner.device_type()
+    }
+
+    fn get_info(&self) -> LocalBoxFuture<'_, DeviceInfo> {
+        self.inner
+            .get_info()
+            .instrument(self.span.clone())
+            .boxed_local()
+    }
+
+    fn apply_config<'a>(&'a self, config: &'a GpuConfig) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        self.inner
+            .apply_config(config)
+            .instrument(self.span.clone())
+            .boxed_local()
+    }
+
+    fn supports_dry_run(&self) -> bool {
+        self.inner.supports_dry_run()
+    }
+
+    fn dry_run_log(&self) -> Option<Vec<SysfsWrite>> {
+        self.inner.dry_run_log()
+    }
+
+    fn verify_config(&self, config: &GpuConfig) -> Vec<ConfigDifference> {
+        let _guard = self.span.enter();
+        self.inner.verify_config(config)
+    }
+
+    fn get_stats(&self, gpu_config: Option<&GpuConfig>) -> DeviceStats {
+        let _guard = self.span.enter();
+        self.inner.get_stats(gpu_config)
+    }
+
+    fn get_clocks_info(&self, gpu_config: Option<&GpuConfig>) -> anyhow::Result<ClocksInfo> {
+        let _guard = self.span.enter();
+        self.inner.get_clocks_info(gpu_config)
+    }
+
+    fn get_power_states(&self, gpu_config: Option<&GpuConfig>) -> PowerStates {
+        let _guard = self.span.enter();
+        self.inner.get_power_states(gpu_config)
+    }
+
+    fn get_capabilities(&self) -> GpuCapabilities {
+        let _guard = self.span.enter();
+        self.inner.get_capabilities()
+    }
+
+    fn reset_pmfw_settings(&self) {
+        let _guard = self.span.enter();
+        self.inner.reset_pmfw_settings();
+    }
+
+    fn get_versions(&self) -> VersionsInfo {
+        let _guard = self.span.enter();
+        self.inner.get_versions()
+    }
+
+    fn get_firmware_fan_curve(&self) -> anyhow::Result<FanCurve> {
+        let _guard = self.span.enter();
+        self.inner.get_firmware_fan_curve()
+    }
+
+    fn validate_fan_curve(&self, curve: &FanCurve, unit: FanCurveUnit) -> FanCurveValidation {
+        let _guard = self.span.enter();
+        self.inner.validate_fan_curve(curve, unit)
+    }
+
+    fn temperature_reader(&self) -> Option<TemperatureReader> {
+        self.inner.temperature_reader()
+    }
+
+    fn set_temperature_sources(&self, sources: TemperatureSources) {
+        self.inner.set_temperature_sources(sources);
+    }
+
+    fn self_test(&self) -> anyhow::Result<Vec<SelfTestEntry>> {
+        let _guard = self.span.enter();
+        self.inner.self_test()
+    }
+
+    fn cleanup(&self) -> LocalBoxFuture<'_, ()> {
+        self.inner
+            .cleanup()
+            .instrument(self.span.clone())
+            .boxed_local()
+    }
+
+    fn reset_clocks(&self) -> anyhow::Result<()> {
+        let _guard = self.span.enter();
+        self.inner.reset_clocks()
+    }
+
+    fn get_power_profile_modes(&self) -> anyhow::Result<PowerProfileModesTable> {
+        let _guard = self.span.enter();
+        self.inner.get_power_profile_modes()
+    }
+
+    fn vbios_dump(&self) -> anyhow::Result<Vec<u8>> {
+        let _guard = self.span.enter();
+        self.inner.vbios_dump()
+    }
+
+    fn process_list(&self) -> anyhow::Result<ProcessList> {
+        let _guard = self.span.enter();
+        self.inner.process_list()
+    }
+}
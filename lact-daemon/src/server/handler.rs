@@ -1,12 +1,32 @@
 use super::{
-    gpu_controller::{common::fan_control::FanCurveExt, DynGpuController, GpuController},
+    event_log::EventLog,
+    fan_response::{self, SettleDetector},
+    gpu_controller::{
+        common::fan_control::{self, FanCurveExt, TemperatureSources},
+        DryRunController, DynGpuController, GpuController,
+    },
+    health::HealthMonitor,
+    history::{sample_from_stats, StatsCapture, StatsExtremes, StatsHistory, TemperatureFit},
+    operations::Operations,
     profiles::ProfileWatcherCommand,
     system::{self, detect_initramfs_type},
+    thermal_protection::{
+        hottest_temperature, protected_config, ThermalProtectionState, Transition,
+    },
 };
 use crate::{
     bindings::intel::IntelDrm,
-    config::Config,
-    server::{gpu_controller::init_controller, profiles, system::DAEMON_VERSION},
+    config::{Config, ShutdownFanBehavior},
+    crash::{self, AppliedConfigs},
+    logging::Logging,
+    server::{
+        gpu_controller::{
+            common::{retry::WriteRetry, validate_within_range},
+            init_controller, read_pci_slot_name,
+        },
+        profiles,
+        system::DAEMON_VERSION,
+    },
     system::get_os_release,
 };
 use crate::{server::gpu_controller::NvidiaLibs, system::run_command};
@@ -14,14 +34,26 @@ use amdgpu_sysfs::gpu_handle::{
     power_profile_mode::PowerProfileModesTable, PerformanceLevel, PowerLevelKind,
 };
 use anyhow::{anyhow, bail, Context};
+use futures::{
+    future::{LocalBoxFuture, Shared},
+    FutureExt,
+};
+use indexmap::IndexMap;
 use lact_schema::{
     config::{
-        default_fan_static_speed, FanControlSettings, FanCurve, GpuConfig, Profile, ProfileHooks,
+        default_fan_static_speed, ClockVoltagePoint, FanControlSettings, FanCurve, FanCurvePreset,
+        FanCurveUnit, GpuConfig, LedSettings, Profile, ProfileHooks, SharedGpuConfig,
+        ThermalProtectionAction,
     },
     default_fan_curve,
     request::{ClockspeedType, ConfirmCommand, ProfileBase, SetClocksCommand},
-    ClocksInfo, DeviceInfo, DeviceListEntry, DeviceStats, FanControlMode, FanOptions, PmfwOptions,
-    PowerStates, ProcessList, ProfileRule, ProfileWatcherState, ProfilesInfo,
+    BroadcastOutcome, CaptureSummary, ClientCredentials, ClocksInfo, ConfigDifference, CrashInfo,
+    DaemonEvent, DaemonEventKind, DeviceInfo, DeviceListEntry, DeviceStats, EnergyDelta, ErrorKind,
+    FanControlMode, FanCurveValidation, FanOptions, FanResponse, GpuCapabilities, GpuMetricsStats,
+    GpuStatusLine, HealthWarning, HistoryMetric, HistorySample, ModelBroadcast, OperationInfo,
+    OperationKind, PmfwOptions, PowerCapEstimate, PowerStates, ProcessList, ProfileRule,
+    ProfileWatcherState, ProfilesInfo, SelfTestEntry, SessionExtremes, SettingProvenance,
+    StatsExportFormat, SysfsWrite, SystemPower, ValueRange, VersionsInfo,
 };
 use libdrm_amdgpu_sys::LibDrmAmdgpu;
 use libflate::gzip;
@@ -33,25 +65,30 @@ use serde_json::json;
 #[cfg(not(test))]
 use std::collections::HashMap;
 use std::{
+    borrow::Cow,
     cell::{Cell, LazyCell, RefCell},
-    collections::BTreeMap,
+    collections::{BTreeMap, HashSet},
     env,
     fs::{self, File, Permissions},
     io::{BufWriter, Cursor, Write},
+    ops::RangeInclusive,
     os::unix::fs::{MetadataExt, PermissionsExt},
     path::{Path, PathBuf},
+    process::Stdio,
     rc::Rc,
     time::{Duration, Instant},
 };
 use tokio::{
     process::Command,
-    sync::{mpsc, oneshot, RwLock, RwLockReadGuard},
-    time::sleep,
+    sync::{mpsc, oneshot, Mutex, Notify, RwLock, RwLockReadGuard},
+    time::{sleep, timeout},
 };
 use tracing::{debug, error, info, trace, warn};
 
 const CONTROLLERS_LOAD_RETRY_ATTEMPTS: u8 = 5;
 const CONTROLLERS_LOAD_RETRY_INTERVAL: u64 = 3;
+/// Allowed range for power caps set as a percentage of the default cap
+const POWER_CAP_PERCENT_RANGE: RangeInclusive<f64> = 10.0..=200.0;
 
 const SNAPSHOT_GLOBAL_PATHS: &[&str] = &[
     "/sys/module/amdgpu/parameters",
@@ -78,29 +115,129 @@ const SNAPSHOT_EXCLUDED_FILENAME_PREFIXES: &[&str] = &[
     "msi_irqs",
 ];
 const CONFIG_RESET_CMDLINE_ARG: &str = "lact-reset";
+/// The profile change command is killed after this time, so that it can't hold up profile switches
+const PROFILE_CHANGE_COMMAND_TIMEOUT_SECS: u64 = 10;
+
+/// Settings change waiting for confirmation, see [`Handler::stage_settings`]
+#[derive(Debug, Clone, Copy)]
+pub struct StagedSettings {
+    pub token: u64,
+    pub timeout_secs: u64,
+}
+
+/// Profile which was switched to for a limited time, see [`Handler::apply_temporary_profile`]
+#[derive(Debug, Clone)]
+struct TemporaryProfile {
+    /// Profile to switch back to once the time runs out
+    previous_profile: Option<Rc<str>>,
+    /// Identifies the revert timer of this activation, timers which were replaced don't revert
+    timer_id: u64,
+}
+
+/// Settings which are restored when a pending change gets reverted
+enum PreviousSettings {
+    /// The previous configs of the changed GPUs
+    Gpus(Vec<(String, GpuConfig)>),
+    /// The whole previous config, along with the GPUs it was applied to
+    Config {
+        config: Box<Config>,
+        gpu_ids: Vec<String>,
+    },
+}
+
+impl PreviousSettings {
+    fn gpu_ids(&self) -> Vec<String> {
+        match self {
+            Self::Gpus(previous_configs) => {
+                previous_configs.iter().map(|(id, _)| id.clone()).collect()
+            }
+            Self::Config { gpu_ids, .. } => gpu_ids.clone(),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Handler {
     pub config: Rc<RwLock<Config>>,
     gpu_controllers: Rc<RwLock<BTreeMap<String, DynGpuController>>>,
     confirm_config_tx: Rc<RefCell<Option<oneshot::Sender<ConfirmCommand>>>>,
+    /// Held for the whole read-apply-store cycle of a settings change,
+    /// so that changes from multiple clients at the same time don't overwrite each other
+    config_edit_lock: Rc<Mutex<()>>,
+    /// Token of the pending change when it was staged by a client
+    staged_settings: Rc<Cell<Option<u64>>>,
+    last_staged_token: Rc<Cell<u64>>,
     pub config_last_saved: Rc<Cell<Instant>>,
     profile_watcher_tx: Rc<RefCell<Option<mpsc::Sender<ProfileWatcherCommand>>>>,
     pub profile_watcher_state: Rc<RefCell<Option<ProfileWatcherState>>>,
+    temporary_profile: Rc<RefCell<Option<TemporaryProfile>>>,
+    last_temporary_timer_id: Rc<Cell<u64>>,
+    history: Rc<RefCell<HashMap<String, StatsHistory>>>,
+    /// Reads of each GPU done by [`Handler::get_stats_now`] which are still in progress
+    stats_reads: Rc<RefCell<HashMap<String, Shared<LocalBoxFuture<'static, Option<DeviceStats>>>>>>,
+    /// Captures which were started on a GPU and not ended yet
+    captures: Rc<RefCell<HashMap<String, StatsCapture>>>,
+    extremes: Rc<RefCell<HashMap<String, StatsExtremes>>>,
+    /// Energy accumulator of each GPU at the start of its [`Handler::energy_delta`] measurement
+    energy_snapshots: Rc<RefCell<HashMap<String, (Instant, u64)>>>,
+    thermal_protection: Rc<RefCell<HashMap<String, ThermalProtectionState>>>,
+    /// GPUs whose fan response is being measured, their fan settings differ from the config
+    fan_measurements: Rc<RefCell<HashSet<String>>>,
+    health: Rc<RefCell<HashMap<String, HealthMonitor>>>,
+    /// The most recent change of each kind for every GPU, ordered from oldest to newest
+    setting_provenance: Rc<RefCell<HashMap<String, IndexMap<String, SettingProvenance>>>>,
+    /// Provenance of changes which are waiting for confirmation, only kept once they are confirmed
+    pending_provenance: Rc<RefCell<Vec<(String, SettingProvenance)>>>,
+    event_log: Rc<RefCell<EventLog>>,
+    /// Long-running operations which clients can list and cancel
+    operations: Rc<RefCell<Operations>>,
+    /// When disabled, the GPUs are left at their defaults and no settings get applied
+    management_enabled: Rc<Cell<bool>>,
+    /// Incremented on every suspend/resume event, for telling if a newer one replaced
+    /// the warm-up period of an earlier one
+    resume_generation: Rc<Cell<u64>>,
+    /// Notified when the stats polling interval may have changed
+    pub stats_poll_notify: Rc<Notify>,
+    /// DRM sysfs directory used for discovering GPUs when they get added or removed
+    base_path: Rc<PathBuf>,
+    /// Set when the daemon was started without applying the stored configuration
+    safe_mode: bool,
+    applied_configs: AppliedConfigs,
+    /// Not set when the daemon is embedded and does not own the log output
+    logging: Option<Rc<Logging>>,
+}
+
+/// Daemon state which is decided before the handler gets created
+#[derive(Default)]
+pub struct HandlerOptions {
+    /// Start without applying the stored configuration, see [`crate::safe_mode`]
+    pub safe_mode: bool,
+    /// Shared with the crash report hook
+    pub applied_configs: AppliedConfigs,
+    pub logging: Option<Rc<Logging>>,
 }
 
 impl<'a> Handler {
-    pub async fn new(config: Config) -> anyhow::Result<Self> {
+    pub async fn new(config: Config, options: HandlerOptions) -> anyhow::Result<Self> {
         let base_path = drm_base_path();
         let pci_db = read_pci_db();
 
-        Self::with_base_path(&base_path, config, &pci_db).await
+        Self::with_options(&base_path, config, &pci_db, options).await
     }
 
     pub(crate) async fn with_base_path(
+        base_path: &Path,
+        config: Config,
+        pci_db: &Database,
+    ) -> anyhow::Result<Self> {
+        Self::with_options(base_path, config, pci_db, HandlerOptions::default()).await
+    }
+
+    async fn with_options(
         base_path: &Path,
         mut config: Config,
         pci_db: &Database,
+        options: HandlerOptions,
     ) -> anyhow::Result<Self> {
         let mut controllers = BTreeMap::new();
 
@@ -108,7 +245,14 @@ impl<'a> Handler {
         // For such scenarios there is a retry logic when no GPUs were found,
         // or if some of the PCI devices don't have a drm entry yet.
         for i in 1..=CONTROLLERS_LOAD_RETRY_ATTEMPTS {
-            controllers = load_controllers(base_path, pci_db)?;
+            controllers = load_controllers(
+                base_path,
+                pci_db,
+                &config.ignored_gpus,
+                config.daemon.sysfs_write_retry(),
+                config.daemon.fan_control_interval(),
+                config.daemon.disable_nvapi == Some(true),
+            )?;
 
             let mut should_retry = false;
             #[cfg(not(test))]
@@ -149,6 +293,12 @@ impl<'a> Handler {
         }
         info!("initialized {} GPUs", controllers.len());
 
+        if config.daemon.dry_run {
+            warn!("dry run mode is enabled, settings will not be written to the GPUs");
+            controllers = wrap_dry_run(controllers);
+        }
+        link_temperature_sources(&controllers);
+
         match fs::read_to_string("/proc/cmdline") {
             Ok(cmdline) => {
                 if cmdline
@@ -166,7 +316,7 @@ impl<'a> Handler {
                     }
 
                     info!("detected reset boot argument, resetting config (old config backed up to {backup_filename})");
-                    config = Config::default();
+                    config = Config::default().with_dir(config.dir());
                     if let Err(err) = config.save(&Cell::new(Instant::now())) {
                         error!("could not save config: {err:#}");
                     }
@@ -187,20 +337,59 @@ impl<'a> Handler {
             gpu_controllers: Rc::new(RwLock::new(controllers)),
             config: Rc::new(RwLock::new(config)),
             confirm_config_tx: Rc::new(RefCell::new(None)),
+            config_edit_lock: Rc::new(Mutex::new(())),
+            staged_settings: Rc::new(Cell::new(None)),
+            last_staged_token: Rc::new(Cell::new(0)),
             config_last_saved: Rc::new(Cell::new(Instant::now())),
             profile_watcher_tx: Rc::new(RefCell::new(None)),
             profile_watcher_state: Rc::new(RefCell::new(None)),
+            temporary_profile: Rc::new(RefCell::new(None)),
+            last_temporary_timer_id: Rc::new(Cell::new(0)),
+            history: Rc::new(RefCell::new(HashMap::new())),
+            stats_reads: Rc::new(RefCell::new(HashMap::new())),
+            captures: Rc::new(RefCell::new(HashMap::new())),
+            extremes: Rc::new(RefCell::new(HashMap::new())),
+            energy_snapshots: Rc::new(RefCell::new(HashMap::new())),
+            thermal_protection: Rc::new(RefCell::new(HashMap::new())),
+            fan_measurements: Rc::new(RefCell::new(HashSet::new())),
+            health: Rc::new(RefCell::new(HashMap::new())),
+            setting_provenance: Rc::new(RefCell::new(HashMap::new())),
+            pending_provenance: Rc::new(RefCell::new(Vec::new())),
+            event_log: Rc::new(RefCell::new(EventLog::default())),
+            operations: Rc::new(RefCell::new(Operations::default())),
+            management_enabled: Rc::new(Cell::new(true)),
+            resume_generation: Rc::new(Cell::new(0)),
+            stats_poll_notify: Rc::new(Notify::new()),
+            base_path: Rc::new(base_path.to_owned()),
+            safe_mode: options.safe_mode,
+            applied_configs: options.applied_configs,
+            logging: options.logging,
         };
-        if let Err(err) = handler.apply_current_config().await {
-            error!("could not apply config: {err:#}");
-        }
+        if handler.safe_mode {
+            warn!("safe mode is active, not applying the stored configuration");
+        } else {
+            {
+                let mut config = handler.config.write().await;
+                let controllers = handler.gpu_controllers.read().await;
+                let reattached = reattach_gpu_configs(&controllers, &mut config);
+                if add_first_run_configs(&controllers, &mut config) || reattached {
+                    if let Err(err) = config.save(&handler.config_last_saved) {
+                        error!("could not save config: {err:#}");
+                    }
+                }
+            }
 
-        if let Some(profile_name) = &handler.config.read().await.current_profile {
-            info!("using profile '{profile_name}'");
-        }
+            if let Err(err) = handler.apply_current_config().await {
+                error!("could not apply config: {err:#}");
+            }
 
-        if handler.config.read().await.auto_switch_profiles {
-            handler.start_profile_watcher().await;
+            if let Some(profile_name) = &handler.config.read().await.current_profile {
+                info!("using profile '{profile_name}'");
+            }
+
+            if handler.config.read().await.auto_switch_profiles {
+                handler.start_profile_watcher().await;
+            }
         }
 
         // Eagerly release memory
@@ -214,43 +403,169 @@ impl<'a> Handler {
         Ok(handler)
     }
 
+    pub fn is_safe_mode(&self) -> bool {
+        self.safe_mode
+    }
+
+    /// Changes the log level of the running daemon
+    pub fn set_log_level(&self, level: &str) -> anyhow::Result<()> {
+        self.logging
+            .as_ref()
+            .context("Logging is not initialized")?
+            .set_level(level)
+    }
+
+    /// Writes out the queued log lines, used before exiting
+    pub fn flush_logs(&self) {
+        if let Some(logging) = &self.logging {
+            logging.flush();
+        }
+    }
+
     pub async fn apply_current_config(&self) -> anyhow::Result<()> {
+        if !self.management_enabled.get() {
+            debug!("management is disabled, not applying the configuration");
+            return Ok(());
+        }
+
         let config = self.config.read().await;
         let controllers = self.gpu_controllers.read().await;
-        apply_config_to_controllers(&controllers, &config).await
+        apply_config_to_controllers(&self.applied_configs, &controllers, &config, false).await
     }
 
     pub async fn reload_gpus(&self) {
+        self.reload_gpus_with(false).await;
+    }
+
+    /// Reloads the GPUs after the system was suspended or resumed.
+    /// The settings that are delayed on resume get applied once the warm-up period has passed.
+    pub async fn reload_gpus_on_resume(&self) {
+        let generation = self.resume_generation.get() + 1;
+        self.resume_generation.set(generation);
+
+        if !self.reload_gpus_with(true).await {
+            return;
+        }
+
+        let warm_up = {
+            let config = self.config.read().await;
+            let has_delayed_settings = config.gpus().is_ok_and(|gpus| {
+                gpus.values()
+                    .any(|gpu_config| !gpu_config.delayed_on_resume.is_empty())
+            });
+            if !has_delayed_settings {
+                return;
+            }
+            Duration::from_millis(config.daemon.resume_warm_up_ms)
+        };
+
+        let handler = self.clone();
+        tokio::task::spawn_local(async move {
+            sleep(warm_up).await;
+            let _edit_guard = handler.config_edit_lock.lock().await;
+            // Another event came in during the warm-up, which starts its own one
+            if handler.resume_generation.get() != generation || handler.safe_mode {
+                return;
+            }
+
+            info!("resume warm-up period has passed, applying the delayed settings");
+            if let Err(err) = handler.apply_current_config().await {
+                error!("could not apply the delayed settings: {err:#}");
+            }
+        });
+    }
+
+    /// Returns if the configuration was reapplied
+    async fn reload_gpus_with(&self, on_resume: bool) -> bool {
+        let _edit_guard = self.config_edit_lock.lock().await;
         let mut controllers_guard = self.gpu_controllers.write().await;
-        let config = self.config.read().await;
+        let mut config = self.config.write().await;
 
-        let base_path = drm_base_path();
         let pci_db = read_pci_db();
-        match load_controllers(&base_path, &pci_db) {
-            Ok(new_controllers) => {
+        match load_controllers(
+            &self.base_path,
+            &pci_db,
+            &config.ignored_gpus,
+            config.daemon.sysfs_write_retry(),
+            config.daemon.fan_control_interval(),
+            config.daemon.disable_nvapi == Some(true),
+        ) {
+            Ok(mut new_controllers) => {
+                if config.daemon.dry_run {
+                    new_controllers = wrap_dry_run(new_controllers);
+                }
+                link_temperature_sources(&new_controllers);
+
                 info!(
                     "GPU list reloaded with {} devices, reapplying configuration",
                     new_controllers.len()
                 );
 
-                for old_controller in controllers_guard.values() {
+                for (id, old_controller) in controllers_guard.iter() {
+                    // Stops the fan control task
                     old_controller.cleanup().await;
-                    let _ = old_controller.reset_clocks();
+
+                    if new_controllers.contains_key(id) {
+                        let _ = old_controller.reset_clocks();
+                    } else {
+                        // The sysfs files are already gone, so there is nothing to reset
+                        info!("GPU {id} was removed");
+                        self.history.borrow_mut().remove(id);
+                        self.captures.borrow_mut().remove(id);
+                        self.extremes.borrow_mut().remove(id);
+                        self.energy_snapshots.borrow_mut().remove(id);
+                        self.thermal_protection.borrow_mut().remove(id);
+                        self.health.borrow_mut().remove(id);
+                        self.setting_provenance.borrow_mut().remove(id);
+                    }
+                }
+                for id in new_controllers.keys() {
+                    if !controllers_guard.contains_key(id) {
+                        info!("GPU {id} was added");
+                    }
                 }
 
+                // Dropping the old controllers closes their sysfs and DRM handles
                 *controllers_guard = new_controllers;
 
-                match apply_config_to_controllers(&controllers_guard, &config).await {
+                if self.safe_mode {
+                    warn!("safe mode is active, not reapplying the configuration");
+                    return false;
+                }
+                if !self.management_enabled.get() {
+                    info!("management is disabled, not reapplying the configuration");
+                    return false;
+                }
+
+                // Settings are only moved to other slots on startup, a GPU which appears later
+                // is a new one rather than one of the missing GPUs
+                if add_first_run_configs(&controllers_guard, &mut config) {
+                    if let Err(err) = config.save(&self.config_last_saved) {
+                        error!("could not save config: {err:#}");
+                    }
+                }
+
+                match apply_config_to_controllers(
+                    &self.applied_configs,
+                    &controllers_guard,
+                    &config,
+                    on_resume,
+                )
+                .await
+                {
                     Ok(()) => {
                         info!("configuration applied");
+                        true
                     }
                     Err(err) => {
                         error!("could not reapply config: {err:#}");
+                        false
                     }
                 }
             }
             Err(err) => {
                 error!("could not load GPU controllers: {err:#}");
+                false
             }
         }
     }
@@ -276,6 +591,56 @@ impl<'a> Handler {
         id: String,
         f: F,
     ) -> anyhow::Result<u64> {
+        self.edit_gpu_config_with_timer(id, None, f).await
+    }
+
+    /// Like `edit_gpu_config`, but the edit can be rejected based on the current config
+    async fn try_edit_gpu_config<F: FnOnce(&mut GpuConfig) -> anyhow::Result<()>>(
+        &self,
+        id: String,
+        f: F,
+    ) -> anyhow::Result<u64> {
+        self.try_edit_gpu_config_with_timer(id, None, f).await
+    }
+
+    async fn edit_gpu_config_with_timer<F: FnOnce(&mut GpuConfig)>(
+        &self,
+        id: String,
+        apply_timer: Option<u64>,
+        f: F,
+    ) -> anyhow::Result<u64> {
+        self.try_edit_gpu_config_with_timer(id, apply_timer, |config| {
+            f(config);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Applies the edited config, reverting it unless it gets confirmed before the timer runs out.
+    /// Uses the configured apply timer when none is given.
+    async fn try_edit_gpu_config_with_timer<F: FnOnce(&mut GpuConfig) -> anyhow::Result<()>>(
+        &self,
+        id: String,
+        apply_timer: Option<u64>,
+        f: F,
+    ) -> anyhow::Result<u64> {
+        let _edit_guard = self.config_edit_lock.lock().await;
+        self.ensure_config_editable()?;
+
+        let apply_timer = match apply_timer {
+            Some(apply_timer) => apply_timer,
+            None => self.config.read().await.apply_settings_timer,
+        };
+
+        let previous_config = self.apply_gpu_config(&id, f).await?;
+        self.wait_config_confirm(
+            PreviousSettings::Gpus(vec![(id, previous_config)]),
+            apply_timer,
+        )?;
+        Ok(apply_timer)
+    }
+
+    fn ensure_config_editable(&self) -> anyhow::Result<()> {
         if self
             .confirm_config_tx
             .try_borrow_mut()
@@ -287,32 +652,66 @@ impl<'a> Handler {
             ));
         }
 
-        let (previous_config, apply_timer) = {
-            let config = self.config.read().await;
-            let apply_timer = config.apply_settings_timer;
-            let gpu_config = config.gpus()?.get(&id).cloned().unwrap_or_default();
-            (gpu_config, apply_timer)
-        };
-
-        let mut new_config = previous_config.clone();
-        f(&mut new_config);
+        if !self.management_enabled.get() {
+            return Err(anyhow!("GPU management is disabled"));
+        }
+        Ok(())
+    }
 
-        let controller = self.controller_by_id(&id).await?;
+    /// Applies the edited config and stores it, returning the previous config.
+    /// The previous settings are restored if the new ones can't be applied.
+    async fn apply_gpu_config<F: FnOnce(&mut GpuConfig) -> anyhow::Result<()>>(
+        &self,
+        id: &str,
+        f: F,
+    ) -> anyhow::Result<GpuConfig> {
+        let previous_config = self
+            .config
+            .read()
+            .await
+            .gpus()?
+            .get(id)
+            .cloned()
+            .unwrap_or_default();
+
+        if self.fan_measurements.borrow().contains(id) {
+            return Err(ErrorKind::HardwareBusy
+                .error("The settings can't be changed while the fan response is being measured"));
+        }
 
-        match controller.apply_config(&new_config).await {
+        let mut new_config = previous_config.clone();
+        f(&mut new_config)?;
+
+        check_external_temperatures(
+            &*self.gpu_controllers.read().await,
+            id,
+            &previous_config,
+            &new_config,
+        )?;
+        let controller = self.controller_by_id(id).await?;
+        check_changed_limits(
+            &controller.get_capabilities(),
+            &previous_config,
+            &new_config,
+        )?;
+
+        let applied_config = self.protected_gpu_config(id, &*controller, &new_config);
+        match apply_and_record(&self.applied_configs, &controller, &applied_config).await {
             Ok(()) => {
                 self.config
                     .write()
                     .await
                     .gpus_mut()?
-                    .insert(id.clone(), new_config);
-                self.wait_config_confirm(id, previous_config, apply_timer)?;
-
-                Ok(apply_timer)
+                    .insert(id.to_owned(), new_config);
+                self.log_event(DaemonEventKind::SettingsApplied {
+                    gpu_id: id.to_owned(),
+                });
+                Ok(previous_config)
             }
             Err(apply_err) => {
                 error!("could not apply settings: {apply_err:?}");
-                match controller.apply_config(&previous_config).await {
+                let applied_config = self.protected_gpu_config(id, &*controller, &previous_config);
+                match apply_and_record(&self.applied_configs, &controller, &applied_config).await {
                     Ok(()) => Err(apply_err.context("Could not apply settings")),
                     Err(err) => Err(apply_err.context(err.context(
                         "Could not apply settings, and could not reset to previous settings",
@@ -322,11 +721,11 @@ impl<'a> Handler {
         }
     }
 
-    /// Should be called after applying new config without writing it
+    /// Should be called after applying new config without writing it.
+    /// All of the given GPUs are confirmed or reverted together.
     fn wait_config_confirm(
         &self,
-        id: String,
-        previous_config: GpuConfig,
+        previous_settings: PreviousSettings,
         apply_timer: u64,
     ) -> anyhow::Result<()> {
         let (tx, rx) = oneshot::channel();
@@ -335,45 +734,47 @@ impl<'a> Handler {
             .try_borrow_mut()
             .map_err(|err| anyhow!("{err}"))? = Some(tx);
 
+        let gpu_ids = previous_settings.gpu_ids();
+        let operation = Operations::start_all(
+            &self.operations,
+            OperationKind::PendingConfirmation,
+            &gpu_ids.iter().map(String::as_str).collect::<Vec<_>>(),
+            chrono::Local::now().timestamp_millis(),
+        );
         let handler = self.clone();
 
         tokio::task::spawn_local(async move {
-            let controller = handler
-                .controller_by_id(&id)
-                .await
-                .expect("GPU controller disappeared");
-
-            tokio::select! {
+            let (command, timed_out) = tokio::select! {
                 () = tokio::time::sleep(Duration::from_secs(apply_timer)) => {
                     info!("no confirmation received, reverting settings");
+                    handler.pending_provenance.take();
+                    (ConfirmCommand::Revert, true)
+                }
+                result = rx => (result.unwrap_or(ConfirmCommand::Revert), false),
+                () = operation.cancelled() => {
+                    info!("settings confirmation was cancelled, reverting settings");
+                    handler.pending_provenance.take();
+                    (ConfirmCommand::Revert, false)
+                }
+            };
+
+            match command {
+                ConfirmCommand::Confirm => {
+                    info!("saving updated config");
 
-                    if let Err(err) = controller.apply_config(&previous_config).await {
-                        error!("could not revert settings: {err:#}");
+                    if let Err(err) = handler.config.read().await.save(&handler.config_last_saved) {
+                        error!("{err:#}");
                     }
                 }
-                result = rx => {
-                    match result {
-                        Ok(ConfirmCommand::Confirm) => {
-                            info!("saving updated config");
+                ConfirmCommand::Revert => {
+                    handler.log_event(DaemonEventKind::SettingsReverted { gpu_ids, timed_out });
 
-                            if let Err(err) = handler.config.read().await.save(&handler.config_last_saved) {
-                                error!("{err:#}");
-                            }
+                    match previous_settings {
+                        PreviousSettings::Gpus(previous_configs) => {
+                            handler.revert_gpu_configs(previous_configs).await;
                         }
-                        Ok(ConfirmCommand::Revert) | Err(_) => {
-                            let mut config_guard = handler.config.write().await;
-                            match config_guard.gpus_mut() {
-                                Ok(gpus) => {
-                                    gpus.insert(id, previous_config.clone());
-                                }
-                                Err(err) => {
-                                    error!("could not revert config: {err}") ;
-                                }
-                            }
-
-                            if let Err(err) = controller.apply_config(&previous_config).await {
-                                error!("could not revert settings: {err:#}");
-                            }
+                        PreviousSettings::Config { config, .. } => {
+                            handler.revert_config(*config).await;
                         }
                     }
                 }
@@ -383,24 +784,67 @@ impl<'a> Handler {
                 Ok(mut guard) => *guard = None,
                 Err(err) => error!("{err}"),
             }
+            handler.staged_settings.set(None);
+            // Only listed as done once the settings were saved or reverted
+            drop(operation);
         });
 
         Ok(())
     }
 
+    async fn revert_gpu_configs(&self, previous_configs: Vec<(String, GpuConfig)>) {
+        match self.config.write().await.gpus_mut() {
+            Ok(gpus) => {
+                gpus.extend(previous_configs.iter().cloned());
+            }
+            Err(err) => {
+                error!("could not revert config: {err}");
+            }
+        }
+
+        for (id, previous_config) in previous_configs {
+            let result = match self.controller_by_id(&id).await {
+                Ok(controller) => {
+                    let applied_config =
+                        self.protected_gpu_config(&id, &*controller, &previous_config);
+                    apply_and_record(&self.applied_configs, &controller, &applied_config).await
+                }
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                error!("could not revert settings of GPU {id}: {err:#}");
+            }
+        }
+    }
+
+    /// Goes back to the config from before an import
+    async fn revert_config(&self, previous_config: Config) {
+        let auto_switch = previous_config.auto_switch_profiles;
+        self.cleanup().await;
+        if let Err(err) = self.replace_config(previous_config, true).await {
+            error!("could not revert config: {err:#}");
+        }
+
+        if auto_switch {
+            self.start_profile_watcher().await;
+        } else {
+            self.stop_profile_watcher().await;
+        }
+    }
+
     async fn controller_by_id(
         &self,
         id: &str,
     ) -> anyhow::Result<RwLockReadGuard<'_, dyn GpuController>> {
         let guard = self.gpu_controllers.read().await;
         RwLockReadGuard::try_map(guard, |controllers| controllers.get(id).map(Box::as_ref))
-            .map_err(|_| anyhow!("Controller '{id}' not found"))
+            .map_err(|_| ErrorKind::GpuNotFound.error(format!("Controller '{id}' not found")))
     }
 
     pub async fn list_devices(&'a self) -> Vec<DeviceListEntry> {
-        self.gpu_controllers
-            .read()
-            .await
+        let controllers = self.gpu_controllers.read().await;
+        let config = self.config.read().await;
+        controllers
             .iter()
             .map(|(id, controller)| {
                 let name = controller
@@ -415,11 +859,31 @@ impl<'a> Handler {
                     id: id.to_owned(),
                     name,
                     device_type,
+                    alias: config.gpu_aliases.get(id).cloned(),
                 }
             })
             .collect()
     }
 
+    pub async fn set_gpu_alias(&'a self, id: &str, alias: Option<String>) -> anyhow::Result<()> {
+        self.controller_by_id(id).await?;
+        let alias = alias
+            .map(|alias| alias.trim().to_owned())
+            .filter(|alias| !alias.is_empty());
+
+        let mut config = self.config.write().await;
+        match alias {
+            Some(alias) => {
+                info!("setting the alias of GPU {id} to '{alias}'");
+                config.gpu_aliases.insert(id.to_owned(), alias);
+            }
+            None => {
+                config.gpu_aliases.shift_remove(id);
+            }
+        }
+        config.save(&self.config_last_saved)
+    }
+
     pub async fn get_device_info(&'a self, id: &str) -> anyhow::Result<DeviceInfo> {
         Ok(self.controller_by_id(id).await?.get_info().await)
     }
@@ -427,7 +891,517 @@ impl<'a> Handler {
     pub async fn get_gpu_stats(&'a self, id: &str) -> anyhow::Result<DeviceStats> {
         let config = self.config.read().await;
         let gpu_config = config.gpus()?.get(id);
-        Ok(self.controller_by_id(id).await?.get_stats(gpu_config))
+        let mut stats = self.controller_by_id(id).await?.get_stats(gpu_config);
+        stats.temperature_unit = config.daemon.temperature_unit;
+        Ok(stats)
+    }
+
+    /// Reads the stats right away, for checking the effect of a setting without waiting for the
+    /// next poll. Requests which come in while a read is in progress share it.
+    pub async fn get_stats_now(&'a self, id: &str) -> anyhow::Result<DeviceStats> {
+        self.controller_by_id(id).await?;
+
+        let existing_read = self.stats_reads.borrow().get(id).cloned();
+        let read = match existing_read {
+            Some(read) => read,
+            None => {
+                let handler = self.clone();
+                let gpu_id = id.to_owned();
+                let read = async move {
+                    // Let the requests which arrived at the same time join this read
+                    tokio::task::yield_now().await;
+                    let stats = handler.get_gpu_stats(&gpu_id).await.ok();
+                    handler.stats_reads.borrow_mut().remove(&gpu_id);
+                    stats
+                }
+                .boxed_local()
+                .shared();
+
+                self.stats_reads
+                    .borrow_mut()
+                    .insert(id.to_owned(), read.clone());
+                read
+            }
+        };
+
+        read.await
+            .ok_or_else(|| ErrorKind::GpuNotFound.error(format!("Controller '{id}' not found")))
+    }
+
+    /// Settings of the GPU which were changed since the stored config was applied
+    pub async fn get_config_diff(&self, id: &str) -> anyhow::Result<Vec<ConfigDifference>> {
+        let config = self.config.read().await;
+        let controller = self.controller_by_id(id).await?;
+        let differences = config
+            .gpus()?
+            .get(id)
+            .map(|gpu_config| controller.verify_config(gpu_config))
+            .unwrap_or_default();
+        Ok(differences)
+    }
+
+    pub async fn get_system_power(&self) -> SystemPower {
+        let config = self.config.read().await;
+        let controllers = self.gpu_controllers.read().await;
+
+        let mut system_power = SystemPower::default();
+        for (id, controller) in controllers.iter() {
+            let gpu_config = config.gpus().ok().and_then(|gpus| gpus.get(id));
+            let power = controller.get_stats(gpu_config).power;
+
+            match power.current.or(power.average) {
+                Some(watts) => {
+                    system_power.total += watts;
+                    system_power.gpus.insert(id.clone(), watts);
+                }
+                None => system_power.excluded_gpus.push(id.clone()),
+            }
+        }
+        system_power
+    }
+
+    pub async fn get_status_line(&self) -> Vec<GpuStatusLine> {
+        let config = self.config.read().await;
+        let controllers = self.gpu_controllers.read().await;
+
+        controllers
+            .iter()
+            .map(|(id, controller)| {
+                let gpu_config = config.gpus().ok().and_then(|gpus| gpus.get(id));
+                let mut stats = controller.get_stats(gpu_config);
+                stats.temperature_unit = config.daemon.temperature_unit;
+
+                let model = &controller.controller_info().pci_info.device_pci_info.model;
+                let name = config.gpu_aliases.get(id).or(model.as_ref()).cloned();
+                GpuStatusLine::new(id.clone(), name, &stats)
+            })
+            .collect()
+    }
+
+    /// Records a stats sample of every GPU into its history buffer and evaluates the health rules
+    pub async fn record_history(&self) {
+        let config = self.config.read().await;
+        let capacity = config.daemon.history_length;
+        let controllers = self.gpu_controllers.read().await;
+        let timestamp = chrono::Local::now().timestamp_millis();
+
+        let mut history = self.history.borrow_mut();
+        history.retain(|id, _| controllers.contains_key(id));
+        let mut captures = self.captures.borrow_mut();
+        let mut extremes = self.extremes.borrow_mut();
+        extremes.retain(|id, _| controllers.contains_key(id));
+        let mut health = self.health.borrow_mut();
+        health.retain(|id, _| controllers.contains_key(id));
+
+        for (id, controller) in controllers.iter() {
+            let gpu_config = config.gpus().ok().and_then(|gpus| gpus.get(id));
+            let stats = controller.get_stats(gpu_config);
+
+            let monitor = health.entry(id.clone()).or_default();
+            for warning in monitor.update(&stats) {
+                warn!("GPU {id}: {warning}");
+            }
+
+            let sample = sample_from_stats(&stats, timestamp);
+            if let Some(capture) = captures.get_mut(id) {
+                capture.push(&sample);
+            }
+            extremes
+                .entry(id.clone())
+                .or_insert_with(|| StatsExtremes::new(timestamp))
+                .push(&sample);
+
+            let gpu_history = history
+                .entry(id.clone())
+                .or_insert_with(|| StatsHistory::new(capacity));
+            gpu_history.set_capacity(capacity);
+            gpu_history.push(sample);
+        }
+    }
+
+    pub async fn check_thermal_protection(&self) {
+        if !self.management_enabled.get() {
+            return;
+        }
+
+        let config = self.config.read().await;
+        let Ok(gpu_configs) = config.gpus() else {
+            return;
+        };
+        let controllers = self.gpu_controllers.read().await;
+
+        let mut transitions = vec![];
+        {
+            let mut states = self.thermal_protection.borrow_mut();
+            states.retain(|id, _| {
+                gpu_configs
+                    .get(id)
+                    .is_some_and(|gpu_config| gpu_config.thermal_protection.is_some())
+            });
+
+            for (id, controller) in controllers.iter() {
+                let Some(gpu_config) = gpu_configs.get(id) else {
+                    continue;
+                };
+                let Some(settings) = &gpu_config.thermal_protection else {
+                    continue;
+                };
+
+                let stats = controller.get_stats(Some(gpu_config));
+                let Some(temp) = hottest_temperature(&stats) else {
+                    continue;
+                };
+
+                let state = states.entry(id.clone()).or_default();
+                if let Some(transition) = state.update(settings, temp) {
+                    transitions.push((id, controller, gpu_config, temp, transition, stats));
+                }
+            }
+        }
+
+        for (id, controller, gpu_config, temp, transition, stats) in transitions {
+            let settings = gpu_config.thermal_protection.as_ref().unwrap();
+            let result = match transition {
+                Transition::Trigger => {
+                    warn!(
+                        "GPU {id} reached {temp}°C, applying thermal protection action {:?}",
+                        settings.action
+                    );
+                    // The measurement would otherwise keep stepping the fan
+                    if self
+                        .operations
+                        .borrow()
+                        .cancel_gpu(OperationKind::FanResponseMeasurement, id)
+                    {
+                        warn!("cancelling the fan response measurement of GPU {id}");
+                    }
+                    self.log_event(DaemonEventKind::ThermalProtectionTriggered {
+                        gpu_id: id.clone(),
+                        temperature: temp,
+                    });
+                    let protected =
+                        protected_config(gpu_config, settings.action, stats.power.cap.current);
+                    apply_and_record(&self.applied_configs, controller, &protected).await
+                }
+                Transition::Revert => {
+                    info!("GPU {id} cooled down to {temp}°C, reverting thermal protection action");
+                    self.log_event(DaemonEventKind::ThermalProtectionReverted {
+                        gpu_id: id.clone(),
+                        temperature: temp,
+                    });
+                    apply_and_record(&self.applied_configs, controller, gpu_config).await
+                }
+            };
+
+            if let Err(err) = result {
+                error!("could not apply thermal protection settings for GPU {id}: {err:#}");
+            }
+        }
+    }
+
+    /// Reapplies the config of GPUs whose settings were changed externally,
+    /// returning the amount of GPUs that had to be corrected
+    pub async fn check_config_integrity(&self) -> usize {
+        if self.safe_mode
+            || !self.management_enabled.get()
+            || self.confirm_config_tx.borrow().is_some()
+        {
+            return 0;
+        }
+        // Settings which are being changed right now will get applied by the edit itself
+        let Ok(_edit_guard) = self.config_edit_lock.try_lock() else {
+            return 0;
+        };
+
+        let config = self.config.read().await;
+        let Ok(gpu_configs) = config.gpus() else {
+            return 0;
+        };
+        let controllers = self.gpu_controllers.read().await;
+
+        let drifted_gpus: Vec<_> = {
+            let thermal_protection = self.thermal_protection.borrow();
+            let fan_measurements = self.fan_measurements.borrow();
+            controllers
+                .iter()
+                .filter(|(id, _)| {
+                    // The applied settings are expected to differ while thermal protection is active
+                    !thermal_protection
+                        .get(*id)
+                        .is_some_and(ThermalProtectionState::is_active)
+                })
+                .filter(|(id, _)| !fan_measurements.contains(*id))
+                .filter_map(|(id, controller)| {
+                    let gpu_config = gpu_configs.get(id)?;
+                    let drifted = controller.verify_config(gpu_config);
+                    (!drifted.is_empty()).then_some((id, controller, gpu_config, drifted))
+                })
+                .collect()
+        };
+
+        let mut corrected = 0;
+        for (id, controller, gpu_config, drifted) in drifted_gpus {
+            for difference in &drifted {
+                warn!("settings of GPU {id} were changed externally: {difference}");
+            }
+
+            match apply_and_record(&self.applied_configs, controller, gpu_config).await {
+                Ok(()) => {
+                    info!("reapplied settings of GPU {id}");
+                    corrected += 1;
+                }
+                Err(err) => error!("could not reapply settings of GPU {id}: {err:#}"),
+            }
+        }
+        corrected
+    }
+
+    /// Reverts the voltage offset of a GPU after the kernel reported that it had to be reset,
+    /// as an unstable undervolt is the most likely cause of the hang
+    pub async fn handle_gpu_reset(&self, pci_slot_name: &str) {
+        let _edit_guard = self.config_edit_lock.lock().await;
+        let controllers = self.gpu_controllers.read().await;
+        let Some((id, controller)) = controllers
+            .iter()
+            .find(|(_, controller)| controller.controller_info().pci_slot_name == pci_slot_name)
+        else {
+            debug!("got a reset event for unknown GPU {pci_slot_name}");
+            return;
+        };
+        warn!("GPU {id} was reset by the kernel");
+
+        if self.safe_mode || !self.management_enabled.get() {
+            return;
+        }
+
+        let mut config = self.config.write().await;
+        let Some(gpu_config) = config.gpus_mut().ok().and_then(|gpus| gpus.get_mut(id)) else {
+            return;
+        };
+        let Some(offset) = gpu_config
+            .clocks_configuration
+            .voltage_offset
+            .filter(|offset| *offset != 0)
+        else {
+            return;
+        };
+
+        warn!("reverting the voltage offset of {offset}mV on GPU {id}");
+        gpu_config.clocks_configuration.voltage_offset = None;
+
+        if let Err(err) = apply_and_record(&self.applied_configs, controller, gpu_config).await {
+            error!("could not apply settings without the voltage offset: {err:#}");
+        }
+        if let Err(err) = config.save(&self.config_last_saved) {
+            error!("could not save config: {err:#}");
+        }
+    }
+
+    pub async fn get_history(
+        &self,
+        id: &str,
+        metrics: &[HistoryMetric],
+    ) -> anyhow::Result<Vec<HistorySample>> {
+        // Make sure the GPU exists
+        self.controller_by_id(id).await?;
+
+        Ok(self
+            .history
+            .borrow()
+            .get(id)
+            .map(|history| history.get(metrics))
+            .unwrap_or_default())
+    }
+
+    pub async fn begin_capture(&self, id: &str) -> anyhow::Result<()> {
+        self.controller_by_id(id).await?;
+
+        let capture = StatsCapture::new(chrono::Local::now().timestamp_millis());
+        self.captures.borrow_mut().insert(id.to_owned(), capture);
+        Ok(())
+    }
+
+    pub async fn end_capture(&self, id: &str) -> anyhow::Result<CaptureSummary> {
+        self.controller_by_id(id).await?;
+
+        let capture = self
+            .captures
+            .borrow_mut()
+            .remove(id)
+            .with_context(|| format!("No capture was started on GPU {id}"))?;
+        Ok(capture.finish(chrono::Local::now().timestamp_millis()))
+    }
+
+    pub async fn get_extremes(&self, id: &str) -> anyhow::Result<SessionExtremes> {
+        self.controller_by_id(id).await?;
+
+        let now = chrono::Local::now().timestamp_millis();
+        Ok(self
+            .extremes
+            .borrow_mut()
+            .entry(id.to_owned())
+            .or_insert_with(|| StatsExtremes::new(now))
+            .get())
+    }
+
+    pub async fn reset_extremes(&self, id: &str) -> anyhow::Result<()> {
+        self.controller_by_id(id).await?;
+
+        let now = chrono::Local::now().timestamp_millis();
+        self.extremes
+            .borrow_mut()
+            .entry(id.to_owned())
+            .or_insert_with(|| StatsExtremes::new(now))
+            .reset(now);
+        Ok(())
+    }
+
+    pub async fn estimate_power_cap(
+        &self,
+        id: &str,
+        target_temperature: f64,
+    ) -> anyhow::Result<PowerCapEstimate> {
+        let cap = self.get_gpu_stats(id).await?.power.cap;
+        let (Some(min), Some(max)) = (cap.min, cap.max) else {
+            return Err(ErrorKind::Unsupported.error("The GPU does not report its power cap range"));
+        };
+
+        let samples = self
+            .get_history(id, &[HistoryMetric::Power, HistoryMetric::Temperature])
+            .await?;
+        let fit = TemperatureFit::from_samples(&samples)?;
+
+        let estimate = fit.power_at(target_temperature);
+        Ok(PowerCapEstimate {
+            power_cap: estimate.clamp(min, max),
+            target_temperature,
+            degrees_per_watt: fit.degrees_per_watt,
+            sample_count: fit.sample_count,
+            clamped: estimate < min || estimate > max,
+        })
+    }
+
+    /// Returns the energy consumed since the previous call for the same GPU and starts a new
+    /// measurement, or `None` when there was no previous measurement or the energy counter
+    /// was reset since then
+    pub async fn energy_delta(&self, id: &str) -> anyhow::Result<Option<EnergyDelta>> {
+        let counter = self
+            .get_gpu_stats(id)
+            .await?
+            .gpu_metrics
+            .and_then(|metrics| metrics.energy_accumulator)
+            .ok_or_else(|| {
+                ErrorKind::Unsupported.error("The GPU does not report its energy consumption")
+            })?;
+
+        let now = Instant::now();
+        let previous = self
+            .energy_snapshots
+            .borrow_mut()
+            .insert(id.to_owned(), (now, counter));
+
+        let Some((read_at, previous_counter)) = previous else {
+            return Ok(None);
+        };
+        let Some(joules) = GpuMetricsStats::energy_between(previous_counter, counter) else {
+            warn!("energy counter of GPU {id} was reset, starting a new measurement");
+            return Ok(None);
+        };
+        Ok(Some(EnergyDelta {
+            joules,
+            elapsed_ms: u64::try_from((now - read_at).as_millis()).unwrap_or(u64::MAX),
+        }))
+    }
+
+    pub async fn get_last_crash(&self) -> anyhow::Result<Option<CrashInfo>> {
+        let config = self.config.read().await;
+        crash::last_crash(config.dir())
+    }
+
+    pub async fn get_health_warnings(&self, id: &str) -> anyhow::Result<Vec<HealthWarning>> {
+        self.controller_by_id(id).await?;
+
+        Ok(self
+            .health
+            .borrow()
+            .get(id)
+            .map(|monitor| monitor.warnings().to_vec())
+            .unwrap_or_default())
+    }
+
+    pub fn record_setting_provenance(
+        &self,
+        id: &str,
+        request: &str,
+        client: Option<ClientCredentials>,
+    ) {
+        let provenance = SettingProvenance {
+            request: request.to_owned(),
+            client,
+            timestamp: chrono::Local::now().timestamp_millis(),
+        };
+
+        if self.confirm_config_tx.borrow().is_some() {
+            self.pending_provenance
+                .borrow_mut()
+                .push((id.to_owned(), provenance));
+        } else {
+            self.insert_setting_provenance(id.to_owned(), provenance);
+        }
+    }
+
+    fn insert_setting_provenance(&self, id: String, provenance: SettingProvenance) {
+        let mut setting_provenance = self.setting_provenance.borrow_mut();
+        let changes = setting_provenance.entry(id).or_default();
+        // Move the change to the end, so that the newest one is always last
+        changes.shift_remove(&provenance.request);
+        changes.insert(provenance.request.clone(), provenance);
+    }
+
+    fn log_event(&self, kind: DaemonEventKind) {
+        let timestamp = chrono::Local::now().timestamp_millis();
+        self.event_log.borrow_mut().push(timestamp, kind);
+    }
+
+    pub fn get_event_log(&self, since: Option<i64>) -> Vec<DaemonEvent> {
+        self.event_log.borrow().get(since)
+    }
+
+    pub fn list_operations(&self) -> Vec<OperationInfo> {
+        self.operations.borrow().list()
+    }
+
+    pub fn cancel_operation(&self, id: u64) -> anyhow::Result<()> {
+        if self.operations.borrow().cancel(id) {
+            info!("cancelling operation {id}");
+            Ok(())
+        } else {
+            Err(anyhow!("No operation with id {id} is in progress"))
+        }
+    }
+
+    pub async fn get_setting_provenance(&self, id: &str) -> anyhow::Result<Vec<SettingProvenance>> {
+        self.controller_by_id(id).await?;
+
+        Ok(self
+            .setting_provenance
+            .borrow()
+            .get(id)
+            .map(|changes| changes.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    pub async fn export_stats(
+        &self,
+        id: &str,
+        format: StatsExportFormat,
+        since: Option<i64>,
+    ) -> anyhow::Result<String> {
+        let mut samples = self.get_history(id, &[]).await?;
+        if let Some(since) = since {
+            samples.retain(|sample| sample.timestamp >= since);
+        }
+        Ok(format.serialize(&samples))
     }
 
     pub async fn get_clocks_info(&'a self, id: &str) -> anyhow::Result<ClocksInfo> {
@@ -436,6 +1410,52 @@ impl<'a> Handler {
         self.controller_by_id(id).await?.get_clocks_info(gpu_config)
     }
 
+    pub async fn get_capabilities(&'a self, id: &str) -> anyhow::Result<GpuCapabilities> {
+        Ok(self.controller_by_id(id).await?.get_capabilities())
+    }
+
+    pub async fn generate_fan_curve(
+        &'a self,
+        id: &str,
+        preset: FanCurvePreset,
+    ) -> anyhow::Result<FanCurve> {
+        let capabilities = self.get_capabilities(id).await?;
+        if !capabilities.fan_control {
+            return Err(ErrorKind::Unsupported.error("The GPU does not support fan control"));
+        }
+
+        Ok(fan_control::generate_curve(
+            preset,
+            capabilities.fan_curve_temperature,
+            capabilities.fan_curve_speed,
+            capabilities.fan_curve_points,
+        ))
+    }
+
+    pub async fn validate_fan_curve(
+        &'a self,
+        id: &str,
+        curve: &FanCurve,
+        unit: FanCurveUnit,
+    ) -> anyhow::Result<FanCurveValidation> {
+        let controller = self.controller_by_id(id).await?;
+        if !controller.get_capabilities().fan_control {
+            return Err(ErrorKind::Unsupported.error("The GPU does not support fan control"));
+        }
+        Ok(controller.validate_fan_curve(curve, unit))
+    }
+
+    /// Versions of the kernel, driver and firmware, collected for bug reports
+    pub async fn get_versions(&self, id: &str) -> anyhow::Result<VersionsInfo> {
+        let mut versions = self.controller_by_id(id).await?.get_versions();
+        versions.kernel_version = system::kernel_version().await?;
+        Ok(versions)
+    }
+
+    pub async fn get_firmware_fan_curve(&'a self, id: &str) -> anyhow::Result<FanCurve> {
+        self.controller_by_id(id).await?.get_firmware_fan_curve()
+    }
+
     pub async fn set_fan_control(&'a self, opts: FanOptions<'_>) -> anyhow::Result<u64> {
         let settings = {
             let mut config_guard = self.config.write().await;
@@ -449,7 +1469,9 @@ impl<'a> Handler {
                     FanControlMode::Static => {
                         if matches!(opts.static_speed, Some(speed) if !(0.0..=1.0).contains(&speed))
                         {
-                            return Err(anyhow!("static speed value out of range"));
+                            return Err(
+                                ErrorKind::OutOfRange.error("static speed value out of range")
+                            );
                         }
 
                         if let Some(mut existing_settings) = gpu_config.fan_control_settings.clone()
@@ -484,6 +1506,7 @@ impl<'a> Handler {
                                 let curve = FanCurve(raw_curve);
                                 curve.validate()?;
                                 existing_settings.curve = curve;
+                                existing_settings.curve_unit = FanCurveUnit::Percentage;
                             }
                             Some(existing_settings)
                         } else {
@@ -498,6 +1521,20 @@ impl<'a> Handler {
                             })
                         }
                     }
+                    FanControlMode::OffsetCurve => {
+                        let mut settings =
+                            gpu_config.fan_control_settings.clone().unwrap_or_default();
+                        settings.mode = mode;
+                        if let Some(offset) = opts.curve_offset {
+                            if !(-1.0..=1.0).contains(&offset) {
+                                return Err(
+                                    ErrorKind::OutOfRange.error("curve offset value out of range")
+                                );
+                            }
+                            settings.curve_offset = Some(offset);
+                        }
+                        Some(settings)
+                    }
                 },
                 None => None,
             }
@@ -514,6 +1551,38 @@ impl<'a> Handler {
         .context("Failed to edit GPU config")
     }
 
+    /// Switches the fan to the static mode with the given PWM value.
+    /// The other fan settings are kept, so they are used again when switching back to a curve.
+    pub async fn set_fan_static(&self, id: &str, pwm: u8) -> anyhow::Result<u64> {
+        if !self
+            .controller_by_id(id)
+            .await?
+            .get_capabilities()
+            .fan_control
+        {
+            return Err(ErrorKind::Unsupported.error("The GPU does not support fan control"));
+        }
+
+        // Checked against the config which gets edited, so a concurrent change of the limits
+        // can't be missed
+        self.try_edit_gpu_config(id.to_owned(), |gpu_config| {
+            let outside_limits = gpu_config
+                .fan_control_settings
+                .as_ref()
+                .is_some_and(|settings| settings.clamp_pwm(pwm) != pwm);
+            if outside_limits {
+                return Err(ErrorKind::OutOfRange.error(format!(
+                    "Fan PWM {pwm} is outside of the configured PWM limits"
+                )));
+            }
+
+            *gpu_config = static_fan_config(gpu_config, pwm);
+            Ok(())
+        })
+        .await
+        .context("Failed to edit GPU config and set static fan speed")
+    }
+
     pub async fn reset_pmfw(&self, id: &str) -> anyhow::Result<u64> {
         info!("Resetting PMFW settings");
         self.controller_by_id(id).await?.reset_pmfw_settings();
@@ -525,6 +1594,206 @@ impl<'a> Handler {
         .context("Failed to edit GPU config and reset pmfw")
     }
 
+    pub async fn set_fan_zero_rpm(&self, id: &str, enabled: bool) -> anyhow::Result<u64> {
+        let pmfw_info = self.get_gpu_stats(id).await?.fan.pmfw_info;
+        if pmfw_info.zero_rpm_enable.is_none() {
+            return Err(ErrorKind::Unsupported.error("Zero RPM mode is not supported by the GPU"));
+        }
+
+        self.edit_gpu_config(id.to_owned(), |gpu_config| {
+            gpu_config.pmfw_options.zero_rpm = Some(enabled);
+        })
+        .await
+        .context("Failed to edit GPU config and set zero RPM mode")
+    }
+
+    /// Steps the fan from one PWM value to another, measuring how long its speed takes to settle.
+    /// The fan is switched to static control outside of the stored config while measuring,
+    /// so this is not allowed while another settings change is pending, and changes to the
+    /// settings of the GPU are refused until the measurement is done.
+    /// While running, the measurement is listed as an operation which can be cancelled.
+    /// It is also cancelled when thermal protection gets triggered.
+    pub async fn measure_fan_response(
+        &self,
+        id: &str,
+        from_pwm: u8,
+        to_pwm: u8,
+    ) -> anyhow::Result<FanResponse> {
+        let (operation, gpu_config) = {
+            let _edit_guard = self.config_edit_lock.lock().await;
+            self.ensure_config_editable()?;
+            if self.is_thermal_protection_active(id) {
+                return Err(ErrorKind::HardwareBusy.error(
+                    "The fan response can't be measured while thermal protection is active",
+                ));
+            }
+            if self.fan_measurements.borrow().contains(id) {
+                return Err(ErrorKind::HardwareBusy
+                    .error("The fan response of the GPU is already being measured"));
+            }
+            if !self
+                .controller_by_id(id)
+                .await?
+                .get_capabilities()
+                .fan_control
+            {
+                return Err(ErrorKind::Unsupported.error("The GPU does not support fan control"));
+            }
+
+            let gpu_config = self.stored_gpu_config(id).await?;
+            info!("measuring the fan response of GPU {id} from PWM {from_pwm} to {to_pwm}");
+            self.fan_measurements.borrow_mut().insert(id.to_owned());
+            let operation = Operations::start(
+                &self.operations,
+                OperationKind::FanResponseMeasurement,
+                id,
+                chrono::Local::now().timestamp_millis(),
+            );
+            (operation, gpu_config)
+        };
+
+        // The cancellation is checked first, so no further step is applied
+        // once thermal protection has taken over the fan
+        let result = tokio::select! {
+            biased;
+            () = operation.cancelled() => {
+                if self.is_thermal_protection_active(id) {
+                    Err(ErrorKind::HardwareBusy
+                        .error("Thermal protection was triggered while measuring the fan response"))
+                } else {
+                    Err(anyhow!("The fan response measurement was cancelled"))
+                }
+            }
+            result = self.step_fan(id, &gpu_config, from_pwm, to_pwm) => result,
+        };
+        drop(operation);
+        self.fan_measurements.borrow_mut().remove(id);
+
+        // Thermal protection has applied its own settings, which should stay in effect
+        if self.is_thermal_protection_active(id) {
+            warn!("thermal protection is active, not restoring the fan settings of GPU {id}");
+        } else if let Err(err) = self.restore_gpu_config(id).await {
+            error!("could not restore the fan settings of GPU {id}: {err:#}");
+        }
+        result
+    }
+
+    async fn stored_gpu_config(&self, id: &str) -> anyhow::Result<GpuConfig> {
+        Ok(self
+            .config
+            .read()
+            .await
+            .gpus()?
+            .get(id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    /// Applies the stored settings of the GPU again
+    async fn restore_gpu_config(&self, id: &str) -> anyhow::Result<()> {
+        let gpu_config = self.stored_gpu_config(id).await?;
+        let controller = self.controller_by_id(id).await?;
+        apply_and_record(&self.applied_configs, &*controller, &gpu_config).await
+    }
+
+    /// The controller is only borrowed for each step, so the GPU list can be reloaded
+    /// while waiting for the fan
+    async fn step_fan(
+        &self,
+        id: &str,
+        gpu_config: &GpuConfig,
+        from_pwm: u8,
+        to_pwm: u8,
+    ) -> anyhow::Result<FanResponse> {
+        let from_config = static_fan_config(gpu_config, from_pwm);
+        apply_and_record(
+            &self.applied_configs,
+            &*self.controller_by_id(id).await?,
+            &from_config,
+        )
+        .await?;
+        let (_, initial_rpm) = self.wait_fan_settled(id).await?;
+
+        let to_config = static_fan_config(gpu_config, to_pwm);
+        apply_and_record(
+            &self.applied_configs,
+            &*self.controller_by_id(id).await?,
+            &to_config,
+        )
+        .await?;
+        let (settle_time, final_rpm) = self.wait_fan_settled(id).await?;
+
+        Ok(FanResponse {
+            settle_time_ms: u64::try_from(settle_time.as_millis()).unwrap_or(u64::MAX),
+            initial_rpm,
+            final_rpm,
+        })
+    }
+
+    /// Reads the fan speed until it stops changing, returning how long that took and the speed
+    async fn wait_fan_settled(&self, id: &str) -> anyhow::Result<(Duration, u32)> {
+        let start = Instant::now();
+        let mut detector = SettleDetector::default();
+
+        loop {
+            sleep(fan_response::SAMPLE_INTERVAL).await;
+            if self.is_thermal_protection_active(id) {
+                return Err(ErrorKind::HardwareBusy
+                    .error("Thermal protection was triggered while measuring the fan response"));
+            }
+
+            let elapsed = start.elapsed();
+            let rpm = self
+                .controller_by_id(id)
+                .await?
+                .get_stats(None)
+                .fan
+                .speed_current
+                .context("The GPU does not report its fan speed")?;
+            if let Some(settle_time) = detector.push(elapsed, rpm) {
+                return Ok((settle_time, rpm));
+            }
+
+            if elapsed >= fan_response::SETTLE_TIMEOUT {
+                bail!(
+                    "The fan speed did not settle within {} seconds",
+                    fan_response::SETTLE_TIMEOUT.as_secs()
+                );
+            }
+        }
+    }
+
+    /// The settings to write to the GPU. While thermal protection is in effect, its action stays
+    /// applied on top of the settings, the rest of them take effect once the GPU has cooled down.
+    fn protected_gpu_config<'c>(
+        &self,
+        id: &str,
+        controller: &dyn GpuController,
+        gpu_config: &'c GpuConfig,
+    ) -> Cow<'c, GpuConfig> {
+        let Some(settings) = &gpu_config.thermal_protection else {
+            return Cow::Borrowed(gpu_config);
+        };
+        if !self.is_thermal_protection_active(id) {
+            return Cow::Borrowed(gpu_config);
+        }
+
+        info!(
+            "thermal protection is active on GPU {id}, keeping its action {:?} in effect",
+            settings.action
+        );
+        // The current cap is already the reduced one
+        let default_cap = controller.get_stats(Some(gpu_config)).power.cap.default;
+        Cow::Owned(protected_config(gpu_config, settings.action, default_cap))
+    }
+
+    fn is_thermal_protection_active(&self, id: &str) -> bool {
+        self.thermal_protection
+            .borrow()
+            .get(id)
+            .is_some_and(ThermalProtectionState::is_active)
+    }
+
     pub async fn set_power_cap(&'a self, id: &str, maybe_cap: Option<f64>) -> anyhow::Result<u64> {
         self.edit_gpu_config(id.to_owned(), |gpu_config| {
             gpu_config.power_cap = maybe_cap;
@@ -533,6 +1802,91 @@ impl<'a> Handler {
         .context("Failed to edit GPU config and set power cap")
     }
 
+    pub async fn set_power_cap_percent(&'a self, id: &str, percent: f64) -> anyhow::Result<u64> {
+        if !POWER_CAP_PERCENT_RANGE.contains(&percent) {
+            return Err(ErrorKind::OutOfRange.error(format!(
+                "Power cap percentage {percent} is outside of the allowed range {}-{}%",
+                POWER_CAP_PERCENT_RANGE.start(),
+                POWER_CAP_PERCENT_RANGE.end()
+            )));
+        }
+
+        let power_stats = self.get_gpu_stats(id).await?.power;
+        let cap = power_stats.cap.from_percent(percent).ok_or_else(|| {
+            ErrorKind::Unsupported
+                .error("The GPU does not report its default and maximum power cap")
+        })?;
+
+        self.set_power_cap(id, Some(cap)).await
+    }
+
+    pub async fn set_voltage_offset(&self, id: &str, offset: Option<i32>) -> anyhow::Result<u64> {
+        let offset = match offset {
+            Some(offset) => {
+                let ValueRange { min, max } = self
+                    .get_capabilities(id)
+                    .await?
+                    .voltage_offset
+                    .ok_or_else(|| {
+                        ErrorKind::Unsupported.error(
+                            "The GPU does not support voltage offsets or does not report their range",
+                        )
+                    })?;
+
+                let clamped = offset.clamp(min, max);
+                if clamped != offset {
+                    warn!(
+                        "voltage offset {offset}mV is outside of the allowed range {min}..{max}mV, \
+                        using {clamped}mV"
+                    );
+                }
+                Some(clamped)
+            }
+            None => None,
+        };
+
+        self.edit_gpu_config(id.to_owned(), |gpu_config| {
+            gpu_config.clocks_configuration.voltage_offset = offset;
+        })
+        .await
+        .context("Failed to edit GPU config and set voltage offset")
+    }
+
+    pub async fn set_memory_clock_offset(
+        &self,
+        id: &str,
+        offset: Option<i32>,
+    ) -> anyhow::Result<u64> {
+        let ranges = self.get_capabilities(id).await?.mem_clock_offsets;
+        if ranges.is_empty() {
+            return Err(
+                ErrorKind::Unsupported.error("The GPU does not support memory clock offsets")
+            );
+        }
+
+        if let Some(offset) = offset {
+            for (pstate, range) in &ranges {
+                validate_within_range(
+                    "Memory clock offset",
+                    offset,
+                    Some(range.min),
+                    Some(range.max),
+                    "MHz",
+                )
+                .with_context(|| format!("Invalid offset for power state {pstate}"))?;
+            }
+        }
+
+        self.edit_gpu_config(id.to_owned(), |gpu_config| {
+            gpu_config.clocks_configuration.mem_clock_offsets = match offset {
+                Some(offset) => ranges.keys().map(|pstate| (*pstate, offset)).collect(),
+                None => IndexMap::new(),
+            };
+        })
+        .await
+        .context("Failed to edit GPU config and set memory clock offset")
+    }
+
     pub async fn get_power_states(&self, id: &str) -> anyhow::Result<PowerStates> {
         let config = self.config.read().await;
         let gpu_config = config.gpus()?.get(id);
@@ -549,6 +1903,13 @@ impl<'a> Handler {
         id: &str,
         level: PerformanceLevel,
     ) -> anyhow::Result<u64> {
+        let capabilities = self.get_capabilities(id).await?;
+        if !capabilities.performance_levels.contains(&level) {
+            return Err(ErrorKind::Unsupported.error(format!(
+                "The GPU does not support the `{level}` performance level"
+            )));
+        }
+
         self.edit_gpu_config(id.to_owned(), |gpu_config| {
             gpu_config.performance_level = Some(level);
 
@@ -629,6 +1990,118 @@ impl<'a> Handler {
         .context("Failed to edit GPU config and set enabled power states")
     }
 
+    pub async fn set_clock_states(
+        &self,
+        id: &str,
+        kind: PowerLevelKind,
+        states: IndexMap<u8, ClockVoltagePoint>,
+    ) -> anyhow::Result<u64> {
+        ensure_clock_state_kind(kind)?;
+
+        self.edit_gpu_config(id.to_owned(), |gpu| {
+            let clocks = &mut gpu.clocks_configuration;
+            match kind {
+                PowerLevelKind::MemoryClock => clocks.memory_clock_states = states,
+                _ => clocks.core_clock_states = states,
+            }
+        })
+        .await
+        .context("Failed to edit GPU config and set clock states")
+    }
+
+    /// Unlike [`Handler::set_clock_states`], the overrides of the other states are kept
+    pub async fn set_clock_state(
+        &self,
+        id: &str,
+        kind: PowerLevelKind,
+        index: u8,
+        point: Option<ClockVoltagePoint>,
+    ) -> anyhow::Result<u64> {
+        ensure_clock_state_kind(kind)?;
+
+        self.edit_gpu_config(id.to_owned(), |gpu| {
+            let clocks = &mut gpu.clocks_configuration;
+            let states = match kind {
+                PowerLevelKind::MemoryClock => &mut clocks.memory_clock_states,
+                _ => &mut clocks.core_clock_states,
+            };
+            match point {
+                Some(point) => {
+                    states.insert(index, point);
+                }
+                None => {
+                    states.shift_remove(&index);
+                }
+            }
+        })
+        .await
+        .context("Failed to edit GPU config and set clock state")
+    }
+
+    pub async fn set_memory_clock_level(&self, id: &str, level: Option<u8>) -> anyhow::Result<u64> {
+        self.edit_gpu_config(id.to_owned(), |gpu| {
+            gpu.memory_clock_level = level;
+        })
+        .await
+        .context("Failed to edit GPU config and set memory clock level")
+    }
+
+    pub async fn set_pcie_level(&self, id: &str, level: Option<u8>) -> anyhow::Result<u64> {
+        self.edit_gpu_config(id.to_owned(), |gpu| {
+            gpu.pcie_level = level;
+        })
+        .await
+        .context("Failed to edit GPU config and set PCIe level")
+    }
+
+    pub async fn set_idle_lock(&self, id: &str, enabled: bool) -> anyhow::Result<u64> {
+        if enabled && !self.get_capabilities(id).await?.performance_level {
+            return Err(
+                ErrorKind::Unsupported.error("The GPU does not support forcing clock levels")
+            );
+        }
+
+        self.edit_gpu_config(id.to_owned(), |gpu| {
+            gpu.idle_lock = enabled;
+        })
+        .await
+        .context("Failed to edit GPU config and set idle lock")
+    }
+
+    pub async fn set_led(
+        &self,
+        id: &str,
+        name: &str,
+        settings: Option<LedSettings>,
+    ) -> anyhow::Result<u64> {
+        // The settings are checked against the LEDs of the GPU when they get applied
+        self.edit_gpu_config(id.to_owned(), |gpu| match settings {
+            Some(settings) => {
+                gpu.leds.insert(name.to_owned(), settings);
+            }
+            None => {
+                gpu.leds.shift_remove(name);
+            }
+        })
+        .await
+        .context("Failed to edit GPU config and set LED")
+    }
+
+    pub async fn get_dry_run_log(&self, id: &str) -> anyhow::Result<Vec<SysfsWrite>> {
+        self.controller_by_id(id)
+            .await?
+            .dry_run_log()
+            .context("Dry run mode is not enabled")
+    }
+
+    /// Holds the config edit lock, so that the values which get written back
+    /// can't be changed by applying other settings in the meantime
+    pub async fn run_self_test(&self, id: &str) -> anyhow::Result<Vec<SelfTestEntry>> {
+        let _edit_guard = self.config_edit_lock.lock().await;
+        self.ensure_config_editable()?;
+        self.controller_by_id(id).await?.self_test()
+    }
+
     pub async fn vbios_dump(&self, id: &str) -> anyhow::Result<Vec<u8>> {
         self.controller_by_id(id).await?.vbios_dump()
     }
@@ -698,7 +2171,7 @@ impl<'a> Handler {
             Err(err) => warn!("could not read service log: {err:#}"),
         }
 
-        let system_info = system::info()
+        let system_info = system::info(self.safe_mode)
             .await
             .ok()
             .map(|info| serde_json::to_value(info).unwrap());
@@ -803,25 +2276,106 @@ impl<'a> Handler {
         name: Option<Rc<str>>,
         auto_switch: bool,
     ) -> anyhow::Result<()> {
+        // The choice of the user replaces the temporary profile, it should not get reverted
+        self.temporary_profile.borrow_mut().take();
+
         if auto_switch {
             self.start_profile_watcher().await;
         } else {
             self.stop_profile_watcher().await;
-            self.set_current_profile(name).await?;
+        }
+
+        let _edit_guard = self.config_edit_lock.lock().await;
+        if !auto_switch {
+            self.switch_profile(name).await?;
         }
 
         let mut config = self.config.write().await;
         config.auto_switch_profiles = auto_switch;
+        config.saved_profile = None;
         config.save(&self.config_last_saved)?;
 
         Ok(())
     }
 
-    pub(super) async fn set_current_profile(&self, name: Option<Rc<str>>) -> anyhow::Result<()> {
+    /// Switches to a profile for the given amount of time, after which the previous profile
+    /// is restored without needing a confirmation. Applying another temporary profile while
+    /// one is active restarts the timer, the original profile is still the one restored.
+    ///
+    /// The temporary profile is not saved in the config, so it doesn't stay after a restart.
+    pub async fn apply_temporary_profile(
+        &self,
+        name: Rc<str>,
+        duration_secs: u64,
+    ) -> anyhow::Result<()> {
+        if duration_secs == 0 {
+            return Err(ErrorKind::OutOfRange.error("The duration has to be at least one second"));
+        }
+        let _edit_guard = self.config_edit_lock.lock().await;
+        self.ensure_config_editable()?;
+        if self.config.read().await.auto_switch_profiles {
+            bail!("Temporary profiles can't be used while profiles are switched automatically");
+        }
+
+        let active_previous = self
+            .temporary_profile
+            .borrow()
+            .as_ref()
+            .map(|active| active.previous_profile.clone());
+        let previous_profile = match active_previous {
+            Some(previous_profile) => previous_profile,
+            None => self.config.read().await.current_profile.clone(),
+        };
+
+        self.switch_profile(Some(name.clone())).await?;
+        self.config.write().await.saved_profile = Some(previous_profile.clone());
+
+        let timer_id = self.last_temporary_timer_id.get() + 1;
+        self.last_temporary_timer_id.set(timer_id);
+        *self.temporary_profile.borrow_mut() = Some(TemporaryProfile {
+            previous_profile,
+            timer_id,
+        });
+        info!("applied temporary profile '{name}' for {duration_secs}s");
+
+        let handler = self.clone();
+        tokio::task::spawn_local(async move {
+            sleep(Duration::from_secs(duration_secs)).await;
+            handler.revert_temporary_profile(timer_id).await;
+        });
+
+        Ok(())
+    }
+
+    async fn revert_temporary_profile(&self, timer_id: u64) {
+        let _edit_guard = self.config_edit_lock.lock().await;
+        let active = self.temporary_profile.borrow().clone();
+        // Another temporary profile or a profile change by the user has replaced this one
+        let Some(active) = active.filter(|active| active.timer_id == timer_id) else {
+            return;
+        };
+        self.temporary_profile.borrow_mut().take();
+        self.config.write().await.saved_profile = None;
+
+        info!("temporary profile time has run out, switching back to the previous profile");
+        if let Err(err) = self.switch_profile(active.previous_profile).await {
+            error!("could not switch back from the temporary profile: {err:#}");
+        }
+    }
+
+    pub(crate) async fn set_current_profile(&self, name: Option<Rc<str>>) -> anyhow::Result<()> {
+        let _edit_guard = self.config_edit_lock.lock().await;
+        self.switch_profile(name).await
+    }
+
+    /// Applies the given profile, the caller has to hold the config edit lock
+    async fn switch_profile(&self, name: Option<Rc<str>>) -> anyhow::Result<()> {
         let mut activation_hook = None;
         let mut deactivation_hook = None;
+        let mut on_profile_change = None;
         {
             let config = self.config.read().await;
+            on_profile_change.clone_from(&config.daemon.on_profile_change);
             // Make sure the profile exists
             if let Some(name) = &name {
                 let new_profile = config.profile(name)?;
@@ -835,10 +2389,14 @@ impl<'a> Handler {
             }
         }
 
+        let profile = name.as_deref().map(str::to_owned);
         self.cleanup().await;
         self.config.write().await.current_profile = name;
 
         self.apply_current_config().await?;
+        self.log_event(DaemonEventKind::ProfileSwitched {
+            profile: profile.clone(),
+        });
 
         if let Some(deactivated) = &deactivation_hook {
             run_hook_command(deactivated).await?;
@@ -846,6 +2404,12 @@ impl<'a> Handler {
         if let Some(activated) = &activation_hook {
             run_hook_command(activated).await?;
         }
+        if let Some(command) = &on_profile_change {
+            let profile = profile.as_deref().unwrap_or_default();
+            if let Err(err) = run_profile_change_command(command, profile).await {
+                error!("could not run the profile change command: {err:#}");
+            }
+        }
 
         Ok(())
     }
@@ -876,16 +2440,28 @@ impl<'a> Handler {
     }
 
     pub async fn delete_profile(&self, name: String) -> anyhow::Result<()> {
+        let _edit_guard = self.config_edit_lock.lock().await;
         if self.config.read().await.current_profile.as_deref() == Some(&name) {
-            self.set_current_profile(None).await?;
+            self.switch_profile(None).await?;
+        }
+        // The temporary profile can't return to a profile which doesn't exist anymore
+        if let Some(active) = self.temporary_profile.borrow_mut().as_mut() {
+            if active.previous_profile.as_deref() == Some(&name) {
+                active.previous_profile = None;
+            }
         }
-        self.config
-            .write()
-            .await
-            .profiles
-            .shift_remove(name.as_str());
 
-        self.config.write().await.save(&self.config_last_saved)?;
+        let mut config = self.config.write().await;
+        if config
+            .saved_profile
+            .as_ref()
+            .is_some_and(|saved_profile| saved_profile.as_deref() == Some(&name))
+        {
+            config.saved_profile = Some(None);
+        }
+        config.profiles.shift_remove(name.as_str());
+        config.save(&self.config_last_saved)?;
+        drop(config);
 
         let tx = self.profile_watcher_tx.borrow().clone();
         if let Some(tx) = tx {
@@ -905,7 +2481,7 @@ impl<'a> Handler {
                 .with_context(|| format!("Profile {name} not found"))?;
 
             if new_position >= config.profiles.len() {
-                bail!("Provided index is out of bounds");
+                return Err(ErrorKind::OutOfRange.error("Provided index is out of bounds"));
             }
 
             config.profiles.swap_indices(current_index, new_position);
@@ -961,6 +2537,124 @@ impl<'a> Handler {
             .await
     }
 
+    pub async fn export_gpu_config(&self, id: &str) -> anyhow::Result<String> {
+        let (model_id, model_name) = self.gpu_model(id).await?;
+        let config = self
+            .get_gpu_config(id)
+            .await?
+            .with_context(|| format!("GPU {id} has no stored settings"))?;
+
+        let shared = SharedGpuConfig {
+            model_id,
+            model_name,
+            config,
+        };
+        serde_yml::to_string(&shared).context("Could not serialize settings")
+    }
+
+    pub async fn import_gpu_config(
+        &self,
+        id: &str,
+        snippet: &str,
+        allow_model_mismatch: bool,
+    ) -> anyhow::Result<u64> {
+        let shared: SharedGpuConfig =
+            serde_yml::from_str(snippet).context("Could not parse settings snippet")?;
+
+        let (model_id, _) = self.gpu_model(id).await?;
+        if shared.model_id != model_id {
+            let exported_from = match &shared.model_name {
+                Some(name) => format!("{name} ({})", shared.model_id),
+                None => shared.model_id.clone(),
+            };
+            if !allow_model_mismatch {
+                return Err(anyhow!(
+                    "The settings were exported from a different GPU model, {exported_from}. \
+                    Allow the model mismatch to import them anyway"
+                ));
+            }
+            warn!("importing settings from {exported_from} onto GPU {id} with model {model_id}");
+        }
+
+        self.set_gpu_config(id, shared.config).await
+    }
+
+    /// Applies the settings to every GPU with the given vendor and device id, such as `1002:73BF`.
+    /// GPUs which don't support some of the settings are skipped, and a GPU failing to apply them
+    /// doesn't stop the others. The changes of all GPUs get confirmed or reverted together.
+    pub async fn broadcast_to_model(
+        &self,
+        model: &str,
+        changes: GpuConfig,
+    ) -> anyhow::Result<ModelBroadcast> {
+        let _edit_guard = self.config_edit_lock.lock().await;
+        self.ensure_config_editable()?;
+
+        let candidates: Vec<(String, GpuCapabilities)> = self
+            .gpu_controllers
+            .read()
+            .await
+            .iter()
+            .filter(|(_, controller)| pci_model_id(controller.as_ref()).eq_ignore_ascii_case(model))
+            .map(|(id, controller)| (id.clone(), controller.get_capabilities()))
+            .collect();
+        if candidates.is_empty() {
+            return Err(ErrorKind::GpuNotFound.error(format!("No GPUs of model {model} found")));
+        }
+
+        let mut gpus = BTreeMap::new();
+        let mut previous_configs = vec![];
+        for (id, capabilities) in candidates {
+            let unsupported = unsupported_settings(&changes, &capabilities);
+            let outcome = if unsupported.is_empty() {
+                match self
+                    .apply_gpu_config(&id, |config| {
+                        merge_settings(config, &changes);
+                        Ok(())
+                    })
+                    .await
+                {
+                    Ok(previous_config) => {
+                        previous_configs.push((id.clone(), previous_config));
+                        BroadcastOutcome::Applied
+                    }
+                    Err(err) => BroadcastOutcome::Failed {
+                        error: format!("{err:#}"),
+                    },
+                }
+            } else {
+                info!(
+                    "skipping GPU {id}, it does not support {}",
+                    unsupported.join(", ")
+                );
+                BroadcastOutcome::Skipped { unsupported }
+            };
+            gpus.insert(id, outcome);
+        }
+
+        // The GPUs which were changed share one confirmation timer
+        let apply_timer = if previous_configs.is_empty() {
+            None
+        } else {
+            let apply_timer = self.config.read().await.apply_settings_timer;
+            let settings = PreviousSettings::Gpus(previous_configs.clone());
+            if let Err(err) = self.wait_config_confirm(settings, apply_timer) {
+                self.revert_gpu_configs(previous_configs).await;
+                return Err(err);
+            }
+            Some(apply_timer)
+        };
+
+        Ok(ModelBroadcast { apply_timer, gpus })
+    }
+
+    /// PCI vendor and device id, along with the name of the model
+    async fn gpu_model(&self, id: &str) -> anyhow::Result<(String, Option<String>)> {
+        let controller = self.controller_by_id(id).await?;
+        let pci_info = &controller.controller_info().pci_info.device_pci_info;
+        Ok((pci_model_id(&*controller), pci_info.model.clone()))
+    }
+
     pub fn evaluate_profile_rule(&self, rule: &ProfileRule) -> anyhow::Result<bool> {
         let profile_watcher_state_guard = self.profile_watcher_state.borrow();
         match profile_watcher_state_guard.as_ref() {
@@ -971,6 +2665,60 @@ impl<'a> Handler {
         }
     }
 
+    /// Applies the config like `set_gpu_config`, but with a custom confirmation timer.
+    /// The returned token identifies the staged change, so the client connection that staged it
+    /// can revert it with `revert_staged_settings` when it goes away without confirming.
+    pub async fn stage_settings(
+        &self,
+        id: &str,
+        new_config: GpuConfig,
+        timeout_secs: Option<u64>,
+    ) -> anyhow::Result<StagedSettings> {
+        if timeout_secs == Some(0) {
+            return Err(ErrorKind::OutOfRange.error("The confirmation timeout cannot be zero"));
+        }
+
+        let timeout_secs = self
+            .edit_gpu_config_with_timer(id.to_owned(), timeout_secs, |config| {
+                *config = new_config;
+            })
+            .await?;
+
+        let token = self.last_staged_token.get() + 1;
+        self.last_staged_token.set(token);
+        self.staged_settings.set(Some(token));
+
+        Ok(StagedSettings {
+            token,
+            timeout_secs,
+        })
+    }
+
+    /// Confirms the staged change with the given token,
+    /// settings which were staged by a different client can't be confirmed
+    pub fn confirm_staged_settings(&self, token: Option<u64>) -> anyhow::Result<()> {
+        match self.staged_settings.get() {
+            None => Err(anyhow!("No staged settings")),
+            Some(staged) if token == Some(staged) => {
+                self.confirm_pending_config(ConfirmCommand::Confirm)
+            }
+            Some(_) => {
+                Err(ErrorKind::PermissionDenied
+                    .error("The settings were staged by a different client"))
+            }
+        }
+    }
+
+    /// Reverts the staged change with the given token if it is still waiting for confirmation
+    pub fn revert_staged_settings(&self, token: u64) {
+        if self.staged_settings.get() == Some(token) {
+            info!("client disconnected without confirming the staged settings, reverting");
+            if let Err(err) = self.confirm_pending_config(ConfirmCommand::Revert) {
+                error!("could not revert staged settings: {err:#}");
+            }
+        }
+    }
+
     pub fn confirm_pending_config(&self, command: ConfirmCommand) -> anyhow::Result<()> {
         if let Some(tx) = self
             .confirm_config_tx
@@ -978,6 +2726,13 @@ impl<'a> Handler {
             .map_err(|err| anyhow!("{err}"))?
             .take()
         {
+            self.staged_settings.set(None);
+            let pending_provenance = self.pending_provenance.take();
+            if command == ConfirmCommand::Confirm {
+                for (id, provenance) in pending_provenance {
+                    self.insert_setting_provenance(id, provenance);
+                }
+            }
             tx.send(command)
                 .map_err(|_| anyhow!("Could not confirm config"))
         } else {
@@ -985,7 +2740,161 @@ impl<'a> Handler {
         }
     }
 
+    /// Disabling management resets the GPUs to their defaults without touching the stored config,
+    /// which gets reapplied once management is enabled again
+    pub async fn set_management_enabled(&self, enabled: bool) -> anyhow::Result<()> {
+        if self.management_enabled.get() == enabled {
+            return Ok(());
+        }
+        if self.confirm_config_tx.borrow().is_some() {
+            return Err(anyhow!(
+                "There is an unconfirmed configuration change pending"
+            ));
+        }
+
+        self.management_enabled.set(enabled);
+        if enabled {
+            info!("management enabled, reapplying the configuration");
+            self.apply_current_config().await
+        } else {
+            info!("management disabled, resetting GPUs to their defaults");
+            self.cleanup().await;
+            Ok(())
+        }
+    }
+
+    /// Swaps in a new config, such as after the config file was edited.
+    /// The settings are applied to the GPUs only when `apply` is set.
+    pub async fn replace_config(&self, new_config: Config, apply: bool) -> anyhow::Result<()> {
+        let log_level = new_config.daemon.log_level.clone();
+        let old_config = std::mem::replace(&mut *self.config.write().await, new_config);
+        if old_config.daemon.log_level != log_level {
+            match self.set_log_level(&log_level) {
+                Ok(()) => info!("log level changed to {log_level}"),
+                Err(err) => error!("could not change log level: {err:#}"),
+            }
+        }
+        self.stats_poll_notify.notify_one();
+
+        if apply {
+            self.apply_current_config().await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the whole config in the format of the config file
+    pub async fn export_config(&self) -> anyhow::Result<String> {
+        self.config.read().await.to_yaml()
+    }
+
+    /// Replaces the GPU settings, profiles and aliases with the ones from an exported config.
+    /// The daemon settings of this system are kept.
+    /// Settings of GPUs which are in a different PCI slot on this system are moved to them,
+    /// like on startup. Settings for GPUs which are not present are only accepted when allowed.
+    ///
+    /// When the settings are applied, they have to be confirmed like any other change before
+    /// they get saved, and the returned timer is the time left for that.
+    pub async fn import_config(
+        &self,
+        raw_config: &str,
+        apply: bool,
+        allow_missing_gpus: bool,
+    ) -> anyhow::Result<Option<u64>> {
+        let _edit_guard = self.config_edit_lock.lock().await;
+        self.ensure_config_editable()?;
+
+        let mut new_config: Config =
+            serde_yml::from_str(raw_config).context("Could not parse config")?;
+        // Makes sure that the current profile exists
+        new_config.gpus()?;
+
+        {
+            let controllers = self.gpu_controllers.read().await;
+            new_config.migrate_versions(&controllers);
+            reattach_gpu_configs(&controllers, &mut new_config);
+
+            let missing_ids: Vec<&str> = new_config
+                .stored_gpu_ids()
+                .into_iter()
+                .filter(|id| !controllers.contains_key(*id))
+                .collect();
+            if !missing_ids.is_empty() {
+                let missing_ids = missing_ids.join(", ");
+                if !allow_missing_gpus {
+                    return Err(ErrorKind::GpuNotFound.error(format!(
+                        "The config has settings for GPUs which are not present: {missing_ids}. \
+                        Allow missing GPUs to import it anyway"
+                    )));
+                }
+                warn!("importing config with settings for missing GPUs: {missing_ids}");
+            }
+
+            // Settings are checked the same way as when they get changed one by one, as
+            // errors while applying an imported config only get logged
+            let current_config = self.config.read().await;
+            let current_gpus = current_config.gpus()?;
+            for (id, gpu_config) in new_config.all_gpu_configs() {
+                let Some(controller) = controllers.get(id) else {
+                    continue;
+                };
+                let previous_config = current_gpus.get(id).cloned().unwrap_or_default();
+                check_external_temperatures(&controllers, id, &previous_config, gpu_config)
+                    .and_then(|()| {
+                        check_gpu_config(controller.as_ref(), &previous_config, gpu_config)
+                    })
+                    .with_context(|| format!("Invalid settings for GPU {id}"))?;
+            }
+        }
+
+        let mut previous_config = self.config.read().await.clone();
+        // A revert goes back to the profile which is saved, not to the temporary one
+        if let Some(saved_profile) = previous_config.saved_profile.take() {
+            previous_config.current_profile = saved_profile;
+        }
+        let mut config = previous_config.clone();
+        config.import_settings(new_config);
+
+        // The profile to switch back to is from the previous config
+        self.temporary_profile.borrow_mut().take();
+        let auto_switch = config.auto_switch_profiles;
+        if !auto_switch {
+            self.stop_profile_watcher().await;
+        }
+
+        if !apply {
+            self.replace_config(config, false).await?;
+            self.config.read().await.save(&self.config_last_saved)?;
+            info!("imported config");
+            return Ok(None);
+        }
+
+        // GPUs which don't have any settings in the new config should not keep the old ones
+        self.cleanup().await;
+        if let Err(err) = self.replace_config(config, true).await {
+            self.revert_config(previous_config).await;
+            return Err(err.context("Could not apply the imported config"));
+        }
+        if auto_switch {
+            self.start_profile_watcher().await;
+        }
+
+        let gpu_ids = self.gpu_controllers.read().await.keys().cloned().collect();
+        let apply_timer = previous_config.apply_settings_timer;
+        self.wait_config_confirm(
+            PreviousSettings::Config {
+                config: Box::new(previous_config),
+                gpu_ids,
+            },
+            apply_timer,
+        )?;
+
+        info!("imported config, waiting for confirmation");
+        Ok(Some(apply_timer))
+    }
+
     pub async fn reset_config(&self) {
+        let _edit_guard = self.config_edit_lock.lock().await;
+        self.temporary_profile.borrow_mut().take();
         self.cleanup().await;
 
         let mut config = self.config.write().await;
@@ -997,7 +2906,15 @@ impl<'a> Handler {
     }
 
     pub async fn cleanup(&self) {
-        let disable_clocks_cleanup = self.config.read().await.daemon.disable_clocks_cleanup;
+        let (disable_clocks_cleanup, fan_behavior, applied_configs) = {
+            let config = self.config.read().await;
+            let applied_configs = config.gpus().cloned().unwrap_or_default();
+            (
+                config.daemon.disable_clocks_cleanup,
+                config.daemon.shutdown_fan_behavior,
+                applied_configs,
+            )
+        };
 
         let controllers = self.gpu_controllers.read().await;
         for (id, controller) in controllers.iter() {
@@ -1008,9 +2925,20 @@ impl<'a> Handler {
                 }
             }
 
-            controller.reset_pmfw_settings();
+            // The PMFW settings are a part of the fan control
+            if fan_behavior != ShutdownFanBehavior::LeaveAsIs {
+                controller.reset_pmfw_settings();
+            }
 
-            if let Err(err) = controller.apply_config(&GpuConfig::default()).await {
+            let reset_config = shutdown_config(
+                fan_behavior,
+                applied_configs.get(id),
+                controller.get_capabilities().fan_control,
+            );
+            debug!("resetting controller {id} with fan behavior {fan_behavior:?}");
+            if let Err(err) =
+                apply_and_record(&self.applied_configs, controller, &reset_config).await
+            {
                 error!("Could not reset settings for controller {id}: {err:#}");
             }
 
@@ -1019,15 +2947,63 @@ impl<'a> Handler {
     }
 }
 
+/// Settings which a GPU gets reset to when the daemon exits
+fn shutdown_config(
+    fan_behavior: ShutdownFanBehavior,
+    applied_config: Option<&GpuConfig>,
+    fan_control_supported: bool,
+) -> GpuConfig {
+    match fan_behavior {
+        ShutdownFanBehavior::RestoreFirmwareControl => GpuConfig::default(),
+        ShutdownFanBehavior::LeaveAsIs => match applied_config {
+            Some(applied_config) => GpuConfig {
+                fan_control_enabled: applied_config.fan_control_enabled,
+                fan_control_settings: applied_config.fan_control_settings.clone(),
+                pmfw_options: applied_config.pmfw_options.clone(),
+                ..Default::default()
+            },
+            None => GpuConfig::default(),
+        },
+        ShutdownFanBehavior::SetFullSpeed if fan_control_supported => protected_config(
+            &GpuConfig::default(),
+            ThermalProtectionAction::MaxFanSpeed,
+            None,
+        ),
+        ShutdownFanBehavior::SetFullSpeed => GpuConfig::default(),
+    }
+}
+
+/// Applies the settings to a controller, after recording them for crash reports
+async fn apply_and_record(
+    applied_configs: &AppliedConfigs,
+    controller: &dyn GpuController,
+    config: &GpuConfig,
+) -> anyhow::Result<()> {
+    crash::record_applied_config(
+        applied_configs,
+        &controller.controller_info().build_id(),
+        config,
+    );
+    controller.apply_config(config).await
+}
+
+/// Right after a resume, the settings which are delayed on resume are left out
 async fn apply_config_to_controllers(
+    applied_configs: &AppliedConfigs,
     controllers: &BTreeMap<String, Box<dyn GpuController>>,
     config: &Config,
+    on_resume: bool,
 ) -> anyhow::Result<()> {
     let gpus = config.gpus()?;
     for (id, gpu_config) in gpus {
         if let Some(controller) = controllers.get(id) {
+            let gpu_config = if on_resume {
+                gpu_config.without_delayed_on_resume()
+            } else {
+                gpu_config.clone()
+            };
             debug!("applying config {gpu_config:#?} to controller {id}");
-            if let Err(err) = controller.apply_config(gpu_config).await {
+            if let Err(err) = apply_and_record(applied_configs, controller, &gpu_config).await {
                 error!("could not apply existing config for gpu {id}: {err:#}");
             }
         } else {
@@ -1038,6 +3014,473 @@ async fn apply_config_to_controllers(
     Ok(())
 }
 
+/// Per-state clocks can only be edited for the core and memory clocks
+fn ensure_clock_state_kind(kind: PowerLevelKind) -> anyhow::Result<()> {
+    match kind {
+        PowerLevelKind::CoreClock | PowerLevelKind::MemoryClock => Ok(()),
+        _ => Err(ErrorKind::Unsupported.error("Only core and memory clock states can be set")),
+    }
+}
+
+/// The settings with the fan running at a static PWM value
+fn static_fan_config(gpu_config: &GpuConfig, pwm: u8) -> GpuConfig {
+    let mut gpu_config = gpu_config.clone();
+    gpu_config.fan_control_enabled = true;
+    let settings = gpu_config
+        .fan_control_settings
+        .get_or_insert_with(FanControlSettings::default);
+    settings.mode = FanControlMode::Static;
+    settings.static_speed = f32::from(pwm) / f32::from(u8::MAX);
+    gpu_config
+}
+
+/// Has to run before the first run settings are added,
+/// otherwise GPUs which were moved to a different slot would be treated as new ones
+fn reattach_gpu_configs(
+    controllers: &BTreeMap<String, DynGpuController>,
+    config: &mut Config,
+) -> bool {
+    let detected_ids: Vec<&str> = controllers.keys().map(String::as_str).collect();
+    config.reattach_gpu_configs(&detected_ids)
+}
+
+/// Gives GPUs that don't have any stored settings the balanced fan curve,
+/// if it is enabled in the config. Returns if any settings were added.
+fn add_first_run_configs(
+    controllers: &BTreeMap<String, DynGpuController>,
+    config: &mut Config,
+) -> bool {
+    if !config.daemon.first_run_fan_curve {
+        return false;
+    }
+
+    let new_ids: Vec<&String> = controllers
+        .keys()
+        .filter(|id| !config.has_stored_gpu_config(id))
+        .collect();
+    let Ok(gpus) = config.gpus_mut() else {
+        return false;
+    };
+
+    let mut added = false;
+    for id in new_ids {
+        let capabilities = controllers[id].get_capabilities();
+        if !capabilities.fan_control {
+            continue;
+        }
+
+        let curve = fan_control::generate_curve(
+            FanCurvePreset::Balanced,
+            capabilities.fan_curve_temperature,
+            capabilities.fan_curve_speed,
+            capabilities.fan_curve_points,
+        );
+        let gpu_config = GpuConfig {
+            fan_control_enabled: true,
+            fan_control_settings: Some(FanControlSettings {
+                mode: FanControlMode::Curve,
+                curve,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        info!("GPU {id} has no stored settings, using the balanced fan curve");
+        gpus.insert(id.clone(), gpu_config);
+        added = true;
+    }
+    added
+}
+
+/// PCI vendor and device id of the GPU, such as `1002:73BF`
+fn pci_model_id(controller: &dyn GpuController) -> String {
+    let pci_info = &controller.controller_info().pci_info.device_pci_info;
+    format!("{}:{}", pci_info.vendor_id, pci_info.model_id)
+}
+
+/// Takes over the settings which are set in the changes, the rest of the config is kept.
+/// Fan control is taken over along with its settings, and the idle lock is always taken over
+/// as it has no unset value.
+fn merge_settings(config: &mut GpuConfig, changes: &GpuConfig) {
+    fn merge<T: Clone>(target: &mut Option<T>, value: &Option<T>) {
+        if value.is_some() {
+            target.clone_from(value);
+        }
+    }
+
+    if changes.fan_control_settings.is_some() {
+        config.fan_control_enabled = changes.fan_control_enabled;
+        config
+            .fan_control_settings
+            .clone_from(&changes.fan_control_settings);
+    }
+
+    let pmfw = &changes.pmfw_options;
+    merge(
+        &mut config.pmfw_options.acoustic_limit,
+        &pmfw.acoustic_limit,
+    );
+    merge(
+        &mut config.pmfw_options.acoustic_target,
+        &pmfw.acoustic_target,
+    );
+    merge(&mut config.pmfw_options.minimum_pwm, &pmfw.minimum_pwm);
+    merge(
+        &mut config.pmfw_options.target_temperature,
+        &pmfw.target_temperature,
+    );
+    merge(&mut config.pmfw_options.zero_rpm, &pmfw.zero_rpm);
+    merge(
+        &mut config.pmfw_options.zero_rpm_threshold,
+        &pmfw.zero_rpm_threshold,
+    );
+
+    merge(&mut config.power_cap, &changes.power_cap);
+    merge(&mut config.performance_level, &changes.performance_level);
+    merge(
+        &mut config.power_profile_mode_index,
+        &changes.power_profile_mode_index,
+    );
+    merge(&mut config.memory_clock_level, &changes.memory_clock_level);
+    merge(&mut config.pcie_level, &changes.pcie_level);
+    merge(&mut config.thermal_protection, &changes.thermal_protection);
+    if !changes.custom_power_profile_mode_hueristics.is_empty() {
+        config
+            .custom_power_profile_mode_hueristics
+            .clone_from(&changes.custom_power_profile_mode_hueristics);
+    }
+    config.power_states.extend(changes.power_states.clone());
+    config.idle_lock = changes.idle_lock;
+    config.leds.extend(changes.leds.clone());
+    if !changes.delayed_on_resume.is_empty() {
+        config
+            .delayed_on_resume
+            .clone_from(&changes.delayed_on_resume);
+    }
+
+    let clocks = &mut config.clocks_configuration;
+    let clock_changes = &changes.clocks_configuration;
+    merge(&mut clocks.min_core_clock, &clock_changes.min_core_clock);
+    merge(
+        &mut clocks.min_memory_clock,
+        &clock_changes.min_memory_clock,
+    );
+    merge(&mut clocks.min_voltage, &clock_changes.min_voltage);
+    merge(&mut clocks.max_core_clock, &clock_changes.max_core_clock);
+    merge(
+        &mut clocks.max_memory_clock,
+        &clock_changes.max_memory_clock,
+    );
+    merge(&mut clocks.max_voltage, &clock_changes.max_voltage);
+    merge(&mut clocks.voltage_offset, &clock_changes.voltage_offset);
+    clocks
+        .gpu_clock_offsets
+        .extend(clock_changes.gpu_clock_offsets.clone());
+    clocks
+        .mem_clock_offsets
+        .extend(clock_changes.mem_clock_offsets.clone());
+    clocks
+        .core_clock_states
+        .extend(clock_changes.core_clock_states.clone());
+    clocks
+        .memory_clock_states
+        .extend(clock_changes.memory_clock_states.clone());
+}
+
+/// Names of the settings in the config which the GPU has no support for
+fn unsupported_settings(config: &GpuConfig, capabilities: &GpuCapabilities) -> Vec<String> {
+    let clocks = &config.clocks_configuration;
+    let thermal_protection_supported = match config
+        .thermal_protection
+        .as_ref()
+        .map(|protection| protection.action)
+    {
+        None => true,
+        Some(ThermalProtectionAction::MaxFanSpeed) => capabilities.fan_control,
+        Some(ThermalProtectionAction::LowestPerformanceLevel) => capabilities.performance_level,
+        Some(ThermalProtectionAction::ReducePowerCap { .. }) => capabilities.power_cap.is_some(),
+    };
+    let checks = [
+        (
+            "power_cap",
+            config.power_cap.is_some() && capabilities.power_cap.is_none(),
+        ),
+        (
+            "fan_control",
+            config.fan_control_enabled && !capabilities.fan_control,
+        ),
+        (
+            "performance_level",
+            config.performance_level.is_some() && !capabilities.performance_level,
+        ),
+        (
+            "power_profile_mode",
+            config.power_profile_mode_index.is_some() && !capabilities.power_profile_mode,
+        ),
+        (
+            "max_core_clock",
+            clocks.max_core_clock.is_some() && capabilities.max_core_clock.is_none(),
+        ),
+        (
+            "min_core_clock",
+            clocks.min_core_clock.is_some() && capabilities.min_core_clock.is_none(),
+        ),
+        (
+            "max_memory_clock",
+            clocks.max_memory_clock.is_some() && capabilities.max_memory_clock.is_none(),
+        ),
+        (
+            "min_memory_clock",
+            clocks.min_memory_clock.is_some() && capabilities.min_memory_clock.is_none(),
+        ),
+        (
+            "max_voltage",
+            clocks.max_voltage.is_some() && capabilities.max_voltage.is_none(),
+        ),
+        (
+            "min_voltage",
+            clocks.min_voltage.is_some() && capabilities.min_voltage.is_none(),
+        ),
+        (
+            "voltage_offset",
+            clocks.voltage_offset.is_some() && capabilities.voltage_offset.is_none(),
+        ),
+        (
+            "gpu_clock_offsets",
+            !clocks.gpu_clock_offsets.is_empty() && capabilities.gpu_clock_offsets.is_empty(),
+        ),
+        (
+            "mem_clock_offsets",
+            !clocks.mem_clock_offsets.is_empty() && capabilities.mem_clock_offsets.is_empty(),
+        ),
+        // Power states and the idle lock both need the manual performance level
+        (
+            "power_states",
+            !config.power_states.is_empty() && !capabilities.performance_level,
+        ),
+        (
+            "idle_lock",
+            config.idle_lock && !capabilities.performance_level,
+        ),
+        (
+            "leds",
+            config
+                .leds
+                .keys()
+                .any(|name| !capabilities.leds.iter().any(|led| led.name == *name)),
+        ),
+        ("thermal_protection", !thermal_protection_supported),
+    ];
+
+    checks
+        .into_iter()
+        .filter(|(_, unsupported)| *unsupported)
+        .map(|(name, _)| name.to_owned())
+        .collect()
+}
+
+fn wrap_dry_run(
+    controllers: BTreeMap<String, DynGpuController>,
+) -> BTreeMap<String, DynGpuController> {
+    controllers
+        .into_iter()
+        .map(|(id, controller)| {
+            let controller: DynGpuController = Box::new(DryRunController::new(controller));
+            (id, controller)
+        })
+        .collect()
+}
+
+/// Lets the fan curves read the temperatures of the other GPUs
+fn link_temperature_sources(controllers: &BTreeMap<String, DynGpuController>) {
+    let sources: TemperatureSources = Rc::new(
+        controllers
+            .iter()
+            .filter_map(|(id, controller)| Some((id.clone(), controller.temperature_reader()?)))
+            .collect(),
+    );
+    for controller in controllers.values() {
+        controller.set_temperature_sources(sources.clone());
+    }
+}
+
+/// Newly referenced GPUs have to be present. GPUs which were already referenced are allowed
+/// to be missing, so that other settings can still be changed while one of them is removed.
+fn check_external_temperatures(
+    controllers: &BTreeMap<String, DynGpuController>,
+    id: &str,
+    previous_config: &GpuConfig,
+    new_config: &GpuConfig,
+) -> anyhow::Result<()> {
+    let external_temperatures = |gpu_config: &GpuConfig| {
+        gpu_config
+            .fan_control_settings
+            .as_ref()
+            .map(|settings| settings.external_temperatures.clone())
+            .unwrap_or_default()
+    };
+    let previous = external_temperatures(previous_config);
+
+    for external in external_temperatures(new_config) {
+        if external.gpu_id == id {
+            return Err(ErrorKind::OutOfRange
+                .error("A fan curve can't reference its own GPU, use `temperature_key` instead"));
+        }
+        if !controllers.contains_key(&external.gpu_id) && !previous.contains(&external) {
+            return Err(ErrorKind::GpuNotFound.error(format!(
+                "GPU {} referenced by the fan curve was not found",
+                external.gpu_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The checks done by the individual setters, for settings which are replaced as a whole
+fn check_gpu_config(
+    controller: &dyn GpuController,
+    previous_config: &GpuConfig,
+    new_config: &GpuConfig,
+) -> anyhow::Result<()> {
+    if let Some(settings) = &new_config.fan_control_settings {
+        if !(0.0..=1.0).contains(&settings.static_speed) {
+            return Err(ErrorKind::OutOfRange.error("static speed value out of range"));
+        }
+        settings.curve.validate_with_unit(settings.curve_unit)?;
+        fan_control::validate_pwm_limits(settings)?;
+    }
+    check_changed_limits(&controller.get_capabilities(), previous_config, new_config)
+}
+
+/// Changed values are checked against the limits reported by the GPU before they get applied.
+/// Values which were already stored are left to the driver, so that settings it accepted before
+/// keep working when a GPU reports stricter limits than it enforces.
+fn check_changed_limits(
+    capabilities: &GpuCapabilities,
+    previous_config: &GpuConfig,
+    new_config: &GpuConfig,
+) -> anyhow::Result<()> {
+    fn check_range<T: PartialOrd + std::fmt::Display + Copy>(
+        name: &str,
+        value: T,
+        range: Option<ValueRange<T>>,
+        unit: &str,
+    ) -> anyhow::Result<()> {
+        let min = range.map(|range| range.min);
+        let max = range.map(|range| range.max);
+        validate_within_range(name, value, min, max, unit)
+    }
+
+    if let Some(cap) = new_config.power_cap {
+        if previous_config.power_cap != Some(cap) {
+            check_range("Power cap", cap, capabilities.power_cap, "W")?;
+        }
+    }
+
+    // The firmware fan curve always follows the edge temperature
+    if capabilities.fan_curve_points.is_some() {
+        let curve_temperature_key = |gpu_config: &GpuConfig| {
+            gpu_config
+                .fan_control_settings
+                .as_ref()
+                .filter(|settings| {
+                    gpu_config.fan_control_enabled && settings.mode == FanControlMode::Curve
+                })
+                .map(|settings| settings.temperature_key.clone())
+        };
+        if let Some(key) = curve_temperature_key(new_config) {
+            if key != "edge" && curve_temperature_key(previous_config).as_ref() != Some(&key) {
+                return Err(ErrorKind::Unsupported.error(format!(
+                    "The firmware fan curve can't follow the '{key}' temperature, only 'edge'"
+                )));
+            }
+        }
+    }
+
+    let previous = &previous_config.clocks_configuration;
+    let clocks = &new_config.clocks_configuration;
+    let values = [
+        (
+            "Minimum core clock",
+            clocks.min_core_clock,
+            previous.min_core_clock,
+            capabilities.min_core_clock,
+            "MHz",
+        ),
+        (
+            "Minimum memory clock",
+            clocks.min_memory_clock,
+            previous.min_memory_clock,
+            capabilities.min_memory_clock,
+            "MHz",
+        ),
+        (
+            "Minimum voltage",
+            clocks.min_voltage,
+            previous.min_voltage,
+            capabilities.min_voltage,
+            "mV",
+        ),
+        (
+            "Maximum core clock",
+            clocks.max_core_clock,
+            previous.max_core_clock,
+            capabilities.max_core_clock,
+            "MHz",
+        ),
+        (
+            "Maximum memory clock",
+            clocks.max_memory_clock,
+            previous.max_memory_clock,
+            capabilities.max_memory_clock,
+            "MHz",
+        ),
+        (
+            "Maximum voltage",
+            clocks.max_voltage,
+            previous.max_voltage,
+            capabilities.max_voltage,
+            "mV",
+        ),
+        (
+            "Voltage offset",
+            clocks.voltage_offset,
+            previous.voltage_offset,
+            capabilities.voltage_offset,
+            "mV",
+        ),
+    ];
+    for (name, value, previous_value, range, unit) in values {
+        if let Some(value) = value.filter(|value| previous_value != Some(*value)) {
+            check_range(name, value, range, unit)?;
+        }
+    }
+
+    let offsets = [
+        (
+            "GPU clock offset",
+            &clocks.gpu_clock_offsets,
+            &previous.gpu_clock_offsets,
+            &capabilities.gpu_clock_offsets,
+        ),
+        (
+            "VRAM clock offset",
+            &clocks.mem_clock_offsets,
+            &previous.mem_clock_offsets,
+            &capabilities.mem_clock_offsets,
+        ),
+    ];
+    for (name, offsets, previous_offsets, ranges) in offsets {
+        for (pstate, offset) in offsets {
+            if previous_offsets.get(pstate) != Some(offset) {
+                check_range(name, *offset, ranges.get(pstate).copied(), "MHz")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 pub(crate) fn read_pci_db() -> Database {
     let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/pci.ids");
@@ -1067,22 +3510,20 @@ pub(crate) fn read_pci_db() -> Database {
 fn load_controllers(
     base_path: &Path,
     pci_db: &Database,
+    ignored_gpus: &[String],
+    write_retry: WriteRetry,
+    fan_control_interval: Option<Duration>,
+    disable_nvapi: bool,
 ) -> anyhow::Result<BTreeMap<String, DynGpuController>> {
     let mut controllers = BTreeMap::new();
 
     #[cfg(all(not(test), feature = "nvidia"))]
-    let nvml: LazyCell<Option<NvidiaLibs>> = LazyCell::new(|| match Nvml::init() {
+    let nvml = LazyCell::new(move || match Nvml::init() {
         Ok(nvml) => {
             use crate::server::gpu_controller::NvApi;
 
-            // The config has to be re-read here, because a LazyCell cannot capture external variables into the init closure
-            let disable_nvapi = Config::load()
-                .ok()
-                .flatten()
-                .and_then(|config| config.daemon.disable_nvapi);
-
             info!("Nvidia management library loaded");
-            let nvapi = if disable_nvapi == Some(true) {
+            let nvapi = if disable_nvapi {
                 info!("NvAPI support is disabled");
                 None
             } else {
@@ -1105,6 +3546,8 @@ fn load_controllers(
     });
     #[cfg(any(test, not(feature = "nvidia")))]
     let nvml: LazyCell<Option<NvidiaLibs>> = LazyCell::new(|| None);
+    #[cfg(any(test, not(feature = "nvidia")))]
+    let _ = disable_nvapi;
 
     let amd_drm: LazyCell<Option<LibDrmAmdgpu>> = LazyCell::new(|| match LibDrmAmdgpu::new() {
         Ok(drm) => {
@@ -1144,7 +3587,22 @@ fn load_controllers(
             trace!("trying gpu controller at {:?}", entry.path());
             let device_path = entry.path().join("device");
 
-            match init_controller(device_path.clone(), pci_db, &nvml, &amd_drm, &intel_drm) {
+            if let Some(slot_name) = read_pci_slot_name(&device_path) {
+                if is_gpu_ignored(&slot_name, ignored_gpus) {
+                    info!("GPU at {slot_name} is ignored in the config, skipping");
+                    continue;
+                }
+            }
+
+            match init_controller(
+                device_path.clone(),
+                pci_db,
+                &nvml,
+                &amd_drm,
+                &intel_drm,
+                write_retry,
+                fan_control_interval,
+            ) {
                 Ok(controller) => {
                     let info = controller.controller_info();
                     let id = info.build_id();
@@ -1170,6 +3628,15 @@ fn load_controllers(
     Ok(controllers)
 }
 
+/// The PCI domain may be omitted in the config, so `03:00.0` matches `0000:03:00.0`
+fn is_gpu_ignored(slot_name: &str, ignored_gpus: &[String]) -> bool {
+    let short_slot_name = slot_name.strip_prefix("0000:").unwrap_or(slot_name);
+    ignored_gpus.iter().any(|ignored| {
+        let ignored = ignored.trim();
+        ignored.eq_ignore_ascii_case(slot_name) || ignored.eq_ignore_ascii_case(short_slot_name)
+    })
+}
+
 fn add_path_recursively(
     archive: &mut tar::Builder<impl Write>,
     entry_path: &Path,
@@ -1251,7 +3718,7 @@ fn add_path_to_archive(
     Ok(())
 }
 
-fn drm_base_path() -> PathBuf {
+pub(crate) fn drm_base_path() -> PathBuf {
     match env::var("_LACT_DRM_SYSFS_PATH") {
         Ok(custom_path) => PathBuf::from(custom_path),
         Err(_) => PathBuf::from("/sys/class/drm"),
@@ -1275,3 +3742,42 @@ async fn run_hook_command(command: &str) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Runs the profile change command with `sh`, the name of the profile is available to it as `$1`
+async fn run_profile_change_command(command: &str, profile: &str) -> anyhow::Result<()> {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .arg("lact")
+        .arg(profile)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let output = timeout(
+        Duration::from_secs(PROFILE_CHANGE_COMMAND_TIMEOUT_SECS),
+        child.wait_with_output(),
+    )
+    .await
+    .context("The command did not finish in time")??;
+
+    if output.status.success() {
+        debug!(
+            "profile change command exited with status {}",
+            output.status
+        );
+    } else {
+        let mut error_text = String::new();
+        error_text.push_str(&String::from_utf8_lossy(&output.stdout));
+        error_text.push(' ');
+        error_text.push_str(&String::from_utf8_lossy(&output.stderr));
+
+        warn!(
+            "profile change command exited with status {}: {error_text}",
+            output.status
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use super::thermal_protection::hottest_temperature;
+use lact_schema::{DeviceStats, HealthWarning};
+use std::mem::discriminant;
+
+/// Sensor names used by the different drivers
+const VRAM_SENSORS: &[&str] = &["mem", "VRAM"];
+const HOTSPOT_SENSORS: &[&str] = &["junction", "GPU Hotspot"];
+const EDGE_SENSORS: &[&str] = &["edge", "GPU"];
+
+/// In °C
+const VRAM_TEMPERATURE_THRESHOLD: f32 = 100.0;
+/// Difference between the hotspot and edge temperatures in °C,
+/// a large gap usually means the cooler is not making good contact
+const HOTSPOT_DELTA_THRESHOLD: f32 = 30.0;
+/// Below this temperature the fan not spinning is not a concern
+const STUCK_FAN_TEMPERATURE: f32 = 70.0;
+/// Below this PWM value the fan may legitimately be stopped
+const STUCK_FAN_MIN_PWM: u8 = 64;
+/// Fans take a moment to spin up after the PWM target is raised,
+/// so the condition has to hold for this many evaluations in a row
+const STUCK_FAN_EVALUATIONS: u32 = 3;
+/// How many degrees below the threshold the value has to drop before a warning is cleared
+const HYSTERESIS: f32 = 5.0;
+
+/// Evaluates the health rules of a GPU on every stats sample
+#[derive(Debug, Default)]
+pub struct HealthMonitor {
+    warnings: Vec<HealthWarning>,
+    stuck_fan_evaluations: u32,
+}
+
+impl HealthMonitor {
+    pub fn warnings(&self) -> &[HealthWarning] {
+        &self.warnings
+    }
+
+    /// Updates the active warnings, returning the ones which were not active before
+    pub fn update(&mut self, stats: &DeviceStats) -> Vec<HealthWarning> {
+        let mut warnings = vec![];
+
+        if let Some(temperature) = sensor_temperature(stats, VRAM_SENSORS) {
+            let threshold = VRAM_TEMPERATURE_THRESHOLD;
+            let warning = HealthWarning::VramTemperature {
+                temperature,
+                threshold,
+            };
+            if self.exceeds(&warning, temperature, threshold) {
+                warnings.push(warning);
+            }
+        }
+
+        let hotspot = sensor_temperature(stats, HOTSPOT_SENSORS);
+        let edge = sensor_temperature(stats, EDGE_SENSORS);
+        if let (Some(hotspot), Some(edge)) = (hotspot, edge) {
+            let delta = hotspot - edge;
+            let threshold = HOTSPOT_DELTA_THRESHOLD;
+            let warning = HealthWarning::HotspotDelta { delta, threshold };
+            if self.exceeds(&warning, delta, threshold) {
+                warnings.push(warning);
+            }
+        }
+
+        match stuck_fan(stats) {
+            Some((temperature, pwm)) => {
+                self.stuck_fan_evaluations = self.stuck_fan_evaluations.saturating_add(1);
+                if self.stuck_fan_evaluations >= STUCK_FAN_EVALUATIONS {
+                    warnings.push(HealthWarning::StuckFan { temperature, pwm });
+                }
+            }
+            None => self.stuck_fan_evaluations = 0,
+        }
+
+        let raised = warnings
+            .iter()
+            .filter(|warning| !self.is_active(warning))
+            .cloned()
+            .collect();
+        self.warnings = warnings;
+        raised
+    }
+
+    fn is_active(&self, warning: &HealthWarning) -> bool {
+        self.warnings
+            .iter()
+            .any(|active| discriminant(active) == discriminant(warning))
+    }
+
+    fn exceeds(&self, warning: &HealthWarning, value: f32, threshold: f32) -> bool {
+        if self.is_active(warning) {
+            value > threshold - HYSTERESIS
+        } else {
+            value >= threshold
+        }
+    }
+}
+
+/// The fan is being driven hard enough that it should be spinning, but reports no RPM
+fn stuck_fan(stats: &DeviceStats) -> Option<(f32, u8)> {
+    if stats.fan.speed_current != Some(0) {
+        return None;
+    }
+    let pwm = stats.fan.pwm_current?;
+    let temperature = hottest_temperature(stats)?;
+    (pwm >= STUCK_FAN_MIN_PWM && temperature >= STUCK_FAN_TEMPERATURE).then_some((temperature, pwm))
+}
+
+fn sensor_temperature(stats: &DeviceStats, sensors: &[&str]) -> Option<f32> {
+    sensors
+        .iter()
+        .find_map(|sensor| stats.temps.get(*sensor)?.current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HealthMonitor, STUCK_FAN_EVALUATIONS};
+    use amdgpu_sysfs::hw_mon::Temperature;
+    use lact_schema::{DeviceStats, FanStats, HealthWarning};
+    use pretty_assertions::assert_eq;
+
+    fn stats(temps: &[(&str, f32)], rpm: u32, pwm: u8) -> DeviceStats {
+        DeviceStats {
+            temps: temps
+                .iter()
+                .map(|(name, temp)| {
+                    let temp = Temperature {
+                        current: Some(*temp),
+                        crit: None,
+                        crit_hyst: None,
+                    };
+                    ((*name).to_owned(), temp)
+                })
+                .collect(),
+            fan: FanStats {
+                speed_current: Some(rpm),
+                pwm_current: Some(pwm),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn vram_temperature() {
+        let mut monitor = HealthMonitor::default();
+
+        let raised = monitor.update(&stats(&[("mem", 90.0)], 1000, 100));
+        assert!(raised.is_empty());
+
+        let raised = monitor.update(&stats(&[("mem", 102.0)], 1000, 100));
+        assert_eq!(
+            vec![HealthWarning::VramTemperature {
+                temperature: 102.0,
+                threshold: 100.0
+            }],
+            raised
+        );
+
+        // Stays active within the hysteresis range, without being raised again
+        let raised = monitor.update(&stats(&[("mem", 97.0)], 1000, 100));
+        assert!(raised.is_empty());
+        assert_eq!(1, monitor.warnings().len());
+
+        monitor.update(&stats(&[("mem", 94.0)], 1000, 100));
+        assert!(monitor.warnings().is_empty());
+    }
+
+    #[test]
+    fn hotspot_delta() {
+        let mut monitor = HealthMonitor::default();
+
+        monitor.update(&stats(&[("edge", 60.0), ("junction", 80.0)], 1000, 100));
+        assert!(monitor.warnings().is_empty());
+
+        let raised = monitor.update(&stats(&[("edge", 60.0), ("junction", 95.0)], 1000, 100));
+        assert_eq!(
+            vec![HealthWarning::HotspotDelta {
+                delta: 35.0,
+                threshold: 30.0
+            }],
+            raised
+        );
+
+        monitor.update(&stats(&[("GPU", 60.0), ("GPU Hotspot", 80.0)], 1000, 100));
+        assert!(monitor.warnings().is_empty());
+    }
+
+    #[test]
+    fn stuck_fan() {
+        let mut monitor = HealthMonitor::default();
+        let stuck = stats(&[("edge", 85.0)], 0, 150);
+
+        // The fan gets some time to spin up
+        for _ in 1..STUCK_FAN_EVALUATIONS {
+            assert!(monitor.update(&stuck).is_empty());
+        }
+        let raised = monitor.update(&stuck);
+        assert_eq!(
+            vec![HealthWarning::StuckFan {
+                temperature: 85.0,
+                pwm: 150
+            }],
+            raised
+        );
+        assert!(monitor.update(&stuck).is_empty());
+        assert_eq!(1, monitor.warnings().len());
+
+        monitor.update(&stats(&[("edge", 85.0)], 1200, 150));
+        assert!(monitor.warnings().is_empty());
+    }
+
+    #[test]
+    fn stopped_fan_is_not_stuck() {
+        let mut monitor = HealthMonitor::default();
+
+        // Zero RPM mode while the GPU is cool
+        let idle = stats(&[("edge", 45.0)], 0, 150);
+        // The fan is not being driven
+        let not_driven = stats(&[("edge", 85.0)], 0, 20);
+
+        for _ in 0..STUCK_FAN_EVALUATIONS {
+            monitor.update(&idle);
+        }
+        assert!(monitor.warnings().is_empty());
+
+        for _ in 0..STUCK_FAN_EVALUATIONS {
+            monitor.update(&not_driven);
+        }
+        assert!(monitor.warnings().is_empty());
+    }
+}
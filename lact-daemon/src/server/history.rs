@@ -0,0 +1,470 @@
+use anyhow::anyhow;
+use lact_schema::{
+    CaptureSummary, DeviceStats, HistoryMetric, HistorySample, MetricSummary, SessionExtremes,
+    ValueRange,
+};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Least amount of samples with both the power draw and the temperature to fit them
+const MIN_FIT_SAMPLES: usize = 10;
+/// The power draw has to vary by at least this many watts for the fit to mean anything
+const MIN_FIT_POWER_SPREAD: f64 = 10.0;
+
+/// Bounded buffer of the most recent stats samples of a GPU
+pub struct StatsHistory {
+    capacity: usize,
+    samples: VecDeque<HistorySample>,
+}
+
+impl StatsHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.samples.len() > capacity {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn push(&mut self, sample: HistorySample) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        while self.samples.len() >= self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Returns the samples from oldest to newest, only including the given metrics.
+    /// All metrics are included if none are specified.
+    pub fn get(&self, metrics: &[HistoryMetric]) -> Vec<HistorySample> {
+        self.samples
+            .iter()
+            .map(|sample| HistorySample {
+                timestamp: sample.timestamp,
+                values: sample
+                    .values
+                    .iter()
+                    .filter(|(metric, _)| metrics.is_empty() || metrics.contains(metric))
+                    .map(|(metric, value)| (*metric, *value))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Aggregates the samples recorded while a capture is running,
+/// so that it is not limited by the capacity of the history buffer
+pub struct StatsCapture {
+    started_at: i64,
+    sample_count: usize,
+    metrics: BTreeMap<HistoryMetric, MetricAggregate>,
+}
+
+struct MetricAggregate {
+    sum: f64,
+    count: usize,
+    min: f64,
+    max: f64,
+}
+
+impl StatsCapture {
+    pub fn new(started_at: i64) -> Self {
+        Self {
+            started_at,
+            sample_count: 0,
+            metrics: BTreeMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, sample: &HistorySample) {
+        self.sample_count += 1;
+
+        for (metric, value) in &sample.values {
+            let aggregate = self.metrics.entry(*metric).or_insert(MetricAggregate {
+                sum: 0.0,
+                count: 0,
+                min: *value,
+                max: *value,
+            });
+            aggregate.sum += value;
+            aggregate.count += 1;
+            aggregate.min = aggregate.min.min(*value);
+            aggregate.max = aggregate.max.max(*value);
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn finish(self, ended_at: i64) -> CaptureSummary {
+        let metrics = self
+            .metrics
+            .into_iter()
+            .map(|(metric, aggregate)| {
+                let summary = MetricSummary {
+                    avg: aggregate.sum / aggregate.count as f64,
+                    min: aggregate.min,
+                    max: aggregate.max,
+                };
+                (metric, summary)
+            })
+            .collect();
+
+        CaptureSummary {
+            started_at: self.started_at,
+            ended_at,
+            sample_count: self.sample_count,
+            metrics,
+        }
+    }
+}
+
+/// Lowest and highest value of each metric, which unlike the history are kept until a reset
+pub struct StatsExtremes {
+    since: i64,
+    metrics: BTreeMap<HistoryMetric, ValueRange<f64>>,
+}
+
+impl StatsExtremes {
+    pub fn new(since: i64) -> Self {
+        Self {
+            since,
+            metrics: BTreeMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, sample: &HistorySample) {
+        for (metric, value) in &sample.values {
+            let range = self
+                .metrics
+                .entry(*metric)
+                .or_insert(ValueRange::from((*value, *value)));
+            range.min = range.min.min(*value);
+            range.max = range.max.max(*value);
+        }
+    }
+
+    pub fn reset(&mut self, since: i64) {
+        self.since = since;
+        self.metrics.clear();
+    }
+
+    pub fn get(&self) -> SessionExtremes {
+        SessionExtremes {
+            since: self.since,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+/// Linear relationship between the power draw and the temperature of a GPU,
+/// fitted to the history samples with the least squares method
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemperatureFit {
+    /// Temperature in °C at no power draw
+    pub base: f64,
+    pub degrees_per_watt: f64,
+    pub sample_count: usize,
+}
+
+impl TemperatureFit {
+    /// Fails when there are too few samples, or when they don't show the temperature rising
+    /// with the power draw
+    #[allow(clippy::cast_precision_loss)]
+    pub fn from_samples(samples: &[HistorySample]) -> anyhow::Result<Self> {
+        let points: Vec<(f64, f64)> = samples
+            .iter()
+            .filter_map(|sample| {
+                let power = sample.values.get(&HistoryMetric::Power)?;
+                let temperature = sample.values.get(&HistoryMetric::Temperature)?;
+                Some((*power, *temperature))
+            })
+            .collect();
+
+        if points.len() < MIN_FIT_SAMPLES {
+            return Err(anyhow!(
+                "Only {} samples with the power draw and temperature were recorded, \
+                 at least {MIN_FIT_SAMPLES} are needed",
+                points.len()
+            ));
+        }
+
+        let powers = points.iter().map(|(power, _)| *power);
+        let min_power = powers.clone().fold(f64::MAX, f64::min);
+        let max_power = powers.fold(f64::MIN, f64::max);
+        if max_power - min_power < MIN_FIT_POWER_SPREAD {
+            return Err(anyhow!(
+                "The power draw only varied between {min_power:.1} and {max_power:.1} W, \
+                 the GPU has to run under different loads for an estimate"
+            ));
+        }
+
+        let count = points.len() as f64;
+        let mean_power = points.iter().map(|(power, _)| power).sum::<f64>() / count;
+        let mean_temperature = points.iter().map(|(_, temp)| temp).sum::<f64>() / count;
+
+        let covariance: f64 = points
+            .iter()
+            .map(|(power, temp)| (power - mean_power) * (temp - mean_temperature))
+            .sum();
+        let variance: f64 = points
+            .iter()
+            .map(|(power, _)| (power - mean_power).powi(2))
+            .sum();
+        let degrees_per_watt = covariance / variance;
+        if degrees_per_watt <= 0.0 {
+            return Err(anyhow!(
+                "The recorded temperature doesn't rise with the power draw"
+            ));
+        }
+
+        Ok(Self {
+            base: mean_temperature - degrees_per_watt * mean_power,
+            degrees_per_watt,
+            sample_count: points.len(),
+        })
+    }
+
+    /// Power draw in watts which the GPU is expected to reach the given temperature at
+    pub fn power_at(&self, temperature: f64) -> f64 {
+        (temperature - self.base) / self.degrees_per_watt
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn sample_from_stats(stats: &DeviceStats, timestamp: i64) -> HistorySample {
+    let mut values = BTreeMap::new();
+
+    if let Some(power) = stats.power.current.or(stats.power.average) {
+        values.insert(HistoryMetric::Power, power);
+    }
+
+    let max_temp = stats
+        .temps
+        .values()
+        .filter_map(|temp| temp.current)
+        .reduce(f32::max);
+    if let Some(temp) = max_temp {
+        values.insert(HistoryMetric::Temperature, f64::from(temp));
+    }
+
+    if let Some(clockspeed) = stats.clockspeed.gpu_clockspeed {
+        values.insert(HistoryMetric::GpuClockspeed, clockspeed as f64);
+    }
+    if let Some(clockspeed) = stats.clockspeed.vram_clockspeed {
+        values.insert(HistoryMetric::VramClockspeed, clockspeed as f64);
+    }
+
+    HistorySample { timestamp, values }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StatsCapture, StatsExtremes, StatsHistory, TemperatureFit};
+    use lact_schema::{CaptureSummary, HistoryMetric, HistorySample, MetricSummary, ValueRange};
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeMap;
+
+    fn sample(timestamp: i64) -> HistorySample {
+        #[allow(clippy::cast_precision_loss)]
+        let power = timestamp as f64;
+
+        HistorySample {
+            timestamp,
+            values: BTreeMap::from([
+                (HistoryMetric::Power, power),
+                (HistoryMetric::Temperature, 50.0),
+            ]),
+        }
+    }
+
+    #[test]
+    fn history_wraps_at_capacity() {
+        let mut history = StatsHistory::new(3);
+        for timestamp in 0..5 {
+            history.push(sample(timestamp));
+        }
+
+        let timestamps: Vec<i64> = history
+            .get(&[])
+            .into_iter()
+            .map(|sample| sample.timestamp)
+            .collect();
+        assert_eq!(vec![2, 3, 4], timestamps);
+    }
+
+    #[test]
+    fn history_filters_metrics() {
+        let mut history = StatsHistory::new(2);
+        history.push(sample(1));
+
+        let samples = history.get(&[HistoryMetric::Power]);
+        assert_eq!(
+            vec![HistorySample {
+                timestamp: 1,
+                values: BTreeMap::from([(HistoryMetric::Power, 1.0)]),
+            }],
+            samples
+        );
+    }
+
+    #[test]
+    fn history_shrinks_capacity() {
+        let mut history = StatsHistory::new(5);
+        for timestamp in 0..5 {
+            history.push(sample(timestamp));
+        }
+        history.set_capacity(2);
+
+        let timestamps: Vec<i64> = history
+            .get(&[])
+            .into_iter()
+            .map(|sample| sample.timestamp)
+            .collect();
+        assert_eq!(vec![3, 4], timestamps);
+    }
+
+    #[test]
+    fn temperature_fit() {
+        // The temperature rises by 0.25 °C per watt, with some noise
+        let samples: Vec<HistorySample> = (0..20_i32)
+            .map(|index| {
+                let power = 50.0 + f64::from(index) * 5.0;
+                let noise = if index % 2 == 0 { 0.5 } else { -0.5 };
+                HistorySample {
+                    timestamp: i64::from(index),
+                    values: BTreeMap::from([
+                        (HistoryMetric::Power, power),
+                        (HistoryMetric::Temperature, 30.0 + power * 0.25 + noise),
+                    ]),
+                }
+            })
+            .collect();
+
+        let fit = TemperatureFit::from_samples(&samples).unwrap();
+        assert_eq!(20, fit.sample_count);
+        assert!((fit.degrees_per_watt - 0.25).abs() < 0.01, "{fit:?}");
+        assert!((fit.power_at(75.0) - 180.0).abs() < 5.0, "{fit:?}");
+
+        assert!(TemperatureFit::from_samples(&samples[..5]).is_err());
+        // The power draw barely changes
+        let idle: Vec<HistorySample> = (0..20).map(|timestamp| sample(timestamp % 5)).collect();
+        assert!(TemperatureFit::from_samples(&idle).is_err());
+        // The temperature stays the same regardless of the power draw
+        let flat: Vec<HistorySample> = (0..20).map(sample).collect();
+        assert!(TemperatureFit::from_samples(&flat).is_err());
+    }
+
+    #[test]
+    fn zero_capacity_stores_nothing() {
+        let mut history = StatsHistory::new(0);
+        history.push(sample(0));
+        assert!(history.get(&[]).is_empty());
+    }
+
+    #[test]
+    fn capture_aggregates() {
+        let mut capture = StatsCapture::new(100);
+        let series = [(300.0, 60.0), (320.0, 65.0), (280.0, 70.0), (300.0, 75.0)];
+        for (timestamp, (power, temperature)) in (101..).zip(series) {
+            capture.push(&HistorySample {
+                timestamp,
+                values: BTreeMap::from([
+                    (HistoryMetric::Power, power),
+                    (HistoryMetric::Temperature, temperature),
+                ]),
+            });
+        }
+        // Metrics missing from some samples are averaged over the samples that have them
+        capture.push(&HistorySample {
+            timestamp: 105,
+            values: BTreeMap::from([(HistoryMetric::GpuClockspeed, 2500.0)]),
+        });
+
+        let expected = CaptureSummary {
+            started_at: 100,
+            ended_at: 110,
+            sample_count: 5,
+            metrics: BTreeMap::from([
+                (
+                    HistoryMetric::Power,
+                    MetricSummary {
+                        avg: 300.0,
+                        min: 280.0,
+                        max: 320.0,
+                    },
+                ),
+                (
+                    HistoryMetric::Temperature,
+                    MetricSummary {
+                        avg: 67.5,
+                        min: 60.0,
+                        max: 75.0,
+                    },
+                ),
+                (
+                    HistoryMetric::GpuClockspeed,
+                    MetricSummary {
+                        avg: 2500.0,
+                        min: 2500.0,
+                        max: 2500.0,
+                    },
+                ),
+            ]),
+        };
+        assert_eq!(expected, capture.finish(110));
+    }
+
+    #[test]
+    fn empty_capture() {
+        let summary = StatsCapture::new(100).finish(200);
+        assert_eq!(0, summary.sample_count);
+        assert!(summary.metrics.is_empty());
+    }
+
+    #[test]
+    fn track_extremes() {
+        let mut extremes = StatsExtremes::new(100);
+        let series = [(150.0, 45.0), (300.0, 70.0), (90.0, 82.0), (210.0, 60.0)];
+        for (timestamp, (power, temperature)) in (101..).zip(series) {
+            extremes.push(&HistorySample {
+                timestamp,
+                values: BTreeMap::from([
+                    (HistoryMetric::Power, power),
+                    (HistoryMetric::Temperature, temperature),
+                ]),
+            });
+        }
+
+        let tracked = extremes.get();
+        assert_eq!(100, tracked.since);
+        assert_eq!(
+            BTreeMap::from([
+                (HistoryMetric::Power, ValueRange::from((90.0, 300.0))),
+                (HistoryMetric::Temperature, ValueRange::from((45.0, 82.0))),
+            ]),
+            tracked.metrics
+        );
+
+        extremes.reset(200);
+        let tracked = extremes.get();
+        assert_eq!(200, tracked.since);
+        assert!(tracked.metrics.is_empty());
+
+        // Only the samples after the reset are taken into account
+        extremes.push(&HistorySample {
+            timestamp: 201,
+            values: BTreeMap::from([(HistoryMetric::Power, 120.0)]),
+        });
+        assert_eq!(
+            BTreeMap::from([(HistoryMetric::Power, ValueRange::from((120.0, 120.0)))]),
+            extremes.get().metrics
+        );
+    }
+}
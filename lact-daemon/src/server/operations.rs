@@ -0,0 +1,169 @@
+use lact_schema::{OperationInfo, OperationKind};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+use tokio::sync::Notify;
+
+/// Long-running operations which are in progress, so that clients can show and cancel them
+#[derive(Default)]
+pub struct Operations {
+    last_id: u64,
+    active: BTreeMap<u64, (OperationInfo, Rc<Notify>)>,
+}
+
+impl Operations {
+    /// Registers a new operation, which stays listed until the returned guard is dropped
+    pub fn start(
+        operations: &Rc<RefCell<Self>>,
+        kind: OperationKind,
+        gpu_id: &str,
+        started_at: i64,
+    ) -> OperationGuard {
+        Self::start_all(operations, kind, &[gpu_id], started_at)
+    }
+
+    /// Registers an operation which covers several GPUs. Each of them is listed separately,
+    /// and cancelling any of them cancels the whole operation.
+    pub fn start_all(
+        operations: &Rc<RefCell<Self>>,
+        kind: OperationKind,
+        gpu_ids: &[&str],
+        started_at: i64,
+    ) -> OperationGuard {
+        let mut this = operations.borrow_mut();
+        let cancel = Rc::new(Notify::new());
+
+        let ids = gpu_ids
+            .iter()
+            .map(|gpu_id| {
+                this.last_id += 1;
+                let id = this.last_id;
+
+                let info = OperationInfo {
+                    id,
+                    kind,
+                    gpu_id: (*gpu_id).to_owned(),
+                    started_at,
+                };
+                this.active.insert(id, (info, cancel.clone()));
+                id
+            })
+            .collect();
+
+        OperationGuard {
+            operations: operations.clone(),
+            ids,
+            cancel,
+        }
+    }
+
+    pub fn list(&self) -> Vec<OperationInfo> {
+        self.active.values().map(|(info, _)| info.clone()).collect()
+    }
+
+    /// Returns `false` when no operation with the given id is in progress
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.active.get(&id) {
+            Some((_, cancel)) => {
+                // Stores a permit, so the cancellation is not missed when the operation
+                // is not waiting for it at this moment
+                cancel.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancels the operations of the given kind which cover the GPU,
+    /// returning `false` when there were none
+    pub fn cancel_gpu(&self, kind: OperationKind, gpu_id: &str) -> bool {
+        let mut cancelled = false;
+        for (info, cancel) in self.active.values() {
+            if info.kind == kind && info.gpu_id == gpu_id {
+                cancel.notify_one();
+                cancelled = true;
+            }
+        }
+        cancelled
+    }
+}
+
+pub struct OperationGuard {
+    operations: Rc<RefCell<Operations>>,
+    ids: Vec<u64>,
+    cancel: Rc<Notify>,
+}
+
+impl OperationGuard {
+    /// Completes once the operation gets cancelled
+    pub async fn cancelled(&self) {
+        self.cancel.notified().await;
+    }
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        let mut operations = self.operations.borrow_mut();
+        for id in &self.ids {
+            operations.active.remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Operations;
+    use lact_schema::OperationKind;
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    fn active_ids(operations: &RefCell<Operations>) -> Vec<u64> {
+        operations
+            .borrow()
+            .list()
+            .iter()
+            .map(|operation| operation.id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn cancel_operation() {
+        let operations = Rc::new(RefCell::new(Operations::default()));
+        let kind = OperationKind::FanResponseMeasurement;
+
+        let first = Operations::start(&operations, kind, "gpu-1", 100);
+        let second = Operations::start(&operations, kind, "gpu-2", 200);
+        assert_eq!(vec![1, 2], active_ids(&operations));
+
+        assert!(operations.borrow().cancel(2));
+        tokio::time::timeout(Duration::from_secs(1), second.cancelled())
+            .await
+            .unwrap();
+        let still_running = tokio::time::timeout(Duration::from_millis(50), first.cancelled());
+        assert!(still_running.await.is_err());
+
+        drop(second);
+        assert_eq!(vec![1], active_ids(&operations));
+        assert!(!operations.borrow().cancel(2));
+    }
+
+    #[tokio::test]
+    async fn cancel_operation_of_several_gpus() {
+        let operations = Rc::new(RefCell::new(Operations::default()));
+        let kind = OperationKind::PendingConfirmation;
+
+        let operation = Operations::start_all(&operations, kind, &["gpu-1", "gpu-2"], 100);
+        let gpu_ids: Vec<String> = operations
+            .borrow()
+            .list()
+            .into_iter()
+            .map(|operation| operation.gpu_id)
+            .collect();
+        assert_eq!(vec!["gpu-1", "gpu-2"], gpu_ids);
+
+        assert!(operations.borrow().cancel(2));
+        tokio::time::timeout(Duration::from_secs(1), operation.cancelled())
+            .await
+            .unwrap();
+
+        drop(operation);
+        assert!(active_ids(&operations).is_empty());
+    }
+}
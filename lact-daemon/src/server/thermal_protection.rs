@@ -0,0 +1,190 @@
+use amdgpu_sysfs::gpu_handle::PerformanceLevel;
+use lact_schema::{
+    config::{FanControlSettings, GpuConfig, ThermalProtection, ThermalProtectionAction},
+    DeviceStats, FanControlMode,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Trigger,
+    Revert,
+}
+
+/// Tracks whether the protective action is currently in effect for a GPU
+#[derive(Debug, Default)]
+pub struct ThermalProtectionState {
+    active: bool,
+}
+
+impl ThermalProtectionState {
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn update(&mut self, settings: &ThermalProtection, temp: f32) -> Option<Transition> {
+        if !self.active && temp >= settings.temperature {
+            self.active = true;
+            Some(Transition::Trigger)
+        } else if self.active && temp <= settings.temperature - settings.hysteresis {
+            self.active = false;
+            Some(Transition::Revert)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn hottest_temperature(stats: &DeviceStats) -> Option<f32> {
+    stats
+        .temps
+        .values()
+        .filter_map(|temp| temp.current)
+        .reduce(f32::max)
+}
+
+/// Returns the config with the protective action applied on top of it
+pub fn protected_config(
+    config: &GpuConfig,
+    action: ThermalProtectionAction,
+    current_power_cap: Option<f64>,
+) -> GpuConfig {
+    let mut config = config.clone();
+
+    match action {
+        ThermalProtectionAction::MaxFanSpeed => {
+            config.fan_control_enabled = true;
+            let settings = config
+                .fan_control_settings
+                .get_or_insert_with(FanControlSettings::default);
+            settings.mode = FanControlMode::Static;
+            settings.static_speed = 1.0;
+        }
+        ThermalProtectionAction::LowestPerformanceLevel => {
+            config.performance_level = Some(PerformanceLevel::Low);
+            // These settings all require the manual performance level
+            config.power_profile_mode_index = None;
+            config.custom_power_profile_mode_hueristics.clear();
+            config.power_states.clear();
+            config.memory_clock_level = None;
+            config.pcie_level = None;
+        }
+        ThermalProtectionAction::ReducePowerCap { percent } => {
+            if let Some(cap) = config.power_cap.or(current_power_cap) {
+                config.power_cap = Some(cap * (1.0 - percent.clamp(0.0, 100.0) / 100.0));
+            }
+        }
+    }
+
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{protected_config, ThermalProtectionState, Transition};
+    use amdgpu_sysfs::gpu_handle::PerformanceLevel;
+    use lact_schema::{
+        config::{FanControlSettings, GpuConfig, ThermalProtection, ThermalProtectionAction},
+        FanControlMode,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn trigger_and_revert() {
+        let settings = ThermalProtection {
+            temperature: 80.0,
+            hysteresis: 5.0,
+            action: ThermalProtectionAction::MaxFanSpeed,
+        };
+        let mut state = ThermalProtectionState::default();
+
+        let temps = [
+            70.0, 79.0, 80.0, 85.0, 78.0, 81.0, 76.0, 75.0, 74.0, 79.0, 82.0,
+        ];
+        let transitions: Vec<_> = temps
+            .into_iter()
+            .map(|temp| state.update(&settings, temp))
+            .collect();
+
+        assert_eq!(
+            vec![
+                None,
+                None,
+                Some(Transition::Trigger),
+                None,
+                // Still within the hysteresis range, so the action stays in effect
+                None,
+                None,
+                None,
+                Some(Transition::Revert),
+                None,
+                None,
+                Some(Transition::Trigger),
+            ],
+            transitions
+        );
+    }
+
+    #[test]
+    fn max_fan_speed_action() {
+        let config = GpuConfig {
+            fan_control_enabled: false,
+            fan_control_settings: Some(FanControlSettings {
+                mode: FanControlMode::Curve,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let protected = protected_config(&config, ThermalProtectionAction::MaxFanSpeed, None);
+        assert!(protected.fan_control_enabled);
+        assert_eq!(
+            Some(FanControlSettings {
+                mode: FanControlMode::Static,
+                static_speed: 1.0,
+                ..Default::default()
+            }),
+            protected.fan_control_settings
+        );
+    }
+
+    #[test]
+    fn lowest_performance_level_action() {
+        let config = GpuConfig {
+            performance_level: Some(PerformanceLevel::Manual),
+            power_profile_mode_index: Some(1),
+            pcie_level: Some(1),
+            ..Default::default()
+        };
+
+        let protected = protected_config(
+            &config,
+            ThermalProtectionAction::LowestPerformanceLevel,
+            None,
+        );
+        assert_eq!(Some(PerformanceLevel::Low), protected.performance_level);
+        assert_eq!(None, protected.power_profile_mode_index);
+        assert_eq!(None, protected.pcie_level);
+    }
+
+    #[test]
+    fn reduce_power_cap_action() {
+        let action = ThermalProtectionAction::ReducePowerCap { percent: 25.0 };
+
+        let config = GpuConfig {
+            power_cap: Some(200.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            Some(150.0),
+            protected_config(&config, action, Some(300.0)).power_cap
+        );
+
+        // Uses the current cap when it's not configured
+        let config = GpuConfig::default();
+        assert_eq!(
+            Some(225.0),
+            protected_config(&config, action, Some(300.0)).power_cap
+        );
+        assert_eq!(None, protected_config(&config, action, None).power_cap);
+    }
+}
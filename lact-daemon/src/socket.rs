@@ -1,7 +1,7 @@
 use anyhow::{anyhow, Context};
 use nix::{
     sys::stat::{umask, Mode},
-    unistd::{chown, getuid, Gid, Group, User},
+    unistd::{chown, getuid, Gid, Group, Uid, User},
 };
 use std::{
     env, fs,
@@ -28,18 +28,14 @@ pub fn get_socket_path() -> PathBuf {
     }
 }
 
-pub fn cleanup() {
-    let socket_path = get_socket_path();
-
+pub fn cleanup(socket_path: &Path) {
     if socket_path.exists() {
         fs::remove_file(socket_path).expect("failed to remove socket");
     }
     debug!("removed socket");
 }
 
-pub fn listen() -> anyhow::Result<(UnixListener, PathBuf)> {
-    let socket_path = get_socket_path();
-
+pub fn listen(socket_path: &Path) -> anyhow::Result<UnixListener> {
     if socket_path.exists() {
         return Err(anyhow!(
             "Socket {socket_path:?} already exists. \
@@ -51,16 +47,44 @@ pub fn listen() -> anyhow::Result<(UnixListener, PathBuf)> {
     let socket_mask = Mode::S_IXUSR | Mode::S_IXGRP | Mode::S_IRWXO;
     umask(socket_mask);
 
-    let listener = UnixListener::bind(&socket_path)?;
+    let listener = UnixListener::bind(socket_path)?;
 
     info!("listening on {socket_path:?}");
-    Ok((listener, socket_path))
+    Ok(listener)
 }
 
 pub async fn set_permissions(
     socket_path: &Path,
     daemon_config: &config::Daemon,
 ) -> anyhow::Result<()> {
+    let (user, group) = resolve_owner(daemon_config)?;
+
+    debug!("using gid {group} uid {user:?} for socket");
+
+    if *IS_FLATBOX {
+        let owner_arg = match user {
+            Some(user) => format!("{}:{}", user.as_raw(), group.as_raw()),
+            None => format!(":{}", group.as_raw()),
+        };
+
+        let path = socket_path
+            .to_str()
+            .expect("Invalid socket path")
+            .trim_start_matches("/run/host/root");
+
+        run_command("chown", &[&owner_arg, path])
+            .await
+            .context("Could not set socket permissions")?;
+    } else {
+        chown(socket_path, user, Some(group)).context("Could not set socket permissions")?;
+    }
+
+    Ok(())
+}
+
+/// Returns the user and group that the socket should be owned by.
+/// The user is `None` when the socket should keep the daemon's user as the owner.
+pub fn resolve_owner(daemon_config: &config::Daemon) -> anyhow::Result<(Option<Uid>, Gid)> {
     let group = daemon_config
         .admin_group
         .as_ref()
@@ -83,25 +107,23 @@ pub async fn set_permissions(
         .transpose()?
         .map(|user| user.uid);
 
-    debug!("using gid {group} uid {user:?} for socket");
-
-    if *IS_FLATBOX {
-        let owner_arg = match user {
-            Some(user) => format!("{}:{}", user.as_raw(), group.as_raw()),
-            None => format!(":{}", group.as_raw()),
-        };
+    Ok((user, group))
+}
 
-        let path = socket_path
-            .to_str()
-            .expect("Invalid socket path")
-            .trim_start_matches("/run/host/root");
+/// Whether a client is allowed to change settings when access is limited to the given group.
+/// Besides root, either the primary group of the client or its group list has to match.
+pub fn peer_in_group(uid: Uid, gid: Gid, group_name: &str) -> anyhow::Result<bool> {
+    if uid.is_root() {
+        return Ok(true);
+    }
 
-        run_command("chown", &[&owner_arg, path])
-            .await
-            .context("Could not set socket permissions")?;
-    } else {
-        chown(socket_path, user, Some(group)).context("Could not set socket permissions")?;
+    let group = Group::from_name(group_name)
+        .context("Could not get group")?
+        .with_context(|| format!("Group {group_name} does not exist"))?;
+    if gid == group.gid {
+        return Ok(true);
     }
 
-    Ok(())
+    let user = User::from_uid(uid).context("Could not get user")?;
+    Ok(user.is_some_and(|user| user.gid == group.gid || group.mem.contains(&user.name)))
 }
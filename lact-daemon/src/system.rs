@@ -1,7 +1,10 @@
 pub mod power_profiles_daemon;
 
 use anyhow::{anyhow, ensure, Context};
-use lact_schema::{InitramfsType, SystemInfo, GIT_COMMIT};
+use lact_schema::{
+    DaemonInfo, ErrorKind, InitramfsType, ModuleConfigStatus, ModuleConfigUpdate, OverclockStatus,
+    SystemInfo, GIT_COMMIT, PROTOCOL_VERSION, SCHEMA_VERSION,
+};
 use nix::sys::socket::{
     bind, recv, socket, AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol, SockType,
 };
@@ -18,12 +21,18 @@ use std::{
         LazyLock,
     },
 };
-use tokio::{process::Command, sync::Notify};
+use tokio::{
+    process::Command,
+    sync::{mpsc, Notify},
+};
 use tracing::{debug, error, info, warn};
 
 static OC_TOGGLED: AtomicBool = AtomicBool::new(false);
 
 const PP_OVERDRIVE_MASK: u64 = 0x4000;
+/// The core, memory and PCIe clock DPM and the power containment, which the power cap needs.
+/// GPUs are stuck at fixed clocks or can't be managed at all without these.
+const PP_REQUIRED_MASK: u64 = 0x1 | 0x2 | 0x4 | 0x10;
 pub const PP_FEATURE_MASK_PATH: &str = "/sys/module/amdgpu/parameters/ppfeaturemask";
 pub const BASE_MODULE_CONF_PATH: &str = "/etc/modprobe.d/99-amdgpu-overdrive.conf";
 pub const DAEMON_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -38,7 +47,7 @@ static MODULE_CONF_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
     }
 });
 
-pub async fn info() -> anyhow::Result<SystemInfo> {
+pub async fn info(safe_mode: bool) -> anyhow::Result<SystemInfo> {
     let version = DAEMON_VERSION.to_owned();
     let profile = if cfg!(debug_assertions) {
         "debug"
@@ -47,21 +56,9 @@ pub async fn info() -> anyhow::Result<SystemInfo> {
     }
     .to_owned();
 
-    let kernel_output = Command::new("uname")
-        .arg("-r")
-        .output()
-        .await
-        .context("Could not read kernel version")?;
-    let kernel_version = String::from_utf8(kernel_output.stdout)
-        .context("Invalid kernel version output")?
-        .trim()
-        .to_owned();
+    let kernel_version = kernel_version().await?;
 
-    let amdgpu_overdrive_enabled = if let Ok(mask) = read_current_mask() {
-        Some((mask & PP_OVERDRIVE_MASK) > 0)
-    } else {
-        None
-    };
+    let amdgpu_overdrive_enabled = read_current_mask().ok().map(is_overdrive_enabled);
 
     Ok(SystemInfo {
         version,
@@ -69,9 +66,40 @@ pub async fn info() -> anyhow::Result<SystemInfo> {
         kernel_version,
         amdgpu_overdrive_enabled,
         commit: Some(GIT_COMMIT.to_owned()),
+        safe_mode,
     })
 }
 
+pub async fn kernel_version() -> anyhow::Result<String> {
+    let kernel_output = Command::new("uname")
+        .arg("-r")
+        .output()
+        .await
+        .context("Could not read kernel version")?;
+    let kernel_version =
+        String::from_utf8(kernel_output.stdout).context("Invalid kernel version output")?;
+    Ok(kernel_version.trim().to_owned())
+}
+
+pub fn daemon_info() -> DaemonInfo {
+    let features = [
+        ("nvidia", cfg!(feature = "nvidia")),
+        ("dbus", cfg!(feature = "dbus")),
+    ]
+    .into_iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(name, _)| name.to_owned())
+    .collect();
+
+    DaemonInfo {
+        version: DAEMON_VERSION.to_owned(),
+        commit: Some(GIT_COMMIT.to_owned()),
+        schema_version: SCHEMA_VERSION.to_owned(),
+        protocol_version: PROTOCOL_VERSION,
+        features,
+    }
+}
+
 pub async fn enable_overdrive() -> anyhow::Result<String> {
     ensure!(
         !OC_TOGGLED.load(Ordering::SeqCst),
@@ -85,24 +113,8 @@ pub async fn enable_overdrive() -> anyhow::Result<String> {
         return Err(anyhow!("Overdrive mask already enabled"));
     }
 
-    let conf = format!("options amdgpu ppfeaturemask=0x{new_mask:X}");
-
-    let mut file = File::create(&*MODULE_CONF_PATH).context("Could not open module conf file")?;
-    file.set_permissions(Permissions::from_mode(0o644))
-        .context("Could not conf file permissions")?;
-
-    file.write_all(conf.as_bytes())
-        .context("Could not write config")?;
-
-    let message = match regenerate_initramfs().await {
-        Ok(initramfs_type) => {
-            OC_TOGGLED.store(true, Ordering::SeqCst);
-            format!("Initramfs was successfully regenerated (detected type {initramfs_type:?})")
-        }
-        Err(err) => format!("{err:#}"),
-    };
-
-    Ok(message)
+    write_module_config(new_mask)?;
+    Ok(finish_module_config_change().await)
 }
 
 pub async fn disable_overdrive() -> anyhow::Result<String> {
@@ -113,15 +125,7 @@ pub async fn disable_overdrive() -> anyhow::Result<String> {
 
     if Path::new(&*MODULE_CONF_PATH).exists() {
         fs::remove_file(&*MODULE_CONF_PATH).context("Could not remove module config file")?;
-        match regenerate_initramfs().await {
-            Ok(initramfs_type) => {
-                OC_TOGGLED.store(true, Ordering::SeqCst);
-                Ok(format!(
-                    "Initramfs was successfully regenerated (detected type {initramfs_type:?})"
-                ))
-            }
-            Err(err) => Ok(format!("{err:#}")),
-        }
+        Ok(finish_module_config_change().await)
     } else {
         Err(anyhow!(
             "Overclocking was not enabled through LACT (file at {} does not exist)",
@@ -130,14 +134,128 @@ pub async fn disable_overdrive() -> anyhow::Result<String> {
     }
 }
 
+pub async fn set_module_config(ppfeaturemask: u64) -> anyhow::Result<ModuleConfigUpdate> {
+    validate_feature_mask(ppfeaturemask)?;
+    write_module_config(ppfeaturemask)?;
+    let message = finish_module_config_change().await;
+
+    Ok(ModuleConfigUpdate {
+        status: module_config_status(),
+        message,
+    })
+}
+
+pub fn module_config_status() -> ModuleConfigStatus {
+    let running_mask = read_current_mask().ok();
+    let module_conf = fs::read_to_string(&*MODULE_CONF_PATH).ok();
+    config_status(
+        running_mask,
+        module_conf.as_deref(),
+        OC_TOGGLED.load(Ordering::SeqCst),
+    )
+}
+
+fn config_status(
+    running_mask: Option<u64>,
+    module_conf: Option<&str>,
+    toggled: bool,
+) -> ModuleConfigStatus {
+    let configured_mask = module_conf.and_then(parse_configured_mask);
+    let mismatch = configured_mask.is_some_and(|configured| running_mask != Some(configured));
+
+    ModuleConfigStatus {
+        running_ppfeaturemask: running_mask,
+        configured_ppfeaturemask: configured_mask,
+        reboot_required: toggled || mismatch,
+    }
+}
+
+pub fn overclock_status() -> OverclockStatus {
+    let running_mask = read_current_mask().ok();
+    let module_conf = fs::read_to_string(&*MODULE_CONF_PATH).ok();
+    overclock_status_from(running_mask, module_conf.as_deref())
+}
+
+fn overclock_status_from(running_mask: Option<u64>, module_conf: Option<&str>) -> OverclockStatus {
+    let enabled = running_mask.map(is_overdrive_enabled);
+    let enabled_on_boot = module_conf
+        .and_then(parse_configured_mask)
+        .map(is_overdrive_enabled);
+
+    OverclockStatus {
+        enabled,
+        enabled_on_boot,
+        reboot_required: enabled_on_boot.is_some_and(|on_boot| enabled != Some(on_boot)),
+    }
+}
+
+fn validate_feature_mask(ppfeaturemask: u64) -> anyhow::Result<()> {
+    // The module parameter is a 32-bit value
+    if u32::try_from(ppfeaturemask).is_err() {
+        return Err(ErrorKind::OutOfRange.error(format!(
+            "The ppfeaturemask 0x{ppfeaturemask:X} does not fit into 32 bits"
+        )));
+    }
+
+    let missing = PP_REQUIRED_MASK & !ppfeaturemask;
+    if missing != 0 {
+        return Err(ErrorKind::OutOfRange.error(format!(
+            "The ppfeaturemask 0x{ppfeaturemask:X} disables required power management features \
+            (missing bits 0x{missing:X})"
+        )));
+    }
+    Ok(())
+}
+
+fn is_overdrive_enabled(ppfeaturemask: u64) -> bool {
+    ppfeaturemask & PP_OVERDRIVE_MASK != 0
+}
+
+fn write_module_config(ppfeaturemask: u64) -> anyhow::Result<()> {
+    let conf = format!("options amdgpu ppfeaturemask=0x{ppfeaturemask:X}");
+
+    let mut file = File::create(&*MODULE_CONF_PATH).context("Could not open module conf file")?;
+    file.set_permissions(Permissions::from_mode(0o644))
+        .context("Could not conf file permissions")?;
+
+    file.write_all(conf.as_bytes())
+        .context("Could not write config")
+}
+
+/// Regenerates the initramfs so that the module config is used on the next boot
+async fn finish_module_config_change() -> String {
+    match regenerate_initramfs().await {
+        Ok(initramfs_type) => {
+            OC_TOGGLED.store(true, Ordering::SeqCst);
+            format!("Initramfs was successfully regenerated (detected type {initramfs_type:?})")
+        }
+        Err(err) => format!("{err:#}"),
+    }
+}
+
 fn read_current_mask() -> anyhow::Result<u64> {
     let ppfeaturemask = fs::read_to_string(PP_FEATURE_MASK_PATH)?;
-    let ppfeaturemask = ppfeaturemask
-        .trim()
-        .strip_prefix("0x")
-        .context("Invalid ppfeaturemask")?;
+    parse_mask(ppfeaturemask.trim()).context("Invalid ppfeaturemask")
+}
 
-    u64::from_str_radix(ppfeaturemask, 16).context("Invalid ppfeaturemask")
+fn parse_configured_mask(module_conf: &str) -> Option<u64> {
+    module_conf
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let is_amdgpu = words.next() == Some("options") && words.next() == Some("amdgpu");
+            is_amdgpu.then_some(words)
+        })
+        .flatten()
+        .find_map(|option| option.strip_prefix("ppfeaturemask="))
+        .and_then(parse_mask)
+}
+
+fn parse_mask(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
 }
 
 async fn regenerate_initramfs() -> anyhow::Result<InitramfsType> {
@@ -218,7 +336,24 @@ pub async fn run_command(exec: &str, args: &[&str]) -> anyhow::Result<Output> {
     }
 }
 
-pub(crate) fn listen_netlink_kernel_event(notify: &Notify) -> anyhow::Result<()> {
+/// Sets the nice value of the daemon process, which the processes started by it inherit
+pub fn set_nice(nice: i32) -> io::Result<()> {
+    // The value is clamped to the allowed range by the kernel
+    // SAFETY: `setpriority` only takes integer arguments, `who = 0` refers to the calling process
+    let result = unsafe { nix::libc::setpriority(nix::libc::PRIO_PROCESS, 0, nice) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Listens to kernel uevents, notifying about DRM subsystem changes
+/// and sending the PCI slot names of GPUs that were reset
+pub(crate) fn listen_netlink_kernel_event(
+    notify: &Notify,
+    reset_tx: &mpsc::UnboundedSender<String>,
+) -> anyhow::Result<()> {
     let socket = socket(
         AddressFamily::Netlink,
         SockType::Raw,
@@ -246,17 +381,12 @@ pub(crate) fn listen_netlink_kernel_event(notify: &Notify) -> anyhow::Result<()>
         recv(socket.as_raw_fd(), &mut buf, MsgFlags::empty())
             .context("Could not read netlink message")?;
 
+        let mut lines = Vec::new();
         for raw_line in buf.split(|c| *c == b'\0') {
             match std::str::from_utf8(raw_line) {
                 Ok(line) => {
-                    if line.is_empty() {
-                        continue;
-                    }
-
-                    if let Some(subsystem) = line.strip_prefix("SUBSYSTEM=") {
-                        if subsystem == "drm" {
-                            notify.notify_one();
-                        }
+                    if !line.is_empty() {
+                        lines.push(line);
                     }
                 }
                 Err(_) => {
@@ -267,13 +397,45 @@ pub(crate) fn listen_netlink_kernel_event(notify: &Notify) -> anyhow::Result<()>
                 }
             }
         }
+
+        // A reset event is also a DRM event, the GPU list may need to be reloaded after it
+        if let Some(pci_slot_name) = reset_event_pci_slot(&lines) {
+            let _ = reset_tx.send(pci_slot_name.to_owned());
+        }
+        if lines.contains(&"SUBSYSTEM=drm") {
+            notify.notify_one();
+        }
     }
 }
 
+/// Finds the PCI slot of the GPU from a DRM "wedged" uevent,
+/// which the kernel sends after a GPU was reset
+fn reset_event_pci_slot<'a>(lines: &[&'a str]) -> Option<&'a str> {
+    let is_wedged_event = lines.iter().any(|line| line.starts_with("WEDGED="));
+    if !is_wedged_event || !lines.contains(&"SUBSYSTEM=drm") {
+        return None;
+    }
+
+    // The path looks like `/devices/pci0000:00/0000:00:01.1/.../0000:03:00.0/drm/card1`
+    let dev_path = lines
+        .iter()
+        .find_map(|line| line.strip_prefix("DEVPATH="))?;
+    let (device_path, _) = dev_path.rsplit_once("/drm/")?;
+    device_path
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::detect_initramfs_type;
-    use lact_schema::InitramfsType;
+    use super::{
+        config_status, daemon_info, detect_initramfs_type, overclock_status_from,
+        parse_configured_mask, reset_event_pci_slot, validate_feature_mask,
+    };
+    use lact_schema::{
+        ErrorKind, InitramfsType, ModuleConfigStatus, OverclockStatus, PROTOCOL_VERSION,
+    };
     use os_release::OsRelease;
 
     #[tokio::test]
@@ -316,4 +478,165 @@ UBUNTU_CODENAME=jammy
             detect_initramfs_type(&os_release).await
         );
     }
+
+    #[test]
+    fn parse_reset_event() {
+        let lines = [
+            "ACTION=change",
+            "DEVPATH=/devices/pci0000:00/0000:00:01.1/0000:02:00.0/0000:03:00.0/drm/card1",
+            "SUBSYSTEM=drm",
+            "WEDGED=none",
+            "DEVNAME=dri/card1",
+        ];
+        assert_eq!(Some("0000:03:00.0"), reset_event_pci_slot(&lines));
+    }
+
+    #[test]
+    fn ignore_hotplug_event() {
+        let lines = [
+            "ACTION=change",
+            "DEVPATH=/devices/pci0000:00/0000:00:01.1/0000:03:00.0/drm/card1",
+            "SUBSYSTEM=drm",
+            "HOTPLUG=1",
+        ];
+        assert_eq!(None, reset_event_pci_slot(&lines));
+    }
+
+    #[test]
+    fn parse_module_conf() {
+        assert_eq!(
+            Some(0xFFF7_FFFF),
+            parse_configured_mask("options amdgpu ppfeaturemask=0xFFF7FFFF")
+        );
+        assert_eq!(
+            Some(4_294_967_295),
+            parse_configured_mask("# comment\noptions amdgpu dc=1 ppfeaturemask=4294967295\n")
+        );
+        assert_eq!(
+            None,
+            parse_configured_mask("options radeon ppfeaturemask=0xFFF7FFFF")
+        );
+        assert_eq!(None, parse_configured_mask("options amdgpu dc=1"));
+    }
+
+    #[test]
+    fn required_feature_bits() {
+        validate_feature_mask(0xFFF7_FFFF).unwrap();
+        // Overdrive can be turned off
+        validate_feature_mask(0xFFF7_BFFF).unwrap();
+
+        for mask in [0xFFF7_FFFE, 0xFFF7_FFEF, 0, 0x1_FFFF_FFFF] {
+            let err = validate_feature_mask(mask).unwrap_err();
+            assert_eq!(
+                Some(ErrorKind::OutOfRange),
+                ErrorKind::from_error(&err),
+                "{mask:X}"
+            );
+        }
+    }
+
+    #[test]
+    fn module_config_mask_mismatch() {
+        let conf = "options amdgpu ppfeaturemask=0xFFF7FFFF";
+
+        assert_eq!(
+            ModuleConfigStatus {
+                running_ppfeaturemask: Some(0xFFF7_BFFF),
+                configured_ppfeaturemask: Some(0xFFF7_FFFF),
+                reboot_required: true,
+            },
+            config_status(Some(0xFFF7_BFFF), Some(conf), false)
+        );
+        assert!(!config_status(Some(0xFFF7_FFFF), Some(conf), false).reboot_required);
+        // The module is not loaded at all
+        assert!(config_status(None, Some(conf), false).reboot_required);
+    }
+
+    #[test]
+    fn module_config_without_file() {
+        assert_eq!(
+            ModuleConfigStatus {
+                running_ppfeaturemask: Some(0xFFF7_FFFF),
+                configured_ppfeaturemask: None,
+                reboot_required: false,
+            },
+            config_status(Some(0xFFF7_FFFF), None, false)
+        );
+        // The config file was removed since the module was loaded
+        assert!(config_status(Some(0xFFF7_FFFF), None, true).reboot_required);
+    }
+
+    #[test]
+    fn overclock_enabled() {
+        let conf = "options amdgpu ppfeaturemask=0xFFF7FFFF";
+        assert_eq!(
+            OverclockStatus {
+                enabled: Some(true),
+                enabled_on_boot: Some(true),
+                reboot_required: false,
+            },
+            overclock_status_from(Some(0xFFF7_FFFF), Some(conf))
+        );
+
+        // Enabled through the kernel command line, without a module config file
+        let status = overclock_status_from(Some(0xFFF7_FFFF), None);
+        assert_eq!(Some(true), status.enabled);
+        assert_eq!(None, status.enabled_on_boot);
+        assert!(!status.reboot_required);
+    }
+
+    #[test]
+    fn overclock_disabled() {
+        assert_eq!(
+            OverclockStatus {
+                enabled: Some(false),
+                enabled_on_boot: None,
+                reboot_required: false,
+            },
+            overclock_status_from(Some(0xFFF7_BFFF), None)
+        );
+
+        // The module is not loaded, such as on systems without AMD GPUs
+        let status = overclock_status_from(None, None);
+        assert_eq!(None, status.enabled);
+        assert!(!status.reboot_required);
+    }
+
+    #[test]
+    fn overclock_mismatch() {
+        // Enabled in the config, but the module was loaded before that
+        let status = overclock_status_from(
+            Some(0xFFF7_BFFF),
+            Some("options amdgpu ppfeaturemask=0xFFF7FFFF"),
+        );
+        assert_eq!(Some(false), status.enabled);
+        assert_eq!(Some(true), status.enabled_on_boot);
+        assert!(status.reboot_required);
+
+        // Disabled in the config while it is still enabled in the running module
+        let status = overclock_status_from(
+            Some(0xFFF7_FFFF),
+            Some("options amdgpu ppfeaturemask=0xFFF7BFFF"),
+        );
+        assert_eq!(Some(true), status.enabled);
+        assert_eq!(Some(false), status.enabled_on_boot);
+        assert!(status.reboot_required);
+
+        // A different mask with the same overclocking state doesn't need a reboot for it
+        let status = overclock_status_from(
+            Some(0xFFFF_FFFF),
+            Some("options amdgpu ppfeaturemask=0xFFF7FFFF"),
+        );
+        assert!(!status.reboot_required);
+    }
+
+    #[test]
+    fn daemon_info_versions() {
+        let info = daemon_info();
+        assert_eq!(env!("CARGO_PKG_VERSION"), info.version);
+        assert_eq!(info.version, info.schema_version);
+        assert_eq!(PROTOCOL_VERSION, info.protocol_version);
+        assert!(info.is_compatible());
+        assert_eq!(cfg!(feature = "nvidia"), info.has_feature("nvidia"));
+    }
 }
@@ -4,20 +4,27 @@ use easy_fuser::{
         DefaultFuseHandler,
     },
     types::{
-        BorrowedFileHandle, FUSESetXAttrFlags, FUSEWriteFlags, FileAttribute, FuseResult,
-        OpenFlags, SetAttrRequest,
+        BorrowedFileHandle, ErrorKind, FUSESetXAttrFlags, FUSEWriteFlags, FileAttribute,
+        FuseResult, OpenFlags, PosixError, SetAttrRequest,
     },
     FuseHandler,
 };
 use std::{
     ffi::OsStr,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
+/// Decides if a write should be accepted, based on the previous writes, the path and the contents
+type WriteFilter = Box<dyn Fn(&[(PathBuf, String)], &Path, &str) -> bool + Send + Sync>;
+
 pub struct MockSysfs {
     inner: MirrorFsReadOnly,
     pub writes: Arc<Mutex<Vec<(PathBuf, String)>>>,
+    write_filter: Option<WriteFilter>,
+    read_only_paths: Vec<PathBuf>,
+    /// Paths with the amount of writes to them which still fail with `EBUSY`
+    busy_paths: Mutex<Vec<(PathBuf, usize)>>,
 }
 
 impl MockSysfs {
@@ -25,8 +32,36 @@ impl MockSysfs {
         MockSysfs {
             inner: MirrorFsReadOnly::new(source_path, DefaultFuseHandler::new()),
             writes: Arc::default(),
+            write_filter: None,
+            read_only_paths: Vec::new(),
+            busy_paths: Mutex::default(),
         }
     }
+
+    /// Rejected writes fail with `EINVAL`, like the driver does for invalid commands
+    pub fn with_write_filter(
+        mut self,
+        filter: impl Fn(&[(PathBuf, String)], &Path, &str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.write_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Writes to files ending with any of the given paths fail with `EACCES`
+    pub fn with_read_only_paths(mut self, paths: &[&str]) -> Self {
+        self.read_only_paths = paths.iter().map(PathBuf::from).collect();
+        self
+    }
+
+    /// The first `count` writes to files ending with the given path fail with `EBUSY`,
+    /// like the driver does while the GPU is busy with a power state transition
+    pub fn with_busy_writes(self, path: &str, count: usize) -> Self {
+        self.busy_paths
+            .lock()
+            .unwrap()
+            .push((PathBuf::from(path), count));
+        self
+    }
 }
 
 impl FuseHandler<PathBuf> for MockSysfs {
@@ -45,10 +80,42 @@ impl FuseHandler<PathBuf> for MockSysfs {
         _flags: OpenFlags,
         _lock_owner: Option<u64>,
     ) -> FuseResult<u32> {
-        self.writes
-            .lock()
-            .unwrap()
-            .push((file_id, String::from_utf8_lossy(&data).into_owned()));
+        if self
+            .read_only_paths
+            .iter()
+            .any(|path| file_id.ends_with(path))
+        {
+            return Err(PosixError::new(
+                ErrorKind::PermissionDenied,
+                format!("{} is read-only", file_id.display()),
+            ));
+        }
+
+        let mut busy_paths = self.busy_paths.lock().unwrap();
+        let busy = busy_paths
+            .iter_mut()
+            .find(|(path, remaining)| *remaining > 0 && file_id.ends_with(path));
+        if let Some((_, remaining)) = busy {
+            *remaining -= 1;
+            return Err(PosixError::new(
+                ErrorKind::ResourceBusy,
+                format!("{} is busy", file_id.display()),
+            ));
+        }
+        drop(busy_paths);
+
+        let contents = String::from_utf8_lossy(&data).into_owned();
+        let mut writes = self.writes.lock().unwrap();
+
+        if let Some(filter) = &self.write_filter {
+            if !filter(&writes, &file_id, &contents) {
+                return Err(PosixError::new(
+                    ErrorKind::InvalidArgument,
+                    format!("write to {} was rejected", file_id.display()),
+                ));
+            }
+        }
+        writes.push((file_id, contents));
 
         Ok(data.len().try_into().unwrap())
     }
@@ -1,15 +1,45 @@
 mod mock_fs;
 
 use crate::{
-    config::Config,
-    server::handler::{read_pci_db, Handler},
+    config::{Config, ShutdownFanBehavior},
+    server::{
+        handle_payload, handle_stream, handle_unix_stream,
+        handler::{read_pci_db, Handler},
+        Connection,
+    },
+    socket,
 };
+use amdgpu_sysfs::gpu_handle::{PerformanceLevel, PowerLevelKind};
+use indexmap::IndexMap;
 use insta::{assert_debug_snapshot, assert_json_snapshot};
-use lact_schema::config::GpuConfig;
+use lact_schema::{
+    config::{
+        ClockVoltagePoint, ClocksConfiguration, ExternalTemperature, FanControlSettings, FanCurve,
+        FanCurvePreset, GpuConfig, LedSettings, Profile, ResumeDelayedSetting, SharedGpuConfig,
+        ThermalProtection, ThermalProtectionAction,
+    },
+    request::{ConfirmCommand, ProfileBase},
+    BroadcastOutcome, ClientCredentials, ConfigDifference, CurveTemperature, DaemonEventKind,
+    ErrorKind, FanControlMode, FanOptions, FanStats, HardwareFanMode, HistoryMetric, LinkInfo,
+    OperationKind, PowerCap, Request, Response, SelfTestControl, SelfTestStatus, SettingProvenance,
+    ThermalLimits, ValueRange, VbiosInfo,
+};
 use mock_fs::MockSysfs;
-use std::{fs, path::PathBuf, sync::OnceLock};
-use tempfile::tempdir;
-use tokio::task::LocalSet;
+use nix::unistd::{getgid, getuid, Group};
+use pretty_assertions::assert_eq;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    pin::pin,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+use tempfile::{tempdir, TempDir};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+    task::LocalSet,
+};
 
 fn init_tracing() {
     static TRACING_LOCK: OnceLock<()> = OnceLock::new();
@@ -18,6 +48,39 @@ fn init_tracing() {
     });
 }
 
+pub(crate) fn copy_dir(src: &Path, dst: &Path) {
+    fs::create_dir_all(dst).unwrap();
+    for entry in fs::read_dir(src).unwrap().flatten() {
+        let target = dst.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
+
+/// Copies a device from the test data (e.g. `amd/rx580`) into a temporary sysfs directory,
+/// which tests can modify freely
+fn copy_test_device(device: &str) -> TempDir {
+    let device_data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/tests/data")
+        .join(device);
+    let sysfs_dir = tempdir().unwrap();
+    copy_dir(&device_data_dir, sysfs_dir.path());
+    sysfs_dir
+}
+
+/// Creates a handler for the given sysfs directory, along with the id of its first GPU
+async fn test_handler(sysfs_dir: &Path, config: Config) -> (Handler, String) {
+    let pci_db = read_pci_db();
+    let handler = Handler::with_base_path(sysfs_dir, config, &pci_db)
+        .await
+        .unwrap();
+    let gpu_id = handler.list_devices().await[0].id.clone();
+    (handler, gpu_id)
+}
+
 #[tokio::test]
 async fn snapshot_everything() {
     init_tracing();
@@ -114,3 +177,4629 @@ async fn apply_settings() {
 
     local_set.await;
 }
+
+#[tokio::test]
+async fn report_pcie_link() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx7900xtx");
+
+    let device_dir = sysfs_dir.path().join("card0/device");
+    let link_files = [
+        ("current_link_speed", "2.5 GT/s PCIe"),
+        ("current_link_width", "1"),
+        ("max_link_speed", "16.0 GT/s PCIe"),
+        ("max_link_width", "16"),
+    ];
+    for (name, contents) in link_files {
+        fs::write(device_dir.join(name), format!("{contents}\n")).unwrap();
+    }
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+    assert_eq!(
+        Some(LinkInfo {
+            current_width: Some("1".to_owned()),
+            current_speed: Some("2.5 GT/s PCIe".to_owned()),
+            max_width: Some("16".to_owned()),
+            max_speed: Some("16.0 GT/s PCIe".to_owned()),
+        }),
+        stats.pcie_link
+    );
+
+    for (name, _) in link_files {
+        fs::remove_file(device_dir.join(name)).unwrap();
+    }
+
+    let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+    assert_eq!(None, stats.pcie_link);
+}
+
+#[tokio::test]
+async fn report_memory_vendor() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx7900xtx");
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    let info = handler.get_device_info(&gpu_id).await.unwrap();
+    assert_eq!(None, info.memory_vendor);
+    assert_eq!(None, info.vram_total);
+
+    let device_dir = sysfs_dir.path().join("card0/device");
+    fs::write(device_dir.join("mem_info_vram_vendor"), "micron\n").unwrap();
+    fs::write(device_dir.join("mem_info_vram_total"), "25753026560\n").unwrap();
+
+    let info = handler.get_device_info(&gpu_id).await.unwrap();
+    assert_eq!(Some("micron"), info.memory_vendor.as_deref());
+    assert_eq!(Some(25_753_026_560), info.vram_total);
+
+    // Reported when the VBIOS doesn't specify the memory vendor
+    fs::write(device_dir.join("mem_info_vram_vendor"), "unknown\n").unwrap();
+    let info = handler.get_device_info(&gpu_id).await.unwrap();
+    assert_eq!(None, info.memory_vendor);
+}
+
+#[tokio::test]
+async fn report_thermal_limits() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx580");
+
+    let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+    fs::write(hw_mon_dir.join("temp1_max"), "85000\n").unwrap();
+    fs::write(hw_mon_dir.join("temp1_emergency"), "99000\n").unwrap();
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    let info = handler.get_device_info(&gpu_id).await.unwrap();
+    assert_eq!(
+        Some(&ThermalLimits {
+            max: Some(85.0),
+            crit: Some(94.0),
+            emergency: Some(99.0),
+        }),
+        info.thermal_limits.get("edge")
+    );
+
+    // Missing thresholds are left out
+    fs::remove_file(hw_mon_dir.join("temp1_crit")).unwrap();
+    fs::remove_file(hw_mon_dir.join("temp1_max")).unwrap();
+    let info = handler.get_device_info(&gpu_id).await.unwrap();
+    assert_eq!(
+        Some(&ThermalLimits {
+            max: None,
+            crit: None,
+            emergency: Some(99.0),
+        }),
+        info.thermal_limits.get("edge")
+    );
+
+    fs::remove_file(hw_mon_dir.join("temp1_emergency")).unwrap();
+    let info = handler.get_device_info(&gpu_id).await.unwrap();
+    assert!(info.thermal_limits.is_empty());
+}
+
+#[tokio::test]
+async fn stats_now_reads_current_values() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx580");
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    let stats = handler.get_stats_now(&gpu_id).await.unwrap();
+    assert_eq!(Some(11), stats.busy_percent);
+
+    let busy_percent_path = sysfs_dir.path().join("card0/device/gpu_busy_percent");
+    fs::write(busy_percent_path, "64\n").unwrap();
+
+    let stats = handler.get_stats_now(&gpu_id).await.unwrap();
+    assert_eq!(Some(64), stats.busy_percent);
+    assert_eq!(Some(64), stats.activity.busy);
+}
+
+#[tokio::test]
+async fn stats_now_shares_reads_in_progress() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx580");
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    let mut first = pin!(handler.get_stats_now(&gpu_id));
+    assert!(futures::poll!(&mut first).is_pending());
+
+    // The read has started already, so a request made now joins it
+    let busy_percent_path = sysfs_dir.path().join("card0/device/gpu_busy_percent");
+    fs::write(busy_percent_path, "64\n").unwrap();
+    let (first, second) = futures::join!(first, handler.get_stats_now(&gpu_id));
+    assert_eq!(Some(11), first.unwrap().busy_percent);
+    assert_eq!(Some(11), second.unwrap().busy_percent);
+
+    // Once it's done, the next request reads the hardware again
+    let stats = handler.get_stats_now(&gpu_id).await.unwrap();
+    assert_eq!(Some(64), stats.busy_percent);
+}
+
+#[tokio::test]
+async fn report_vbios_info() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx7900xtx");
+
+    let vbios_path = sysfs_dir.path().join("card0/device/vbios_version");
+    fs::write(&vbios_path, "113-D7020100-102\n").unwrap();
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    let info = handler.get_device_info(&gpu_id).await.unwrap();
+    assert_eq!(
+        Some(VbiosInfo {
+            version: "113-D7020100-102".to_owned(),
+            part_number: Some("113-D7020100-102".to_owned()),
+            build_date: None,
+            subsystem_vendor_id: "1DA2".to_owned(),
+            subsystem_device_id: "E471".to_owned(),
+        }),
+        info.vbios
+    );
+
+    fs::remove_file(&vbios_path).unwrap();
+
+    let info = handler.get_device_info(&gpu_id).await.unwrap();
+    assert_eq!(None, info.vbios);
+    assert_eq!(None, info.vbios_version);
+}
+
+async fn fan_stats_without_files(removed_files: &[&str]) -> (FanStats, anyhow::Result<u64>) {
+    let sysfs_dir = copy_test_device("amd/rx580");
+
+    let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+    for name in removed_files {
+        fs::remove_file(hw_mon_dir.join(name)).unwrap();
+    }
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+
+    let gpu_config = GpuConfig {
+        fan_control_enabled: true,
+        fan_control_settings: Some(FanControlSettings {
+            mode: FanControlMode::Static,
+            static_speed: 0.5,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let apply_result = handler.set_gpu_config(&gpu_id, gpu_config).await;
+    if apply_result.is_ok() {
+        handler
+            .confirm_pending_config(ConfirmCommand::Confirm)
+            .unwrap();
+    }
+
+    (stats.fan, apply_result)
+}
+
+#[tokio::test]
+async fn fan_rpm_only() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let (fan, apply_result) = fan_stats_without_files(&["pwm1", "pwm1_enable"]).await;
+
+            assert!(fan.speed_current.is_some());
+            assert_eq!(None, fan.pwm_current);
+            assert!(fan.monitor_only);
+
+            let err = apply_result.unwrap_err();
+            assert_eq!(Some(ErrorKind::Unsupported), ErrorKind::from_error(&err));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn fan_pwm_only() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let (fan, apply_result) = fan_stats_without_files(&["fan1_input"]).await;
+
+            assert_eq!(None, fan.speed_current);
+            assert!(fan.pwm_current.is_some());
+            assert!(!fan.monitor_only);
+            apply_result.unwrap();
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn fan_rpm_and_pwm() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let (fan, apply_result) = fan_stats_without_files(&[]).await;
+
+            assert!(fan.speed_current.is_some());
+            assert!(fan.pwm_current.is_some());
+            assert!(!fan.monitor_only);
+            apply_result.unwrap();
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn hardware_fan_mode() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx580");
+    let pwm_enable_path = sysfs_dir
+        .path()
+        .join("card0/device/hwmon/hwmon4/pwm1_enable");
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    // Written behind the back of the daemon, like another tool would do
+    for (value, mode) in [
+        ("0\n", Some(HardwareFanMode::Full)),
+        ("1\n", Some(HardwareFanMode::Manual)),
+        ("2\n", Some(HardwareFanMode::Auto)),
+        ("7\n", None),
+    ] {
+        fs::write(&pwm_enable_path, value).unwrap();
+        let fan = handler.get_gpu_stats(&gpu_id).await.unwrap().fan;
+        assert_eq!(mode, fan.hardware_mode, "{value:?}");
+        assert!(!fan.control_enabled);
+    }
+
+    fs::remove_file(&pwm_enable_path).unwrap();
+    let fan = handler.get_gpu_stats(&gpu_id).await.unwrap().fan;
+    assert_eq!(None, fan.hardware_mode);
+}
+
+async fn power_cap_without_files(
+    removed_files: &[&str],
+) -> (PowerCap, Option<ValueRange<f64>>, anyhow::Result<u64>) {
+    let sysfs_dir = copy_test_device("amd/rx580");
+
+    let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+    for name in removed_files {
+        fs::remove_file(hw_mon_dir.join(name)).unwrap();
+    }
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+    let capabilities = handler.get_capabilities(&gpu_id).await.unwrap();
+    let set_result = handler.set_power_cap_percent(&gpu_id, 90.0).await;
+
+    (stats.power.cap, capabilities.power_cap, set_result)
+}
+
+#[tokio::test]
+async fn power_cap_file_combinations() {
+    init_tracing();
+
+    let all_values = PowerCap {
+        current: Some(155.0),
+        max: Some(201.0),
+        min: Some(0.0),
+        default: Some(155.0),
+    };
+    let full_range = Some(ValueRange::from((0.0, 201.0)));
+
+    // Removed files, the expected cap values and range,
+    // and the expected error when setting the cap as a percentage
+    let cases: [(
+        &[&str],
+        PowerCap,
+        Option<ValueRange<f64>>,
+        Option<ErrorKind>,
+    ); 4] = [
+        (&[], all_values, full_range, None),
+        (
+            &["power1_cap_min"],
+            PowerCap {
+                min: None,
+                ..all_values
+            },
+            full_range,
+            None,
+        ),
+        (
+            &["power1_cap_max"],
+            PowerCap {
+                max: None,
+                ..all_values
+            },
+            None,
+            Some(ErrorKind::Unsupported),
+        ),
+        (
+            &["power1_cap_default", "power1_cap_max", "power1_cap_min"],
+            PowerCap {
+                current: Some(155.0),
+                ..Default::default()
+            },
+            None,
+            Some(ErrorKind::Unsupported),
+        ),
+    ];
+
+    LocalSet::new()
+        .run_until(async {
+            for (removed_files, expected_cap, expected_range, expected_error) in cases {
+                let (cap, range, set_result) = power_cap_without_files(removed_files).await;
+
+                assert_eq!(expected_cap, cap, "{removed_files:?}");
+                assert_eq!(expected_range, range, "{removed_files:?}");
+                assert_eq!(
+                    expected_error,
+                    set_result.err().and_then(|err| ErrorKind::from_error(&err)),
+                    "{removed_files:?}"
+                );
+            }
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn stats_poll_interval_change() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+            let pci_db = read_pci_db();
+
+            let mut config = Config::default();
+            config.daemon.stats_poll_interval_ms = 100;
+
+            let handler = Handler::with_base_path(&device_dir, config, &pci_db)
+                .await
+                .unwrap();
+            let gpu_id = handler.list_devices().await[0].id.clone();
+
+            let task = tokio::task::spawn_local(crate::record_stats_history(handler.clone()));
+
+            tokio::time::sleep(Duration::from_millis(1050)).await;
+            let fast_samples = handler.get_history(&gpu_id, &[]).await.unwrap().len();
+
+            handler.config.write().await.daemon.stats_poll_interval_ms = 500;
+            handler.stats_poll_notify.notify_one();
+
+            tokio::time::sleep(Duration::from_millis(1050)).await;
+            let slow_samples =
+                handler.get_history(&gpu_id, &[]).await.unwrap().len() - fast_samples;
+            task.abort();
+
+            assert!(
+                (5..=11).contains(&fast_samples),
+                "unexpected amount of samples with a 100ms interval: {fast_samples}"
+            );
+            assert!(
+                (1..=3).contains(&slow_samples),
+                "unexpected amount of samples with a 500ms interval: {slow_samples}"
+            );
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn fan_control_interval() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+            let pci_db = read_pci_db();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir);
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let mut config = Config::default();
+            config.daemon.stats_poll_interval_ms = 1000;
+            config.daemon.fan_control_interval_ms = Some(100);
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), config).await;
+            let stats_task = tokio::task::spawn_local(crate::record_stats_history(handler.clone()));
+
+            let gpu_config = GpuConfig {
+                fan_control_enabled: true,
+                // Overridden by the interval in the daemon settings
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Curve,
+                    interval_ms: 1000,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            let pwm_writes = || {
+                writes
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|(name, _)| name.ends_with("pwm1"))
+                    .count()
+            };
+
+            // Counted from a point between two evaluations, so the writes made
+            // when the fan control was started are left out
+            tokio::time::sleep(Duration::from_millis(1050)).await;
+            let start_writes = pwm_writes();
+            let start_samples = handler.get_history(&gpu_id, &[]).await.unwrap().len();
+
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+            let fan_writes = pwm_writes() - start_writes;
+            let stats_samples =
+                handler.get_history(&gpu_id, &[]).await.unwrap().len() - start_samples;
+
+            stats_task.abort();
+            handler.cleanup().await;
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            assert_eq!(10, fan_writes, "fan writes with a 100ms interval");
+            assert_eq!(1, stats_samples, "stats samples with a 1000ms interval");
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn ignored_gpu() {
+    init_tracing();
+
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+    let pci_db = read_pci_db();
+
+    let sysfs_dir = tempdir().unwrap();
+    copy_dir(
+        &data_dir.join("rx580/card0"),
+        &sysfs_dir.path().join("card0"),
+    );
+    copy_dir(
+        &data_dir.join("vega56/card0"),
+        &sysfs_dir.path().join("card1"),
+    );
+
+    let handler = Handler::with_base_path(sysfs_dir.path(), Config::default(), &pci_db)
+        .await
+        .unwrap();
+    assert_eq!(2, handler.list_devices().await.len());
+
+    for ignored in ["0000:09:00.0", "09:00.0"] {
+        let config = Config {
+            ignored_gpus: vec![ignored.to_owned()],
+            ..Default::default()
+        };
+        let handler = Handler::with_base_path(sysfs_dir.path(), config, &pci_db)
+            .await
+            .unwrap();
+
+        let devices = handler.list_devices().await;
+        assert_eq!(1, devices.len(), "ignored GPU: {ignored}");
+        assert!(devices[0].id.ends_with("0000:0b:00.0"));
+    }
+}
+
+#[tokio::test]
+async fn share_gpu_config() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+            let pci_db = read_pci_db();
+
+            let sysfs_dir = tempdir().unwrap();
+            copy_dir(
+                &data_dir.join("rx580/card0"),
+                &sysfs_dir.path().join("card0"),
+            );
+            copy_dir(
+                &data_dir.join("vega56/card0"),
+                &sysfs_dir.path().join("card1"),
+            );
+
+            let handler = Handler::with_base_path(sysfs_dir.path(), Config::default(), &pci_db)
+                .await
+                .unwrap();
+            let devices = handler.list_devices().await;
+            let gpu_id = |slot: &str| {
+                let device = devices.iter().find(|device| device.id.ends_with(slot));
+                device.unwrap().id.clone()
+            };
+            let rx580_id = gpu_id("0000:09:00.0");
+            let vega_id = gpu_id("0000:0b:00.0");
+
+            let gpu_config = GpuConfig {
+                performance_level: Some(PerformanceLevel::High),
+                ..Default::default()
+            };
+            handler
+                .set_gpu_config(&rx580_id, gpu_config.clone())
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            let snippet = handler.export_gpu_config(&rx580_id).await.unwrap();
+            let shared: SharedGpuConfig = serde_yml::from_str(&snippet).unwrap();
+            assert_eq!("1002:67DF", shared.model_id);
+            assert_eq!(gpu_config, shared.config);
+            assert!(handler.export_gpu_config(&vega_id).await.is_err());
+
+            // Round trip onto the same model
+            handler
+                .set_gpu_config(&rx580_id, GpuConfig::default())
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            handler
+                .import_gpu_config(&rx580_id, &snippet, false)
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            assert_eq!(
+                Some(&gpu_config),
+                handler.get_gpu_config(&rx580_id).await.unwrap().as_ref()
+            );
+
+            // A different model needs the mismatch to be allowed
+            let err = handler
+                .import_gpu_config(&vega_id, &snippet, false)
+                .await
+                .unwrap_err();
+            assert!(format!("{err:#}").contains("different GPU model"));
+            assert_eq!(None, handler.get_gpu_config(&vega_id).await.unwrap());
+
+            handler
+                .import_gpu_config(&vega_id, &snippet, true)
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            assert_eq!(
+                Some(gpu_config),
+                handler.get_gpu_config(&vega_id).await.unwrap()
+            );
+
+            assert!(handler
+                .import_gpu_config(&vega_id, "not a snippet", true)
+                .await
+                .is_err());
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn export_import_config() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let power_cap_path = sysfs_dir
+                .path()
+                .join("card0/device/hwmon/hwmon4/power1_cap");
+            let read_power_cap = || {
+                fs::read_to_string(&power_cap_path)
+                    .unwrap()
+                    .trim()
+                    .to_owned()
+            };
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            handler.set_power_cap(&gpu_id, Some(150.0)).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            handler
+                .create_profile("quiet".to_owned(), ProfileBase::Default)
+                .await
+                .unwrap();
+            handler
+                .set_gpu_alias(&gpu_id, Some("Main".to_owned()))
+                .await
+                .unwrap();
+
+            let exported = handler.export_config().await.unwrap();
+            let expected_config = handler.config.read().await.clone();
+
+            handler.reset_config().await;
+            assert_ne!(expected_config, *handler.config.read().await);
+            assert_eq!("155000000", read_power_cap());
+
+            // Applied settings are only kept once confirmed
+            let apply_timer = handler.import_config(&exported, true, false).await.unwrap();
+            assert_eq!(Some(5), apply_timer);
+            assert_eq!("150000000", read_power_cap());
+            handler
+                .confirm_pending_config(ConfirmCommand::Revert)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!("155000000", read_power_cap());
+            assert!(!handler.config.read().await.has_stored_gpu_config(&gpu_id));
+
+            handler.import_config(&exported, true, false).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(expected_config, *handler.config.read().await);
+            assert_eq!("150000000", read_power_cap());
+
+            // The daemon settings of this system are not replaced
+            let other_timer =
+                exported.replace("apply_settings_timer: 5", "apply_settings_timer: 30");
+            assert_ne!(exported, other_timer);
+            assert_eq!(
+                None,
+                handler
+                    .import_config(&other_timer, false, false)
+                    .await
+                    .unwrap()
+            );
+            assert_eq!(5, handler.config.read().await.apply_settings_timer);
+
+            // Settings for a GPU which is not in this system
+            let other_id = "1002:73BF-1DA2:440E-0000:03:00.0";
+            let other_gpu_config = exported.replace(&gpu_id, other_id);
+            let err = handler
+                .import_config(&other_gpu_config, false, false)
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::GpuNotFound), ErrorKind::from_error(&err));
+            assert!(format!("{err:#}").contains(other_id), "{err:#}");
+            assert_eq!(expected_config, *handler.config.read().await);
+
+            handler
+                .import_config(&other_gpu_config, false, true)
+                .await
+                .unwrap();
+            assert!(handler.config.read().await.has_stored_gpu_config(other_id));
+
+            // Invalid configs are rejected without replacing the current one
+            let missing_profile =
+                exported.replace("current_profile: null", "current_profile: missing");
+            assert_ne!(exported, missing_profile);
+            let err = handler
+                .import_config(&missing_profile, false, true)
+                .await
+                .unwrap_err();
+            assert!(format!("{err:#}").contains("missing"), "{err:#}");
+            let over_limit = exported.replace("power_cap: 150.0", "power_cap: 1000.0");
+            assert_ne!(exported, over_limit);
+            let err = handler
+                .import_config(&over_limit, false, true)
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::OutOfRange), ErrorKind::from_error(&err));
+            assert!(handler
+                .import_config("not a config", false, true)
+                .await
+                .is_err());
+            assert!(handler.config.read().await.has_stored_gpu_config(other_id));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn system_power() {
+    init_tracing();
+
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+    let pci_db = read_pci_db();
+
+    let sysfs_dir = tempdir().unwrap();
+    copy_dir(
+        &data_dir.join("rx580/card0"),
+        &sysfs_dir.path().join("card0"),
+    );
+    copy_dir(
+        &data_dir.join("vega56/card0"),
+        &sysfs_dir.path().join("card1"),
+    );
+
+    let handler = Handler::with_base_path(sysfs_dir.path(), Config::default(), &pci_db)
+        .await
+        .unwrap();
+
+    let power = handler.get_system_power().await;
+    assert_eq!(2, power.gpus.len());
+    assert!(power.excluded_gpus.is_empty());
+    assert!((power.total - 49.045).abs() < 0.001);
+
+    // A GPU that doesn't report its power draw is left out of the total
+    let hw_mon_dir = sysfs_dir.path().join("card1/device/hwmon/hwmon4");
+    fs::remove_file(hw_mon_dir.join("power1_average")).unwrap();
+
+    let power = handler.get_system_power().await;
+    assert_eq!(1, power.gpus.len());
+    assert!(power.gpus.keys().all(|id| id.ends_with("0000:09:00.0")));
+    assert_eq!(1, power.excluded_gpus.len());
+    assert!(power.excluded_gpus[0].ends_with("0000:0b:00.0"));
+    assert!((power.total - 41.045).abs() < 0.001);
+}
+
+#[tokio::test]
+async fn status_line() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx580");
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    let lines = handler.get_status_line().await;
+    assert_eq!(1, lines.len());
+    let line = &lines[0];
+    assert_eq!(gpu_id, line.id);
+    assert!(line.name.is_some());
+    assert_eq!(Some(44.0), line.temperature);
+    assert_eq!(Some(41.045), line.power);
+    assert_eq!(Some(798), line.core_clock);
+    assert_eq!(Some(14), line.fan_percent);
+    assert_eq!(Some(595), line.fan_rpm);
+
+    // The alias is shown instead of the model name
+    handler
+        .set_gpu_alias(&gpu_id, Some("Main".to_owned()))
+        .await
+        .unwrap();
+    let line = handler.get_status_line().await.remove(0);
+    assert_eq!(
+        format!("{gpu_id} (Main): 44°C, 41.0 W, 798 MHz, fan 14% (595 RPM)"),
+        line.to_string()
+    );
+}
+
+#[tokio::test]
+async fn energy_delta() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx6900xt");
+    let metrics_path = sysfs_dir.path().join("card0/device/gpu_metrics");
+    let write_counter = |counter: u64| {
+        let mut metrics = fs::read(&metrics_path).unwrap();
+        metrics[24..32].copy_from_slice(&counter.to_le_bytes());
+        fs::write(&metrics_path, metrics).unwrap();
+    };
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    // The first request only starts the measurement
+    write_counter(1_000_000);
+    assert_eq!(None, handler.energy_delta(&gpu_id).await.unwrap());
+
+    // 50 J
+    write_counter(1_000_000 + 50 * 65_536);
+    let delta = handler.energy_delta(&gpu_id).await.unwrap().unwrap();
+    assert!((delta.joules - 50.0).abs() < 0.001, "{delta:?}");
+
+    // A counter which went down was reset, the measurement starts over from its new value
+    write_counter(65_536);
+    assert_eq!(None, handler.energy_delta(&gpu_id).await.unwrap());
+    write_counter(5 * 65_536);
+    let delta = handler.energy_delta(&gpu_id).await.unwrap().unwrap();
+    assert!((delta.joules - 4.0).abs() < 0.001, "{delta:?}");
+
+    // GPUs without a metrics table don't report the counter
+    fs::remove_file(&metrics_path).unwrap();
+    let err = handler.energy_delta(&gpu_id).await.unwrap_err();
+    assert_eq!(Some(ErrorKind::Unsupported), ErrorKind::from_error(&err));
+}
+
+#[tokio::test]
+async fn stats_capture() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx580");
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    let err = handler.end_capture(&gpu_id).await.unwrap_err();
+    assert!(format!("{err:#}").contains("No capture was started"));
+
+    // Samples from before the capture was started are not included
+    handler.record_history().await;
+    handler.begin_capture(&gpu_id).await.unwrap();
+    for _ in 0..3 {
+        handler.record_history().await;
+    }
+
+    let summary = handler.end_capture(&gpu_id).await.unwrap();
+    assert_eq!(3, summary.sample_count);
+    assert!(summary.started_at <= summary.ended_at);
+    let power = summary.metrics[&HistoryMetric::Power];
+    assert!(power.min <= power.avg && power.avg <= power.max);
+
+    // The capture is gone once it was ended
+    assert!(handler.end_capture(&gpu_id).await.is_err());
+}
+
+#[tokio::test]
+async fn estimate_power_cap() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx580");
+    let hwmon_path = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    // There is nothing to base the estimate on yet
+    assert!(handler.estimate_power_cap(&gpu_id, 75.0).await.is_err());
+
+    // The temperature rises by 0.25 °C per watt
+    for power in (50..150).step_by(5) {
+        let temperature = 30_000 + power * 250;
+        fs::write(
+            hwmon_path.join("power1_average"),
+            (power * 1_000_000).to_string(),
+        )
+        .unwrap();
+        fs::write(hwmon_path.join("temp1_input"), temperature.to_string()).unwrap();
+        handler.record_history().await;
+    }
+
+    let estimate = handler.estimate_power_cap(&gpu_id, 75.0).await.unwrap();
+    assert!((estimate.power_cap - 180.0).abs() < 1.0, "{estimate:?}");
+    assert!(
+        (estimate.degrees_per_watt - 0.25).abs() < 0.01,
+        "{estimate:?}"
+    );
+    assert!(!estimate.clamped);
+
+    // Limited to the maximum cap of 201 W
+    let estimate = handler.estimate_power_cap(&gpu_id, 90.0).await.unwrap();
+    assert!((estimate.power_cap - 201.0).abs() < 0.001, "{estimate:?}");
+    assert!(estimate.clamped);
+}
+
+#[tokio::test]
+async fn reapply_drifted_settings() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let gpu_config = GpuConfig {
+                fan_control_enabled: true,
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Static,
+                    static_speed: 0.5,
+                    ..Default::default()
+                }),
+                power_cap: Some(150.0),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            assert_eq!(0, handler.check_config_integrity().await);
+
+            // Simulate something else resetting the settings
+            fs::write(hw_mon_dir.join("power1_cap"), "100000000\n").unwrap();
+            fs::write(hw_mon_dir.join("pwm1_enable"), "2\n").unwrap();
+
+            assert_eq!(1, handler.check_config_integrity().await);
+            assert_eq!(
+                "150000000",
+                fs::read_to_string(hw_mon_dir.join("power1_cap"))
+                    .unwrap()
+                    .trim()
+            );
+            assert_eq!(
+                "1",
+                fs::read_to_string(hw_mon_dir.join("pwm1_enable"))
+                    .unwrap()
+                    .trim()
+            );
+
+            assert_eq!(0, handler.check_config_integrity().await);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn config_diff() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let device_dir = sysfs_dir.path().join("card0/device");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+            assert!(handler.get_config_diff(&gpu_id).await.unwrap().is_empty());
+
+            let gpu_config = GpuConfig {
+                power_cap: Some(150.0),
+                performance_level: Some(PerformanceLevel::High),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert!(handler.get_config_diff(&gpu_id).await.unwrap().is_empty());
+
+            fs::write(device_dir.join("hwmon/hwmon4/power1_cap"), "100000000\n").unwrap();
+            fs::write(
+                device_dir.join("power_dpm_force_performance_level"),
+                "auto\n",
+            )
+            .unwrap();
+
+            let expected = vec![
+                ConfigDifference {
+                    setting: "power_cap".to_owned(),
+                    stored: "150W".to_owned(),
+                    current: "100W".to_owned(),
+                },
+                ConfigDifference {
+                    setting: "performance_level".to_owned(),
+                    stored: "high".to_owned(),
+                    current: "auto".to_owned(),
+                },
+            ];
+            assert_eq!(expected, handler.get_config_diff(&gpu_id).await.unwrap());
+
+            // Clocks differences list the stored and the current values of the changed states
+            let od_table_path = device_dir.join("pp_od_clk_voltage");
+            let original_table = fs::read_to_string(&od_table_path).unwrap();
+            let gpu_config = GpuConfig {
+                clocks_configuration: ClocksConfiguration {
+                    max_core_clock: Some(1400),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            fs::write(&od_table_path, original_table).unwrap();
+
+            let differences = handler.get_config_diff(&gpu_id).await.unwrap();
+            assert_eq!(1, differences.len());
+            assert_eq!("clocks_table", differences[0].setting);
+            assert!(differences[0].stored.contains("1400"), "{differences:?}");
+            assert!(differences[0].current.contains("1366"), "{differences:?}");
+            assert!(!differences[0].current.contains("1400"), "{differences:?}");
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn config_diff_with_idle_lock() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            // The lock overrides the configured performance level, which is not a difference
+            let gpu_config = GpuConfig {
+                performance_level: Some(PerformanceLevel::High),
+                idle_lock: true,
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            let performance_level = fs::read_to_string(
+                sysfs_dir
+                    .path()
+                    .join("card0/device/power_dpm_force_performance_level"),
+            )
+            .unwrap();
+            assert_eq!("manual", performance_level.trim());
+            assert!(handler.get_config_diff(&gpu_id).await.unwrap().is_empty());
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn broadcast_to_model() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+            let pci_db = read_pci_db();
+
+            // One RX 580 and two RX 6900 XT cards
+            let sysfs_dir = tempdir().unwrap();
+            copy_dir(&data_dir.join("rx580"), sysfs_dir.path());
+            for card in ["card1", "card2"] {
+                copy_dir(
+                    &data_dir.join("rx6900xt/card0"),
+                    &sysfs_dir.path().join(card),
+                );
+            }
+            let uevent_path = sysfs_dir.path().join("card2/device/uevent");
+            let uevent = fs::read_to_string(&uevent_path).unwrap();
+            fs::write(&uevent_path, uevent.replace("0000:0c:00.0", "0000:0d:00.0")).unwrap();
+
+            let read_power_cap = |card: &str| {
+                let path = sysfs_dir
+                    .path()
+                    .join(card)
+                    .join("device/hwmon/hwmon5/power1_cap");
+                fs::read_to_string(path).unwrap().trim().to_owned()
+            };
+
+            let handler = Handler::with_base_path(sysfs_dir.path(), Config::default(), &pci_db)
+                .await
+                .unwrap();
+            let devices = handler.list_devices().await;
+            assert_eq!(3, devices.len());
+            let first_id = "1002:73BF-1DA2:440E-0000:0c:00.0";
+            let second_id = "1002:73BF-1DA2:440E-0000:0d:00.0";
+
+            let changes = GpuConfig {
+                power_cap: Some(250.0),
+                ..Default::default()
+            };
+            let broadcast = handler
+                .broadcast_to_model("1002:73bf", changes)
+                .await
+                .unwrap();
+            assert_eq!(Some(5), broadcast.apply_timer);
+            assert_eq!(2, broadcast.gpus.len());
+            assert_eq!(BroadcastOutcome::Applied, broadcast.gpus[first_id]);
+            assert_eq!(BroadcastOutcome::Applied, broadcast.gpus[second_id]);
+
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            assert_eq!("250000000", read_power_cap("card1"));
+            assert_eq!("250000000", read_power_cap("card2"));
+            for device in &devices {
+                let gpu_config = handler.get_gpu_config(&device.id).await.unwrap();
+                if device.id.starts_with("1002:73BF") {
+                    assert_eq!(Some(250.0), gpu_config.unwrap().power_cap);
+                } else {
+                    assert_eq!(None, gpu_config);
+                }
+            }
+
+            // Only the settings in the changes are replaced
+            let changes = GpuConfig {
+                performance_level: Some(PerformanceLevel::High),
+                ..Default::default()
+            };
+            handler
+                .broadcast_to_model("1002:73BF", changes)
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            for id in [first_id, second_id] {
+                let gpu_config = handler.get_gpu_config(id).await.unwrap().unwrap();
+                assert_eq!(Some(PerformanceLevel::High), gpu_config.performance_level);
+                assert_eq!(Some(250.0), gpu_config.power_cap);
+            }
+
+            // A card without power cap support is skipped, without stopping the other one
+            let hw_mon_dir = sysfs_dir.path().join("card2/device/hwmon/hwmon5");
+            fs::remove_file(hw_mon_dir.join("power1_cap_max")).unwrap();
+
+            let changes = GpuConfig {
+                power_cap: Some(200.0),
+                ..Default::default()
+            };
+            let broadcast = handler
+                .broadcast_to_model("1002:73BF", changes)
+                .await
+                .unwrap();
+            assert_eq!(BroadcastOutcome::Applied, broadcast.gpus[first_id]);
+            assert_eq!(
+                BroadcastOutcome::Skipped {
+                    unsupported: vec!["power_cap".to_owned()]
+                },
+                broadcast.gpus[second_id]
+            );
+            assert_eq!("200000000", read_power_cap("card1"));
+            assert_eq!("250000000", read_power_cap("card2"));
+
+            // Reverting covers all of the GPUs that were changed
+            handler
+                .confirm_pending_config(ConfirmCommand::Revert)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!("250000000", read_power_cap("card1"));
+
+            // LEDs which the cards don't have are not supported
+            let changes = GpuConfig {
+                leds: [("missing".to_owned(), LedSettings::default())]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            };
+            let broadcast = handler
+                .broadcast_to_model("1002:73BF", changes)
+                .await
+                .unwrap();
+            assert_eq!(None, broadcast.apply_timer);
+            assert_eq!(
+                BroadcastOutcome::Skipped {
+                    unsupported: vec!["leds".to_owned()]
+                },
+                broadcast.gpus[first_id]
+            );
+
+            // The idle lock can be turned off again
+            for idle_lock in [true, false] {
+                let changes = GpuConfig {
+                    idle_lock,
+                    ..Default::default()
+                };
+                handler
+                    .broadcast_to_model("1002:73BF", changes)
+                    .await
+                    .unwrap();
+                handler
+                    .confirm_pending_config(ConfirmCommand::Confirm)
+                    .unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                let gpu_config = handler.get_gpu_config(first_id).await.unwrap().unwrap();
+                assert_eq!(idle_lock, gpu_config.idle_lock);
+            }
+
+            let err = handler
+                .broadcast_to_model("10DE:2704", GpuConfig::default())
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::GpuNotFound), ErrorKind::from_error(&err));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn swapped_gpu_slots() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+            let pci_db = read_pci_db();
+
+            let sysfs_dir = tempdir().unwrap();
+            copy_dir(&data_dir.join("rx580"), sysfs_dir.path());
+            copy_dir(
+                &data_dir.join("rx6900xt/card0"),
+                &sysfs_dir.path().join("card1"),
+            );
+
+            let rx580_id = "1002:67DF-1DA2:E387-0000:09:00.0";
+            let rx6900xt_id = "1002:73BF-1DA2:440E-0000:0c:00.0";
+            let power_cap = |cap| GpuConfig {
+                power_cap: Some(cap),
+                ..Default::default()
+            };
+
+            let mut config = Config::default();
+            let gpus = config.gpus_mut().unwrap();
+            gpus.insert(rx580_id.to_owned(), power_cap(100.0));
+            gpus.insert(rx6900xt_id.to_owned(), power_cap(250.0));
+            let profile = Profile {
+                gpus: IndexMap::from([(rx580_id.to_owned(), power_cap(90.0))]),
+                ..Default::default()
+            };
+            config.profiles.insert("quiet".into(), profile);
+
+            // The cards are swapped between the slots
+            for (card, old_slot, new_slot) in [
+                ("card0", "0000:09:00.0", "0000:0c:00.0"),
+                ("card1", "0000:0c:00.0", "0000:09:00.0"),
+            ] {
+                let uevent_path = sysfs_dir.path().join(card).join("device/uevent");
+                let uevent = fs::read_to_string(&uevent_path).unwrap();
+                fs::write(&uevent_path, uevent.replace(old_slot, new_slot)).unwrap();
+            }
+
+            let handler = Handler::with_base_path(sysfs_dir.path(), config, &pci_db)
+                .await
+                .unwrap();
+            let new_rx580_id = "1002:67DF-1DA2:E387-0000:0c:00.0";
+            let new_rx6900xt_id = "1002:73BF-1DA2:440E-0000:09:00.0";
+
+            let gpu_config = handler.get_gpu_config(new_rx580_id).await.unwrap();
+            assert_eq!(Some(100.0), gpu_config.unwrap().power_cap);
+            let gpu_config = handler.get_gpu_config(new_rx6900xt_id).await.unwrap();
+            assert_eq!(Some(250.0), gpu_config.unwrap().power_cap);
+
+            let read_power_cap = |card: &str, hw_mon: &str| {
+                let path = sysfs_dir
+                    .path()
+                    .join(format!("{card}/device/hwmon/{hw_mon}/power1_cap"));
+                fs::read_to_string(path).unwrap().trim().to_owned()
+            };
+            assert_eq!("100000000", read_power_cap("card0", "hwmon4"));
+            assert_eq!("250000000", read_power_cap("card1", "hwmon5"));
+
+            let config = handler.config.read().await;
+            assert!(!config.has_stored_gpu_config(rx580_id));
+            assert!(!config.has_stored_gpu_config(rx6900xt_id));
+            let profile = config.profile("quiet").unwrap();
+            assert_eq!(Some(90.0), profile.gpus[new_rx580_id].power_cap);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn management_disabled() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+            let read_file = |name: &str| {
+                let contents = fs::read_to_string(hw_mon_dir.join(name)).unwrap();
+                contents.trim().to_owned()
+            };
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let gpu_config = GpuConfig {
+                fan_control_enabled: true,
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Static,
+                    static_speed: 0.5,
+                    ..Default::default()
+                }),
+                power_cap: Some(150.0),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!("150000000", read_file("power1_cap"));
+
+            // The GPU goes back to the default cap
+            handler.set_management_enabled(false).await.unwrap();
+            assert_eq!("155000000", read_file("power1_cap"));
+
+            // Nothing gets written while management is disabled
+            fs::write(hw_mon_dir.join("power1_cap"), "100000000\n").unwrap();
+            fs::write(hw_mon_dir.join("pwm1_enable"), "2\n").unwrap();
+
+            handler.apply_current_config().await.unwrap();
+            assert_eq!(0, handler.check_config_integrity().await);
+            handler.check_thermal_protection().await;
+            assert!(handler
+                .set_gpu_config(&gpu_id, GpuConfig::default())
+                .await
+                .is_err());
+
+            assert_eq!("100000000", read_file("power1_cap"));
+            assert_eq!("2", read_file("pwm1_enable"));
+
+            // Stats are still available
+            let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+            assert_eq!(Some(100.0), stats.power.cap.current);
+
+            handler.set_management_enabled(true).await.unwrap();
+            assert_eq!("150000000", read_file("power1_cap"));
+            assert_eq!("1", read_file("pwm1_enable"));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn settings_deferred_during_thermal_protection() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+            let read_pwm = || fs::read_to_string(hw_mon_dir.join("pwm1")).unwrap();
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            // The GPU is at 44°C
+            let gpu_config = |temperature| GpuConfig {
+                fan_control_enabled: true,
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Static,
+                    static_speed: 0.2,
+                    ..Default::default()
+                }),
+                thermal_protection: Some(ThermalProtection {
+                    temperature,
+                    hysteresis: 5.0,
+                    action: ThermalProtectionAction::MaxFanSpeed,
+                }),
+                ..Default::default()
+            };
+            handler
+                .set_gpu_config(&gpu_id, gpu_config(40.0))
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            handler.check_thermal_protection().await;
+            assert_eq!("255", read_pwm().trim());
+
+            // New fan settings are stored, but the fan stays at full speed until the GPU cools down
+            handler
+                .set_gpu_config(&gpu_id, gpu_config(60.0))
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!("255", read_pwm().trim());
+            let stored_config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(
+                Some(60.0),
+                stored_config.thermal_protection.map(|tp| tp.temperature)
+            );
+
+            handler.check_thermal_protection().await;
+            assert_eq!("51", read_pwm().trim());
+
+            handler.cleanup().await;
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn profile_change_command() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let log_path = sysfs_dir.path().join("profile_changes");
+
+            let mut config = Config::default();
+            config.daemon.on_profile_change = Some(format!(
+                "echo \"switched to '$1'\" >> {}",
+                log_path.display()
+            ));
+            let (handler, _) = test_handler(sysfs_dir.path(), config).await;
+
+            handler
+                .create_profile("quiet".to_owned(), ProfileBase::Empty)
+                .await
+                .unwrap();
+            assert!(!log_path.exists());
+
+            handler
+                .set_profile(Some("quiet".into()), false)
+                .await
+                .unwrap();
+            handler.set_profile(None, false).await.unwrap();
+            assert_eq!(
+                "switched to 'quiet'\nswitched to ''\n",
+                fs::read_to_string(&log_path).unwrap()
+            );
+
+            // A failing command is only logged
+            handler.config.write().await.daemon.on_profile_change = Some("exit 3".to_owned());
+            handler
+                .set_profile(Some("quiet".into()), false)
+                .await
+                .unwrap();
+            assert_eq!(
+                Some("quiet"),
+                handler.config.read().await.current_profile.as_deref()
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn event_log() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+            assert!(handler.get_event_log(None).is_empty());
+
+            // The GPU is at 44°C
+            let gpu_config = GpuConfig {
+                thermal_protection: Some(ThermalProtection {
+                    temperature: 40.0,
+                    hysteresis: 5.0,
+                    action: ThermalProtectionAction::MaxFanSpeed,
+                }),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            handler.check_thermal_protection().await;
+
+            handler
+                .set_gpu_config(&gpu_id, GpuConfig::default())
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Revert)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            handler
+                .create_profile("gaming".to_owned(), ProfileBase::Empty)
+                .await
+                .unwrap();
+            handler
+                .set_profile(Some("gaming".into()), false)
+                .await
+                .unwrap();
+
+            let events = handler.get_event_log(None);
+            let kinds: Vec<DaemonEventKind> =
+                events.iter().map(|event| event.kind.clone()).collect();
+            assert_eq!(
+                vec![
+                    DaemonEventKind::SettingsApplied {
+                        gpu_id: gpu_id.clone(),
+                    },
+                    DaemonEventKind::ThermalProtectionTriggered {
+                        gpu_id: gpu_id.clone(),
+                        temperature: 44.0,
+                    },
+                    DaemonEventKind::SettingsApplied {
+                        gpu_id: gpu_id.clone(),
+                    },
+                    DaemonEventKind::SettingsReverted {
+                        gpu_ids: vec![gpu_id.clone()],
+                        timed_out: false,
+                    },
+                    DaemonEventKind::ProfileSwitched {
+                        profile: Some("gaming".to_owned()),
+                    },
+                ],
+                kinds
+            );
+
+            let last_timestamp = events.last().unwrap().timestamp;
+            assert!(handler
+                .get_event_log(Some(last_timestamp))
+                .iter()
+                .all(|event| event.timestamp >= last_timestamp));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn measure_fan_response() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+            let read_file = |name: &str| {
+                let contents = fs::read_to_string(hw_mon_dir.join(name)).unwrap();
+                contents.trim().to_owned()
+            };
+
+            // Fan which covers half of the distance to 10 RPM per PWM step on every update
+            let fan_dir = hw_mon_dir.clone();
+            let fan_task = tokio::task::spawn_local(async move {
+                let mut rpm: u32 = 595;
+                loop {
+                    let pwm = fs::read_to_string(fan_dir.join("pwm1")).unwrap();
+                    let target_rpm = pwm.trim().parse::<u32>().unwrap() * 10;
+                    rpm = (rpm + target_rpm) / 2;
+                    fs::write(fan_dir.join("fan1_input"), format!("{rpm}\n")).unwrap();
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+            });
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let response = handler
+                .measure_fan_response(&gpu_id, 50, 200)
+                .await
+                .unwrap();
+            assert!(response.initial_rpm.abs_diff(500) <= 20, "{response:?}");
+            assert!(response.final_rpm.abs_diff(2000) <= 40, "{response:?}");
+            assert!(response.settle_time_ms < 5000, "{response:?}");
+
+            // The fan goes back to automatic control, as there are no stored fan settings
+            assert_eq!("2", read_file("pwm1_enable"));
+            assert_eq!(None, handler.get_gpu_config(&gpu_id).await.unwrap());
+
+            // The measurement is refused while thermal protection is in effect
+            let gpu_config = GpuConfig {
+                thermal_protection: Some(ThermalProtection {
+                    temperature: 40.0,
+                    hysteresis: 5.0,
+                    action: ThermalProtectionAction::MaxFanSpeed,
+                }),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            handler.check_thermal_protection().await;
+
+            let err = handler
+                .measure_fan_response(&gpu_id, 50, 200)
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::HardwareBusy), ErrorKind::from_error(&err));
+
+            fan_task.abort();
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn cancel_fan_response_measurement() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+            let read_file = |name: &str| {
+                let contents = fs::read_to_string(hw_mon_dir.join(name)).unwrap();
+                contents.trim().to_owned()
+            };
+
+            // Fan whose speed never settles, so the measurement only ends when it gets cancelled
+            let fan_dir = hw_mon_dir.clone();
+            let fan_task = tokio::task::spawn_local(async move {
+                for rpm in [500, 1500].into_iter().cycle() {
+                    fs::write(fan_dir.join("fan1_input"), format!("{rpm}\n")).unwrap();
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+            });
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+            assert!(handler.list_operations().is_empty());
+
+            let measurement = tokio::task::spawn_local({
+                let handler = handler.clone();
+                let gpu_id = gpu_id.clone();
+                async move { handler.measure_fan_response(&gpu_id, 50, 200).await }
+            });
+            tokio::time::sleep(Duration::from_millis(300)).await;
+
+            let operations = handler.list_operations();
+            assert_eq!(1, operations.len());
+            assert_eq!(OperationKind::FanResponseMeasurement, operations[0].kind);
+            assert_eq!(gpu_id, operations[0].gpu_id);
+            assert_eq!("1", read_file("pwm1_enable"));
+
+            handler.cancel_operation(operations[0].id).unwrap();
+            let err = tokio::time::timeout(Duration::from_secs(5), measurement)
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap_err();
+            assert!(format!("{err:#}").contains("cancelled"), "{err:#}");
+
+            // The stepping stopped and the fan is back under automatic control
+            assert!(handler.list_operations().is_empty());
+            assert_eq!("2", read_file("pwm1_enable"));
+            assert!(handler.cancel_operation(operations[0].id).is_err());
+
+            fan_task.abort();
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn thermal_protection_stops_fan_response_measurement() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+            let read_file = |name: &str| {
+                let contents = fs::read_to_string(hw_mon_dir.join(name)).unwrap();
+                contents.trim().to_owned()
+            };
+
+            let fan_dir = hw_mon_dir.clone();
+            let fan_task = tokio::task::spawn_local(async move {
+                for rpm in [500, 1500].into_iter().cycle() {
+                    fs::write(fan_dir.join("fan1_input"), format!("{rpm}\n")).unwrap();
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+            });
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+            let gpu_config = GpuConfig {
+                thermal_protection: Some(ThermalProtection {
+                    temperature: 90.0,
+                    hysteresis: 5.0,
+                    action: ThermalProtectionAction::MaxFanSpeed,
+                }),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            let measurement = tokio::task::spawn_local({
+                let handler = handler.clone();
+                let gpu_id = gpu_id.clone();
+                async move { handler.measure_fan_response(&gpu_id, 50, 200).await }
+            });
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            assert_eq!(1, handler.list_operations().len());
+
+            // The settings of the GPU are left alone while its fan is stepped
+            let err = handler
+                .set_power_cap(&gpu_id, Some(100.0))
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::HardwareBusy), ErrorKind::from_error(&err));
+
+            fs::write(hw_mon_dir.join("temp1_input"), "95000\n").unwrap();
+            handler.check_thermal_protection().await;
+
+            let err = tokio::time::timeout(Duration::from_secs(5), measurement)
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::HardwareBusy), ErrorKind::from_error(&err));
+
+            // The fan stays at the full speed set by thermal protection
+            assert!(handler.list_operations().is_empty());
+            assert_eq!("1", read_file("pwm1_enable"));
+            assert_eq!(read_file("pwm1_max"), read_file("pwm1"));
+
+            fan_task.abort();
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn cancel_pending_confirmation() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            handler
+                .set_performance_level(&gpu_id, PerformanceLevel::Low)
+                .await
+                .unwrap();
+            let operations = handler.list_operations();
+            assert_eq!(1, operations.len());
+            assert_eq!(OperationKind::PendingConfirmation, operations[0].kind);
+            assert_eq!(gpu_id, operations[0].gpu_id);
+
+            handler.cancel_operation(operations[0].id).unwrap();
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while !handler.list_operations().is_empty() {
+                    tokio::task::yield_now().await;
+                }
+            })
+            .await
+            .unwrap();
+
+            // Cancelling works like the timer running out
+            let config = handler.config.read().await;
+            let gpu_config = config.gpus().unwrap().get(&gpu_id).cloned();
+            assert_eq!(None, gpu_config.and_then(|config| config.performance_level));
+            drop(config);
+            assert!(handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .is_err());
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn set_gpu_alias() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let device_data_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+            let pci_db = read_pci_db();
+
+            let handler = Handler::with_base_path(&device_data_dir, Config::default(), &pci_db)
+                .await
+                .unwrap();
+            let gpu_id = handler.list_devices().await[0].id.clone();
+
+            handler
+                .set_gpu_alias(&gpu_id, Some(" Render Card ".to_owned()))
+                .await
+                .unwrap();
+            let entry = handler.list_devices().await.remove(0);
+            assert_eq!(Some("Render Card"), entry.alias.as_deref());
+            assert_eq!("Render Card", entry.to_string());
+
+            // The alias is a part of the stored config
+            let raw_config = serde_yml::to_string(&*handler.config.read().await).unwrap();
+            let config: Config = serde_yml::from_str(&raw_config).unwrap();
+            assert_eq!(
+                Some("Render Card"),
+                config.gpu_aliases.get(&gpu_id).map(String::as_str)
+            );
+
+            // An empty alias clears it
+            handler
+                .set_gpu_alias(&gpu_id, Some(String::new()))
+                .await
+                .unwrap();
+            assert_eq!(None, handler.list_devices().await[0].alias);
+            assert!(handler.config.read().await.gpu_aliases.is_empty());
+
+            let err = handler
+                .set_gpu_alias("missing", Some("GPU".to_owned()))
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::GpuNotFound), ErrorKind::from_error(&err));
+        })
+        .await;
+}
+
+// The clock is paused so that the warm-up timers fire in a fixed order relative to the test
+#[tokio::test(start_paused = true)]
+async fn delayed_settings_on_resume() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+            let read_file = |name: &str| {
+                let contents = fs::read_to_string(hw_mon_dir.join(name)).unwrap();
+                contents.trim().to_owned()
+            };
+            // The GPU comes back from suspend with its default settings
+            let reset_gpu = || {
+                fs::write(hw_mon_dir.join("power1_cap"), "155000000\n").unwrap();
+                fs::write(hw_mon_dir.join("pwm1_enable"), "2\n").unwrap();
+            };
+
+            let mut config = Config::default();
+            config.daemon.resume_warm_up_ms = 200;
+            let gpu_config = GpuConfig {
+                fan_control_enabled: true,
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Static,
+                    static_speed: 0.5,
+                    ..Default::default()
+                }),
+                power_cap: Some(120.0),
+                delayed_on_resume: vec![ResumeDelayedSetting::PowerCap],
+                ..Default::default()
+            };
+            let gpu_id = "1002:67DF-1DA2:E387-0000:09:00.0";
+            config
+                .gpus_mut()
+                .unwrap()
+                .insert(gpu_id.to_owned(), gpu_config);
+
+            // Everything is applied right away on startup
+            let (handler, _) = test_handler(sysfs_dir.path(), config).await;
+            assert_eq!("120000000", read_file("power1_cap"));
+
+            reset_gpu();
+            handler.reload_gpus_on_resume().await;
+            assert_eq!("1", read_file("pwm1_enable"));
+            assert_eq!("155000000", read_file("power1_cap"));
+
+            // Another event during the warm-up period starts it over
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            reset_gpu();
+            handler.reload_gpus_on_resume().await;
+            assert_eq!("1", read_file("pwm1_enable"));
+
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            assert_eq!("155000000", read_file("power1_cap"));
+
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            assert_eq!("120000000", read_file("power1_cap"));
+            assert_eq!("1", read_file("pwm1_enable"));
+
+            // Regular reloads apply everything at once
+            reset_gpu();
+            handler.reload_gpus().await;
+            assert_eq!("120000000", read_file("power1_cap"));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn validate_limits() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx7900xtx");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let power_cap = |cap| GpuConfig {
+                power_cap: Some(cap),
+                ..Default::default()
+            };
+            let clocks = |clocks_configuration| GpuConfig {
+                clocks_configuration,
+                ..Default::default()
+            };
+            let voltage_offset = |offset| {
+                clocks(ClocksConfiguration {
+                    voltage_offset: Some(offset),
+                    ..Default::default()
+                })
+            };
+            let max_core_clock = |clockspeed| {
+                clocks(ClocksConfiguration {
+                    max_core_clock: Some(clockspeed),
+                    ..Default::default()
+                })
+            };
+
+            // The card allows a power cap of 261-333W, a voltage offset of -450-0mV
+            // and a maximum core clock of 500-5000MHz
+            let cases = [
+                (
+                    power_cap(200.0),
+                    Some("Power cap 200W is below the minimum of 261W"),
+                ),
+                (
+                    power_cap(400.0),
+                    Some("Power cap 400W is above the maximum of 333W"),
+                ),
+                (power_cap(300.0), None),
+                (
+                    voltage_offset(-500),
+                    Some("Voltage offset -500mV is below the minimum of -450mV"),
+                ),
+                (
+                    voltage_offset(50),
+                    Some("Voltage offset 50mV is above the maximum of 0mV"),
+                ),
+                (voltage_offset(-100), None),
+                (
+                    max_core_clock(400),
+                    Some("Maximum core clock 400MHz is below the minimum of 500MHz"),
+                ),
+                (
+                    max_core_clock(6000),
+                    Some("Maximum core clock 6000MHz is above the maximum of 5000MHz"),
+                ),
+                (max_core_clock(3000), None),
+            ];
+
+            for (gpu_config, expected_error) in cases {
+                let result = handler.set_gpu_config(&gpu_id, gpu_config.clone()).await;
+                match expected_error {
+                    Some(expected_error) => {
+                        let err = result.unwrap_err();
+                        assert_eq!(Some(ErrorKind::OutOfRange), ErrorKind::from_error(&err));
+                        let err = format!("{err:#}");
+                        assert!(err.contains(expected_error), "unexpected error: {err}");
+                    }
+                    None => {
+                        result.unwrap_or_else(|err| panic!("{gpu_config:?} failed: {err:#}"));
+                        handler
+                            .confirm_pending_config(ConfirmCommand::Confirm)
+                            .unwrap();
+                    }
+                }
+            }
+
+            // A stored value outside of the reported limits is left to the driver,
+            // so that other settings can still be changed
+            handler
+                .config
+                .write()
+                .await
+                .gpus_mut()
+                .unwrap()
+                .insert(gpu_id.clone(), power_cap(245.0));
+            let mut gpu_config = power_cap(245.0);
+            gpu_config.clocks_configuration.max_core_clock = Some(3000);
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn firmware_fan_curve_temperature_key() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx7900xtx");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let gpu_config = GpuConfig {
+                fan_control_enabled: true,
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Curve,
+                    temperature_key: "junction".to_owned(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let err = handler
+                .set_gpu_config(&gpu_id, gpu_config)
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::Unsupported), ErrorKind::from_error(&err));
+            assert!(format!("{err:#}").contains("junction"), "{err:#}");
+            assert_eq!(None, handler.get_gpu_config(&gpu_id).await.unwrap());
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn gpu_hotplug() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+            let pci_db = read_pci_db();
+
+            let sysfs_dir = tempdir().unwrap();
+            copy_dir(&data_dir.join("rx580"), sysfs_dir.path());
+            let read_file = |path: &str| {
+                let contents = fs::read_to_string(sysfs_dir.path().join(path)).unwrap();
+                contents.trim().to_owned()
+            };
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let gpu_config = GpuConfig {
+                fan_control_enabled: true,
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Curve,
+                    interval_ms: 50,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            handler.record_history().await;
+
+            // The eGPU already has settings stored from a previous connection
+            let egpu_id = "1002:73BF-1DA2:440E-0000:0c:00.0";
+            let egpu_config = GpuConfig {
+                power_cap: Some(250.0),
+                ..Default::default()
+            };
+            handler
+                .config
+                .write()
+                .await
+                .gpus_mut()
+                .unwrap()
+                .insert(egpu_id.to_owned(), egpu_config);
+
+            copy_dir(
+                &data_dir.join("rx6900xt/card0"),
+                &sysfs_dir.path().join("card1"),
+            );
+            handler.reload_gpus().await;
+
+            let devices = handler.list_devices().await;
+            assert_eq!(2, devices.len());
+            assert!(devices.iter().any(|device| device.id == egpu_id));
+            assert_eq!(
+                "250000000",
+                read_file("card1/device/hwmon/hwmon5/power1_cap")
+            );
+            assert_eq!("1", read_file("card0/device/hwmon/hwmon4/pwm1_enable"));
+
+            // The files of a removed GPU may briefly stay around, but it can no longer be loaded
+            fs::remove_file(sysfs_dir.path().join("card0/device/uevent")).unwrap();
+            handler.reload_gpus().await;
+
+            let devices = handler.list_devices().await;
+            assert_eq!(1, devices.len());
+            assert_eq!(egpu_id, devices[0].id);
+            assert!(handler.get_history(&gpu_id, &[]).await.is_err());
+
+            // The fan control task was stopped and has not written anything since
+            assert_eq!("2", read_file("card0/device/hwmon/hwmon4/pwm1_enable"));
+            let pwm_path = sysfs_dir.path().join("card0/device/hwmon/hwmon4/pwm1");
+            fs::write(pwm_path, "17\n").unwrap();
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            assert_eq!("17", read_file("card0/device/hwmon/hwmon4/pwm1"));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn first_run_fan_curve() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+            let pci_db = read_pci_db();
+
+            let sysfs_dir = tempdir().unwrap();
+            copy_dir(&data_dir.join("rx580"), sysfs_dir.path());
+
+            let mut config = Config::default();
+            config.daemon.first_run_fan_curve = true;
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), config).await;
+
+            // A GPU without stored settings gets the balanced curve on startup
+            let curve = handler
+                .generate_fan_curve(&gpu_id, FanCurvePreset::Balanced)
+                .await
+                .unwrap();
+            let gpu_config = handler.config.read().await.gpus().unwrap()[&gpu_id].clone();
+            assert!(gpu_config.fan_control_enabled);
+            let settings = gpu_config.fan_control_settings.clone().unwrap();
+            assert_eq!(FanControlMode::Curve, settings.mode);
+            assert_eq!(curve, settings.curve);
+
+            // Settings stored from a previous connection are kept as they are
+            let egpu_id = "1002:73BF-1DA2:440E-0000:0c:00.0";
+            let egpu_config = GpuConfig {
+                power_cap: Some(250.0),
+                ..Default::default()
+            };
+            handler
+                .config
+                .write()
+                .await
+                .gpus_mut()
+                .unwrap()
+                .insert(egpu_id.to_owned(), egpu_config.clone());
+
+            copy_dir(
+                &data_dir.join("rx6900xt/card0"),
+                &sysfs_dir.path().join("card1"),
+            );
+            handler.reload_gpus().await;
+            assert_eq!(2, handler.list_devices().await.len());
+
+            let config = handler.config.read().await;
+            let gpus = config.gpus().unwrap();
+            assert_eq!(egpu_config, gpus[egpu_id]);
+            assert_eq!(gpu_config, gpus[&gpu_id]);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn force_pcie_level() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let data_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx7900xtx");
+            let pci_db = read_pci_db();
+
+            // Writes are not persisted by the mock fs, so the fixture reports the forced level as active
+            let device_dir = tempdir().unwrap();
+            copy_dir(&data_dir, device_dir.path());
+            let pcie_levels_path = device_dir.path().join("card0/device/pp_dpm_pcie");
+            fs::write(
+                &pcie_levels_path,
+                "0: 16.0GT/s, x16 78Mhz\n1: 16.0GT/s, x16 156Mhz *\n2: 16.0GT/s, x16 623Mhz\n",
+            )
+            .unwrap();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir.path().to_path_buf());
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            let gpu_config = GpuConfig {
+                performance_level: Some(PerformanceLevel::Manual),
+                pcie_level: Some(1),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+            assert_eq!(Some(1), stats.pcie_level);
+
+            // The level is not reported when the hardware is running at a different one
+            fs::write(
+                &pcie_levels_path,
+                "0: 16.0GT/s, x16 78Mhz *\n1: 16.0GT/s, x16 156Mhz\n2: 16.0GT/s, x16 623Mhz\n",
+            )
+            .unwrap();
+            let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+            assert_eq!(None, stats.pcie_level);
+
+            let err = handler.set_pcie_level(&gpu_id, Some(3)).await.unwrap_err();
+            assert!(format!("{err:#}").contains("PCIe level 3 is not available"));
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            let pcie_writes = writes
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(name, _)| name.ends_with("pp_dpm_pcie"))
+                .map(|(_, contents)| contents.clone())
+                .collect::<Vec<String>>();
+            // The second write comes from reverting to the previous config after the invalid level was rejected
+            assert_eq!(vec!["1".to_owned(), "1".to_owned()], pcie_writes);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn idle_lock() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx6900xt");
+            let pci_db = read_pci_db();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir);
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            // Core clock levels which were forced before the lock
+            let gpu_config = GpuConfig {
+                performance_level: Some(PerformanceLevel::Manual),
+                power_states: IndexMap::from([(PowerLevelKind::CoreClock, vec![1])]),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            for enabled in [true, false] {
+                handler.set_idle_lock(&gpu_id, enabled).await.unwrap();
+                handler
+                    .confirm_pending_config(ConfirmCommand::Confirm)
+                    .unwrap();
+            }
+
+            let gpu_config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert!(!gpu_config.idle_lock);
+            assert_eq!(
+                Some(&vec![1]),
+                gpu_config.power_states.get(&PowerLevelKind::CoreClock)
+            );
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            let writes = writes.lock().unwrap();
+            let file_writes = |file_name: &str| {
+                writes
+                    .iter()
+                    .filter(|(name, _)| name.ends_with(file_name))
+                    .map(|(_, contents)| contents.trim().to_owned())
+                    .collect::<Vec<String>>()
+            };
+            assert_eq!(vec!["1", "0", "1"], file_writes("pp_dpm_sclk"));
+            // Memory clock levels are re-enabled once the lock is removed
+            assert_eq!(vec!["0 1 2 3", "0", "0 1 2 3"], file_writes("pp_dpm_mclk"));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn apply_settings_order() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx6900xt");
+            let pci_db = read_pci_db();
+
+            // Clocks can only be changed after switching to the manual performance level
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir).with_write_filter(|writes, path, _| {
+                !path.ends_with("pp_od_clk_voltage")
+                    || writes
+                        .iter()
+                        .rev()
+                        .find(|(name, _)| name.ends_with("power_dpm_force_performance_level"))
+                        .is_some_and(|(_, contents)| contents.contains("manual"))
+            });
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            let clocks_configuration = ClocksConfiguration {
+                max_core_clock: Some(2600),
+                ..Default::default()
+            };
+
+            let gpu_config = GpuConfig {
+                performance_level: Some(PerformanceLevel::Auto),
+                clocks_configuration: clocks_configuration.clone(),
+                ..Default::default()
+            };
+            let err = handler
+                .set_gpu_config(&gpu_id, gpu_config)
+                .await
+                .unwrap_err();
+            let message = format!("{err:#}");
+            assert!(message.contains("Could not apply clocks and voltage settings"));
+            assert!(message.contains("completed steps: [performance level]"));
+
+            let gpu_config = GpuConfig {
+                performance_level: Some(PerformanceLevel::Manual),
+                clocks_configuration,
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            let writes = writes.lock().unwrap();
+            let manual_position = writes
+                .iter()
+                .position(|(_, contents)| contents == "manual")
+                .unwrap();
+            let clocks_writes = writes[manual_position..]
+                .iter()
+                .filter(|(path, _)| path.ends_with("pp_od_clk_voltage"))
+                .map(|(_, contents)| contents.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(vec!["r\n", "s 1 2600\n", "c\n"], clocks_writes);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn force_memory_clock_level() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx7900xtx");
+            let pci_db = read_pci_db();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir);
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            let power_states = handler.get_power_states(&gpu_id).await.unwrap();
+            assert_eq!(4, power_states.vram.len());
+
+            let gpu_config = GpuConfig {
+                performance_level: Some(PerformanceLevel::Manual),
+                memory_clock_level: Some(3),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+            assert_eq!(Some(3), stats.memory_clock_level);
+
+            handler.set_memory_clock_level(&gpu_id, None).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            let err = handler
+                .set_memory_clock_level(&gpu_id, Some(4))
+                .await
+                .unwrap_err();
+            assert!(format!("{err:#}").contains("Memory clock level 4 is not available"));
+
+            // Configured power states are kept when no level is forced
+            let gpu_config = GpuConfig {
+                performance_level: Some(PerformanceLevel::Manual),
+                power_states: IndexMap::from([(PowerLevelKind::MemoryClock, vec![0, 1])]),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            let mclk_writes = writes
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(name, _)| name.ends_with("pp_dpm_mclk"))
+                .map(|(_, contents)| contents.clone())
+                .collect::<Vec<String>>();
+            // Reverting after the invalid level was rejected leaves the levels alone,
+            // since the previous config did not force one
+            assert_eq!(
+                vec!["3".to_owned(), "0 1 2 3".to_owned(), "0 1".to_owned()],
+                mclk_writes
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn select_power_profile_mode() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+            let pci_db = read_pci_db();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir);
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+            assert_eq!(Some("3D_FULL_SCREEN"), stats.power_profile_mode.as_deref());
+
+            let gpu_config = GpuConfig {
+                performance_level: Some(PerformanceLevel::Manual),
+                power_profile_mode_index: Some(3),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            let err = handler
+                .set_power_profile_mode(&gpu_id, Some(9), vec![])
+                .await
+                .unwrap_err();
+            assert!(format!("{err:#}").contains("Power profile mode 9 is not available"));
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            let mode_writes = writes
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(name, _)| name.ends_with("pp_power_profile_mode"))
+                .map(|(_, contents)| contents.trim().to_owned())
+                .collect::<Vec<String>>();
+            // The second write comes from reverting to the previous config after the invalid mode was rejected
+            assert_eq!(vec!["3".to_owned(), "3".to_owned()], mode_writes);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn performance_level() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            fs::write(
+                sysfs_dir
+                    .path()
+                    .join("card0/device/power_dpm_force_performance_level"),
+                "profile_peak\n",
+            )
+            .unwrap();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(sysfs_dir.path().to_owned());
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+            assert_eq!(None, stats.performance_level);
+            assert_eq!(
+                Some("profile_peak"),
+                stats.profiling_performance_level.as_deref()
+            );
+
+            let capabilities = handler.get_capabilities(&gpu_id).await.unwrap();
+            assert!(capabilities.performance_level);
+            assert_eq!(
+                vec![
+                    PerformanceLevel::Auto,
+                    PerformanceLevel::Low,
+                    PerformanceLevel::High,
+                    PerformanceLevel::Manual,
+                ],
+                capabilities.performance_levels
+            );
+
+            // The profiling level gets replaced even though it can't be parsed
+            handler
+                .set_performance_level(&gpu_id, PerformanceLevel::Manual)
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            let config = handler.config.read().await;
+            let gpu_config = config.gpus().unwrap().get(&gpu_id).unwrap();
+            assert_eq!(Some(PerformanceLevel::Manual), gpu_config.performance_level);
+            drop(config);
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            let level_writes = writes
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(name, _)| name.ends_with("power_dpm_force_performance_level"))
+                .map(|(_, contents)| contents.trim().to_owned())
+                .collect::<Vec<String>>();
+            assert_eq!(vec!["manual".to_owned()], level_writes);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn performance_levels_without_dpm_levels() {
+    init_tracing();
+
+    let sysfs_dir = copy_test_device("amd/rx580");
+    fs::remove_file(sysfs_dir.path().join("card0/device/pp_dpm_sclk")).unwrap();
+
+    let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+    let capabilities = handler.get_capabilities(&gpu_id).await.unwrap();
+    assert!(capabilities.performance_level);
+    assert_eq!(
+        vec![
+            PerformanceLevel::Auto,
+            PerformanceLevel::Low,
+            PerformanceLevel::High,
+        ],
+        capabilities.performance_levels
+    );
+}
+
+#[tokio::test]
+async fn set_led() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let led_dir = sysfs_dir.path().join("card0/device/leds/amdgpu:rgb:status");
+            fs::create_dir_all(&led_dir).unwrap();
+            for (file_name, contents) in [
+                ("brightness", "0\n"),
+                ("max_brightness", "255\n"),
+                ("multi_index", "red green blue\n"),
+                ("multi_intensity", "255 255 255\n"),
+            ] {
+                fs::write(led_dir.join(file_name), contents).unwrap();
+            }
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(sysfs_dir.path().to_owned());
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            let capabilities = handler.get_capabilities(&gpu_id).await.unwrap();
+            assert_eq!(1, capabilities.leds.len());
+            let led = &capabilities.leds[0];
+            assert_eq!("amdgpu:rgb:status", led.name);
+            assert_eq!(255, led.max_brightness);
+            assert_eq!(vec!["red", "green", "blue"], led.color_channels);
+
+            let settings = |brightness, color: Option<Vec<u32>>| {
+                Some(LedSettings {
+                    brightness: Some(brightness),
+                    color,
+                })
+            };
+
+            let err = handler
+                .set_led(&gpu_id, "amdgpu:white:logo", settings(255, None))
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::Unsupported), ErrorKind::from_error(&err));
+            let err = handler
+                .set_led(&gpu_id, "amdgpu:rgb:status", settings(256, None))
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::OutOfRange), ErrorKind::from_error(&err));
+            let err = handler
+                .set_led(
+                    &gpu_id,
+                    "amdgpu:rgb:status",
+                    settings(255, Some(vec![255, 0])),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::OutOfRange), ErrorKind::from_error(&err));
+
+            handler
+                .set_led(
+                    &gpu_id,
+                    "amdgpu:rgb:status",
+                    settings(128, Some(vec![255, 0, 64])),
+                )
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            // Removing the settings restores the state from before they were applied
+            handler
+                .set_led(&gpu_id, "amdgpu:rgb:status", None)
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            let led_writes = writes
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(path, _)| {
+                    path.parent()
+                        .is_some_and(|dir| dir.ends_with("amdgpu:rgb:status"))
+                })
+                .map(|(path, contents)| {
+                    let file_name = path.file_name().unwrap().to_string_lossy();
+                    (file_name.into_owned(), contents.trim().to_owned())
+                })
+                .collect::<Vec<(String, String)>>();
+            assert_eq!(
+                vec![
+                    ("multi_intensity".to_owned(), "255 0 64".to_owned()),
+                    ("brightness".to_owned(), "128".to_owned()),
+                    ("multi_intensity".to_owned(), "255 255 255".to_owned()),
+                    ("brightness".to_owned(), "0".to_owned()),
+                ],
+                led_writes
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn set_fan_static() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+            let read_file = |name: &str| {
+                let contents = fs::read_to_string(hw_mon_dir.join(name)).unwrap();
+                contents.trim().to_owned()
+            };
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let gpu_config = GpuConfig {
+                fan_control_settings: Some(FanControlSettings {
+                    max_pwm: Some(200),
+                    interval_ms: 50,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            let err = handler.set_fan_static(&gpu_id, 220).await.unwrap_err();
+            assert_eq!(Some(ErrorKind::OutOfRange), ErrorKind::from_error(&err));
+
+            handler.set_fan_static(&gpu_id, 128).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            assert_eq!("1", read_file("pwm1_enable"));
+            assert_eq!("128", read_file("pwm1"));
+
+            // The value is held while the stats keep getting polled
+            for _ in 0..3 {
+                handler.get_gpu_stats(&gpu_id).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            assert_eq!("128", read_file("pwm1"));
+
+            let gpu_config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert!(gpu_config.fan_control_enabled);
+            let settings = gpu_config.fan_control_settings.unwrap();
+            assert_eq!(FanControlMode::Static, settings.mode);
+            assert_eq!(Some(200), settings.max_pwm);
+
+            // Switching back to the curve hands the fan back to it
+            let curve = [(40, 0.4), (60, 0.4)]
+                .map(|(temp, speed)| (CurveTemperature::from(temp), speed))
+                .into();
+            let opts = FanOptions {
+                id: &gpu_id,
+                enabled: true,
+                mode: Some(FanControlMode::Curve),
+                curve: Some(curve),
+                ..Default::default()
+            };
+            handler.set_fan_control(opts).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while read_file("pwm1") != "102" {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+            })
+            .await
+            .expect("the curve should set the fan speed again");
+            assert_eq!("1", read_file("pwm1_enable"));
+            let gpu_config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            let settings = gpu_config.fan_control_settings.unwrap();
+            assert_eq!(FanControlMode::Curve, settings.mode);
+            assert_eq!(Some(200), settings.max_pwm);
+
+            // Disabling the fan control gives it back to the firmware
+            let opts = FanOptions {
+                id: &gpu_id,
+                enabled: false,
+                ..Default::default()
+            };
+            handler.set_fan_control(opts).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            assert_eq!("2", read_file("pwm1_enable"));
+
+            handler.cleanup().await;
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn fan_zero_rpm() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+            let pci_db = read_pci_db();
+
+            // GPUs without the firmware fan control interface
+            let sysfs_dir = tempdir().unwrap();
+            copy_dir(&data_dir.join("rx580"), sysfs_dir.path());
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+            let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+            assert_eq!(None, stats.fan.pmfw_info.zero_rpm_enable);
+            let err = handler.set_fan_zero_rpm(&gpu_id, true).await.unwrap_err();
+            assert!(format!("{err:#}").contains("not supported"));
+
+            // A pmfw-capable card which exposes the zero RPM toggle
+            let device_dir = tempdir().unwrap();
+            copy_dir(&data_dir.join("rx7900xtx"), device_dir.path());
+            fs::write(
+                device_dir
+                    .path()
+                    .join("card0/device/gpu_od/fan_ctrl/fan_zero_rpm_enable"),
+                "FAN_ZERO_RPM_ENABLE:\n0\nOD_RANGE:\nZERO_RPM_ENABLE: 0 1\n",
+            )
+            .unwrap();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir.path().to_owned());
+            let writes = mock_fs.writes.clone();
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+            let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+            assert_eq!(Some(false), stats.fan.pmfw_info.zero_rpm_enable);
+
+            handler.set_fan_zero_rpm(&gpu_id, true).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            let zero_rpm = handler.config.read().await.gpus().unwrap()[&gpu_id]
+                .pmfw_options
+                .zero_rpm;
+            assert_eq!(Some(true), zero_rpm);
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            let zero_rpm_writes = writes
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(name, _)| name.ends_with("fan_zero_rpm_enable"))
+                .map(|(_, contents)| contents.trim().to_owned())
+                .collect::<Vec<String>>();
+            // The value is followed by the commit
+            assert_eq!("1", zero_rpm_writes[0]);
+        })
+        .await;
+}
+
+async fn apply_custom_heuristics(
+    device: &str,
+    heuristics: Vec<Vec<Option<i32>>>,
+) -> (anyhow::Result<u64>, Vec<String>) {
+    let device_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/tests/data/amd")
+        .join(device);
+    let pci_db = read_pci_db();
+
+    let mock_fs_dir = tempdir().unwrap();
+    let mock_fs = MockSysfs::new(device_dir);
+    let writes = mock_fs.writes.clone();
+
+    let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+        .expect("Could not mount mock fs");
+
+    let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+    handler
+        .set_performance_level(&gpu_id, PerformanceLevel::Manual)
+        .await
+        .unwrap();
+    handler
+        .confirm_pending_config(ConfirmCommand::Confirm)
+        .unwrap();
+    let result = handler
+        .set_power_profile_mode(&gpu_id, Some(6), heuristics)
+        .await;
+    if result.is_ok() {
+        handler
+            .confirm_pending_config(ConfirmCommand::Confirm)
+            .unwrap();
+    }
+
+    mount.join();
+    mock_fs_dir.close().unwrap();
+
+    let mode_writes = writes
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(name, _)| name.ends_with("pp_power_profile_mode"))
+        .map(|(_, contents)| contents.clone())
+        .collect();
+    (result, mode_writes)
+}
+
+fn contains_values(writes: &[String], values: &[i32]) -> bool {
+    let values: Vec<String> = values.iter().map(i32::to_string).collect();
+    writes.iter().any(|write| {
+        let tokens: Vec<&str> = write.split_whitespace().collect();
+        tokens.windows(values.len()).any(|window| window == values)
+    })
+}
+
+#[tokio::test]
+async fn set_custom_power_profile_heuristics() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            // Single row layout
+            let values = [55, 60, 1, 3];
+            let (result, writes) =
+                apply_custom_heuristics("vega56", vec![values.map(Some).to_vec()]).await;
+            result.unwrap();
+            assert!(contains_values(&writes, &values), "{writes:?}");
+
+            // Per clock type layout with a different set of columns
+            let gfx_values = [0, 0, 1100, 4, 0, 655_360, -3276, -65536];
+            let fclk_values = [0, 3, 0, 3, 0, 1_310_720, -6553, -6553];
+            let (result, writes) = apply_custom_heuristics(
+                "rx7900xtx",
+                vec![
+                    gfx_values.map(Some).to_vec(),
+                    fclk_values.map(Some).to_vec(),
+                ],
+            )
+            .await;
+            result.unwrap();
+            assert!(contains_values(&writes, &gfx_values), "{writes:?}");
+
+            // Using the column count of a different GPU family should be rejected
+            let (result, writes) =
+                apply_custom_heuristics("rx7900xtx", vec![values.map(Some).to_vec()]).await;
+            let err = result.unwrap_err();
+            assert!(format!("{err:#}").contains("expects 2 heuristics components, got 1"));
+            assert!(!contains_values(&writes, &values));
+
+            let (result, _) =
+                apply_custom_heuristics("vega56", vec![gfx_values.map(Some).to_vec()]).await;
+            let err = result.unwrap_err();
+            assert!(format!("{err:#}").contains("expects 4 values"));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn set_clock_states() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/vega56");
+            let pci_db = read_pci_db();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir);
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            let states = IndexMap::from([(
+                3,
+                ClockVoltagePoint {
+                    clockspeed: 1300,
+                    voltage: 1010,
+                },
+            )]);
+            handler
+                .set_clock_states(&gpu_id, PowerLevelKind::CoreClock, states.clone())
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(states, config.clocks_configuration.core_clock_states);
+
+            let out_of_range_states = IndexMap::from([(
+                7,
+                ClockVoltagePoint {
+                    clockspeed: 2500,
+                    voltage: 1200,
+                },
+            )]);
+            let err = handler
+                .set_clock_states(&gpu_id, PowerLevelKind::CoreClock, out_of_range_states)
+                .await
+                .unwrap_err();
+            assert!(format!("{err:#}").contains("outside of the allowed range 852-2400"));
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            let writes = writes.lock().unwrap();
+            assert!(writes.iter().any(|(name, contents)| {
+                name.ends_with("pp_od_clk_voltage") && contents == "s 3 1300 1010\n"
+            }));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn set_single_clock_state() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/vega56");
+            let pci_db = read_pci_db();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir);
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            let set_state = |index, point| {
+                let handler = handler.clone();
+                let gpu_id = gpu_id.clone();
+                async move {
+                    let result = handler
+                        .set_clock_state(&gpu_id, PowerLevelKind::CoreClock, index, point)
+                        .await;
+                    if result.is_ok() {
+                        handler
+                            .confirm_pending_config(ConfirmCommand::Confirm)
+                            .unwrap();
+                    }
+                    result
+                }
+            };
+            let low_state = ClockVoltagePoint {
+                clockspeed: 1000,
+                voltage: 850,
+            };
+            // Undervolting only the top state
+            let top_state = ClockVoltagePoint {
+                clockspeed: 1590,
+                voltage: 1100,
+            };
+
+            set_state(1, Some(low_state)).await.unwrap();
+            set_state(7, Some(top_state)).await.unwrap();
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(
+                IndexMap::from([(1, low_state), (7, top_state)]),
+                config.clocks_configuration.core_clock_states
+            );
+
+            let too_low = ClockVoltagePoint {
+                clockspeed: 1590,
+                voltage: 700,
+            };
+            let err = set_state(7, Some(too_low)).await.unwrap_err();
+            assert!(format!("{err:#}").contains("outside of the allowed range 800-1200"));
+
+            // Removing the override of a single state
+            set_state(1, None).await.unwrap();
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(
+                IndexMap::from([(7, top_state)]),
+                config.clocks_configuration.core_clock_states
+            );
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            // All the other states are written with the values that were read from the GPU
+            let original_states = [
+                "s 0 852 800",
+                "s 2 1138 950",
+                "s 3 1269 1000",
+                "s 4 1312 1050",
+                "s 5 1474 1100",
+                "s 6 1538 1150",
+            ];
+            let writes = writes.lock().unwrap();
+            let state_writes = writes
+                .iter()
+                .filter(|(name, _)| name.ends_with("pp_od_clk_voltage"))
+                .map(|(_, contents)| contents.trim())
+                .filter(|contents| contents.starts_with("s "))
+                .collect::<Vec<&str>>();
+            assert!(state_writes.contains(&"s 1 1000 850"));
+            assert!(state_writes.contains(&"s 7 1590 1100"));
+            assert!(!state_writes
+                .iter()
+                .any(|contents| contents.ends_with(" 700")));
+            for contents in &state_writes {
+                let index = contents.split(' ').nth(1).unwrap();
+                if index != "1" && index != "7" {
+                    assert!(original_states.contains(contents), "{contents}");
+                }
+            }
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn dry_run_apply() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/vega56");
+            let pci_db = read_pci_db();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir);
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let mut config = Config::default();
+            config.daemon.dry_run = true;
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), config).await;
+
+            let gpu_config = GpuConfig {
+                fan_control_enabled: true,
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Static,
+                    static_speed: 0.5,
+                    ..Default::default()
+                }),
+                power_cap: Some(150.0),
+                performance_level: Some(PerformanceLevel::Manual),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            let planned_writes = handler
+                .get_dry_run_log(&gpu_id)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|write| {
+                    let path = write
+                        .path
+                        .strip_prefix(mock_fs_dir.path().to_str().unwrap())
+                        .unwrap()
+                        .to_owned();
+                    (path, write.value)
+                })
+                .collect::<Vec<_>>();
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            assert!(writes.lock().unwrap().is_empty());
+            // The writes are recorded by the regular apply steps, in the order they happen in
+            assert_eq!(
+                vec![
+                    (
+                        "/card0/device/power_dpm_force_performance_level".to_owned(),
+                        "manual".to_owned()
+                    ),
+                    ("/card0/device/pp_od_clk_voltage".to_owned(), "r".to_owned()),
+                    (
+                        "/card0/device/hwmon/hwmon4/power1_cap".to_owned(),
+                        "150000000".to_owned()
+                    ),
+                    (
+                        "/card0/device/hwmon/hwmon4/pwm1_enable".to_owned(),
+                        "1".to_owned()
+                    ),
+                    (
+                        "/card0/device/hwmon/hwmon4/pwm1".to_owned(),
+                        "127".to_owned()
+                    ),
+                ],
+                planned_writes
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn set_power_cap_percent() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let cap_path = sysfs_dir
+                .path()
+                .join("card0/device/hwmon/hwmon4/power1_cap");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            // The default cap is 155W and the max is 201W
+            handler.set_power_cap_percent(&gpu_id, 80.0).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!("124000000", fs::read_to_string(&cap_path).unwrap().trim());
+
+            let stats = handler.get_gpu_stats(&gpu_id).await.unwrap();
+            assert_eq!(Some(124.0), stats.power.cap.current);
+            assert_eq!(Some(80.0), stats.power.cap_percent);
+
+            handler.set_power_cap_percent(&gpu_id, 150.0).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!("201000000", fs::read_to_string(&cap_path).unwrap().trim());
+
+            let err = handler
+                .set_power_cap_percent(&gpu_id, 5.0)
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::OutOfRange), ErrorKind::from_error(&err));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn set_voltage_offset() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx7900xtx");
+            let pci_db = read_pci_db();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir);
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            // The reported offset range is -450mV to 0mV
+            handler
+                .set_voltage_offset(&gpu_id, Some(-600))
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(Some(-450), config.clocks_configuration.voltage_offset);
+
+            handler.set_voltage_offset(&gpu_id, Some(50)).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(Some(0), config.clocks_configuration.voltage_offset);
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            let table_writes = writes
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(name, _)| name.ends_with("pp_od_clk_voltage"))
+                .map(|(_, contents)| contents.trim().to_owned())
+                .collect::<Vec<String>>();
+
+            let offset_index = table_writes
+                .iter()
+                .position(|contents| contents == "vo -450")
+                .expect("Voltage offset was not written");
+            assert!(
+                table_writes[offset_index..]
+                    .iter()
+                    .any(|contents| contents == "c"),
+                "Voltage offset was not committed: {table_writes:?}"
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn voltage_offset_unsupported() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+            let pci_db = read_pci_db();
+
+            let handler = Handler::with_base_path(&device_dir, Config::default(), &pci_db)
+                .await
+                .unwrap();
+            let gpu_id = handler.list_devices().await[0].id.clone();
+
+            let err = handler
+                .set_voltage_offset(&gpu_id, Some(-50))
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::Unsupported), ErrorKind::from_error(&err));
+
+            // The offset is reported without an allowed range, which is not made up
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx6900xt");
+            let handler = Handler::with_base_path(&device_dir, Config::default(), &pci_db)
+                .await
+                .unwrap();
+            let gpu_id = handler.list_devices().await[0].id.clone();
+
+            let capabilities = handler.get_capabilities(&gpu_id).await.unwrap();
+            assert_eq!(None, capabilities.voltage_offset);
+            let err = handler
+                .set_voltage_offset(&gpu_id, Some(-50))
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::Unsupported), ErrorKind::from_error(&err));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn amd_memory_clock_offset() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx7900xtx");
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir);
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            // The offset moves the stock maximum memory clock of 1250 MHz within its range of 97-1500 MHz
+            let capabilities = handler.get_capabilities(&gpu_id).await.unwrap();
+            assert_eq!(
+                IndexMap::from([(0, ValueRange::from((-1153, 250)))]),
+                capabilities.mem_clock_offsets
+            );
+
+            let err = handler
+                .set_memory_clock_offset(&gpu_id, Some(300))
+                .await
+                .unwrap_err();
+            assert!(format!("{err:#}").contains("above the maximum of 250MHz"));
+
+            handler
+                .set_memory_clock_offset(&gpu_id, Some(100))
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(
+                IndexMap::from([(0, 100)]),
+                config.clocks_configuration.mem_clock_offsets
+            );
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            let writes = writes.lock().unwrap();
+            assert!(writes.iter().any(|(name, contents)| {
+                name.ends_with("pp_od_clk_voltage") && contents.trim() == "m 1 1350"
+            }));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn firmware_fan_curve() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+            let pci_db = read_pci_db();
+
+            let handler =
+                Handler::with_base_path(&data_dir.join("rx7900xtx"), Config::default(), &pci_db)
+                    .await
+                    .unwrap();
+            let gpu_id = handler.list_devices().await[0].id.clone();
+            let curve = handler.get_firmware_fan_curve(&gpu_id).await.unwrap();
+            assert_eq!(
+                FanCurve::from([(40, 0.25), (50, 0.4), (60, 0.6), (70, 0.8), (80, 1.0)]),
+                curve
+            );
+
+            // Older generations only have the hwmon fan control
+            let handler =
+                Handler::with_base_path(&data_dir.join("rx580"), Config::default(), &pci_db)
+                    .await
+                    .unwrap();
+            let gpu_id = handler.list_devices().await[0].id.clone();
+            let err = handler.get_firmware_fan_curve(&gpu_id).await.unwrap_err();
+            assert_eq!(Some(ErrorKind::Unsupported), ErrorKind::from_error(&err));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn driver_and_firmware_versions() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+            let pci_db = read_pci_db();
+
+            let sysfs_dir = tempdir().unwrap();
+            copy_dir(&data_dir.join("rx7900xtx"), sysfs_dir.path());
+            copy_dir(
+                &data_dir.join("rx6900xt/card0"),
+                &sysfs_dir.path().join("card1"),
+            );
+            for card in ["card0", "card1"] {
+                let device_dir = sysfs_dir.path().join(card).join("device");
+                fs::create_dir_all(device_dir.join("driver/module")).unwrap();
+                fs::write(device_dir.join("driver/module/version"), "6.10.5\n").unwrap();
+
+                let fw_dir = device_dir.join("fw_version");
+                fs::create_dir(&fw_dir).unwrap();
+                fs::write(fw_dir.join("smc_fw_version"), "0x00504500\n").unwrap();
+                fs::write(fw_dir.join("mec_fw_version"), "0x000001f4\n").unwrap();
+            }
+
+            let handler = Handler::with_base_path(sysfs_dir.path(), Config::default(), &pci_db)
+                .await
+                .unwrap();
+            let devices = handler.list_devices().await;
+            let rdna3_id = &devices
+                .iter()
+                .find(|device| device.id.starts_with("1002:744C"))
+                .unwrap()
+                .id;
+            let rdna2_id = "1002:73BF-1DA2:440E-0000:0c:00.0";
+
+            let versions = handler.get_versions(rdna3_id).await.unwrap();
+            assert!(!versions.kernel_version.is_empty());
+            assert_eq!("amdgpu", versions.driver);
+            assert_eq!(Some("6.10.5"), versions.driver_version.as_deref());
+            assert_eq!(Some("113-D70401XT-N11"), versions.vbios_version.as_deref());
+            assert_eq!(Some("80.69.0"), versions.pmfw_version.as_deref());
+            assert_eq!(
+                Some("0x00504500"),
+                versions.firmware.get("smc").map(String::as_str)
+            );
+            assert_eq!(
+                Some("0x000001f4"),
+                versions.firmware.get("mec").map(String::as_str)
+            );
+
+            // The SMC firmware version is still listed, but there is no PMFW interface
+            let versions = handler.get_versions(rdna2_id).await.unwrap();
+            assert_eq!(None, versions.pmfw_version);
+            assert_eq!(2, versions.firmware.len());
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn capabilities_match_device_nodes() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+            let pci_db = read_pci_db();
+
+            let handler =
+                Handler::with_base_path(&data_dir.join("rx7900xtx"), Config::default(), &pci_db)
+                    .await
+                    .unwrap();
+            let gpu_id = handler.list_devices().await[0].id.clone();
+            let capabilities = handler.get_capabilities(&gpu_id).await.unwrap();
+
+            assert_eq!(
+                Some(ValueRange::from((261.0, 333.0))),
+                capabilities.power_cap
+            );
+            assert_eq!(
+                Some(ValueRange::from((500, 5000))),
+                capabilities.max_core_clock
+            );
+            assert_eq!(
+                Some(ValueRange::from((97, 1500))),
+                capabilities.max_memory_clock
+            );
+            assert_eq!(
+                Some(ValueRange::from((-450, 0))),
+                capabilities.voltage_offset
+            );
+            assert_eq!(
+                Some(ValueRange::from((25, 100))),
+                capabilities.fan_curve_temperature
+            );
+            assert_eq!(
+                Some(ValueRange::from((15, 100))),
+                capabilities.fan_curve_speed
+            );
+            assert_eq!(Some(5), capabilities.fan_curve_points);
+            assert!(capabilities.fan_control);
+            assert!(capabilities.performance_level);
+            assert!(capabilities.power_profile_mode);
+
+            // Older GPU without the pmfw fan curve or a voltage offset
+            let handler =
+                Handler::with_base_path(&data_dir.join("rx580"), Config::default(), &pci_db)
+                    .await
+                    .unwrap();
+            let gpu_id = handler.list_devices().await[0].id.clone();
+            let capabilities = handler.get_capabilities(&gpu_id).await.unwrap();
+
+            assert_eq!(Some(ValueRange::from((0.0, 201.0))), capabilities.power_cap);
+            assert_eq!(
+                Some(ValueRange::from((750, 1200))),
+                capabilities.max_voltage
+            );
+            assert_eq!(None, capabilities.voltage_offset);
+            assert_eq!(None, capabilities.fan_curve_speed);
+            assert_eq!(None, capabilities.fan_curve_points);
+            assert!(capabilities.fan_control);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn revert_voltage_offset_on_reset() {
+    init_tracing();
+
+    let local_set = LocalSet::new();
+    local_set
+        .run_until(async move {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx7900xtx");
+            let pci_db = read_pci_db();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir);
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            handler
+                .set_voltage_offset(&gpu_id, Some(-100))
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            // Resets of other GPUs are ignored
+            handler.handle_gpu_reset("0000:0b:00.0").await;
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(Some(-100), config.clocks_configuration.voltage_offset);
+
+            handler.handle_gpu_reset("0000:03:00.0").await;
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(None, config.clocks_configuration.voltage_offset);
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn stage_settings_confirm() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let cap_path = sysfs_dir
+                .path()
+                .join("card0/device/hwmon/hwmon4/power1_cap");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let config = GpuConfig {
+                power_cap: Some(120.0),
+                ..Default::default()
+            };
+            let staged = handler
+                .stage_settings(&gpu_id, config, Some(1))
+                .await
+                .unwrap();
+            assert_eq!(1, staged.timeout_secs);
+            assert_eq!("120000000", fs::read_to_string(&cap_path).unwrap().trim());
+
+            // Only the client that staged the settings can confirm them
+            let err = handler
+                .confirm_staged_settings(Some(staged.token + 1))
+                .unwrap_err();
+            assert_eq!(
+                Some(ErrorKind::PermissionDenied),
+                ErrorKind::from_error(&err)
+            );
+            handler.confirm_staged_settings(Some(staged.token)).unwrap();
+
+            // Going past the timeout should not revert confirmed settings
+            tokio::time::advance(Duration::from_secs(2)).await;
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!("120000000", fs::read_to_string(&cap_path).unwrap().trim());
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(Some(120.0), config.power_cap);
+
+            // Nothing is reverted when the connection that staged it goes away afterwards
+            handler.revert_staged_settings(staged.token);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!("120000000", fs::read_to_string(&cap_path).unwrap().trim());
+        })
+        .await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn stage_settings_timeout_revert() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let cap_path = sysfs_dir
+                .path()
+                .join("card0/device/hwmon/hwmon4/power1_cap");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let config = GpuConfig {
+                power_cap: Some(120.0),
+                ..Default::default()
+            };
+            handler
+                .stage_settings(&gpu_id, config, Some(1))
+                .await
+                .unwrap();
+            assert_eq!("120000000", fs::read_to_string(&cap_path).unwrap().trim());
+
+            tokio::time::advance(Duration::from_secs(2)).await;
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!("155000000", fs::read_to_string(&cap_path).unwrap().trim());
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(None, config.power_cap);
+
+            let err = handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap_err();
+            assert_eq!("No pending config changes", err.to_string());
+
+            let err = handler
+                .stage_settings(&gpu_id, GpuConfig::default(), Some(0))
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::OutOfRange), ErrorKind::from_error(&err));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn stage_settings_revert_on_disconnect() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let cap_path = sysfs_dir
+                .path()
+                .join("card0/device/hwmon/hwmon4/power1_cap");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let (client, server) = tokio::io::duplex(4096);
+            let stream_task = tokio::task::spawn_local(handle_stream(server, handler.clone()));
+
+            let request = Request::StageSettings {
+                id: &gpu_id,
+                config: GpuConfig {
+                    power_cap: Some(120.0),
+                    ..Default::default()
+                },
+                timeout_secs: Some(30),
+            };
+            let mut client = BufReader::new(client);
+            let mut payload = serde_json::to_string(&request).unwrap();
+            payload.push('\n');
+            client.write_all(payload.as_bytes()).await.unwrap();
+
+            let mut response = String::new();
+            client.read_line(&mut response).await.unwrap();
+            assert_eq!(r#"{"status":"ok","data":30}"#, response.trim_end());
+            assert_eq!("120000000", fs::read_to_string(&cap_path).unwrap().trim());
+
+            drop(client);
+            stream_task.await.unwrap().unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            assert_eq!("155000000", fs::read_to_string(&cap_path).unwrap().trim());
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(None, config.power_cap);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn concurrent_clients() {
+    async fn send_request(
+        client: &mut BufReader<tokio::io::DuplexStream>,
+        request: &Request<'_>,
+    ) -> serde_json::Value {
+        let mut payload = serde_json::to_string(request).unwrap();
+        payload.push('\n');
+        client.write_all(payload.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        client.read_line(&mut response).await.unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    /// Retries the change while the other client's change is waiting for confirmation
+    async fn change_settings(handler: Handler, request: Request<'_>) {
+        let (client, server) = tokio::io::duplex(4096);
+        let stream_task = tokio::task::spawn_local(handle_stream(server, handler));
+        let mut client = BufReader::new(client);
+
+        loop {
+            let response = send_request(&mut client, &request).await;
+            if response["status"] == "ok" {
+                break;
+            }
+            let error = response["data"].to_string();
+            assert!(
+                error.contains("unconfirmed configuration change"),
+                "{error}"
+            );
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let confirm = Request::ConfirmPendingConfig(ConfirmCommand::Confirm);
+        let response = send_request(&mut client, &confirm).await;
+        assert_eq!("ok", response["status"]);
+
+        drop(client);
+        stream_task.await.unwrap().unwrap();
+    }
+
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let power_cap_request = Request::SetPowerCap {
+                id: &gpu_id,
+                cap: Some(120.0),
+            };
+            let performance_level_request = Request::SetPerformanceLevel {
+                id: &gpu_id,
+                performance_level: PerformanceLevel::Low,
+            };
+            futures::future::join(
+                change_settings(handler.clone(), power_cap_request),
+                change_settings(handler.clone(), performance_level_request),
+            )
+            .await;
+
+            let config = handler.get_gpu_config(&gpu_id).await.unwrap().unwrap();
+            assert_eq!(Some(120.0), config.power_cap);
+            assert_eq!(Some(PerformanceLevel::Low), config.performance_level);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn setting_provenance() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let (client, server) = UnixStream::pair().unwrap();
+            let stream_task = tokio::task::spawn_local(handle_unix_stream(server, handler.clone()));
+            let mut client = BufReader::new(client);
+
+            let requests = [
+                Request::SetPowerCap {
+                    id: &gpu_id,
+                    cap: Some(120.0),
+                },
+                Request::ConfirmPendingConfig(ConfirmCommand::Confirm),
+                // Reverted changes are not recorded
+                Request::SetPerformanceLevel {
+                    id: &gpu_id,
+                    performance_level: PerformanceLevel::High,
+                },
+                Request::ConfirmPendingConfig(ConfirmCommand::Revert),
+                Request::GetSettingProvenance { id: &gpu_id },
+            ];
+            let mut response = String::new();
+            for request in requests {
+                let mut payload = serde_json::to_string(&request).unwrap();
+                payload.push('\n');
+                client.write_all(payload.as_bytes()).await.unwrap();
+
+                response.clear();
+                client.read_line(&mut response).await.unwrap();
+            }
+            drop(client);
+            stream_task.await.unwrap().unwrap();
+
+            let response: Response<Vec<SettingProvenance>> =
+                serde_json::from_str(&response).unwrap();
+            let Response::Ok(provenance) = response else {
+                panic!("unexpected response: {response:?}");
+            };
+            assert_eq!(1, provenance.len());
+            assert_eq!("set_power_cap", provenance[0].request);
+            assert!(provenance[0].timestamp > 0);
+
+            let client = provenance[0].client.unwrap();
+            assert_eq!(i32::try_from(std::process::id()).ok(), client.pid);
+            assert_eq!(getuid().as_raw(), client.uid);
+            assert_eq!(getgid().as_raw(), client.gid);
+
+            // Changes made without a socket connection have no known client
+            handler.record_setting_provenance(&gpu_id, "set_power_cap", None);
+            let provenance = handler.get_setting_provenance(&gpu_id).await.unwrap();
+            assert_eq!(None, provenance[0].client);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn read_only_client() {
+    async fn send(
+        handler: &Handler,
+        request: &Request<'_>,
+        mut connection: Connection,
+    ) -> Response<serde_json::Value> {
+        let payload = serde_json::to_string(request).unwrap();
+        let response = handle_payload(&payload, handler, &mut connection)
+            .await
+            .unwrap();
+        serde_json::from_slice(&response).unwrap()
+    }
+
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let power_cap_path = sysfs_dir
+                .path()
+                .join("card0/device/hwmon/hwmon4/power1_cap");
+            let read_power_cap = || fs::read_to_string(&power_cap_path).unwrap();
+            let initial_power_cap = read_power_cap();
+
+            let group = Group::from_gid(getgid()).unwrap().unwrap();
+            let mut config = Config::default();
+            config.daemon.control_group = Some(group.name);
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), config).await;
+
+            let stats = Request::DeviceStats { id: &gpu_id };
+            let set_power_cap = Request::SetPowerCap {
+                id: &gpu_id,
+                cap: Some(120.0),
+            };
+            let revert = Request::ConfirmPendingConfig(ConfirmCommand::Revert);
+
+            let unprivileged = ClientCredentials {
+                pid: None,
+                uid: 65534,
+                gid: 65534,
+            };
+            let unprivileged = || Connection::with_peer(Some(unprivileged));
+            let response = send(&handler, &stats, unprivileged()).await;
+            assert!(matches!(response, Response::Ok(_)), "{response:?}");
+
+            let response = send(&handler, &set_power_cap, unprivileged()).await;
+            let Response::Error(err) = response else {
+                panic!("unexpected response: {response:?}");
+            };
+            assert_eq!(Some(ErrorKind::PermissionDenied), err.kind);
+            assert_eq!(initial_power_cap, read_power_cap());
+
+            // The group of the client grants access
+            let group_member = ClientCredentials {
+                gid: getgid().as_raw(),
+                ..unprivileged
+            };
+            let response = send(
+                &handler,
+                &set_power_cap,
+                Connection::with_peer(Some(group_member)),
+            )
+            .await;
+            assert!(matches!(response, Response::Ok(_)), "{response:?}");
+            assert_eq!("120000000", read_power_cap().trim());
+
+            let response = send(&handler, &revert, unprivileged()).await;
+            assert!(matches!(response, Response::Error(_)), "{response:?}");
+
+            // Clients which can't be identified are denied instead of being let through
+            for connection in [Connection::with_peer(None), Connection::tcp()] {
+                let response = send(&handler, &revert, connection).await;
+                let Response::Error(err) = response else {
+                    panic!("unexpected response: {response:?}");
+                };
+                assert_eq!(Some(ErrorKind::PermissionDenied), err.kind);
+            }
+            let response = send(&handler, &stats, Connection::tcp()).await;
+            assert!(matches!(response, Response::Ok(_)), "{response:?}");
+
+            // In-process callers check access on their own
+            let response = send(&handler, &revert, Connection::default()).await;
+            assert!(matches!(response, Response::Ok(_)), "{response:?}");
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn profile_fan_curve_switch() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+            let read_pwm = || fs::read_to_string(hw_mon_dir.join("pwm1")).unwrap();
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+            create_fan_profiles(&handler, &gpu_id).await;
+
+            handler
+                .set_profile(Some("quiet".into()), false)
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!("51", read_pwm().trim());
+
+            handler
+                .set_profile(Some("gaming".into()), false)
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!("204", read_pwm().trim());
+
+            // A config reload applies the profile without resetting the controller first,
+            // so the running task picks up the new curve in place
+            handler.config.write().await.current_profile = Some("quiet".into());
+            handler.apply_current_config().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!("51", read_pwm().trim());
+            assert_eq!(
+                "1",
+                fs::read_to_string(hw_mon_dir.join("pwm1_enable"))
+                    .unwrap()
+                    .trim()
+            );
+
+            handler.cleanup().await;
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn fan_curve_change_applied_immediately() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+            let read_pwm = || fs::read_to_string(hw_mon_dir.join("pwm1")).unwrap();
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+
+            let gpu_config = |speed| GpuConfig {
+                fan_control_enabled: true,
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Curve,
+                    interval_ms: 60_000,
+                    curve: FanCurve::from([(40, speed), (60, speed)]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            handler
+                .set_gpu_config(&gpu_id, gpu_config(0.2))
+                .await
+                .unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            // Nothing is written before the first interval runs out
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!("35", read_pwm().trim());
+
+            handler
+                .config
+                .write()
+                .await
+                .gpus_mut()
+                .unwrap()
+                .insert(gpu_id.clone(), gpu_config(0.8));
+            handler.apply_current_config().await.unwrap();
+
+            // The running task evaluates the new curve without waiting for the interval
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!("204", read_pwm().trim());
+
+            // Applying the same curve again does not trigger another evaluation
+            fs::write(hw_mon_dir.join("pwm1"), "100").unwrap();
+            handler.apply_current_config().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!("100", read_pwm().trim());
+
+            handler.cleanup().await;
+        })
+        .await;
+}
+
+/// Collects the formatted log output, so that the spans of the messages can be checked
+#[derive(Clone, Default)]
+struct LogCapture(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for LogCapture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn fan_control_error_logged_in_gpu_span() {
+    let capture = LogCapture::default();
+    let writer = capture.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    let _tracing_guard = tracing::subscriber::set_default(subscriber);
+
+    LocalSet::new()
+        .run_until(async {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+            let pci_db = read_pci_db();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir).with_read_only_paths(&["hwmon4/pwm1"]);
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            let gpu_config = GpuConfig {
+                fan_control_enabled: true,
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Curve,
+                    interval_ms: 10,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+
+            // The write fails in the fan control task, which runs separately from the request
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            handler.cleanup().await;
+            mount.join();
+
+            let logs = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+            let error_line = logs
+                .lines()
+                .find(|line| line.contains("could not set fan speed"))
+                .unwrap_or_else(|| panic!("fan speed error was not logged:\n{logs}"));
+            assert!(
+                error_line.contains(&format!("gpu{{id={gpu_id}}}")),
+                "{error_line}"
+            );
+        })
+        .await;
+}
+
+/// Creates the `quiet` and `gaming` profiles, with flat fan curves of 20% and 80%,
+/// so the fan speed does not depend on the current temperature
+async fn create_fan_profiles(handler: &Handler, gpu_id: &str) {
+    for (name, speed) in [("quiet", 0.2), ("gaming", 0.8)] {
+        let gpu_config = GpuConfig {
+            fan_control_enabled: true,
+            fan_control_settings: Some(FanControlSettings {
+                mode: FanControlMode::Curve,
+                interval_ms: 10,
+                curve: FanCurve::from([(40, speed), (60, speed)]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let profile = Profile {
+            gpus: IndexMap::from([(gpu_id.to_owned(), gpu_config)]),
+            ..Default::default()
+        };
+        handler
+            .create_profile(name.to_owned(), ProfileBase::Provided(profile))
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn temporary_profile_reverts() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+            let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+            let read_pwm = || fs::read_to_string(hw_mon_dir.join("pwm1")).unwrap();
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+            create_fan_profiles(&handler, &gpu_id).await;
+
+            handler
+                .set_profile(Some("quiet".into()), false)
+                .await
+                .unwrap();
+            handler
+                .apply_temporary_profile("gaming".into(), 1)
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!("204", read_pwm().trim());
+            let current_profile = handler.list_profiles(false).await.current_profile;
+            assert_eq!(Some("gaming"), current_profile.as_deref());
+
+            // The config file keeps the profile that was chosen
+            let exported: Config =
+                serde_yml::from_str(&handler.export_config().await.unwrap()).unwrap();
+            assert_eq!(Some("quiet"), exported.current_profile.as_deref());
+
+            // Switches back without a confirmation
+            tokio::time::sleep(Duration::from_millis(1300)).await;
+            let current_profile = handler.list_profiles(false).await.current_profile;
+            assert_eq!(Some("quiet"), current_profile.as_deref());
+            assert_eq!("51", read_pwm().trim());
+
+            assert!(handler
+                .apply_temporary_profile("missing".into(), 1)
+                .await
+                .is_err());
+            let err = handler
+                .apply_temporary_profile("gaming".into(), 0)
+                .await
+                .unwrap_err();
+            assert_eq!(Some(ErrorKind::OutOfRange), ErrorKind::from_error(&err));
+            let current_profile = handler.list_profiles(false).await.current_profile;
+            assert_eq!(Some("quiet"), current_profile.as_deref());
+
+            // Not allowed while another change waits for confirmation
+            handler
+                .set_performance_level(&gpu_id, PerformanceLevel::Low)
+                .await
+                .unwrap();
+            assert!(handler
+                .apply_temporary_profile("gaming".into(), 1)
+                .await
+                .is_err());
+            handler
+                .confirm_pending_config(ConfirmCommand::Revert)
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let current_profile = handler.list_profiles(false).await.current_profile;
+            assert_eq!(Some("quiet"), current_profile.as_deref());
+
+            handler.cleanup().await;
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn switch_temporary_profile() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let sysfs_dir = copy_test_device("amd/rx580");
+
+            let (handler, gpu_id) = test_handler(sysfs_dir.path(), Config::default()).await;
+            create_fan_profiles(&handler, &gpu_id).await;
+
+            handler
+                .apply_temporary_profile("quiet".into(), 1)
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(600)).await;
+            handler
+                .apply_temporary_profile("gaming".into(), 1)
+                .await
+                .unwrap();
+
+            // The timer of the first profile was replaced
+            tokio::time::sleep(Duration::from_millis(600)).await;
+            let current_profile = handler.list_profiles(false).await.current_profile;
+            assert_eq!(Some("gaming"), current_profile.as_deref());
+
+            // The profile from before the first temporary one is restored
+            tokio::time::sleep(Duration::from_millis(700)).await;
+            assert_eq!(None, handler.list_profiles(false).await.current_profile);
+
+            // Choosing a profile cancels the revert
+            handler
+                .apply_temporary_profile("quiet".into(), 1)
+                .await
+                .unwrap();
+            handler
+                .set_profile(Some("gaming".into()), false)
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(1300)).await;
+            let current_profile = handler.list_profiles(false).await.current_profile;
+            assert_eq!(Some("gaming"), current_profile.as_deref());
+
+            handler.cleanup().await;
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn external_fan_temperature() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd");
+            let pci_db = read_pci_db();
+
+            let sysfs_dir = tempdir().unwrap();
+            copy_dir(&data_dir.join("rx580"), sysfs_dir.path());
+            copy_dir(
+                &data_dir.join("rx6900xt/card0"),
+                &sysfs_dir.path().join("card1"),
+            );
+            let set_rx580_temp = |temp: &str| {
+                let path = sysfs_dir
+                    .path()
+                    .join("card0/device/hwmon/hwmon4/temp1_input");
+                fs::write(path, temp).unwrap();
+            };
+            let read_rx6900xt_pwm = || {
+                let path = sysfs_dir.path().join("card1/device/hwmon/hwmon5/pwm1");
+                fs::read_to_string(path).unwrap().trim().to_owned()
+            };
+
+            let rx580_id = "1002:67DF-1DA2:E387-0000:09:00.0";
+            let rx6900xt_id = "1002:73BF-1DA2:440E-0000:0c:00.0";
+            let external_temperature = |gpu_id: &str| ExternalTemperature {
+                gpu_id: gpu_id.to_owned(),
+                temperature_key: "edge".to_owned(),
+            };
+            let fan_config = |gpu_id: &str| GpuConfig {
+                fan_control_enabled: true,
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Curve,
+                    interval_ms: 10,
+                    curve: FanCurve::from([(40, 0.25), (80, 1.0)]),
+                    external_temperatures: vec![external_temperature(gpu_id)],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+
+            let mut config = Config::default();
+            config
+                .gpus_mut()
+                .unwrap()
+                .insert(rx6900xt_id.to_owned(), fan_config(rx580_id));
+            let handler = Handler::with_base_path(sysfs_dir.path(), config, &pci_db)
+                .await
+                .unwrap();
+
+            // The RX 6900 XT is hotter at 56°C, so its own temperature is used
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!("140", read_rx6900xt_pwm());
+
+            set_rx580_temp("70000");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!("207", read_rx6900xt_pwm());
+
+            set_rx580_temp("30000");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!("140", read_rx6900xt_pwm());
+
+            for gpu_id in ["1002:73BF-1DA2:440E-0000:0d:00.0", rx6900xt_id] {
+                let err = handler
+                    .set_gpu_config(rx6900xt_id, fan_config(gpu_id))
+                    .await
+                    .unwrap_err();
+                let expected_kind = if gpu_id == rx6900xt_id {
+                    ErrorKind::OutOfRange
+                } else {
+                    ErrorKind::GpuNotFound
+                };
+                assert_eq!(Some(expected_kind), ErrorKind::from_error(&err));
+            }
+            let gpu_config = handler.get_gpu_config(rx6900xt_id).await.unwrap();
+            assert_eq!(Some(fan_config(rx580_id)), gpu_config);
+
+            handler.cleanup().await;
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn retry_busy_writes() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+            let pci_db = read_pci_db();
+
+            // The default config retries 3 times
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir)
+                .with_busy_writes("hwmon4/power1_cap", 2)
+                .with_busy_writes("hwmon4/pwm1", 5);
+            let writes = mock_fs.writes.clone();
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+
+            handler.set_power_cap(&gpu_id, Some(150.0)).await.unwrap();
+            handler
+                .confirm_pending_config(ConfirmCommand::Confirm)
+                .unwrap();
+
+            // Fails more often than it gets retried
+            let gpu_config = GpuConfig {
+                power_cap: Some(150.0),
+                fan_control_enabled: true,
+                fan_control_settings: Some(FanControlSettings {
+                    mode: FanControlMode::Static,
+                    static_speed: 0.5,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let err = handler
+                .set_gpu_config(&gpu_id, gpu_config)
+                .await
+                .unwrap_err();
+            assert!(
+                format!("{err:#}").contains("could not set fan speed"),
+                "{err:#}"
+            );
+
+            handler.cleanup().await;
+            mount.join();
+
+            // Only the attempt which went through is recorded
+            let writes = writes.lock().unwrap();
+            let power_cap_write = writes
+                .iter()
+                .find(|(path, _)| path.ends_with("hwmon4/power1_cap"))
+                .map(|(_, contents)| contents.as_str());
+            assert_eq!(Some("150000000"), power_cap_write);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn self_test() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let device_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+            let pci_db = read_pci_db();
+
+            let mock_fs_dir = tempdir().unwrap();
+            let mock_fs = MockSysfs::new(device_dir.clone())
+                .with_read_only_paths(&["hwmon4/power1_cap"])
+                .with_write_filter(|_, path, _| {
+                    !path.ends_with("power_dpm_force_performance_level")
+                });
+            let writes = mock_fs.writes.clone();
+
+            let mount = easy_fuser::spawn_mount(mock_fs, mock_fs_dir.path(), &[], 1)
+                .expect("Could not mount mock fs");
+
+            let (handler, gpu_id) = test_handler(mock_fs_dir.path(), Config::default()).await;
+            let previous_writes = writes.lock().unwrap().len();
+
+            let entries = handler.run_self_test(&gpu_id).await.unwrap();
+            let statuses: Vec<(SelfTestControl, SelfTestStatus)> = entries
+                .into_iter()
+                .map(|entry| (entry.control, entry.status))
+                .collect();
+            // The filtered write fails with EINVAL, which is reported as is
+            let rejected_message = std::io::Error::from_raw_os_error(nix::libc::EINVAL).to_string();
+            assert_eq!(
+                vec![
+                    (SelfTestControl::FanControlMode, SelfTestStatus::Functional),
+                    (SelfTestControl::FanSpeed, SelfTestStatus::Functional),
+                    (SelfTestControl::PowerCap, SelfTestStatus::PermissionDenied),
+                    (
+                        SelfTestControl::PerformanceLevel,
+                        SelfTestStatus::Failed {
+                            message: rejected_message
+                        }
+                    ),
+                    (SelfTestControl::Clocks, SelfTestStatus::Functional),
+                ],
+                statuses
+            );
+
+            mount.join();
+            mock_fs_dir.close().unwrap();
+
+            // Only the current values were written back
+            let writes = writes.lock().unwrap();
+            let self_test_writes = &writes[previous_writes..];
+            assert_eq!(2, self_test_writes.len());
+            for (path, contents) in self_test_writes {
+                let original = fs::read_to_string(device_dir.join(path)).unwrap();
+                assert_eq!(original.trim(), contents);
+            }
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn shutdown_fan_behavior() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let device_data_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+            let pci_db = read_pci_db();
+
+            // Expected values of `pwm1_enable` and `pwm1` after the cleanup
+            let cases = [
+                (ShutdownFanBehavior::RestoreFirmwareControl, "2", "102"),
+                (ShutdownFanBehavior::LeaveAsIs, "1", "102"),
+                (ShutdownFanBehavior::SetFullSpeed, "1", "255"),
+            ];
+            for (behavior, expected_enable, expected_pwm) in cases {
+                let sysfs_dir = tempdir().unwrap();
+                copy_dir(&device_data_dir, sysfs_dir.path());
+                let hw_mon_dir = sysfs_dir.path().join("card0/device/hwmon/hwmon4");
+                let read_file = |name: &str| fs::read_to_string(hw_mon_dir.join(name)).unwrap();
+
+                let mut config = Config::default();
+                config.daemon.shutdown_fan_behavior = behavior;
+                let (handler, gpu_id) = test_handler(sysfs_dir.path(), config).await;
+
+                let gpu_config = GpuConfig {
+                    fan_control_enabled: true,
+                    fan_control_settings: Some(FanControlSettings {
+                        mode: FanControlMode::Curve,
+                        interval_ms: 10,
+                        curve: FanCurve::from([(40, 0.4), (60, 0.4)]),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                };
+                handler.set_gpu_config(&gpu_id, gpu_config).await.unwrap();
+                handler
+                    .confirm_pending_config(ConfirmCommand::Confirm)
+                    .unwrap();
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                assert_eq!("102", read_file("pwm1").trim());
+
+                handler.cleanup().await;
+                let state = (read_file("pwm1_enable"), read_file("pwm1"));
+                assert_eq!(
+                    (expected_enable, expected_pwm),
+                    (state.0.trim(), state.1.trim()),
+                    "{behavior:?}"
+                );
+            }
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn side_by_side_daemons() {
+    init_tracing();
+
+    LocalSet::new()
+        .run_until(async {
+            let device_data_dir =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx580");
+            let pci_db = read_pci_db();
+
+            let names = ["first", "second"];
+            let mut instances = vec![];
+
+            for name in names {
+                let config_dir = tempdir().unwrap();
+
+                // Each instance has a profile named after it
+                let mut config = Config::default();
+                config.profiles.insert(name.into(), Profile::default());
+                fs::write(
+                    config_dir.path().join("config.yaml"),
+                    serde_yml::to_string(&config).unwrap(),
+                )
+                .unwrap();
+
+                let config = Config::load(Some(config_dir.path())).unwrap().unwrap();
+                assert_eq!(Some(config_dir.path()), config.dir());
+
+                let socket_path = config_dir.path().join("lactd.sock");
+                let listener = socket::listen(&socket_path).unwrap();
+                let handler = Handler::with_base_path(&device_data_dir, config, &pci_db)
+                    .await
+                    .unwrap();
+                tokio::task::spawn_local(async move {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    handle_stream(stream, handler).await.unwrap();
+                });
+
+                instances.push((config_dir, socket_path));
+            }
+
+            for (name, (_, socket_path)) in names.into_iter().zip(&instances) {
+                let mut client = BufReader::new(UnixStream::connect(socket_path).await.unwrap());
+                let request = Request::ListProfiles {
+                    include_state: false,
+                };
+                let mut payload = serde_json::to_string(&request).unwrap();
+                payload.push('\n');
+                client.write_all(payload.as_bytes()).await.unwrap();
+
+                let mut response = String::new();
+                client.read_line(&mut response).await.unwrap();
+                let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+                let profiles: Vec<&str> = response["data"]["profiles"]
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .map(String::as_str)
+                    .collect();
+                assert_eq!(vec![name], profiles);
+            }
+
+            let (_, first_socket) = &instances[0];
+            let (_, second_socket) = &instances[1];
+            socket::cleanup(first_socket);
+            assert!(!first_socket.exists());
+            assert!(second_socket.exists());
+        })
+        .await;
+}
@@ -207,6 +207,11 @@ impl AsyncComponent for AppModel {
             sender.input(AppMsg::Error(err.into()));
         }
 
+        if system_info.safe_mode {
+            let err = anyhow!("The daemon failed to start multiple times in a row and is running in safe mode, the stored configuration was not applied! Fix the settings and restart the service with `sudo systemctl restart lactd`.");
+            sender.input(AppMsg::Error(err.into()));
+        }
+
         let info_page = InformationPage::detach_default();
 
         let oc_page = OcPage::builder()
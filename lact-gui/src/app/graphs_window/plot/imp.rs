@@ -103,7 +103,8 @@ mod benches {
     use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
     use divan::{counter::ItemsCount, Bencher};
     use lact_schema::{
-        ClockspeedStats, DeviceStats, FanStats, PmfwInfo, PowerStats, VoltageStats, VramStats,
+        ClockspeedStats, DeviceStats, FanStats, PmfwInfo, PowerCap, PowerStats, VoltageStats,
+        VramStats,
     };
     use std::{
         collections::HashMap,
@@ -157,6 +158,7 @@ mod benches {
                         gpu_clockspeed: Some(500),
                         vram_clockspeed: Some(1000),
                         current_gfxclk: None,
+                        vram_clock_offset: None,
                     },
                     core_power_state: Some(0),
                     fan: FanStats {
@@ -175,10 +177,14 @@ mod benches {
                     performance_level: Some(PerformanceLevel::Auto),
                     power: PowerStats {
                         average: Some(36.0),
-                        cap_current: Some(289.0),
-                        cap_default: Some(289.0),
-                        cap_max: Some(332.0),
-                        cap_min: Some(0.0),
+                        cap: PowerCap {
+                            current: Some(289.0),
+                            default: Some(289.0),
+                            max: Some(332.0),
+                            min: Some(0.0),
+                        },
+                        cap_percent: Some(100.0),
+                        chip: None,
                         current: None,
                     },
                     temps: HashMap::from([(
@@ -193,8 +199,10 @@ mod benches {
                     vram: VramStats {
                         total: Some(17163091968),
                         used: Some(668274688),
+                        ecc_errors: None,
                     },
                     throttle_info: None,
+                    ..Default::default()
                 };
 
                 data.update_with_timestamp(&stats, 1.0, datetime.and_utc().timestamp_millis());
@@ -52,7 +52,7 @@ impl StatsData {
             ),
             (StatType::PowerAverage, stats.power.average),
             (StatType::PowerCurrent, stats.power.current),
-            (StatType::PowerCap, stats.power.cap_current),
+            (StatType::PowerCap, stats.power.cap.current),
             (
                 StatType::FanPwm,
                 stats
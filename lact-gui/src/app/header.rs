@@ -548,9 +548,8 @@ impl RelmListItem for GpuListItem {
     }
 
     fn bind(&mut self, widgets: &mut Self::Widgets, _root: &mut Self::Root) {
-        widgets
-            .name_label
-            .set_label(self.0.name.as_deref().unwrap_or("Unknown"));
+        let name = self.0.alias.as_deref().or(self.0.name.as_deref());
+        widgets.name_label.set_label(name.unwrap_or("Unknown"));
         widgets.id_label.set_label(&self.0.id);
         widgets
             .type_label
@@ -158,7 +158,7 @@ impl relm4::Component for OcPage {
                             self.power_cap_section
                                 .emit(PowerCapMsg::Update(update.clone()));
 
-                            if stats.power.cap_current.is_some() {
+                            if stats.power.cap.current.is_some() {
                                 self.power_cap_section.widget().set_visible(true);
                             } else {
                                 self.power_cap_section.widget().set_visible(false);
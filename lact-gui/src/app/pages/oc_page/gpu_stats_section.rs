@@ -137,7 +137,7 @@ impl relm4::SimpleComponent for GpuStatsSection {
                         let PowerStats {
                             average: power_average,
                             current: power_current,
-                            cap_current: power_cap_current,
+                            cap,
                             ..
                         } = model.stats.power;
 
@@ -148,7 +148,7 @@ impl relm4::SimpleComponent for GpuStatsSection {
                         format!(
                             "<b>{:.1}/{} {}</b>",
                             power_current.unwrap_or(0.0),
-                            power_cap_current.unwrap_or(0.0),
+                            cap.current.unwrap_or(0.0),
                             fl!(I18N, "watt")
                         )
                     },
@@ -252,7 +252,13 @@ pub fn temperature_text(stats: &DeviceStats) -> Option<String> {
     let mut temperatures: Vec<String> = stats
         .temps
         .iter()
-        .filter_map(|(label, temp)| temp.current.map(|current| format!("{label}: {current}°C")))
+        .filter_map(|(label, temp)| {
+            let current = temp.current?;
+            Some(format!(
+                "{label}: {}",
+                stats.temperature_unit.format(current)
+            ))
+        })
         .collect();
     temperatures.sort_unstable();
     if temperatures.is_empty() {
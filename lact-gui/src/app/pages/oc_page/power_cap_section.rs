@@ -100,10 +100,10 @@ impl relm4::Component for PowerCapSection {
                 self.adjustment.block_signal(&widgets.value_notify);
                 let power = stats.power;
 
-                self.adjustment.set_upper(power.cap_max.unwrap_or_default());
-                self.adjustment.set_lower(power.cap_min.unwrap_or_default());
+                self.adjustment.set_upper(power.cap.max.unwrap_or_default());
+                self.adjustment.set_lower(power.cap.min.unwrap_or_default());
                 self.adjustment
-                    .set_initial_value(power.cap_current.unwrap_or_default());
+                    .set_initial_value(power.cap.current.unwrap_or_default());
 
                 self.adjustment.unblock_signal(&widgets.value_notify);
 
@@ -123,7 +123,7 @@ impl relm4::Component for PowerCapSection {
             }
             PowerCapMsg::Reset => {
                 self.adjustment
-                    .set_value(self.power.cap_default.unwrap_or_default());
+                    .set_value(self.power.cap.default.unwrap_or_default());
             }
         }
 
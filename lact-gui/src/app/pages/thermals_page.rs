@@ -433,7 +433,7 @@ impl relm4::Component for ThermalsPage {
                         let page_name = match stats.fan.control_mode {
                             Some(mode) if stats.fan.control_enabled => match mode {
                                 FanControlMode::Static => STATIC_PAGE,
-                                FanControlMode::Curve => CURVE_PAGE,
+                                FanControlMode::Curve | FanControlMode::OffsetCurve => CURVE_PAGE,
                             },
                             _ => AUTO_PAGE,
                         };
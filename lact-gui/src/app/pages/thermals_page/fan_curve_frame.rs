@@ -16,7 +16,7 @@ use gtk::{
         AdjustmentExt, BoxExt, ButtonExt, DrawingAreaExtManual, OrientableExt, RangeExt, WidgetExt,
     },
 };
-use lact_schema::{default_fan_curve, FanCurveMap};
+use lact_schema::{default_fan_curve, CurveTemperature, FanCurveMap};
 use plotters::{
     chart::ChartBuilder,
     prelude::{Circle, EmptyElement, IntoDrawingArea, Text},
@@ -46,7 +46,7 @@ const DEFAULT_SPINDOWN_DELAY_MS: u64 = 5000;
 pub(super) struct FanCurveFrame {
     pmfw_options: PmfwOptions,
 
-    data: Rc<RefCell<Vec<(i32, f32)>>>,
+    data: Rc<RefCell<Vec<(f32, f32)>>>,
     speed_range: Rc<RefCell<RangeInclusive<f32>>>,
     temperature_range: Rc<RefCell<RangeInclusive<f32>>>,
     temp_keys: gtk::StringList,
@@ -447,7 +447,7 @@ This option allows to work around this limitation by only using the custom curve
 
                 self.edit_curve(
                     |curve| {
-                        curve.push((*temp_range.end() as i32, *speed_range.end()));
+                        curve.push((temp_range.end().trunc(), *speed_range.end()));
                     },
                     widgets,
                 );
@@ -484,7 +484,11 @@ This option allows to work around this limitation by only using the custom curve
 
 impl FanCurveFrame {
     pub fn get_curve(&self) -> FanCurveMap {
-        self.data.borrow().iter().copied().collect()
+        self.data
+            .borrow()
+            .iter()
+            .map(|(temp, speed)| (CurveTemperature::from_celsius(*temp), *speed))
+            .collect()
     }
 
     pub fn spindown_delay(&self) -> u64 {
@@ -517,7 +521,7 @@ impl FanCurveFrame {
             && (self.auto_threshold_adj.upper() == 0.0) // Disable key selection on nvidia
     }
 
-    fn edit_curve(&self, f: impl FnOnce(&mut Vec<(i32, f32)>), widgets: &FanCurveFrameWidgets) {
+    fn edit_curve(&self, f: impl FnOnce(&mut Vec<(f32, f32)>), widgets: &FanCurveFrameWidgets) {
         f(&mut self.data.borrow_mut());
 
         widgets.drawing_area.queue_draw();
@@ -542,7 +546,7 @@ impl FanCurveFrame {
                 Some(idx) => Some(idx),
                 None => {
                     let point = self.data.borrow().iter().position(|(data_x, data_y)| {
-                        (*data_x as f32 - new_value.0).abs() <= 3.0
+                        (*data_x - new_value.0).abs() <= 3.0
                             && (*data_y - new_value.1).abs() <= 0.03
                     });
                     self.drag_point.set(point);
@@ -552,7 +556,8 @@ impl FanCurveFrame {
             if let Some(idx) = drag_point_idx {
                 normalize_to_range(&mut new_value.0, &self.temperature_range.borrow());
                 normalize_to_range(&mut new_value.1, &self.speed_range.borrow());
-                self.data.borrow_mut()[idx] = (new_value.0 as i32, new_value.1);
+                // Dragging moves points in whole degrees
+                self.data.borrow_mut()[idx] = (new_value.0.trunc(), new_value.1);
 
                 APP_BROKER.send(AppMsg::SettingsChanged);
             }
@@ -561,15 +566,15 @@ impl FanCurveFrame {
 }
 
 fn normalize_fan_curve<'a>(
-    curve: impl IntoIterator<Item = (i32, f32)> + 'a,
+    curve: impl IntoIterator<Item = (CurveTemperature, f32)> + 'a,
     temperature_range: &'a RangeInclusive<f32>,
     speed_range: &'a RangeInclusive<f32>,
-) -> impl Iterator<Item = (i32, f32)> + 'a {
+) -> impl Iterator<Item = (f32, f32)> + 'a {
     curve.into_iter().map(|(temp, mut speed)| {
-        let mut temp = temp as f32;
+        let mut temp = temp.celsius();
         normalize_to_range(&mut temp, temperature_range);
         normalize_to_range(&mut speed, speed_range);
-        (temp as i32, speed)
+        (temp, speed)
     })
 }
 
@@ -580,7 +585,7 @@ fn normalize_to_range(value: &mut f32, range: &RangeInclusive<f32>) {
 
 fn draw_chart(
     backend: CairoBackend,
-    data: &[(i32, f32)],
+    data: &[(f32, f32)],
     translate_coord: Option<(f64, f64)>,
     colors: PlotColorScheme,
     temp_range: &RangeInclusive<f32>,
@@ -618,7 +623,7 @@ fn draw_chart(
             data.first()
                 .map(|(_, y)| (*temp_range.start(), { *y }))
                 .into_iter()
-                .chain(data.iter().map(|(x, y)| (*x as f32, *y)))
+                .chain(data.iter().copied())
                 .chain(data.last().map(|(_, y)| (*temp_range.end(), *y))),
             &LIGHTBLUE,
         ))
@@ -626,7 +631,7 @@ fn draw_chart(
 
     chart
         .draw_series(PointSeries::of_element(
-            data.iter().map(|(x, y)| (*x as f32, *y)),
+            data.iter().copied(),
             8,
             ShapeStyle::from(&LIGHTBLUE).filled(),
             &|coord, size, style| {
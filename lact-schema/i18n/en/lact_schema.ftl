@@ -5,6 +5,7 @@ driver-used = Driver Used
 vbios-version = VBIOS Version
 vram-size = VRAM Size
 vram-type = VRAM Type
+vram-vendor = VRAM Vendor
 gpu-family = GPU Family
 asic-name = ASIC Name
 compute-units = Compute Units
@@ -18,6 +19,7 @@ l2-cache = L2 Cache
 l3-cache = L3 Cache
 rebar = Resizable Bar
 cpu-vram = CPU Accessible VRAM
+rebar-size = Resizable Bar Size
 pcie-speed = PCIe Link Speed
 
 enabled = Enabled
@@ -1,6 +1,7 @@
 pub use clap;
 
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 pub struct Args {
@@ -11,13 +12,23 @@ pub struct Args {
 #[derive(Subcommand)]
 pub enum Command {
     /// Run the daemon
-    Daemon,
+    Daemon(DaemonArgs),
     /// Run the GUI
     Gui(GuiArgs),
     /// Run the CLI
     Cli(CliArgs),
 }
 
+#[derive(Default, Parser)]
+pub struct DaemonArgs {
+    /// Directory to store the config in, instead of the default location
+    #[arg(long)]
+    pub config_dir: Option<PathBuf>,
+    /// Path of the socket to listen on, instead of the default location
+    #[arg(long)]
+    pub socket_path: Option<PathBuf>,
+}
+
 #[derive(Default, Parser)]
 pub struct GuiArgs {
     #[arg(long)]
@@ -42,6 +53,12 @@ pub enum CliCommand {
     ListGpus,
     /// Show GPU info
     Info,
+    /// Show the settings that can be changed and their allowed ranges
+    Capabilities,
+    /// Show the combined power draw of all GPUs
+    Power,
+    /// Show the temperature, power, clock and fan speed of each GPU on a single line
+    Status,
     /// Generate debug snapshot
     Snapshot,
 }
@@ -0,0 +1,81 @@
+use libflate::deflate;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+/// Responses smaller than this are sent uncompressed even when compression is enabled
+pub const COMPRESSION_THRESHOLD: usize = 16 * 1024;
+/// Compressed responses start with this instead of a regular response
+const FRAME_PREFIX: &str = r#"{"compressed":"#;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressionCodec {
+    Deflate,
+}
+
+impl CompressionCodec {
+    /// Codecs supported by this version, in the order of preference
+    pub const SUPPORTED: [CompressionCodec; 1] = [CompressionCodec::Deflate];
+
+    pub fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            CompressionCodec::Deflate => {
+                let mut encoder = deflate::Encoder::new(Vec::new());
+                encoder.write_all(data)?;
+                encoder.finish().into_result()
+            }
+        }
+    }
+
+    pub fn decompress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            CompressionCodec::Deflate => {
+                let mut output = Vec::new();
+                deflate::Decoder::new(data).read_to_end(&mut output)?;
+                Ok(output)
+            }
+        }
+    }
+}
+
+/// Header line which is sent in place of a compressed response,
+/// followed by `length` bytes of compressed data.
+///
+/// The compressed data can contain newlines, so it can't be sent as a regular line.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressedFrame {
+    pub codec: CompressionCodec,
+    pub length: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FrameHeader {
+    compressed: CompressedFrame,
+}
+
+impl CompressedFrame {
+    /// Compresses a serialized response into its header line and the compressed data
+    pub fn encode(codec: CompressionCodec, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let data = codec.compress(payload)?;
+        let header = FrameHeader {
+            compressed: CompressedFrame {
+                codec,
+                length: data.len(),
+            },
+        };
+
+        let mut frame = serde_json::to_vec(&header)?;
+        frame.push(b'\n');
+        frame.extend(data);
+        Ok(frame)
+    }
+
+    /// Returns `None` when the line is a regular response
+    pub fn parse_header(line: &str) -> serde_json::Result<Option<Self>> {
+        if !line.starts_with(FRAME_PREFIX) {
+            return Ok(None);
+        }
+        let header: FrameHeader = serde_json::from_str(line)?;
+        Ok(Some(header.compressed))
+    }
+}
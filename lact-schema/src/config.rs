@@ -6,7 +6,7 @@ use serde_with::skip_serializing_none;
 use crate::{
     default_fan_curve,
     request::{ClockspeedType, SetClocksCommand},
-    FanControlMode, FanCurveMap, PmfwOptions, ProfileRule,
+    CurveTemperature, FanControlMode, FanCurveMap, PmfwOptions, ProfileRule,
 };
 
 #[skip_serializing_none]
@@ -50,6 +50,42 @@ pub struct GpuConfig {
     pub custom_power_profile_mode_hueristics: Vec<Vec<Option<i32>>>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
     pub power_states: IndexMap<PowerLevelKind, Vec<u8>>,
+    /// Index of the `pp_dpm_mclk` level to force. `None` leaves the memory clock under driver control
+    pub memory_clock_level: Option<u8>,
+    /// Index of the `pp_dpm_pcie` level to force. `None` leaves the link under driver control
+    pub pcie_level: Option<u8>,
+    /// Force the lowest core and memory clock levels, in place of the configured power states
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub idle_lock: bool,
+    pub thermal_protection: Option<ThermalProtection>,
+    /// Settings of the LEDs of the card by their name
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub leds: IndexMap<String, LedSettings>,
+    /// Settings which only get reapplied after the warm-up period when the system resumes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub delayed_on_resume: Vec<ResumeDelayedSetting>,
+}
+
+/// Settings which can be held back after a resume, while the rest of the config applies right away
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResumeDelayedSetting {
+    PowerCap,
+    /// Clockspeed and voltage limits and offsets
+    Clocks,
+    /// The performance level and the settings that depend on it,
+    /// such as the power profile mode and the enabled power states
+    PerformanceLevel,
+}
+
+/// Settings of a single GPU, in the form that gets shared between systems
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SharedGpuConfig {
+    /// PCI vendor and device id of the GPU the settings were exported from, such as `1002:73BF`
+    pub model_id: String,
+    pub model_name: Option<String>,
+    pub config: GpuConfig,
 }
 
 #[skip_serializing_none]
@@ -74,25 +110,49 @@ pub struct ClocksConfiguration {
     )]
     pub mem_clock_offsets: IndexMap<u32, i32>,
     pub voltage_offset: Option<i32>,
+    /// Per-state overrides of the AMD `OD_SCLK` table, keyed by state index
+    #[serde(
+        default,
+        skip_serializing_if = "IndexMap::is_empty",
+        deserialize_with = "offsets::deserialize"
+    )]
+    pub core_clock_states: IndexMap<u8, ClockVoltagePoint>,
+    /// Per-state overrides of the AMD `OD_MCLK` table, keyed by state index
+    #[serde(
+        default,
+        skip_serializing_if = "IndexMap::is_empty",
+        deserialize_with = "offsets::deserialize"
+    )]
+    pub memory_clock_states: IndexMap<u8, ClockVoltagePoint>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ClockVoltagePoint {
+    /// Clockspeed in MHz
+    pub clockspeed: i32,
+    /// Voltage in mV
+    pub voltage: i32,
 }
 
 mod offsets {
     use indexmap::IndexMap;
     use serde::{de::Error, Deserialize, Deserializer};
     use serde_json::Value;
+    use std::{hash::Hash, str::FromStr};
 
-    pub fn deserialize<'a, D: Deserializer<'a>>(
-        deserializer: D,
-    ) -> Result<IndexMap<u32, i32>, D::Error> {
-        let map: IndexMap<Value, i32> = IndexMap::deserialize(deserializer)?;
+    pub fn deserialize<'a, D, K, V>(deserializer: D) -> Result<IndexMap<K, V>, D::Error>
+    where
+        D: Deserializer<'a>,
+        K: TryFrom<i64> + FromStr + Hash + Eq,
+        V: Deserialize<'a>,
+    {
+        let map: IndexMap<Value, V> = IndexMap::deserialize(deserializer)?;
 
         map.into_iter()
             .map(|(key, value)| {
                 let parsed_key = match &key {
-                    Value::Number(number) => {
-                        number.as_i64().and_then(|val| u32::try_from(val).ok())
-                    }
-                    Value::String(s) => s.parse::<u32>().ok(),
+                    Value::Number(number) => number.as_i64().and_then(|val| K::try_from(val).ok()),
+                    Value::String(s) => s.parse::<K>().ok(),
                     _ => None,
                 };
                 let key =
@@ -109,6 +169,28 @@ impl GpuConfig {
         self.clocks_configuration != ClocksConfiguration::default()
     }
 
+    /// The config to apply right after a resume, with the delayed settings left at their defaults
+    pub fn without_delayed_on_resume(&self) -> GpuConfig {
+        let mut config = self.clone();
+        for setting in &self.delayed_on_resume {
+            match setting {
+                ResumeDelayedSetting::PowerCap => config.power_cap = None,
+                ResumeDelayedSetting::Clocks => {
+                    config.clocks_configuration = ClocksConfiguration::default();
+                }
+                ResumeDelayedSetting::PerformanceLevel => {
+                    config.performance_level = None;
+                    config.power_profile_mode_index = None;
+                    config.custom_power_profile_mode_hueristics.clear();
+                    config.power_states.clear();
+                    config.memory_clock_level = None;
+                    config.pcie_level = None;
+                }
+            }
+        }
+        config
+    }
+
     pub fn apply_clocks_command(&mut self, command: &SetClocksCommand) {
         let clocks = &mut self.clocks_configuration;
         let value = command.value;
@@ -147,12 +229,80 @@ impl GpuConfig {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct FanCurve(pub FanCurveMap);
 
+/// Starting points for generating a fan curve, from the quietest to the coolest
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FanCurvePreset {
+    Silent,
+    Balanced,
+    Performance,
+}
+
+impl FanCurvePreset {
+    pub const ALL: [FanCurvePreset; 3] = [
+        FanCurvePreset::Silent,
+        FanCurvePreset::Balanced,
+        FanCurvePreset::Performance,
+    ];
+}
+
 impl Default for FanCurve {
     fn default() -> Self {
         Self(default_fan_curve())
     }
 }
 
+/// Curve with points at whole degrees
+impl<const N: usize> From<[(i32, f32); N]> for FanCurve {
+    fn from(points: [(i32, f32); N]) -> Self {
+        Self(
+            points
+                .into_iter()
+                .map(|(temp, speed)| (CurveTemperature::from(temp), speed))
+                .collect(),
+        )
+    }
+}
+
+impl FanCurve {
+    /// Converts a curve with 0.0-1.0 speed ratios into one with raw 0-255 PWM values
+    pub fn to_pwm_curve(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|(temp, ratio)| (*temp, ratio * f32::from(u8::MAX)))
+                .collect(),
+        )
+    }
+
+    /// Converts a curve with raw 0-255 PWM values into one with 0.0-1.0 speed ratios
+    pub fn to_percentage_curve(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|(temp, pwm)| (*temp, pwm / f32::from(u8::MAX)))
+                .collect(),
+        )
+    }
+}
+
+/// Unit of the fan speed values in a fan curve
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FanCurveUnit {
+    /// Speed ratio between 0.0 and 1.0
+    #[default]
+    Percentage,
+    /// Raw PWM value between 0 and 255
+    Pwm,
+}
+
+impl FanCurveUnit {
+    pub fn is_percentage(&self) -> bool {
+        *self == Self::Percentage
+    }
+}
+
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct FanControlSettings {
@@ -161,11 +311,62 @@ pub struct FanControlSettings {
     #[serde(default = "default_fan_static_speed")]
     pub static_speed: f32,
     pub temperature_key: String,
+    /// How often the curve is evaluated, independent of the daemon's stats polling interval.
+    /// The daemon's `fan_control_interval_ms` is used in place of it when set
     pub interval_ms: u64,
     pub curve: FanCurve,
+    #[serde(default, skip_serializing_if = "FanCurveUnit::is_percentage")]
+    pub curve_unit: FanCurveUnit,
+    /// Added to the speed of the firmware fan curve in `offset_curve` mode,
+    /// as a fraction of the full speed (-1 to 1)
+    pub curve_offset: Option<f32>,
     pub spindown_delay_ms: Option<u64>,
     pub change_threshold: Option<u64>,
     pub auto_threshold: Option<u64>,
+    /// Lowest PWM value (0-255) the fan curve is allowed to set, regardless of the temperature
+    pub min_pwm: Option<u8>,
+    /// Highest PWM value (0-255) the fan curve is allowed to set, regardless of the temperature
+    pub max_pwm: Option<u8>,
+    /// Largest drop in fan speed per curve evaluation, as a fraction of the full speed (0-1).
+    /// Speed increases are always applied right away.
+    pub spindown_step: Option<f32>,
+    /// Temperatures of other GPUs which share the airflow with this one.
+    /// The curve is evaluated at the highest of these and the GPU's own temperature.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub external_temperatures: Vec<ExternalTemperature>,
+}
+
+impl FanControlSettings {
+    /// Applies the `min_pwm` and `max_pwm` limits to a PWM value calculated from the curve
+    pub fn clamp_pwm(&self, pwm: u8) -> u8 {
+        let pwm = self.min_pwm.map_or(pwm, |min| pwm.max(min));
+        self.max_pwm.map_or(pwm, |max| pwm.min(max))
+    }
+
+    /// Adds the `curve_offset` to a PWM value calculated from the firmware curve when using the
+    /// `offset_curve` mode, staying within the valid PWM range
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn apply_curve_offset(&self, pwm: u8) -> u8 {
+        match self.curve_offset {
+            Some(offset) if self.mode == FanControlMode::OffsetCurve => {
+                let offset = (offset * f32::from(u8::MAX)).round() as i16;
+                (i16::from(pwm) + offset).clamp(0, i16::from(u8::MAX)) as u8
+            }
+            _ => pwm,
+        }
+    }
+
+    /// Applies the `spindown_step` limit when lowering the fan speed from the previous PWM value
+    pub fn limit_spindown(&self, previous_pwm: u8, target_pwm: u8) -> u8 {
+        match self.spindown_step {
+            Some(step) if target_pwm < previous_pwm => {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let max_drop = ((step * f32::from(u8::MAX)).round() as u8).max(1);
+                target_pwm.max(previous_pwm.saturating_sub(max_drop))
+            }
+            _ => target_pwm,
+        }
+    }
 }
 
 impl Default for FanControlSettings {
@@ -176,20 +377,69 @@ impl Default for FanControlSettings {
             temperature_key: "edge".to_owned(),
             interval_ms: 500,
             curve: FanCurve(default_fan_curve()),
+            curve_unit: FanCurveUnit::default(),
+            curve_offset: None,
             spindown_delay_ms: None,
             change_threshold: None,
             auto_threshold: None,
+            min_pwm: None,
+            max_pwm: None,
+            spindown_step: None,
+            external_temperatures: Vec::new(),
         }
     }
 }
 
+/// Temperature sensor of another GPU, referenced by a fan curve
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExternalTemperature {
+    pub gpu_id: String,
+    pub temperature_key: String,
+}
+
+/// State of an LED which the kernel exposes through the LED class
+#[skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct LedSettings {
+    pub brightness: Option<u32>,
+    /// Intensity of each color channel of a multicolor LED, in the order of its `multi_index`
+    pub color: Option<Vec<u32>>,
+}
+
+/// Protective action which is taken when the GPU overheats, and reverted once it has cooled down
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ThermalProtection {
+    /// Temperature in °C at which the action is triggered
+    pub temperature: f32,
+    /// How many degrees below `temperature` the GPU has to cool down to before the action is reverted
+    #[serde(default = "default_thermal_protection_hysteresis")]
+    pub hysteresis: f32,
+    pub action: ThermalProtectionAction,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ThermalProtectionAction {
+    /// Run the fans at full speed
+    MaxFanSpeed,
+    /// Switch to the lowest performance level
+    LowestPerformanceLevel,
+    /// Lower the power cap by the given percentage
+    ReducePowerCap { percent: f64 },
+}
+
 pub fn default_fan_static_speed() -> f32 {
     0.5
 }
 
+fn default_thermal_protection_hysteresis() -> f32 {
+    5.0
+}
+
 #[cfg(test)]
 mod tests {
-    use super::GpuConfig;
+    use super::{ClocksConfiguration, GpuConfig, ResumeDelayedSetting};
+    use amdgpu_sysfs::gpu_handle::PerformanceLevel;
 
     #[test]
     fn deserialize_config_json() {
@@ -204,4 +454,29 @@ mod tests {
                 .unwrap()
         );
     }
+
+    #[test]
+    fn delayed_on_resume() {
+        let mut config = GpuConfig {
+            power_cap: Some(250.0),
+            performance_level: Some(PerformanceLevel::Manual),
+            power_profile_mode_index: Some(2),
+            clocks_configuration: ClocksConfiguration {
+                voltage_offset: Some(-50),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(config, config.without_delayed_on_resume());
+
+        config.delayed_on_resume = vec![
+            ResumeDelayedSetting::Clocks,
+            ResumeDelayedSetting::PerformanceLevel,
+        ];
+        let resume_config = config.without_delayed_on_resume();
+        assert_eq!(Some(250.0), resume_config.power_cap);
+        assert_eq!(None, resume_config.performance_level);
+        assert_eq!(None, resume_config.power_profile_mode_index);
+        assert!(!resume_config.is_core_clocks_used());
+    }
 }
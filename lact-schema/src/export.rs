@@ -0,0 +1,66 @@
+use crate::{HistoryMetric, HistorySample};
+use chrono::{DateTime, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsExportFormat {
+    #[default]
+    Csv,
+}
+
+impl StatsExportFormat {
+    pub fn serialize(self, samples: &[HistorySample]) -> String {
+        match self {
+            StatsExportFormat::Csv => history_to_csv(samples),
+        }
+    }
+}
+
+impl HistoryMetric {
+    pub const ALL: [HistoryMetric; 4] = [
+        HistoryMetric::Power,
+        HistoryMetric::Temperature,
+        HistoryMetric::GpuClockspeed,
+        HistoryMetric::VramClockspeed,
+    ];
+
+    pub fn column_name(self) -> &'static str {
+        match self {
+            HistoryMetric::Power => "power_w",
+            HistoryMetric::Temperature => "temperature_c",
+            HistoryMetric::GpuClockspeed => "gpu_clockspeed_mhz",
+            HistoryMetric::VramClockspeed => "vram_clockspeed_mhz",
+        }
+    }
+}
+
+/// Formats the samples as CSV with a header row, leaving the cells of missing values empty
+pub fn history_to_csv(samples: &[HistorySample]) -> String {
+    let mut output = String::from("timestamp");
+    for metric in HistoryMetric::ALL {
+        output.push(',');
+        output.push_str(metric.column_name());
+    }
+    output.push('\n');
+
+    for sample in samples {
+        match DateTime::from_timestamp_millis(sample.timestamp) {
+            Some(datetime) => {
+                output.push_str(&datetime.to_rfc3339_opts(SecondsFormat::Millis, true));
+            }
+            None => output.push_str(&sample.timestamp.to_string()),
+        }
+
+        for metric in HistoryMetric::ALL {
+            output.push(',');
+            if let Some(value) = sample.values.get(&metric) {
+                write!(output, "{value}").unwrap();
+            }
+        }
+        output.push('\n');
+    }
+
+    output
+}
@@ -1,17 +1,23 @@
 #[cfg(feature = "args")]
 pub mod args;
+mod compression;
 pub mod config;
+mod export;
 pub mod i18n;
 mod profiles;
 pub mod request;
 mod response;
+mod status;
 
 #[cfg(test)]
 mod tests;
 
+pub use compression::{CompressedFrame, CompressionCodec, COMPRESSION_THRESHOLD};
+pub use export::{history_to_csv, StatsExportFormat};
 use i18n_embed_fl::fl;
 pub use request::Request;
-pub use response::Response;
+pub use response::{ErrorKind, KindError, Response, ResponseError};
+pub use status::GpuStatusLine;
 
 use amdgpu_sysfs::{
     gpu_handle::{
@@ -22,7 +28,7 @@ use amdgpu_sysfs::{
     hw_mon::Temperature,
 };
 use indexmap::{IndexMap, IndexSet};
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_with::skip_serializing_none;
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
@@ -31,9 +37,17 @@ use std::{
     sync::Arc,
 };
 
-use crate::{config::ProfileHooks, i18n::LANGUAGE_LOADER};
+use crate::{
+    config::{GpuConfig, ProfileHooks},
+    i18n::LANGUAGE_LOADER,
+};
 
 pub const GIT_COMMIT: &str = env!("VERGEN_GIT_SHA");
+/// Version of the schema crate, which is shared by the daemon and its clients
+pub const SCHEMA_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Gets incremented on changes to the requests or responses which older clients or daemons
+/// would not be able to handle
+pub const PROTOCOL_VERSION: u32 = 1;
 
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
@@ -41,6 +55,8 @@ pub enum FanControlMode {
     Static,
     #[default]
     Curve,
+    /// The firmware fan curve with a fixed offset added to it
+    OffsetCurve,
 }
 
 impl FromStr for FanControlMode {
@@ -50,15 +66,135 @@ impl FromStr for FanControlMode {
         match s {
             "curve" => Ok(Self::Curve),
             "static" => Ok(Self::Static),
+            "offset_curve" => Ok(Self::OffsetCurve),
             _ => Err("unknown fan control mode".to_string()),
         }
     }
 }
 
-pub type FanCurveMap = BTreeMap<i32, f32>;
+/// Fan control mode which the hardware is actually in, as reported by `pwm1_enable`.
+/// It can differ from the settings of LACT when another tool changed it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HardwareFanMode {
+    /// The fan runs at full speed
+    Full,
+    /// The fan speed is set through `pwm1`
+    Manual,
+    /// The firmware controls the fan
+    Auto,
+}
+
+pub type FanCurveMap = BTreeMap<CurveTemperature, f32>;
 
 pub fn default_fan_curve() -> FanCurveMap {
-    [(40, 0.3), (50, 0.35), (60, 0.5), (70, 0.75), (80, 1.0)].into()
+    [(40, 0.3), (50, 0.35), (60, 0.5), (70, 0.75), (80, 1.0)]
+        .map(|(temp, speed)| (CurveTemperature::from(temp), speed))
+        .into()
+}
+
+/// Temperature of a fan curve point in °C.
+/// It is stored in tenths of a degree, so that points with fractional temperatures can be ordered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CurveTemperature(i32);
+
+impl CurveTemperature {
+    const SCALE: i32 = 10;
+
+    /// Rounded to the closest tenth of a degree
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    pub fn from_celsius(temp: f32) -> Self {
+        Self((temp * Self::SCALE as f32).round() as i32)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn celsius(self) -> f32 {
+        self.0 as f32 / Self::SCALE as f32
+    }
+
+    /// Rounded to the closest whole degree, as used by firmware fan curves
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn rounded(self) -> i32 {
+        self.celsius().round() as i32
+    }
+
+    pub fn is_whole(self) -> bool {
+        self.0 % Self::SCALE == 0
+    }
+}
+
+impl From<i32> for CurveTemperature {
+    fn from(temp: i32) -> Self {
+        Self(temp.saturating_mul(Self::SCALE))
+    }
+}
+
+impl Display for CurveTemperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.celsius(), f)
+    }
+}
+
+/// Whole degrees are written as integers, so that existing configs stay the same
+impl Serialize for CurveTemperature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_whole() {
+            serializer.serialize_i32(self.0 / Self::SCALE)
+        } else {
+            serializer.serialize_f32(self.celsius())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CurveTemperature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Map keys are strings in JSON, so both numbers and strings need to be accepted
+        deserializer.deserialize_any(CurveTemperatureVisitor)
+    }
+}
+
+struct CurveTemperatureVisitor;
+
+impl de::Visitor<'_> for CurveTemperatureVisitor {
+    type Value = CurveTemperature;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a temperature in degrees")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        i32::try_from(v)
+            .map(CurveTemperature::from)
+            .map_err(|_| E::custom(format!("temperature {v} is out of range")))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        i32::try_from(v)
+            .map(CurveTemperature::from)
+            .map_err(|_| E::custom(format!("temperature {v} is out of range")))
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        if v.is_finite() {
+            Ok(CurveTemperature::from_celsius(v as f32))
+        } else {
+            Err(E::custom(format!("temperature {v} is not a finite number")))
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        let v = v.trim();
+        match v.parse::<i64>() {
+            Ok(temp) => self.visit_i64(temp),
+            Err(_) => {
+                let temp = v
+                    .parse::<f64>()
+                    .map_err(|_| E::custom(format!("invalid temperature {v}")))?;
+                self.visit_f64(temp)
+            }
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -72,6 +208,82 @@ pub struct SystemInfo {
     pub profile: String,
     pub kernel_version: String,
     pub amdgpu_overdrive_enabled: Option<bool>,
+    /// The daemon was started without applying the configuration after repeatedly failing to start
+    #[serde(default)]
+    pub safe_mode: bool,
+}
+
+/// Versions of the running daemon, used by clients to check that they can talk to it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DaemonInfo {
+    pub version: String,
+    pub commit: Option<String>,
+    pub schema_version: String,
+    pub protocol_version: u32,
+    /// Optional functionality which the daemon was built with
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+impl DaemonInfo {
+    /// If the daemon uses the same protocol version as the client
+    pub fn is_compatible(&self) -> bool {
+        self.protocol_version == PROTOCOL_VERSION
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|item| item == feature)
+    }
+}
+
+/// Versions of the kernel, driver and firmware used by a GPU, for including in bug reports
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct VersionsInfo {
+    pub kernel_version: String,
+    pub driver: String,
+    /// Only reported by drivers that are built outside of the kernel, such as `amdgpu-dkms`
+    pub driver_version: Option<String>,
+    pub vbios_version: Option<String>,
+    /// Version of the power management firmware, which decides the available fan and
+    /// overclocking settings. Only reported on AMD GPUs using the PMFW interface (RDNA3 and newer).
+    pub pmfw_version: Option<String>,
+    /// Versions of the individual firmware components, such as `smc` or `mec`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub firmware: BTreeMap<String, String>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModuleConfigStatus {
+    /// `ppfeaturemask` of the currently loaded amdgpu module
+    pub running_ppfeaturemask: Option<u64>,
+    /// `ppfeaturemask` set in the module config file, used after the next boot
+    pub configured_ppfeaturemask: Option<u64>,
+    /// The module config was changed and will only take effect after a reboot
+    pub reboot_required: bool,
+}
+
+/// State of the overdrive bit in `ppfeaturemask`, without which clocks and voltages
+/// can't be changed on AMD GPUs
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverclockStatus {
+    /// Overclocking is enabled in the currently loaded amdgpu module,
+    /// not known when the module is not loaded
+    pub enabled: Option<bool>,
+    /// Overclocking is enabled by the module config file on the next boot,
+    /// not known when the file doesn't set `ppfeaturemask`
+    pub enabled_on_boot: Option<bool>,
+    /// The module config file would change whether overclocking is enabled after the next boot
+    pub reboot_required: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModuleConfigUpdate {
+    pub status: ModuleConfigStatus,
+    /// Result of regenerating the initramfs
+    pub message: String,
 }
 
 #[skip_serializing_none]
@@ -81,11 +293,13 @@ pub struct DeviceListEntry {
     pub name: Option<String>,
     #[serde(default)]
     pub device_type: DeviceType,
+    /// Name given to the GPU in the config, shown instead of the model name
+    pub alias: Option<String>,
 }
 
 impl Display for DeviceListEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self.name {
+        match self.alias.as_ref().or(self.name.as_ref()) {
             Some(name) => Display::fmt(name, f),
             None => Display::fmt(&self.id, f),
         }
@@ -124,8 +338,82 @@ pub struct DeviceInfo {
     pub opencl_info: Option<OpenCLInfo>,
     pub driver: String,
     pub vbios_version: Option<String>,
+    pub vbios: Option<VbiosInfo>,
     pub link_info: LinkInfo,
     pub drm_info: Option<DrmInfo>,
+    #[serde(default)]
+    pub resizable_bar: Option<ResizableBarInfo>,
+    /// Manufacturer of the VRAM chips, as reported by the driver
+    #[serde(default)]
+    pub memory_vendor: Option<String>,
+    /// In bytes
+    #[serde(default)]
+    pub vram_total: Option<u64>,
+    /// Temperature thresholds of each sensor by its name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub thermal_limits: BTreeMap<String, ThermalLimits>,
+    /// Connectors which have a display connected, empty on headless GPUs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub displays: Vec<DisplayInfo>,
+}
+
+/// Temperature thresholds of a sensor in °C, as configured by the driver or the firmware
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThermalLimits {
+    /// Highest temperature the GPU is meant to run at
+    pub max: Option<f32>,
+    /// The GPU gets throttled when going above this temperature
+    pub crit: Option<f32>,
+    /// The GPU shuts down when going above this temperature
+    pub emergency: Option<f32>,
+}
+
+/// A display connected to the GPU, as reported by the DRM subsystem
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DisplayInfo {
+    /// Name of the connector, such as `DP-1` or `HDMI-A-1`
+    pub connector: String,
+    /// If the display is currently being driven, disabled displays don't affect the clocks
+    pub enabled: bool,
+    /// Power state of the display, such as `On` or `Off`
+    pub dpms: Option<String>,
+    /// Resolutions supported by the display, starting with the preferred one.
+    /// The refresh rates and the mode currently in use are not available through sysfs.
+    #[serde(default)]
+    pub modes: Vec<String>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VbiosInfo {
+    pub version: String,
+    pub part_number: Option<String>,
+    pub build_date: Option<String>,
+    pub subsystem_vendor_id: String,
+    pub subsystem_device_id: String,
+}
+
+/// Configuration of a resizable PCIe BAR, which is usually the one mapping the VRAM
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ResizableBarInfo {
+    pub bar_index: u8,
+    /// In bytes
+    pub current_size: u64,
+    /// Sizes the device supports for the BAR, in bytes
+    pub supported_sizes: Vec<u64>,
+}
+
+impl ResizableBarInfo {
+    pub fn max_size(&self) -> Option<u64> {
+        self.supported_sizes.iter().max().copied()
+    }
+
+    /// Whether the BAR uses the largest size the device supports
+    pub fn is_max_size(&self) -> bool {
+        self.max_size().is_some_and(|max| self.current_size >= max)
+    }
 }
 
 impl DeviceInfo {
@@ -201,12 +489,12 @@ impl DeviceInfo {
             ),
         ];
 
-        if let Some(stats) = stats {
+        if stats.is_some() || self.vram_total.is_some() {
             elements.push((
                 fl!(LANGUAGE_LOADER, "vram-size"),
                 stats
-                    .vram
-                    .total
+                    .and_then(|stats| stats.vram.total)
+                    .or(self.vram_total)
                     .map(|size| format!("{} MiB", size / 1024 / 1024)),
             ));
         }
@@ -218,7 +506,11 @@ impl DeviceInfo {
                     write!(vram_type, " {width}-bit").unwrap();
                 }
 
-                if let Some(vram_vendor) = &drm_info.vram_vendor {
+                let vram_vendor = self
+                    .memory_vendor
+                    .as_ref()
+                    .or(drm_info.vram_vendor.as_ref());
+                if let Some(vram_vendor) = vram_vendor {
                     write!(vram_type, " ({vram_vendor})").unwrap();
                 }
 
@@ -309,6 +601,20 @@ impl DeviceInfo {
             }
         }
 
+        if self.drm_info.is_none() {
+            if let Some(vendor) = &self.memory_vendor {
+                elements.push((fl!(LANGUAGE_LOADER, "vram-vendor"), Some(vendor.clone())));
+            }
+        }
+
+        if let Some(bar) = &self.resizable_bar {
+            let mut size = format!("{} MiB", bar.current_size / 1024 / 1024);
+            if let Some(max_size) = bar.max_size() {
+                write!(size, " / {} MiB", max_size / 1024 / 1024).unwrap();
+            }
+            elements.push((fl!(LANGUAGE_LOADER, "rebar-size"), Some(size)));
+        }
+
         if let (Some(max_link_speed), Some(max_link_width)) =
             (&self.link_info.max_speed, &self.link_info.max_width)
         {
@@ -419,6 +725,106 @@ pub struct IntelClocksTable {
     pub rp0_freq: Option<u64>,
 }
 
+/// Inclusive range of values that a setting accepts
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueRange<T> {
+    pub min: T,
+    pub max: T,
+}
+
+impl<T> From<(T, T)> for ValueRange<T> {
+    fn from((min, max): (T, T)) -> Self {
+        Self { min, max }
+    }
+}
+
+/// Result of checking a fan curve against what the GPU accepts, without applying it
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FanCurveValidation {
+    /// Problems with the curve as a whole, such as an unsupported amount of points
+    pub errors: Vec<String>,
+    /// Every point of the curve, in the order of temperatures
+    pub points: Vec<FanCurvePointValidation>,
+}
+
+impl FanCurveValidation {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+            && self
+                .points
+                .iter()
+                .all(|point| point.temperature_error.is_none() && point.speed_error.is_none())
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FanCurvePointValidation {
+    pub temperature: CurveTemperature,
+    /// In the unit of the validated curve
+    pub speed: f32,
+    /// Set when the temperature of the point is not accepted
+    pub temperature_error: Option<String>,
+    /// Set when the speed of the point is not accepted
+    pub speed_error: Option<String>,
+}
+
+/// Settings that can be changed on a GPU, with the ranges they accept.
+/// Settings which the GPU doesn't support are left empty.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GpuCapabilities {
+    /// In watts
+    pub power_cap: Option<ValueRange<f64>>,
+    /// Clockspeeds are in MHz
+    pub max_core_clock: Option<ValueRange<i32>>,
+    pub min_core_clock: Option<ValueRange<i32>>,
+    pub max_memory_clock: Option<ValueRange<i32>>,
+    pub min_memory_clock: Option<ValueRange<i32>>,
+    /// Voltages are in mV
+    pub max_voltage: Option<ValueRange<i32>>,
+    pub min_voltage: Option<ValueRange<i32>>,
+    pub voltage_offset: Option<ValueRange<i32>>,
+    /// Clock offsets per power state
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub gpu_clock_offsets: IndexMap<u32, ValueRange<i32>>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub mem_clock_offsets: IndexMap<u32, ValueRange<i32>>,
+    #[serde(default)]
+    pub fan_control: bool,
+    /// Temperature range of the firmware fan curve in °C
+    pub fan_curve_temperature: Option<ValueRange<i32>>,
+    /// Speed range of the firmware fan curve in percent
+    pub fan_curve_speed: Option<ValueRange<u32>>,
+    /// Amount of points that the firmware fan curve must have
+    pub fan_curve_points: Option<u32>,
+    #[serde(default)]
+    pub performance_level: bool,
+    /// Performance levels which can be set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub performance_levels: Vec<PerformanceLevel>,
+    #[serde(default)]
+    pub power_profile_mode: bool,
+    /// LEDs which can be controlled, empty when the card has none
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub leds: Vec<LedInfo>,
+}
+
+/// LED of the card, exposed by the kernel through the LED class
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LedInfo {
+    pub name: String,
+    pub brightness: u32,
+    pub max_brightness: u32,
+    /// Color channels of a multicolor LED, such as `red`, `green` and `blue`.
+    /// Empty for LEDs with a single color.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub color_channels: Vec<String>,
+    /// Intensity of each color channel, from 0 to `max_brightness`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub color: Vec<u32>,
+}
+
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub struct NvidiaClockOffset {
     pub current: i32,
@@ -441,7 +847,7 @@ impl From<AmdClocksTableGen> for ClocksInfo {
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct LinkInfo {
     pub current_width: Option<String>,
     pub current_speed: Option<String>,
@@ -449,6 +855,12 @@ pub struct LinkInfo {
     pub max_speed: Option<String>,
 }
 
+impl LinkInfo {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct VulkanInfo {
     pub device_name: String,
@@ -490,6 +902,203 @@ pub struct PciInfo {
     pub model: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryMetric {
+    /// Power draw in watts
+    Power,
+    /// Hottest temperature sensor reading in °C
+    Temperature,
+    /// GPU clockspeed in MHz
+    GpuClockspeed,
+    /// VRAM clockspeed in MHz
+    VramClockspeed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ThrottleReason {
+    Power,
+    Current,
+    Thermal,
+    VoltageRegulator,
+    Other,
+}
+
+/// Potential hardware problem detected by the daemon. Temperatures are in °C
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HealthWarning {
+    VramTemperature {
+        temperature: f32,
+        threshold: f32,
+    },
+    /// The hotspot is much hotter than the rest of the GPU
+    HotspotDelta {
+        delta: f32,
+        threshold: f32,
+    },
+    /// The fan reports no RPM while the GPU is hot and the fan is being driven
+    StuckFan {
+        temperature: f32,
+        pwm: u8,
+    },
+}
+
+impl Display for HealthWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthWarning::VramTemperature {
+                temperature,
+                threshold,
+            } => write!(f, "VRAM temperature {temperature}°C is above {threshold}°C"),
+            HealthWarning::HotspotDelta { delta, threshold } => write!(
+                f,
+                "hotspot is {delta}°C hotter than the edge, more than {threshold}°C"
+            ),
+            HealthWarning::StuckFan { temperature, pwm } => write!(
+                f,
+                "fan is not spinning at {temperature}°C despite a PWM target of {pwm}"
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistorySample {
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    pub values: BTreeMap<HistoryMetric, f64>,
+}
+
+/// Aggregated stats of the samples recorded between the start and end of a capture
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CaptureSummary {
+    /// Unix timestamps in milliseconds
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub sample_count: usize,
+    /// Metrics which were not reported by the GPU during the capture are left out
+    pub metrics: BTreeMap<HistoryMetric, MetricSummary>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MetricSummary {
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Lowest and highest values of the stats since the daemon started or the extremes were reset
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionExtremes {
+    /// Unix timestamp in milliseconds
+    pub since: i64,
+    /// Metrics which were not reported by the GPU yet are left out
+    pub metrics: BTreeMap<HistoryMetric, ValueRange<f64>>,
+}
+
+/// Power cap which is expected to keep the GPU at a target temperature.
+/// This is only an estimate from a linear fit of the power draw and temperature in the stats
+/// history, which doesn't account for the ambient temperature, the fan speed or how long the
+/// GPU takes to heat up. It is not applied to the GPU.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PowerCapEstimate {
+    /// In watts
+    pub power_cap: f64,
+    /// In °C
+    pub target_temperature: f64,
+    /// How much the temperature rises with every watt of power draw
+    pub degrees_per_watt: f64,
+    /// Amount of history samples the estimate is based on
+    pub sample_count: usize,
+    /// The estimate was outside of the supported power cap range and got limited to it
+    pub clamped: bool,
+}
+
+/// A managed setting whose current value on the GPU doesn't match the stored config
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDifference {
+    pub setting: String,
+    pub stored: String,
+    pub current: String,
+}
+
+impl Display for ConfigDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is {}, expected {}",
+            self.setting, self.current, self.stored
+        )
+    }
+}
+
+/// Result of applying settings to all GPUs of the same model
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModelBroadcast {
+    /// Time in seconds until the changes are reverted unless confirmed.
+    /// `None` when no GPU was changed, so there is nothing to confirm.
+    pub apply_timer: Option<u64>,
+    /// Outcome for each GPU of the model
+    pub gpus: BTreeMap<String, BroadcastOutcome>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BroadcastOutcome {
+    Applied,
+    /// The GPU does not support some of the settings, so it was left unchanged
+    Skipped {
+        unsupported: Vec<String>,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// A sysfs write which was planned, but not performed due to the daemon running in dry run mode
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SysfsWrite {
+    pub path: String,
+    pub value: String,
+}
+
+#[skip_serializing_none]
+/// Result of checking whether one of the GPU controls can be written to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestEntry {
+    pub control: SelfTestControl,
+    pub path: Option<String>,
+    pub status: SelfTestStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfTestControl {
+    FanControlMode,
+    FanSpeed,
+    PowerCap,
+    PerformanceLevel,
+    Clocks,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SelfTestStatus {
+    /// The current value could be written back
+    Functional,
+    /// The file can't be written, due to its permissions or because the driver doesn't allow it
+    PermissionDenied,
+    /// The GPU doesn't have this control
+    Unsupported,
+    /// The write was rejected, e.g. by the firmware
+    Failed { message: String },
+    /// Not tested, as writing to the control in its current state could change the GPU settings
+    Skipped { reason: String },
+}
+
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct DeviceStats {
@@ -500,11 +1109,128 @@ pub struct DeviceStats {
     pub power: PowerStats,
     pub temps: HashMap<String, Temperature>,
     pub busy_percent: Option<u8>,
+    /// Busy percentages of the individual engines
+    #[serde(default)]
+    pub activity: ActivityStats,
     pub performance_level: Option<PerformanceLevel>,
+    /// Set instead of `performance_level` when the driver is in one of the profiling levels,
+    /// such as `profile_peak`, which can't be set through LACT
+    pub profiling_performance_level: Option<String>,
     pub core_power_state: Option<usize>,
     pub memory_power_state: Option<usize>,
     pub pcie_power_state: Option<usize>,
     pub throttle_info: Option<BTreeMap<String, Vec<String>>>,
+    /// Why the GPU is currently throttling, empty when it is not or when it's not reported
+    #[serde(default)]
+    pub throttle_reasons: Vec<ThrottleReason>,
+    /// Telemetry from the AMD `gpu_metrics` table, if it's available and the version is supported
+    pub gpu_metrics: Option<GpuMetricsStats>,
+    pub pcie_link: Option<LinkInfo>,
+    /// Forced memory clock DPM level, if any
+    pub memory_clock_level: Option<u8>,
+    /// Forced PCIe DPM level, if any
+    pub pcie_level: Option<u8>,
+    /// Name of the currently active power profile mode
+    pub power_profile_mode: Option<String>,
+    /// Voltage-frequency curve of the core clock, if the driver exposes it
+    pub vf_curve: Option<Vec<VfPoint>>,
+    /// Unit that temperatures should be shown in, the values in `temps` are always in °C
+    #[serde(default, skip_serializing_if = "TemperatureUnit::is_celsius")]
+    pub temperature_unit: TemperatureUnit,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VfPoint {
+    /// In MHz
+    pub clockspeed: u32,
+    /// In mV
+    pub voltage: u32,
+}
+
+/// Values decoded from a single read of the `gpu_metrics` table.
+/// Temperatures are in °C, power in W, voltages in mV, clocks in MHz and activity in percent.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct GpuMetricsStats {
+    pub format_revision: u8,
+    pub content_revision: u8,
+    pub temperature_edge: Option<u16>,
+    pub temperature_hotspot: Option<u16>,
+    pub temperature_mem: Option<u16>,
+    pub temperature_vrgfx: Option<u16>,
+    pub temperature_vrsoc: Option<u16>,
+    pub temperature_vrmem: Option<u16>,
+    pub average_gfx_activity: Option<u16>,
+    pub average_umc_activity: Option<u16>,
+    pub average_mm_activity: Option<u16>,
+    /// Power of the board, or of the whole package on APUs
+    pub average_socket_power: Option<u16>,
+    /// Power of the `SoC` alone, only reported separately by APUs
+    pub average_soc_power: Option<u16>,
+    pub average_gfxclk_frequency: Option<u16>,
+    pub average_uclk_frequency: Option<u16>,
+    pub current_gfxclk: Option<u16>,
+    pub current_uclk: Option<u16>,
+    pub current_fan_speed: Option<u16>,
+    pub pcie_link_width: Option<u16>,
+    /// In 0.1 GT/s
+    pub pcie_link_speed: Option<u16>,
+    pub voltage_soc: Option<u16>,
+    pub voltage_gfx: Option<u16>,
+    pub voltage_mem: Option<u16>,
+    /// Energy consumed by the GPU in units of [`GpuMetricsStats::ENERGY_UNIT`] joules,
+    /// counted from an arbitrary starting point
+    pub energy_accumulator: Option<u64>,
+    /// Only reported by APUs
+    pub fan_pwm: Option<u16>,
+    /// Throttling reasons in the ASIC-independent `SMU_THROTTLER_*` bit layout
+    pub indep_throttle_status: Option<u64>,
+    /// Values of each die by its index. GPUs with a single die only have the die 0.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dies: BTreeMap<u8, GpuDieStats>,
+}
+
+impl GpuMetricsStats {
+    /// Resolution of the energy accumulator in joules, about 15.26 µJ
+    pub const ENERGY_UNIT: f64 = 1.0 / 65536.0;
+
+    /// Energy in joules consumed between two reads of the energy accumulator.
+    /// Returns `None` when the counter went down, which happens when it gets reset
+    /// by a GPU reset or a driver reload.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn energy_between(previous: u64, current: u64) -> Option<f64> {
+        let delta = current.checked_sub(previous)?;
+        Some(delta as f64 * Self::ENERGY_UNIT)
+    }
+}
+
+/// Energy consumed by a GPU since the previous snapshot
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct EnergyDelta {
+    pub joules: f64,
+    /// Time since the previous snapshot
+    pub elapsed_ms: u64,
+}
+
+impl EnergyDelta {
+    /// Average power draw over the measured period in watts
+    pub fn average_power(&self) -> Option<f64> {
+        #[allow(clippy::cast_precision_loss)]
+        let seconds = self.elapsed_ms as f64 / 1000.0;
+        (seconds > 0.0).then(|| self.joules / seconds)
+    }
+}
+
+/// Values of a single die. GPUs which are made of several compute dies, such as the MI300 series,
+/// report some of them for each die, while the rest of the table covers the whole GPU.
+/// The multi-die `gpu_metrics` tables have the clock of each die, and since version 1.6 also
+/// its activity. They have no temperature for each die, it is only filled in for single die GPUs.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GpuDieStats {
+    pub temperature_hotspot: Option<u16>,
+    pub gfx_activity: Option<u16>,
+    pub current_gfxclk: Option<u16>,
 }
 
 #[skip_serializing_none]
@@ -512,6 +1238,8 @@ pub struct DeviceStats {
 pub struct FanStats {
     pub control_enabled: bool,
     pub control_mode: Option<FanControlMode>,
+    /// What the hardware is set to, regardless of whether LACT controls the fan
+    pub hardware_mode: Option<HardwareFanMode>,
     pub static_speed: Option<f32>,
     pub curve: Option<FanCurveMap>,
     pub pwm_current: Option<u8>,
@@ -526,6 +1254,9 @@ pub struct FanStats {
     pub change_threshold: Option<u64>,
     /// Nvidia-only
     pub auto_threshold: Option<u64>,
+    /// The fan speed is reported, but it cannot be controlled
+    #[serde(default)]
+    pub monitor_only: bool,
     // RDNA3+ params
     #[serde(default)]
     pub pmfw_info: PmfwInfo,
@@ -542,6 +1273,17 @@ pub struct PmfwInfo {
     pub zero_rpm_temperature: Option<FanInfo>,
 }
 
+/// How the fan reacted to a step between two PWM values
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FanResponse {
+    /// Time from setting the target PWM until the speed stopped changing
+    pub settle_time_ms: u64,
+    /// Speed at the starting PWM
+    pub initial_rpm: u32,
+    /// Speed the fan settled at with the target PWM
+    pub final_rpm: u32,
+}
+
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
 pub struct ClockspeedStats {
@@ -549,6 +1291,8 @@ pub struct ClockspeedStats {
     /// Target clock
     pub current_gfxclk: Option<u64>,
     pub vram_clockspeed: Option<u64>,
+    /// Memory clock offset of the current power state in MHz
+    pub vram_clock_offset: Option<i32>,
 }
 
 #[skip_serializing_none]
@@ -556,6 +1300,8 @@ pub struct ClockspeedStats {
 pub struct VoltageStats {
     pub gpu: Option<u64>,
     pub northbridge: Option<u64>,
+    /// Currently applied voltage offset in mV
+    pub offset: Option<i32>,
 }
 
 #[skip_serializing_none]
@@ -563,17 +1309,220 @@ pub struct VoltageStats {
 pub struct VramStats {
     pub total: Option<u64>,
     pub used: Option<u64>,
+    /// Corrected memory errors since the driver was loaded, if the memory has error correction.
+    /// An increasing count can mean that the memory is overclocked too far.
+    pub ecc_errors: Option<u64>,
+}
+
+/// Preferred unit for showing temperatures, set in the daemon config.
+/// Temperatures are always collected and stored in °C, this only affects how they are displayed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    pub fn is_celsius(&self) -> bool {
+        *self == Self::Celsius
+    }
+
+    /// Converts a temperature in °C into this unit
+    pub fn from_celsius(self, celsius: f32) -> f32 {
+        match self {
+            Self::Celsius => celsius,
+            Self::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    /// Converts a temperature in this unit back into °C, for values entered by the user
+    pub fn to_celsius(self, value: f32) -> f32 {
+        match self {
+            Self::Celsius => value,
+            Self::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Celsius => "°C",
+            Self::Fahrenheit => "°F",
+        }
+    }
+
+    /// Formats a temperature in °C with the symbol of this unit, rounded to one decimal place
+    pub fn format(self, celsius: f32) -> String {
+        let value = (self.from_celsius(celsius) * 10.0).round() / 10.0;
+        format!("{value}{}", self.symbol())
+    }
+}
+
+/// GPU usage in percent. GPUs which don't report the usage of each engine only have `busy` set.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ActivityStats {
+    /// Overall usage, the same as `busy_percent`
+    pub busy: Option<u8>,
+    pub graphics: Option<u8>,
+    /// Video encoding and decoding
+    pub media: Option<u8>,
+    pub memory_controller: Option<u8>,
 }
 
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
 pub struct PowerStats {
+    /// Power of the whole board, or of the whole package on APUs
     pub average: Option<f64>,
     pub current: Option<f64>,
-    pub cap_current: Option<f64>,
-    pub cap_max: Option<f64>,
-    pub cap_min: Option<f64>,
-    pub cap_default: Option<f64>,
+    /// Power of the GPU chip alone, for GPUs which report it separately from the board power
+    pub chip: Option<f64>,
+    /// Replaces the separate `cap_*` fields of schema versions before 0.9.
+    /// These are flattened, so the serialized format is the same as before.
+    #[serde(flatten)]
+    pub cap: PowerCap,
+    /// Current cap as a percentage of the default cap
+    pub cap_percent: Option<f64>,
+}
+
+/// Power cap values in watts. GPUs can report any subset of these,
+/// so each of them has to be checked separately.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct PowerCap {
+    #[serde(rename = "cap_current")]
+    pub current: Option<f64>,
+    #[serde(rename = "cap_max")]
+    pub max: Option<f64>,
+    #[serde(rename = "cap_min")]
+    pub min: Option<f64>,
+    #[serde(rename = "cap_default")]
+    pub default: Option<f64>,
+}
+
+/// Diagnostics recorded when the daemon crashed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CrashInfo {
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    pub message: String,
+    pub location: Option<String>,
+    pub thread: Option<String>,
+    pub backtrace: String,
+    /// Settings which were most recently applied to each GPU before the crash
+    pub gpu_configs: BTreeMap<String, GpuConfig>,
+}
+
+/// Which client changed a GPU setting, recorded when the change was applied
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SettingProvenance {
+    /// Name of the request which made the change, e.g. `set_power_cap`
+    pub request: String,
+    /// Only known for clients connected over the Unix socket
+    pub client: Option<ClientCredentials>,
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+}
+
+/// Significant action taken by the daemon
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DaemonEvent {
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    #[serde(flatten)]
+    pub kind: DaemonEventKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DaemonEventKind {
+    /// New settings of a GPU were applied, they still have to be confirmed to be kept
+    SettingsApplied {
+        gpu_id: String,
+    },
+    /// Unconfirmed settings were reverted, either by a client or because the confirmation timed out
+    SettingsReverted {
+        gpu_ids: Vec<String>,
+        timed_out: bool,
+    },
+    ProfileSwitched {
+        profile: Option<String>,
+    },
+    /// Temperatures are in °C
+    ThermalProtectionTriggered {
+        gpu_id: String,
+        temperature: f32,
+    },
+    ThermalProtectionReverted {
+        gpu_id: String,
+        temperature: f32,
+    },
+}
+
+/// Long-running operation which is in progress and can be cancelled
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OperationInfo {
+    pub id: u64,
+    pub kind: OperationKind,
+    pub gpu_id: String,
+    /// Unix timestamp in milliseconds
+    pub started_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    FanResponseMeasurement,
+    /// Applied settings which are waiting to be confirmed, cancelling it reverts them
+    PendingConfirmation,
+}
+
+/// Credentials of the process on the other end of the socket
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientCredentials {
+    pub pid: Option<i32>,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Combined power draw of all managed GPUs
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SystemPower {
+    /// In watts
+    pub total: f64,
+    /// Power draw of each GPU that is included in the total
+    pub gpus: IndexMap<String, f64>,
+    /// GPUs which don't report their power draw
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excluded_gpus: Vec<String>,
+}
+
+impl PowerCap {
+    /// Percentage of the default cap that the current cap is set to
+    pub fn current_percent(&self) -> Option<f64> {
+        let default = self.default.filter(|cap| *cap > 0.0)?;
+        Some(self.current? / default * 100.0)
+    }
+
+    /// Computes the cap in watts for a percentage of the default cap,
+    /// clamped to the range allowed by the GPU.
+    /// Returns `None` when the GPU doesn't report the required cap values.
+    pub fn from_percent(&self, percent: f64) -> Option<f64> {
+        let default = self.default.filter(|cap| *cap > 0.0)?;
+        let (min, max) = self.range()?;
+
+        Some((default * percent / 100.0).min(max).max(min))
+    }
+
+    /// Range that the cap can be set in. The minimum is assumed to be 0 when it is not reported,
+    /// ranges which don't allow any adjustment are left out.
+    pub fn range(&self) -> Option<(f64, f64)> {
+        let max = self.max?;
+        let min = self.min.unwrap_or(0.0);
+        (min < max).then_some((min, max))
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -633,6 +1582,7 @@ pub struct FanOptions<'a> {
     pub pmfw: PmfwOptions,
     pub spindown_delay_ms: Option<u64>,
     pub change_threshold: Option<u64>,
+    pub curve_offset: Option<f32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
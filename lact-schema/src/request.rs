@@ -1,10 +1,14 @@
 use std::fmt;
 
 use crate::{
-    config::{GpuConfig, Profile, ProfileHooks},
-    FanOptions, ProfileRule,
+    config::{
+        ClockVoltagePoint, FanCurve, FanCurvePreset, FanCurveUnit, GpuConfig, LedSettings, Profile,
+        ProfileHooks,
+    },
+    CompressionCodec, FanOptions, HistoryMetric, ProfileRule, StatsExportFormat,
 };
 use amdgpu_sysfs::gpu_handle::{PerformanceLevel, PowerLevelKind};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -12,27 +16,179 @@ use serde::{Deserialize, Serialize};
 pub enum Request<'a> {
     Ping,
     ListDevices,
+    /// Sets the name shown for a GPU in place of its model, or clears it when `None`
+    SetGpuAlias {
+        id: &'a str,
+        alias: Option<String>,
+    },
     SystemInfo,
+    /// Versions and features of the daemon, for checking compatibility with the client
+    GetDaemonInfo,
+    /// Enables compression of large responses on this connection, using the first of the given
+    /// codecs which the daemon supports. Returns the chosen codec, if any.
+    NegotiateCompression {
+        codecs: Vec<CompressionCodec>,
+    },
     DeviceInfo {
         id: &'a str,
     },
     DeviceStats {
         id: &'a str,
     },
+    /// Reads the stats from the hardware right away
+    GetStatsNow {
+        id: &'a str,
+    },
+    /// Lists the settings whose current values differ from the stored config
+    GetConfigDiff {
+        id: &'a str,
+    },
+    GetSystemPower,
+    /// Summary of every GPU with one entry per GPU, meant for showing in a terminal
+    GetStatusLine,
+    GetLastCrash,
+    /// Stop applying any settings and leave the GPUs at their defaults, until enabled again
+    SetManagementEnabled {
+        enabled: bool,
+    },
+    /// Changes the log level until the daemon is restarted, using the same syntax as the config
+    SetLogLevel {
+        level: &'a str,
+    },
+    GetHealthWarnings {
+        id: &'a str,
+    },
+    /// Which client last changed the settings of a GPU, for each kind of change.
+    /// Changes which need confirmation are only recorded once they are confirmed
+    GetSettingProvenance {
+        id: &'a str,
+    },
+    /// Recent actions of the daemon, from oldest to newest
+    GetEventLog {
+        /// Only return the events from this Unix timestamp in milliseconds onwards
+        #[serde(default)]
+        since: Option<i64>,
+    },
+    /// Long-running operations which are in progress, such as fan response measurements
+    /// and settings which are waiting for confirmation
+    ListOperations,
+    /// Stops a long-running operation, the settings it changed get restored
+    CancelOperation {
+        id: u64,
+    },
     DeviceClocksInfo {
         id: &'a str,
     },
+    GetHistory {
+        id: &'a str,
+        /// Metrics to include, all metrics are returned when empty
+        #[serde(default)]
+        metrics: Vec<HistoryMetric>,
+    },
+    /// Starts aggregating the stats samples of a GPU, restarting the capture if one is running
+    BeginCapture {
+        id: &'a str,
+    },
+    /// Ends the capture and returns the aggregated stats recorded since it was started
+    EndCapture {
+        id: &'a str,
+    },
+    /// Lowest and highest values of the stats since the daemon started or since the last reset
+    GetExtremes {
+        id: &'a str,
+    },
+    ResetExtremes {
+        id: &'a str,
+    },
+    /// Estimates the power cap which keeps the GPU at the given temperature in °C,
+    /// based on the stats history. Nothing is applied.
+    EstimatePowerCap {
+        id: &'a str,
+        target_temperature: f64,
+    },
+    /// Energy consumed by the GPU since the previous `EnergyDelta` of the same GPU,
+    /// which starts a new measurement. The first one only starts the measurement,
+    /// as does one after the energy counter was reset.
+    EnergyDelta {
+        id: &'a str,
+    },
+    ExportStats {
+        id: &'a str,
+        #[serde(default)]
+        format: StatsExportFormat,
+        /// Unix timestamp in milliseconds, only samples recorded after it are included
+        #[serde(default)]
+        since: Option<i64>,
+    },
+    GetCapabilities {
+        id: &'a str,
+    },
+    /// Kernel, driver and firmware versions, for including in bug reports
+    GetVersions {
+        id: &'a str,
+    },
+    /// Returns a curve for previewing, it is not applied
+    GenerateFanCurve {
+        id: &'a str,
+        preset: FanCurvePreset,
+    },
+    /// Checks each point of the curve against the ranges allowed by the GPU, without applying it
+    ValidateFanCurve {
+        id: &'a str,
+        curve: FanCurve,
+        #[serde(default)]
+        unit: FanCurveUnit,
+    },
+    /// Reads the curve currently used by the firmware fan control, as a software curve.
+    /// Only available on GPUs with a PMFW fan curve (RDNA3 and newer).
+    GetFirmwareFanCurve {
+        id: &'a str,
+    },
     DevicePowerProfileModes {
         id: &'a str,
     },
     SetFanControl(FanOptions<'a>),
+    /// Holds the fan at a fixed PWM value, without evaluating the fan curve
+    SetFanStatic {
+        id: &'a str,
+        pwm: u8,
+    },
     ResetPmfw {
         id: &'a str,
     },
+    /// Toggles the zero RPM mode of the firmware fan control, available on RDNA3 and newer
+    SetFanZeroRpm {
+        id: &'a str,
+        enabled: bool,
+    },
+    /// Switches the fan to `from_pwm`, then measures how long the speed takes to settle
+    /// after stepping to `to_pwm`. The previous fan settings are restored afterwards.
+    MeasureFanResponse {
+        id: &'a str,
+        from_pwm: u8,
+        to_pwm: u8,
+    },
     SetPowerCap {
         id: &'a str,
         cap: Option<f64>,
     },
+    /// Sets the power cap relative to the default cap of the GPU
+    SetPowerCapPercent {
+        id: &'a str,
+        percent: f64,
+    },
+    /// Sets the GPU voltage offset in mV, clamped to the range reported by the GPU
+    SetVoltageOffset {
+        id: &'a str,
+        offset: Option<i32>,
+    },
+    /// Sets the memory clock offset in MHz on all power states.
+    /// Offsets outside of the range reported for any of the states are rejected.
+    /// On AMD the offset is applied to the stock maximum memory clock, in place of a configured maximum memory clock.
+    SetMemoryClockOffset {
+        id: &'a str,
+        offset: Option<i32>,
+    },
     SetPerformanceLevel {
         id: &'a str,
         performance_level: PerformanceLevel,
@@ -59,6 +215,46 @@ pub enum Request<'a> {
         kind: PowerLevelKind,
         states: Vec<u8>,
     },
+    SetClockStates {
+        id: &'a str,
+        kind: PowerLevelKind,
+        states: IndexMap<u8, ClockVoltagePoint>,
+    },
+    /// Overrides a single clock state, leaving the other states as they are.
+    /// Removes the override of the state when no point is given.
+    SetClockState {
+        id: &'a str,
+        kind: PowerLevelKind,
+        index: u8,
+        point: Option<ClockVoltagePoint>,
+    },
+    SetMemoryClockLevel {
+        id: &'a str,
+        level: Option<u8>,
+    },
+    SetPcieLevel {
+        id: &'a str,
+        level: Option<u8>,
+    },
+    /// Keeps the GPU at its lowest core and memory clock levels until disabled again
+    SetIdleLock {
+        id: &'a str,
+        enabled: bool,
+    },
+    /// Sets the brightness and color of an LED, `None` stops managing it
+    SetLed {
+        id: &'a str,
+        name: &'a str,
+        settings: Option<LedSettings>,
+    },
+    GetDryRunLog {
+        id: &'a str,
+    },
+    /// Writes the current value back into each control of the GPU,
+    /// reporting which of them can actually be changed
+    RunSelfTest {
+        id: &'a str,
+    },
     VbiosDump {
         id: &'a str,
     },
@@ -74,6 +270,12 @@ pub enum Request<'a> {
         #[serde(default)]
         auto_switch: bool,
     },
+    /// Switches to a profile for a limited time, after which the previous profile is restored.
+    /// Applying another temporary profile while one is active restarts the timer.
+    ApplyTemporaryProfile {
+        name: String,
+        duration_secs: u64,
+    },
     CreateProfile {
         name: String,
         base: ProfileBase,
@@ -101,16 +303,142 @@ pub enum Request<'a> {
         id: &'a str,
         config: GpuConfig,
     },
+    /// Returns the settings of the GPU as a YAML snippet which can be imported on another system
+    ExportGpuConfig {
+        id: &'a str,
+    },
+    /// Applies settings from an exported snippet, which needs to be confirmed like `SetGpuConfig`
+    ImportGpuConfig {
+        id: &'a str,
+        snippet: String,
+        /// Import settings which were exported from a different GPU model
+        #[serde(default)]
+        allow_model_mismatch: bool,
+    },
+    /// Returns the whole daemon config in the format of the config file
+    ExportConfig,
+    /// Replaces the GPU settings, profiles and aliases with the ones from an exported config.
+    /// Returns the time left for confirming the settings when they are applied.
+    ImportConfig {
+        config: String,
+        /// Apply the settings to the GPUs right away, they then have to be confirmed before
+        /// they get saved. Otherwise they are saved and used the next time the config gets applied
+        #[serde(default)]
+        apply: bool,
+        /// Import a config with settings for GPUs which are not present on this system
+        #[serde(default)]
+        allow_missing_gpus: bool,
+    },
+    /// Applies the settings which are set in the changes to every GPU of the given model,
+    /// identified by the PCI vendor and device id such as `1002:73BF`.
+    /// The changes of all GPUs are confirmed together.
+    BroadcastToModel {
+        model: &'a str,
+        changes: GpuConfig,
+    },
+    /// Applies the config and reverts it unless `ConfirmSettings` is sent within the timeout,
+    /// or when the connection that staged it is closed first
+    StageSettings {
+        id: &'a str,
+        config: GpuConfig,
+        /// Defaults to the configured apply settings timer
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+    /// Confirms the settings staged with `StageSettings` on the same connection
+    ConfirmSettings,
     ProcessList {
         id: &'a str,
     },
     EnableOverdrive,
     DisableOverdrive,
+    GetModuleConfigStatus,
+    /// Reports whether overclocking is enabled in the amdgpu module now and after the next boot
+    GetOverclockStatus,
+    /// Writes the amdgpu module config, which is applied after a reboot.
+    /// Masks which disable the clock DPM or the power containment are rejected.
+    SetModuleConfig {
+        ppfeaturemask: u64,
+    },
     GenerateSnapshot,
     ConfirmPendingConfig(ConfirmCommand),
     RestConfig,
 }
 
+impl Request<'_> {
+    /// The GPU id and command name of requests which change the settings of a GPU.
+    /// Every request which is not read-only and targets a GPU by its id counts as one,
+    /// apart from the ones which leave the settings as they are.
+    pub fn gpu_setting_change(&self) -> Option<(String, String)> {
+        if self.is_read_only()
+            || matches!(
+                self,
+                Request::SetGpuAlias { .. }
+                    | Request::BeginCapture { .. }
+                    | Request::EndCapture { .. }
+                    | Request::ResetExtremes { .. }
+                    | Request::EnergyDelta { .. }
+                    | Request::MeasureFanResponse { .. }
+                    | Request::RunSelfTest { .. }
+            )
+        {
+            return None;
+        }
+
+        let value = serde_json::to_value(self).ok()?;
+        let id = value.get("args")?.get("id")?.as_str()?;
+        let command = value.get("command")?.as_str()?;
+        Some((id.to_owned(), command.to_owned()))
+    }
+
+    /// Requests which don't change any settings or daemon state affecting the GPUs,
+    /// these are served to clients which are not allowed to change settings
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Request::Ping
+                | Request::ListDevices
+                | Request::SystemInfo
+                | Request::GetDaemonInfo
+                | Request::NegotiateCompression { .. }
+                | Request::DeviceInfo { .. }
+                | Request::DeviceStats { .. }
+                | Request::GetStatsNow { .. }
+                | Request::GetConfigDiff { .. }
+                | Request::GetSystemPower
+                | Request::GetStatusLine
+                | Request::GetLastCrash
+                | Request::GetHealthWarnings { .. }
+                | Request::GetSettingProvenance { .. }
+                | Request::GetEventLog { .. }
+                | Request::ListOperations
+                | Request::DeviceClocksInfo { .. }
+                | Request::GetHistory { .. }
+                | Request::GetExtremes { .. }
+                | Request::EstimatePowerCap { .. }
+                | Request::ExportStats { .. }
+                | Request::GetCapabilities { .. }
+                | Request::GetVersions { .. }
+                | Request::GenerateFanCurve { .. }
+                | Request::ValidateFanCurve { .. }
+                | Request::GetFirmwareFanCurve { .. }
+                | Request::DevicePowerProfileModes { .. }
+                | Request::GetPowerStates { .. }
+                | Request::GetDryRunLog { .. }
+                | Request::VbiosDump { .. }
+                | Request::ListProfiles { .. }
+                | Request::GetProfile { .. }
+                | Request::EvaluateProfileRule { .. }
+                | Request::GetGpuConfig { .. }
+                | Request::ExportGpuConfig { .. }
+                | Request::ExportConfig
+                | Request::ProcessList { .. }
+                | Request::GetModuleConfigStatus
+                | Request::GetOverclockStatus
+        )
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(tag = "command", rename_all = "snake_case")]
 pub enum ConfirmCommand {
@@ -175,6 +503,69 @@ mod tests {
         request::{ClockspeedType, SetClocksCommand},
         Request,
     };
+    use amdgpu_sysfs::gpu_handle::PowerLevelKind;
+
+    #[test]
+    fn setting_change_names() {
+        let requests = [
+            Request::SetPowerCap {
+                id: "asd",
+                cap: Some(100.0),
+            },
+            Request::SetPcieLevel {
+                id: "asd",
+                level: None,
+            },
+            Request::SetFanZeroRpm {
+                id: "asd",
+                enabled: true,
+            },
+            Request::SetFanStatic {
+                id: "asd",
+                pwm: 128,
+            },
+            Request::SetIdleLock {
+                id: "asd",
+                enabled: false,
+            },
+            Request::SetMemoryClockOffset {
+                id: "asd",
+                offset: Some(500),
+            },
+            Request::SetClocksValue {
+                id: "asd",
+                command: SetClocksCommand::reset(),
+            },
+            Request::SetClockState {
+                id: "asd",
+                kind: PowerLevelKind::CoreClock,
+                index: 1,
+                point: None,
+            },
+        ];
+
+        for request in requests {
+            let value = serde_json::to_value(&request).unwrap();
+            let (id, name) = request.gpu_setting_change().unwrap();
+            assert_eq!("asd", id);
+            assert_eq!(value["command"], name);
+        }
+
+        // Changes which don't touch the settings of a GPU
+        let requests = [
+            Request::ListDevices,
+            Request::SetGpuAlias {
+                id: "asd",
+                alias: None,
+            },
+            Request::ResetExtremes { id: "asd" },
+            Request::CancelOperation { id: 1 },
+            Request::SetManagementEnabled { enabled: true },
+        ];
+        for request in requests {
+            assert_eq!(None, request.gpu_setting_change(), "{request:?}");
+        }
+    }
 
     #[test]
     fn deserialize_requests() {
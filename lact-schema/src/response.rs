@@ -1,14 +1,91 @@
 use serde::{Deserialize, Serialize};
+use std::{fmt, io};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "status", content = "data", rename_all = "snake_case")]
 pub enum Response<T> {
     Ok(T),
-    Error(serde_error::Error),
+    Error(ResponseError),
 }
 
 impl<T> From<anyhow::Error> for Response<T> {
     fn from(value: anyhow::Error) -> Self {
-        Response::Error(serde_error::Error::new(&*value))
+        Response::Error(ResponseError {
+            kind: ErrorKind::from_error(&value),
+            error: serde_error::Error::new(&*value),
+        })
+    }
+}
+
+/// Machine-readable category of an error, which clients can act on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    GpuNotFound,
+    OutOfRange,
+    Unsupported,
+    PermissionDenied,
+    HardwareBusy,
+}
+
+impl ErrorKind {
+    /// Creates a new error with this kind attached to it
+    pub fn error(self, message: impl Into<String>) -> anyhow::Error {
+        anyhow::Error::new(KindError {
+            kind: self,
+            message: message.into(),
+        })
+    }
+
+    /// Finds the kind of an error, either explicitly attached or derived from an IO error cause
+    pub fn from_error(error: &anyhow::Error) -> Option<Self> {
+        if let Some(kind_error) = error.downcast_ref::<KindError>() {
+            return Some(kind_error.kind);
+        }
+
+        error.chain().find_map(|cause| {
+            let io_error = cause.downcast_ref::<io::Error>()?;
+            match io_error.kind() {
+                io::ErrorKind::PermissionDenied => Some(Self::PermissionDenied),
+                io::ErrorKind::ResourceBusy => Some(Self::HardwareBusy),
+                io::ErrorKind::Unsupported => Some(Self::Unsupported),
+                _ => None,
+            }
+        })
+    }
+}
+
+/// Error with an [`ErrorKind`] attached, displayed as just the message
+#[derive(Debug)]
+pub struct KindError {
+    pub kind: ErrorKind,
+    message: String,
+}
+
+impl fmt::Display for KindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for KindError {}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResponseError {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<ErrorKind>,
+    #[serde(flatten)]
+    pub error: serde_error::Error,
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for ResponseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        std::error::Error::source(&self.error)
     }
 }
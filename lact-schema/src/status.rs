@@ -0,0 +1,82 @@
+use crate::{DeviceStats, TemperatureUnit};
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::fmt::{self, Display};
+
+/// Short summary of the current state of a GPU, meant to be shown as a single line in a terminal
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GpuStatusLine {
+    pub id: String,
+    /// The alias of the GPU if it has one, otherwise the model name
+    pub name: Option<String>,
+    /// Temperature of the hottest sensor in °C
+    pub temperature: Option<f32>,
+    /// In watts
+    pub power: Option<f64>,
+    /// In MHz
+    pub core_clock: Option<u64>,
+    /// Fan speed in percent
+    pub fan_percent: Option<u8>,
+    pub fan_rpm: Option<u32>,
+    #[serde(default, skip_serializing_if = "TemperatureUnit::is_celsius")]
+    pub temperature_unit: TemperatureUnit,
+}
+
+impl GpuStatusLine {
+    pub fn new(id: String, name: Option<String>, stats: &DeviceStats) -> Self {
+        let temperature = stats
+            .temps
+            .values()
+            .filter_map(|temp| temp.current)
+            .reduce(f32::max);
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let fan_percent = stats
+            .fan
+            .pwm_current
+            .map(|pwm| (f64::from(pwm) / 2.55).round() as u8);
+
+        Self {
+            id,
+            name,
+            temperature,
+            power: stats.power.current.or(stats.power.average),
+            core_clock: stats.clockspeed.gpu_clockspeed,
+            fan_percent,
+            fan_rpm: stats.fan.speed_current,
+            temperature_unit: stats.temperature_unit,
+        }
+    }
+}
+
+/// Formats the values in a fixed order, showing values which the GPU doesn't report as `-`
+impl Display for GpuStatusLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{} ({name}):", self.id)?,
+            None => write!(f, "{}:", self.id)?,
+        }
+
+        match self.temperature {
+            Some(temp) => write!(f, " {}", self.temperature_unit.format(temp))?,
+            None => write!(f, " -")?,
+        }
+        match self.power {
+            Some(power) => write!(f, ", {power:.1} W")?,
+            None => write!(f, ", - W")?,
+        }
+        match self.core_clock {
+            Some(clock) => write!(f, ", {clock} MHz")?,
+            None => write!(f, ", - MHz")?,
+        }
+
+        write!(f, ", fan ")?;
+        match (self.fan_percent, self.fan_rpm) {
+            (Some(percent), Some(rpm)) => write!(f, "{percent}% ({rpm} RPM)"),
+            (Some(percent), None) => write!(f, "{percent}%"),
+            (None, Some(rpm)) => write!(f, "{rpm} RPM"),
+            (None, None) => write!(f, "-"),
+        }
+    }
+}
@@ -1,4 +1,10 @@
-use crate::{FanControlMode, FanOptions, PmfwOptions, Pong, Request, Response};
+use crate::{
+    config::FanCurve, history_to_csv, CompressedFrame, CompressionCodec, CurveTemperature,
+    DeviceStats, EnergyDelta, ErrorKind, FanControlMode, FanOptions, GpuMetricsStats,
+    GpuStatusLine, HistoryMetric, HistorySample, PmfwOptions, Pong, PowerCap, PowerStats, Request,
+    Response, StatsExportFormat, TemperatureUnit, COMPRESSION_THRESHOLD,
+};
+use amdgpu_sysfs::hw_mon::Temperature;
 use anyhow::anyhow;
 use serde_json::json;
 use std::collections::BTreeMap;
@@ -59,6 +65,34 @@ fn error_response() {
     assert_eq!(serde_json::to_value(response).unwrap(), expected_response);
 }
 
+#[test]
+fn error_response_with_kind() {
+    let expected_response = json!({
+        "data": {
+            "kind": "out_of_range",
+            "description": "Could not set fan control",
+            "source": {
+                "description": "Fan speed percentage must be between 0 and 1",
+                "source": null
+            }
+        },
+        "status": "error"
+    });
+
+    let error = ErrorKind::OutOfRange
+        .error("Fan speed percentage must be between 0 and 1")
+        .context("Could not set fan control");
+
+    let response = Response::<()>::from(error);
+    assert_eq!(serde_json::to_value(&response).unwrap(), expected_response);
+
+    let deserialized: Response<()> = serde_json::from_value(expected_response).unwrap();
+    let Response::Error(error) = deserialized else {
+        panic!("expected an error response");
+    };
+    assert_eq!(Some(ErrorKind::OutOfRange), error.kind);
+}
+
 #[test]
 fn set_fan_clocks() {
     let value = r#"{
@@ -79,10 +113,333 @@ fn set_fan_clocks() {
         enabled: true,
         mode: Some(FanControlMode::Curve),
         static_speed: None,
-        curve: Some(BTreeMap::from([(30, 30.0), (50, 50.0)])),
+        curve: Some(FanCurve::from([(30, 30.0), (50, 50.0)]).0),
         pmfw: PmfwOptions::default(),
         spindown_delay_ms: None,
         change_threshold: None,
+        curve_offset: None,
     });
     assert_eq!(expected_request, request);
 }
+
+#[test]
+fn fractional_fan_curve_points() {
+    let curve: FanCurve =
+        serde_json::from_str(r#"{"40": 0.3, "42.5": 0.4, "45.25": 0.5, "50.0": 0.6}"#).unwrap();
+    let temps: Vec<f32> = curve.0.keys().map(|temp| temp.celsius()).collect();
+    assert_eq!(vec![40.0, 42.5, 45.3, 50.0], temps);
+
+    // Whole degrees are still written as integers
+    assert_eq!(
+        r#"{"40":0.3,"42.5":0.4,"45.3":0.5,"50":0.6}"#,
+        serde_json::to_string(&curve).unwrap()
+    );
+
+    assert_eq!(43, CurveTemperature::from_celsius(42.5).rounded());
+    assert!(serde_json::from_str::<FanCurve>(r#"{"hot": 0.3}"#).is_err());
+}
+
+#[test]
+fn export_stats_request() {
+    let value = r#"{
+        "command": "export_stats",
+        "args": {
+            "id": "123",
+            "since": 1700000000000
+        }
+    }"#;
+    let request: Request = serde_json::from_str(value).unwrap();
+    assert_eq!(
+        Request::ExportStats {
+            id: "123",
+            format: StatsExportFormat::Csv,
+            since: Some(1_700_000_000_000),
+        },
+        request
+    );
+}
+
+#[test]
+fn memory_clock_offset_request() {
+    let value = r#"{
+        "command": "set_memory_clock_offset",
+        "args": {
+            "id": "123",
+            "offset": -250
+        }
+    }"#;
+    let request: Request = serde_json::from_str(value).unwrap();
+    assert_eq!(
+        Request::SetMemoryClockOffset {
+            id: "123",
+            offset: Some(-250),
+        },
+        request
+    );
+
+    // Removing the offset
+    let request = Request::SetMemoryClockOffset {
+        id: "123",
+        offset: None,
+    };
+    assert_eq!(
+        json!({"command": "set_memory_clock_offset", "args": {"id": "123", "offset": null}}),
+        serde_json::to_value(&request).unwrap()
+    );
+}
+
+#[test]
+fn history_csv() {
+    let samples = [
+        HistorySample {
+            timestamp: 1_700_000_000_000,
+            values: BTreeMap::from([
+                (HistoryMetric::Power, 150.5),
+                (HistoryMetric::Temperature, 60.0),
+                (HistoryMetric::GpuClockspeed, 2400.0),
+                (HistoryMetric::VramClockspeed, 1250.0),
+            ]),
+        },
+        HistorySample {
+            timestamp: 1_700_000_001_250,
+            values: BTreeMap::from([(HistoryMetric::Temperature, 61.0)]),
+        },
+    ];
+
+    let expected = "\
+timestamp,power_w,temperature_c,gpu_clockspeed_mhz,vram_clockspeed_mhz
+2023-11-14T22:13:20.000Z,150.5,60,2400,1250
+2023-11-14T22:13:21.250Z,,61,,
+";
+    assert_eq!(expected, history_to_csv(&samples));
+    assert_eq!(
+        "timestamp,power_w,temperature_c,gpu_clockspeed_mhz,vram_clockspeed_mhz\n",
+        StatsExportFormat::Csv.serialize(&[])
+    );
+}
+
+#[test]
+fn power_cap_from_percent() {
+    let cap = PowerCap {
+        current: Some(200.0),
+        default: Some(250.0),
+        max: Some(300.0),
+        min: Some(150.0),
+    };
+
+    assert_eq!(Some(80.0), cap.current_percent());
+    assert_eq!(Some(200.0), cap.from_percent(80.0));
+    assert_eq!(Some(250.0), cap.from_percent(100.0));
+    // Clamped to the allowed range
+    assert_eq!(Some(300.0), cap.from_percent(150.0));
+    assert_eq!(Some(150.0), cap.from_percent(20.0));
+
+    let unsupported = PowerCap {
+        current: Some(200.0),
+        ..Default::default()
+    };
+    assert_eq!(None, unsupported.current_percent());
+    assert_eq!(None, unsupported.from_percent(80.0));
+}
+
+#[test]
+fn power_cap_fields() {
+    // The cap values are kept next to the other power stats on the wire
+    let value = json!({
+        "current": 120.0,
+        "cap_current": 155.0,
+        "cap_max": 201.0,
+    });
+    let stats: PowerStats = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(
+        PowerCap {
+            current: Some(155.0),
+            max: Some(201.0),
+            ..Default::default()
+        },
+        stats.cap
+    );
+    assert_eq!(Some((0.0, 201.0)), stats.cap.range());
+    assert_eq!(value, serde_json::to_value(stats).unwrap());
+}
+
+#[test]
+fn temperature_unit_conversion() {
+    let unit = TemperatureUnit::Fahrenheit;
+    assert_eq!(32.0, unit.from_celsius(0.0));
+    assert_eq!(-40.0, unit.from_celsius(-40.0));
+    // The temperature range allowed in the firmware fan curves
+    assert_eq!(77.0, unit.from_celsius(25.0));
+    assert_eq!(212.0, unit.from_celsius(100.0));
+    // The range of the generated fan curve presets
+    assert_eq!(104.0, unit.from_celsius(40.0));
+    assert_eq!(194.0, unit.from_celsius(90.0));
+
+    for celsius in (25u8..=100).map(f32::from) {
+        let converted_back = unit.to_celsius(unit.from_celsius(celsius));
+        assert!((celsius - converted_back).abs() < 0.001, "{celsius}");
+        assert_eq!(celsius, TemperatureUnit::Celsius.from_celsius(celsius));
+        assert_eq!(celsius, TemperatureUnit::Celsius.to_celsius(celsius));
+    }
+
+    assert_eq!("44°C", TemperatureUnit::Celsius.format(44.0));
+    assert_eq!("45.5°C", TemperatureUnit::Celsius.format(45.5));
+    assert_eq!("111.2°F", unit.format(44.0));
+}
+
+#[test]
+fn temperature_unit_in_stats() {
+    let stats = serde_json::to_value(DeviceStats::default()).unwrap();
+    assert_eq!(None, stats.get("temperature_unit"));
+
+    let stats = DeviceStats {
+        temperature_unit: TemperatureUnit::Fahrenheit,
+        ..Default::default()
+    };
+    let value = serde_json::to_value(&stats).unwrap();
+    assert_eq!(Some(&json!("fahrenheit")), value.get("temperature_unit"));
+
+    let stats: DeviceStats = serde_json::from_value(value).unwrap();
+    assert_eq!(TemperatureUnit::Fahrenheit, stats.temperature_unit);
+}
+
+#[test]
+fn read_only_requests() {
+    let reads = [
+        Request::ListDevices,
+        Request::DeviceStats { id: "gpu" },
+        Request::GetGpuConfig { id: "gpu" },
+        Request::ListOperations,
+        Request::ExportConfig,
+        Request::GetOverclockStatus,
+        Request::GetStatusLine,
+        Request::EstimatePowerCap {
+            id: "gpu",
+            target_temperature: 80.0,
+        },
+    ];
+    for request in reads {
+        assert!(request.is_read_only(), "{request:?}");
+        assert_eq!(None, request.gpu_setting_change());
+    }
+
+    let changes = [
+        Request::SetPowerCap {
+            id: "gpu",
+            cap: Some(150.0),
+        },
+        Request::ResetPmfw { id: "gpu" },
+        Request::SetManagementEnabled { enabled: false },
+        Request::DisableOverdrive,
+        Request::ConfirmSettings,
+        Request::CancelOperation { id: 1 },
+        Request::ResetExtremes { id: "gpu" },
+        Request::EnergyDelta { id: "gpu" },
+        // Captures are shared between all clients
+        Request::BeginCapture { id: "gpu" },
+        Request::EndCapture { id: "gpu" },
+        Request::ImportConfig {
+            config: String::new(),
+            apply: true,
+            allow_missing_gpus: false,
+        },
+    ];
+    for request in changes {
+        assert!(!request.is_read_only(), "{request:?}");
+    }
+}
+
+#[test]
+fn compressed_stats_frame() {
+    let response = Response::Ok(vec![DeviceStats::default(); 1000]);
+    let payload = serde_json::to_vec(&response).unwrap();
+    assert!(payload.len() > COMPRESSION_THRESHOLD);
+
+    let frame = CompressedFrame::encode(CompressionCodec::Deflate, &payload).unwrap();
+    let header_end = frame.iter().position(|byte| *byte == b'\n').unwrap() + 1;
+    let header_line = std::str::from_utf8(&frame[..header_end]).unwrap();
+    let header = CompressedFrame::parse_header(header_line).unwrap().unwrap();
+    assert_eq!(CompressionCodec::Deflate, header.codec);
+
+    let data = &frame[header_end..];
+    assert_eq!(header.length, data.len());
+    assert!(data.len() < payload.len() / 10);
+
+    let decompressed = header.codec.decompress(data).unwrap();
+    assert_eq!(payload, decompressed);
+    let decoded: Response<Vec<DeviceStats>> = serde_json::from_slice(&decompressed).unwrap();
+    let Response::Ok(stats) = decoded else {
+        panic!("the response should decode as the original one");
+    };
+    assert_eq!(1000, stats.len());
+
+    // Regular responses are not mistaken for compressed ones
+    let line = String::from_utf8(payload).unwrap();
+    assert_eq!(None, CompressedFrame::parse_header(&line).unwrap());
+}
+
+#[test]
+fn gpu_status_line() {
+    let temp = |current| Temperature {
+        current: Some(current),
+        crit: None,
+        crit_hyst: None,
+    };
+    let mut stats = DeviceStats::default();
+    stats.temps.insert("edge".to_owned(), temp(44.0));
+    stats.temps.insert("junction".to_owned(), temp(51.0));
+    stats.power.average = Some(41.045);
+    stats.clockspeed.gpu_clockspeed = Some(798);
+    stats.fan.pwm_current = Some(35);
+    stats.fan.speed_current = Some(595);
+
+    let line = GpuStatusLine::new("gpu".to_owned(), Some("RX 580".to_owned()), &stats);
+    assert_eq!(Some(51.0), line.temperature);
+    assert_eq!(Some(41.045), line.power);
+    assert_eq!(Some(798), line.core_clock);
+    assert_eq!(Some(14), line.fan_percent);
+    assert_eq!(Some(595), line.fan_rpm);
+    assert_eq!(
+        "gpu (RX 580): 51°C, 41.0 W, 798 MHz, fan 14% (595 RPM)",
+        line.to_string()
+    );
+
+    stats.temperature_unit = TemperatureUnit::Fahrenheit;
+    stats.fan.speed_current = None;
+    let line = GpuStatusLine::new("gpu".to_owned(), None, &stats);
+    assert_eq!("gpu: 123.8°F, 41.0 W, 798 MHz, fan 14%", line.to_string());
+
+    // Values which are not reported are left out of the response
+    let line = GpuStatusLine::new("gpu".to_owned(), None, &DeviceStats::default());
+    assert_eq!("gpu: -, - W, - MHz, fan -", line.to_string());
+    assert_eq!(json!({"id": "gpu"}), serde_json::to_value(&line).unwrap());
+}
+
+#[test]
+fn energy_between_reads() {
+    // 10 J
+    let joules = GpuMetricsStats::energy_between(1_000_000, 1_000_000 + 655_360).unwrap();
+    assert!((joules - 10.0).abs() < f64::EPSILON);
+    assert_eq!(Some(0.0), GpuMetricsStats::energy_between(500, 500));
+
+    // The counter was reset between the reads
+    assert_eq!(
+        None,
+        GpuMetricsStats::energy_between(u64::MAX - 65_535, 65_536)
+    );
+    assert_eq!(
+        None,
+        GpuMetricsStats::energy_between(u64::from(u32::MAX) - 65_535, 65_536)
+    );
+
+    let delta = EnergyDelta {
+        joules: 50.0,
+        elapsed_ms: 2000,
+    };
+    assert_eq!(Some(25.0), delta.average_power());
+    let delta = EnergyDelta {
+        joules: 0.0,
+        elapsed_ms: 0,
+    };
+    assert_eq!(None, delta.average_power());
+}
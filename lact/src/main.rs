@@ -1,3 +1,4 @@
+use lact_daemon::DaemonOptions;
 use lact_schema::args::{clap::Parser, Args, Command, GuiArgs};
 
 fn main() -> anyhow::Result<()> {
@@ -7,7 +8,10 @@ fn main() -> anyhow::Result<()> {
         .unwrap_or_else(|| Command::Gui(GuiArgs::default()));
 
     match command {
-        Command::Daemon => lact_daemon::run(),
+        Command::Daemon(daemon_args) => lact_daemon::run_with_options(DaemonOptions {
+            config_dir: daemon_args.config_dir,
+            socket_path: daemon_args.socket_path,
+        }),
         Command::Gui(gui_args) => run_gui(gui_args),
         Command::Cli(cli_args) => lact_cli::run(cli_args),
     }